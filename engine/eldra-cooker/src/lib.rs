@@ -0,0 +1,228 @@
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use eldra::data::asset_db::{asset_db, sibling_path, META_EXT};
+use eldra::data::manifest::{CookManifest, CookedAsset, MANIFEST_FILE};
+use eldra::data::{res_mgr, BINARY_EXT};
+use eldra::engine::engine_init;
+use eldra::fs::pack::PackWriter;
+
+/**
+ * Offline cooker, imports every source asset of a project and writes its runtime binary
+ * next to it ("<source>.bin", what ExtRes loads in binary mode).
+ *
+ * an asset is cooked again only when its source, or the source of an asset it references,
+ * changed since the last run, according to the hashes kept in the cook manifest.
+ * the cooked binaries and their meta files can then be packed for shipping, see fs/pack.rs.
+ */
+// bump when the cooked format changes so everything gets rebuilt
+pub const COOK_VERSION: u32 = 2;
+
+#[derive(Default,Clone)]
+pub struct CookOptions {
+    pub project: PathBuf,
+    // ignore the manifest and cook everything
+    pub force: bool,
+    // also write the shipped pack archive
+    pub pack: Option<PathBuf>,
+}
+
+#[derive(Default,Debug)]
+pub struct CookReport {
+    pub cooked: Vec<String>,
+    pub up_to_date: Vec<String>,
+    // no registered resource type for the extension
+    pub skipped: Vec<String>,
+    pub removed: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+impl CookReport {
+    pub fn success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+unsafe extern "C" fn ignore_drop(_clz: *const c_char, _id: *const c_char) {}
+
+// engine setup without any window or GPU
+pub fn init() {
+    engine_init(ignore_drop);
+}
+
+struct Source {
+    guid: Uuid,
+    type_id: TypeId,
+    rel: String,
+    path: PathBuf,
+    hash: String,
+}
+
+fn collect_sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(e) => e.flatten().map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with('.') || name == MANIFEST_FILE {
+            continue
+        }
+        if path.is_dir() {
+            collect_sources(&path, out);
+        } else if !path.extension().is_some_and(|e| e == META_EXT || e == BINARY_EXT) {
+            out.push(path);
+        }
+    }
+}
+
+// the extension decides, unless several types share it, then the type recorded in the meta file does
+fn resource_type(guid: &Uuid, ext: &str) -> Result<TypeId, String> {
+    let candidates = res_mgr().registry.types_for_extension(ext);
+    if candidates.len() == 1 {
+        return Ok(candidates[0])
+    }
+    asset_db().read().unwrap().type_of(guid)
+        .and_then(|name| res_mgr().registry.type_for_name(&name))
+        .filter(|t| candidates.contains(t))
+        .ok_or_else(|| format!("several resource types use .{}, set type_name in the meta file", ext))
+}
+
+fn hash_file(path: &Path) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&COOK_VERSION.to_le_bytes());
+    hasher.update(&data);
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn deps_hash(deps: &[Uuid], hashes: &HashMap<Uuid, String>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for dep in deps {
+        hasher.update(dep.as_bytes());
+        hasher.update(hashes.get(dep).map_or("missing", |h| h.as_str()).as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+pub fn cook_project(options: &CookOptions) -> CookReport {
+    let mut report = CookReport::default();
+    let root = options.project.clone();
+    asset_db().write().unwrap().open(&root);
+    let manifest_path = root.join(MANIFEST_FILE);
+    let previous = if options.force { CookManifest::default() } else { CookManifest::load(&manifest_path) };
+
+    let mut manifest = CookManifest::default();
+    let mut seen = HashSet::new();
+    let mut hashes: HashMap<Uuid, String> = HashMap::new();
+    let mut known: HashSet<PathBuf> = HashSet::new();
+    // importers can extract new assets (glTF materials...), cook again until none appears
+    loop {
+        let mut paths = Vec::new();
+        collect_sources(&root, &mut paths);
+        paths.retain(|p| known.insert(p.clone()));
+        if paths.is_empty() {
+            break
+        }
+        cook_sources(paths, &previous, &mut hashes, &mut seen, &mut manifest, &mut report);
+    }
+    // assets extracted during this run were hashed after the ones using them were cooked
+    for asset in manifest.assets.iter_mut() {
+        asset.deps_hash = deps_hash(&asset.dependencies, &hashes);
+    }
+
+    // sources deleted since the last run
+    for prev in previous.assets.iter() {
+        if !seen.contains(&prev.guid) {
+            let _ = fs::remove_file(root.join(&prev.cooked));
+            report.removed.push(prev.source.clone());
+        }
+    }
+    manifest.save(&manifest_path);
+    if let Some(pack) = options.pack.as_ref() {
+        if let Err(e) = write_pack(&root, &manifest, pack) {
+            report.failed.push((pack.display().to_string(), e.to_string()));
+        }
+    }
+    report
+}
+
+fn cook_sources(paths: Vec<PathBuf>, previous: &CookManifest, hashes: &mut HashMap<Uuid, String>, seen: &mut HashSet<Uuid>,
+                manifest: &mut CookManifest, report: &mut CookReport) {
+    // find and hash every source first, dependencies are checked against the current hashes
+    let mut sources = Vec::new();
+    for path in paths {
+        let rel = match asset_db().read().unwrap().relative(&path) {
+            Ok(rel) => rel,
+            Err(e) => { report.failed.push((path.to_string_lossy().to_string(), e)); continue },
+        };
+        let ext = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        if res_mgr().registry.types_for_extension(&ext).is_empty() {
+            report.skipped.push(rel);
+            continue
+        }
+        let guid = match asset_db().write().unwrap().import(&rel) {
+            Ok(guid) => guid,
+            Err(e) => { report.failed.push((rel, e)); continue },
+        };
+        seen.insert(guid);
+        match resource_type(&guid, &ext).and_then(|type_id| hash_file(&path).map(|hash| (type_id, hash))) {
+            Ok((type_id, hash)) => sources.push(Source { guid, type_id, rel, path, hash }),
+            Err(e) => report.failed.push((rel, e)),
+        }
+    }
+    hashes.extend(sources.iter().map(|s| (s.guid, s.hash.clone())));
+
+    for src in sources.iter() {
+        let type_id = src.type_id;
+        let cooked_path = sibling_path(&src.path, BINARY_EXT);
+        let cooked = format!("{}.{}", src.rel, BINARY_EXT);
+
+        if let Some(prev) = previous.find(&src.guid) {
+            if prev.source_hash == src.hash && prev.deps_hash == deps_hash(&prev.dependencies, hashes) && cooked_path.is_file() {
+                manifest.assets.push(prev.clone());
+                report.up_to_date.push(src.rel.clone());
+                continue
+            }
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| res_mgr().registry.cook(type_id, &src.guid, &src.path, &cooked_path)))
+            .unwrap_or_else(|_| Err("cooker panicked".to_string()));
+        // referenced assets were loaded to build this one, don't keep them around
+        res_mgr().collect();
+        match result {
+            Ok(mut dependencies) => {
+                dependencies.sort();
+                manifest.assets.push(CookedAsset {
+                    guid: src.guid,
+                    type_name: res_mgr().registry.type_name(type_id).unwrap_or_default().to_string(),
+                    source: src.rel.clone(),
+                    cooked,
+                    source_hash: src.hash.clone(),
+                    deps_hash: deps_hash(&dependencies, hashes),
+                    dependencies,
+                });
+                report.cooked.push(src.rel.clone());
+            },
+            Err(e) => {
+                let _ = fs::remove_file(&cooked_path);
+                report.failed.push((src.rel.clone(), e));
+            },
+        }
+    }
+}
+
+// cooked binaries with their meta files, at the same project relative paths
+pub fn write_pack(root: &Path, manifest: &CookManifest, pack: &Path) -> std::io::Result<()> {
+    let mut writer = PackWriter::create(pack)?;
+    for asset in manifest.assets.iter() {
+        let meta = format!("{}.{}", asset.source, META_EXT);
+        writer.add_file(&meta, &root.join(&meta))?;
+        writer.add_file(&asset.cooked, &root.join(&asset.cooked))?;
+    }
+    writer.add_file(MANIFEST_FILE, &root.join(MANIFEST_FILE))?;
+    writer.finish()
+}
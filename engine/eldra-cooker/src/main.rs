@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use eldra_cooker::{cook_project, init, CookOptions};
+
+fn usage() -> ExitCode {
+    eprintln!("usage: eldra-cook <project dir> [--force] [--pack <file>]");
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let mut options = CookOptions::default();
+    let mut project = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--force" | "-f" => options.force = true,
+            "--pack" | "-p" => match args.next() {
+                Some(pack) => options.pack = Some(PathBuf::from(pack)),
+                None => return usage(),
+            },
+            "--help" | "-h" => return usage(),
+            _ if arg.starts_with('-') => return usage(),
+            _ => project = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(project) = project else { return usage() };
+    options.project = match project.canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", project.display(), e);
+            return ExitCode::FAILURE
+        },
+    };
+
+    init();
+    let report = cook_project(&options);
+    report.cooked.iter().for_each(|a| println!("cooked     {}", a));
+    report.removed.iter().for_each(|a| println!("removed    {}", a));
+    report.failed.iter().for_each(|(a, e)| eprintln!("FAILED     {} : {}", a, e));
+    println!("{} cooked, {} up to date, {} removed, {} skipped, {} failed",
+             report.cooked.len(), report.up_to_date.len(), report.removed.len(), report.skipped.len(), report.failed.len());
+    if report.success() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
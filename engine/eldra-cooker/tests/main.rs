@@ -0,0 +1,149 @@
+use std::env::current_dir;
+use std::fs;
+use eldra::data::asset_db::asset_db;
+use eldra::data::manifest::{CookManifest, MANIFEST_FILE};
+use eldra::data::material::Material;
+use eldra::data::texture::{Texture, PIXEL_FORMAT_RGBA8};
+use eldra::data::{res_mgr, ExtRes};
+use eldra::fs::vfs;
+use eldra_cooker::{cook_project, init, CookOptions};
+
+fn write_project(root: &std::path::Path) {
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("textures")).unwrap();
+    fs::create_dir_all(root.join("materials")).unwrap();
+    fs::write(root.join("textures/stone.yaml"), "pixel_format :\n  field_type : \"u8\"\n  value : 3\n").unwrap();
+    fs::write(root.join("materials/stone.yaml"),
+              "name :\n  field_type : \"String\"\n  value : \"stone\"\ntex :\n  field_type : \"ExtRes<Texture>\"\n  value : \"textures/stone\"\n").unwrap();
+    image::RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255])).save(root.join("textures/red.png")).unwrap();
+    fs::write(root.join("notes.txt"), "not an asset").unwrap();
+
+    // yaml is shared by several types, the meta file tells which one
+    asset_db().write().unwrap().open(root);
+    asset_db().write().unwrap().import_typed("textures/stone.yaml", "Texture").unwrap();
+    asset_db().write().unwrap().import_typed("materials/stone.yaml", "Material").unwrap();
+}
+
+fn test_cook_all() {
+    let root = current_dir().unwrap().join("../../bin/cook_project");
+    write_project(&root);
+    let options = CookOptions { project: root.clone(), ..Default::default() };
+    let report = cook_project(&options);
+    assert!(report.success(), "{:?}", report.failed);
+    assert_eq!(report.cooked.len(), 3);
+    assert_eq!(report.skipped, vec!["notes.txt".to_string()]);
+
+    let manifest = CookManifest::load(&root.join(MANIFEST_FILE));
+    let db = asset_db().read().unwrap();
+    let tex_guid = db.guid_of("textures/stone.yaml").unwrap();
+    let mat_guid = db.guid_of("materials/stone.yaml").unwrap();
+    let png_guid = db.guid_of("textures/red.png").unwrap();
+    drop(db);
+    let mat_entry = manifest.find(&mat_guid).unwrap();
+    assert_eq!(mat_entry.type_name, "Material");
+    assert_eq!(mat_entry.cooked, "materials/stone.yaml.bin");
+    assert_eq!(mat_entry.dependencies, vec![tex_guid]);
+    assert!(root.join(&manifest.find(&png_guid).unwrap().cooked).is_file());
+
+    // runtime loads the cooked binaries
+    let mat = ExtRes::<Material>::load(mat_guid, true);
+    assert_eq!(mat.get().name, "stone");
+    assert_eq!(mat.get().tex.get().pixel_format, 3);
+    let png = ExtRes::<Texture>::load(png_guid, true);
+    assert_eq!((png.get().width, png.get().height, png.get().pixel_format), (4, 2, PIXEL_FORMAT_RGBA8));
+    assert_eq!(&png.get().pixels[..4], &[255, 0, 0, 255]);
+    drop(mat);
+    drop(png);
+    res_mgr().collect();
+}
+
+fn test_cook_incremental() {
+    let root = current_dir().unwrap().join("../../bin/cook_project");
+    let options = CookOptions { project: root.clone(), ..Default::default() };
+    let report = cook_project(&options);
+    assert!(report.cooked.is_empty());
+    assert_eq!(report.up_to_date.len(), 3);
+
+    // the material is cooked again with its texture
+    fs::write(root.join("textures/stone.yaml"), "pixel_format :\n  field_type : \"u8\"\n  value : 4\n").unwrap();
+    let report = cook_project(&options);
+    assert_eq!(report.cooked, vec!["materials/stone.yaml".to_string(), "textures/stone.yaml".to_string()]);
+    assert_eq!(report.up_to_date, vec!["textures/red.png".to_string()]);
+
+    // deleted sources lose their cooked file
+    fs::remove_file(root.join("textures/red.png")).unwrap();
+    let report = cook_project(&options);
+    assert_eq!(report.removed, vec!["textures/red.png".to_string()]);
+    assert!(!root.join("textures/red.png.bin").exists());
+
+    // yaml asset of unknown type
+    fs::write(root.join("unknown.yaml"), "name :\n  field_type : \"String\"\n  value : \"?\"\n").unwrap();
+    let report = cook_project(&options);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "unknown.yaml");
+
+    let report = cook_project(&CookOptions { project: root.clone(), force: true, ..Default::default() });
+    assert_eq!(report.cooked.len(), 2);
+}
+
+fn test_cook_pack() {
+    let root = current_dir().unwrap().join("../../bin/cook_project");
+    fs::remove_file(root.join("unknown.yaml")).unwrap();
+    fs::remove_file(root.join("unknown.yaml.meta")).unwrap();
+    let pack = current_dir().unwrap().join("../../bin/cook_project.pack");
+    let report = cook_project(&CookOptions { project: root.clone(), pack: Some(pack.clone()), ..Default::default() });
+    assert!(report.success(), "{:?}", report.failed);
+    let mat_guid = asset_db().read().unwrap().guid_of("materials/stone.yaml").unwrap();
+
+    // shipped build, nothing on disk but the pack
+    let shipped = current_dir().unwrap().join("../../bin/cook_shipped");
+    let _ = fs::remove_dir_all(&shipped);
+    fs::create_dir_all(&shipped).unwrap();
+    asset_db().write().unwrap().open(&shipped);
+    let id = vfs().write().unwrap().mount_pack("", &pack).unwrap();
+    asset_db().write().unwrap().scan();
+    assert_eq!(asset_db().read().unwrap().guid_of("materials/stone.yaml"), Some(mat_guid));
+    res_mgr().collect();
+    let mat = ExtRes::<Material>::load(mat_guid, true);
+    assert_eq!(mat.get().name, "stone");
+    assert_eq!(mat.get().tex.get().pixel_format, 4);
+    drop(mat);
+    res_mgr().collect();
+    vfs().write().unwrap().unmount(id);
+}
+
+// extracted materials/textures are cooked in the same run
+fn test_cook_gltf() {
+    let root = current_dir().unwrap().join("../../bin/cook_project");
+    fs::create_dir_all(root.join("models")).unwrap();
+    let positions: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    fs::write(root.join("models/box_data.bin"), positions).unwrap();
+    image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 255, 0, 255])).save(root.join("models/box.png")).unwrap();
+    fs::write(root.join("models/box.gltf"), r#"{
+  "asset": { "version": "2.0" },
+  "buffers": [ { "byteLength": 36, "uri": "box_data.bin" } ],
+  "bufferViews": [ { "buffer": 0, "byteLength": 36 } ],
+  "accessors": [ { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] } ],
+  "images": [ { "uri": "box.png" } ],
+  "textures": [ { "source": 0 } ],
+  "materials": [ { "name": "green", "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } } ],
+  "meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 }, "material": 0 } ] } ]
+}"#).unwrap();
+    let options = CookOptions { project: root.clone(), ..Default::default() };
+    let report = cook_project(&options);
+    assert!(report.success(), "{:?}", report.failed);
+    for asset in ["models/box.gltf", "models/box.png", "models/box.gltf.import/material_0.yaml", "models/box.gltf.import/texture_0.yaml"] {
+        assert!(report.cooked.contains(&asset.to_string()), "{} not cooked", asset);
+    }
+    let report = cook_project(&options);
+    assert!(report.cooked.is_empty(), "{:?}", report.cooked);
+}
+
+#[test]
+fn main() {
+    init();
+    test_cook_all();
+    test_cook_incremental();
+    test_cook_pack();
+    test_cook_gltf();
+}
@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io::BufReader;
+use criterion::{criterion_group, criterion_main, Criterion};
+use eldra::data::blob::{deserialize_binary_file, serialize_binary_file, Blob};
+use eldra::data::render_object::RenderObject;
+use eldra::reflection::Serializable;
+
+const MESH_SIZE: usize = 100 * 1024 * 1024;
+
+fn bench_load_mesh(c: &mut Criterion) {
+    let path = std::env::temp_dir().join("eldra_bench_mesh.bin");
+    let path = path.to_str().unwrap();
+    {
+        let mut mesh = RenderObject::default();
+        mesh.buffer = Blob::from((0..MESH_SIZE).map(|i| i as u8).collect::<Vec<u8>>());
+        serialize_binary_file(&mesh, path).unwrap();
+    }
+
+    let mut group = c.benchmark_group("load_mesh_100mb");
+    group.sample_size(10);
+    group.bench_function("mapped", |b| b.iter(|| {
+        let mut mesh = RenderObject::default();
+        deserialize_binary_file(&mut mesh, path).unwrap();
+        assert_eq!(mesh.buffer.len(), MESH_SIZE);
+    }));
+    group.bench_function("buffered_read", |b| b.iter(|| {
+        let mut mesh = RenderObject::default();
+        mesh.deserialize_binary(&mut BufReader::new(File::open(path).unwrap()));
+        assert_eq!(mesh.buffer.len(), MESH_SIZE);
+    }));
+    group.finish();
+
+    let _ = std::fs::remove_file(path);
+}
+
+criterion_group!(benches, bench_load_mesh);
+criterion_main!(benches);
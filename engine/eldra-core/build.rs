@@ -5,10 +5,12 @@ use std::path::Path;
 
 fn main() {
     let file_path = "../../submodule/wgpu-native/Cargo.toml"; // 目标文件
+    println!("cargo:rerun-if-changed={}", file_path);
 
-    // 检查文件是否存在
+    // 检查文件是否存在, 不存在时只给出警告
     if !Path::new(file_path).exists() {
-        panic!("File not found: {}", file_path);
+        println!("cargo:warning=File not found: {}, run `git submodule update --init`", file_path);
+        return;
     }
 
     // 打开文件进行读取
@@ -17,6 +19,7 @@ fn main() {
 
     // 创建一个新的 String 来保存修改后的内容
     let mut new_content = String::new();
+    let mut changed = false;
 
     // 逐行读取文件
     for line in reader.lines() {
@@ -25,6 +28,7 @@ fn main() {
             // 如果找到要替换的行，替换成新的内容
             new_content.push_str("crate-type = [\"cdylib\", \"staticlib\", \"lib\"]");
             new_content.push('\n');
+            changed = true;
         } else {
             // 否则保持原始行内容
             new_content.push_str(&line);
@@ -32,6 +36,8 @@ fn main() {
         }
     }
 
-    // 将新的内容写回文件
-    fs::write(file_path, new_content).expect("Unable to write to file");
-}
\ No newline at end of file
+    // 只在需要修改时写回文件, 避免每次构建都改动子模块
+    if changed {
+        fs::write(file_path, new_content).expect("Unable to write to file");
+    }
+}
@@ -0,0 +1,550 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::ptr::addr_of;
+use std::rc::Rc;
+use std::str::FromStr;
+use nalgebra::SVector;
+use yaml_rust2::Yaml;
+use eldra_macro::{*};
+use crate::{decode_component, impl_serializable_dyn_type, impl_vec_embed_serialize};
+use crate::comp::transform_component::TransformComponent;
+use crate::data::ExtRes;
+use crate::data::animation_clip::{lerp, slerp, AnimationClip, TrackPath};
+use crate::data::animation_graph::{blend_weights, AnimationGraph, AnimationState, ParameterType, StateKind};
+use crate::data::skeleton::{decompose_matrix, trs_matrix, Skeleton};
+use crate::entity::{*};
+use crate::reflection::{*};
+
+/**
+ * Plays AnimationClips on layers, evaluated in order : each layer blends over the result of
+ * the previous ones by its weight, and cross-fades from its previous clip after a play with fade.
+ * the pose drives the TransformComponent of the descendant entities of the same name, and the
+ * joints of the skeleton, giving the skinning matrices (model space joint * inverse bind).
+ * with an AnimationGraph, its current state is sampled below the layers, parameters are set
+ * by name and transitions are checked once per tick.
+ * a channel blended in by weight starts from the rest pose : the skeleton joints, or the
+ * local transform a driven entity had before it was first animated.
+ * only the tick delta moves time, so a pose is reproducible without a renderer.
+ */
+// animated channels of one node, None keeps the rest value
+#[derive(Clone,Default,Debug,PartialEq)]
+pub struct NodePose {
+    pub translation: Option<Vec3f>,
+    pub rotation: Option<Vec4f>,
+    pub scale: Option<Vec3f>,
+    pub weights: Vec<f32>,
+}
+impl NodePose {
+    // every channel set
+    pub fn from_matrix(matrix: &Mat4f) -> NodePose {
+        let (t, r, s) = decompose_matrix(matrix);
+        NodePose { translation: Some(t), rotation: Some(r), scale: Some(s), weights: Vec::new() }
+    }
+    // s = 0 keeps self, a channel missing from other is kept, one missing from self
+    // is blended from rest (identity when rest doesn't have it either)
+    pub fn blend(&mut self, other: &NodePose, s: f32, rest: Option<&NodePose>) {
+        fn mix<const N: usize>(a: &mut Option<SVector<f32, N>>, b: &Option<SVector<f32, N>>, s: f32, rest: SVector<f32, N>, rotation: bool) {
+            let Some(b) = b else { return };
+            let from = a.unwrap_or(rest);
+            let out = a.insert(from);
+            if rotation {
+                slerp(from.as_slice(), b.as_slice(), s, out.as_mut_slice());
+            } else {
+                lerp(from.as_slice(), b.as_slice(), s, out.as_mut_slice());
+            }
+        }
+        let rest = rest.cloned().unwrap_or_default();
+        mix(&mut self.translation, &other.translation, s, rest.translation.unwrap_or_else(Vec3f::zeros), false);
+        mix(&mut self.rotation, &other.rotation, s, rest.rotation.unwrap_or(Vec4f::new(0., 0., 0., 1.)), true);
+        mix(&mut self.scale, &other.scale, s, rest.scale.unwrap_or(Vec3f::new(1., 1., 1.)), false);
+        if self.weights.is_empty() && !other.weights.is_empty() {
+            self.weights = rest.weights;
+            self.weights.resize(other.weights.len(), 0.0);
+        }
+        if self.weights.len() == other.weights.len() {
+            let from = self.weights.clone();
+            lerp(&from, &other.weights, s, &mut self.weights);
+        }
+    }
+    // local matrix with the animated channels replaced
+    pub fn apply(&self, matrix: &Mat4f) -> Mat4f {
+        let (t, r, s) = decompose_matrix(matrix);
+        trs_matrix(&self.translation.unwrap_or(t), &self.rotation.unwrap_or(r), &self.scale.unwrap_or(s))
+    }
+}
+pub type Pose = HashMap<String, NodePose>;
+
+// tracks whose component count doesn't match their path are skipped
+pub fn sample_clip(clip: &AnimationClip, time: f32, pose: &mut Pose) {
+    let mut value = [0_f32; 4];
+    for track in clip.tracks.iter() {
+        if track.path != TrackPath::WEIGHTS && track.components as usize != track.path.components() {
+            continue
+        }
+        let node = pose.entry(track.target.clone()).or_default();
+        match track.path {
+            TrackPath::TRANSLATION => { track.sample(time, &mut value); node.translation = Some(Vec3f::new(value[0], value[1], value[2])); },
+            TrackPath::ROTATION => { track.sample(time, &mut value); node.rotation = Some(Vec4f::from(value)); },
+            TrackPath::SCALE => { track.sample(time, &mut value); node.scale = Some(Vec3f::new(value[0], value[1], value[2])); },
+            TrackPath::WEIGHTS => {
+                node.weights.resize(track.components as usize, 0.0);
+                track.sample(time, &mut node.weights);
+            },
+        }
+    }
+}
+pub fn blend_pose(pose: &mut Pose, other: &Pose, s: f32, rest: &Pose) {
+    for (name, node) in other.iter() {
+        pose.entry(name.clone()).or_default().blend(node, s, rest.get(name));
+    }
+}
+
+#[derive(Reflection,Clone)]
+pub struct AnimationLayer {
+    #[serialize]
+    pub clip: ExtRes<AnimationClip>,
+    #[serialize]
+    pub time: f32,
+    #[serialize]
+    pub speed: f32,
+    #[serialize]
+    pub weight: f32,
+    #[serialize]
+    pub looping: bool,
+    // clip faded out over fade_duration after a play
+    #[serialize]
+    pub fade_clip: ExtRes<AnimationClip>,
+    #[serialize]
+    pub fade_time: f32,
+    #[serialize]
+    pub fade_elapsed: f32,
+    #[serialize]
+    pub fade_duration: f32,
+}
+impl_vec_embed_serialize!(AnimationLayer);
+impl Default for AnimationLayer {
+    fn default() -> Self {
+        AnimationLayer {
+            clip: ExtRes::default(),
+            time: 0.0,
+            speed: 1.0,
+            weight: 1.0,
+            looping: true,
+            fade_clip: ExtRes::default(),
+            fade_time: 0.0,
+            fade_elapsed: 0.0,
+            fade_duration: 0.0,
+        }
+    }
+}
+fn clip_time(clip: &AnimationClip, time: f32, looping: bool) -> f32 {
+    if looping && clip.duration > 0.0 { time.rem_euclid(clip.duration) } else { time.clamp(0.0, clip.duration) }
+}
+// a blend tree plays its motions in sync : one normalized time, scaled to each clip
+fn state_weights(state: &AnimationState, parameter: &dyn Fn(&str) -> f32) -> Vec<f32> {
+    let p = Vec2f::new(parameter(&state.param_x), if state.kind == StateKind::BLEND_2D { parameter(&state.param_y) } else { 0.0 });
+    blend_weights(state.kind, &state.motions, p)
+}
+fn state_duration(state: &AnimationState, parameter: &dyn Fn(&str) -> f32) -> f32 {
+    match state.kind {
+        StateKind::CLIP => if state.clip.is_loaded() { state.clip.get().duration } else { 0.0 },
+        _ => state_weights(state, parameter).iter().zip(state.motions.iter())
+            .filter(|(_, m)| m.clip.is_loaded())
+            .map(|(w, m)| w * m.clip.get().duration).sum(),
+    }
+}
+fn sample_state(state: &AnimationState, time: f32, parameter: &dyn Fn(&str) -> f32, rest: &Pose, pose: &mut Pose) {
+    match state.kind {
+        StateKind::CLIP => {
+            if state.clip.is_loaded() {
+                let clip = state.clip.get();
+                sample_clip(&clip, clip_time(&clip, time, state.looping), pose);
+            }
+        },
+        _ => {
+            let duration = state_duration(state, parameter);
+            let phase = if duration <= 0.0 { 0.0 } else if state.looping { (time / duration).rem_euclid(1.0) } else { (time / duration).min(1.0) };
+            let mut total = 0.0;
+            for (w, m) in state_weights(state, parameter).iter().zip(state.motions.iter()) {
+                if *w <= 0.0 || !m.clip.is_loaded() {
+                    continue
+                }
+                let mut motion = Pose::new();
+                let clip = m.clip.get();
+                sample_clip(&clip, phase * clip.duration, &mut motion);
+                total += w;
+                blend_pose(pose, &motion, w / total, rest);
+            }
+        },
+    }
+}
+
+// current state of the graph, cross-fading from prev
+#[derive(Clone,Default,Debug)]
+pub struct GraphPlayback {
+    pub state: usize,
+    pub time: f32,
+    pub prev: Option<usize>,
+    pub prev_time: f32,
+    pub fade_elapsed: f32,
+    pub fade_duration: f32,
+}
+
+impl AnimationLayer {
+    pub fn is_fading(&self) -> bool {
+        !self.fade_clip.is_null() && self.fade_elapsed < self.fade_duration
+    }
+    fn advance(&mut self, delta: f32) {
+        self.time += delta * self.speed;
+        if self.is_fading() {
+            self.fade_time += delta * self.speed;
+            self.fade_elapsed += delta;
+            if !self.is_fading() {
+                self.fade_clip = ExtRes::default();
+            }
+        }
+    }
+    fn sample(&self, rest: &Pose, pose: &mut Pose) {
+        if self.clip.is_null() || !self.clip.is_loaded() {
+            return
+        }
+        let clip = self.clip.get();
+        sample_clip(&clip, clip_time(&clip, self.time, self.looping), pose);
+        if self.is_fading() && self.fade_clip.is_loaded() {
+            let mut from = Pose::new();
+            let fade_clip = self.fade_clip.get();
+            sample_clip(&fade_clip, clip_time(&fade_clip, self.fade_time, self.looping), &mut from);
+            blend_pose(&mut from, pose, self.fade_elapsed / self.fade_duration, rest);
+            *pose = from;
+        }
+    }
+}
+
+#[derive(Reflection,ComponentAttr,Default)]
+#[uuid="5b0c8f3e-2d4a-4f61-9a7e-3c1d2e8b6f40"]
+pub struct AnimatorComponent {
+    pub base: BaseObject,
+    // joints animated into skin_matrices, may be null for node animation only
+    #[serialize]
+    pub skeleton: ExtRes<Skeleton>,
+    #[serialize]
+    pub layers: Vec<AnimationLayer>,
+    // below the layers, may be null
+    #[serialize]
+    pub graph: ExtRes<AnimationGraph>,
+    // graph parameters, the graph defaults when not set
+    pub parameters: HashMap<String, f32>,
+    pub playback: Option<GraphPlayback>,
+    // evaluated by tick
+    pub pose: Pose,
+    // rest of the animated nodes, from the skeleton and captured by drive
+    pub rest: Pose,
+    // model space, in joint order
+    pub joint_matrices: Vec<Mat4f>,
+    pub skin_matrices: Vec<Mat4f>,
+}
+impl Uniq for AnimatorComponent {}
+impl Component for AnimatorComponent {
+    fn tick(&mut self, delta: f32, _ancestor: &Option<&Components>) {
+        for layer in self.layers.iter_mut() {
+            layer.advance(delta);
+        }
+        self.advance_graph(delta);
+        self.evaluate();
+    }
+    // animated children before their own tick
+    fn post_tick(&mut self, _world: Option<&Mat4f>, children: &[Rc<RefCell<Entity>>]) {
+        self.drive(children);
+    }
+}
+impl_serializable_dyn_type!(AnimatorComponent, Component);
+
+impl AnimatorComponent {
+    fn layer_mut(&mut self, layer: usize) -> &mut AnimationLayer {
+        if self.layers.len() <= layer {
+            self.layers.resize_with(layer + 1, AnimationLayer::default);
+        }
+        &mut self.layers[layer]
+    }
+    // cross-fades from the current clip of the layer when fade > 0
+    pub fn play(&mut self, layer: usize, clip: ExtRes<AnimationClip>, fade: f32) {
+        let l = self.layer_mut(layer);
+        if fade > 0.0 && !l.clip.is_null() {
+            l.fade_clip = std::mem::take(&mut l.clip);
+            l.fade_time = l.time;
+            l.fade_elapsed = 0.0;
+            l.fade_duration = fade;
+        } else {
+            l.fade_clip = ExtRes::default();
+        }
+        l.clip = clip;
+        l.time = 0.0;
+    }
+    pub fn stop(&mut self, layer: usize) {
+        if let Some(l) = self.layers.get_mut(layer) {
+            *l = AnimationLayer { weight: l.weight, ..Default::default() };
+        }
+    }
+    pub fn set_weight(&mut self, layer: usize, weight: f32) {
+        self.layer_mut(layer).weight = weight.clamp(0.0, 1.0);
+    }
+    pub fn set_graph(&mut self, graph: ExtRes<AnimationGraph>) {
+        self.graph = graph;
+        self.playback = None;
+    }
+    pub fn parameter(&self, name: &str) -> f32 {
+        match self.parameters.get(name) {
+            Some(v) => *v,
+            None if self.graph.is_loaded() => self.graph.get().find_parameter(name).map(|p| p.default_value).unwrap_or(0.0),
+            None => 0.0,
+        }
+    }
+    // NaN and infinities are rejected, the parameter keeps its value
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        if !value.is_finite() {
+            return false
+        }
+        self.parameters.insert(name.to_string(), value);
+        true
+    }
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set_parameter(name, if value { 1.0 } else { 0.0 });
+    }
+    // stays set until a transition testing it fires
+    pub fn set_trigger(&mut self, name: &str) {
+        self.set_parameter(name, 1.0);
+    }
+    pub fn reset_trigger(&mut self, name: &str) {
+        self.set_parameter(name, 0.0);
+    }
+    pub fn current_state(&self) -> Option<String> {
+        let playback = self.playback.as_ref()?;
+        self.graph.get().states.get(playback.state).map(|s| s.name.clone())
+    }
+    // moves the graph time and takes at most one transition
+    fn advance_graph(&mut self, delta: f32) {
+        if self.graph.is_null() || !self.graph.is_loaded() {
+            return
+        }
+        let graph = self.graph.get();
+        if self.playback.is_none() {
+            let Some(entry) = graph.entry_state() else { return };
+            self.playback = Some(GraphPlayback { state: entry, ..Default::default() });
+        }
+        // a reloaded graph may have lost the state, keep the playback as is
+        let Some(state) = self.playback.as_ref().and_then(|p| graph.states.get(p.state)) else { return };
+        let mut playback = self.playback.take().unwrap();
+        playback.time += delta * state.speed;
+        if let Some(prev) = playback.prev {
+            playback.prev_time += delta * graph.states.get(prev).map(|s| s.speed).unwrap_or(1.0);
+            playback.fade_elapsed += delta;
+            if playback.fade_elapsed >= playback.fade_duration {
+                playback.prev = None;
+            }
+        }
+        let parameter = |name: &str| self.parameter(name);
+        let duration = state_duration(state, &parameter);
+        let normalized = if duration > 0.0 { playback.time / duration } else { 1.0 };
+        let fired = graph.transitions.iter().filter(|t| t.leaves(&state.name)).find(|t| {
+            (!t.has_exit_time || normalized >= t.exit_time) && graph.find_state(&t.to).is_some()
+                && t.conditions.iter().all(|c| c.holds(parameter(&c.param)))
+        });
+        if let Some(t) = fired {
+            for c in t.conditions.iter() {
+                if graph.find_parameter(&c.param).is_some_and(|p| p.kind == ParameterType::TRIGGER) {
+                    self.parameters.insert(c.param.clone(), 0.0);
+                }
+            }
+            if t.duration > 0.0 {
+                playback.prev = Some(playback.state);
+                playback.prev_time = playback.time;
+                playback.fade_elapsed = 0.0;
+                playback.fade_duration = t.duration;
+            } else {
+                playback.prev = None;
+            }
+            playback.state = graph.find_state(&t.to).unwrap();
+            playback.time = 0.0;
+        }
+        self.playback = Some(playback);
+    }
+    fn sample_graph(&self, pose: &mut Pose) {
+        let Some(playback) = self.playback.as_ref() else { return };
+        if !self.graph.is_loaded() {
+            return
+        }
+        let parameter = |name: &str| self.parameter(name);
+        let graph = self.graph.get();
+        let Some(state) = graph.states.get(playback.state) else { return };
+        sample_state(state, playback.time, &parameter, &self.rest, pose);
+        if let Some(prev) = playback.prev.and_then(|p| graph.states.get(p)) {
+            let mut from = Pose::new();
+            sample_state(prev, playback.prev_time, &parameter, &self.rest, &mut from);
+            blend_pose(&mut from, pose, playback.fade_elapsed / playback.fade_duration, &self.rest);
+            *pose = from;
+        }
+    }
+    // pose and skinning matrices of the current graph state and layer times
+    pub fn evaluate(&mut self) {
+        if self.skeleton.is_loaded() {
+            for j in self.skeleton.get().joints.iter() {
+                self.rest.entry(j.name.clone()).or_insert_with(|| NodePose::from_matrix(&j.local_matrix()));
+            }
+        }
+        let mut pose = Pose::new();
+        self.sample_graph(&mut pose);
+        for layer in self.layers.iter().filter(|l| l.weight > 0.0) {
+            let mut layer_pose = Pose::new();
+            layer.sample(&self.rest, &mut layer_pose);
+            blend_pose(&mut pose, &layer_pose, layer.weight, &self.rest);
+        }
+        self.pose = pose;
+        self.joint_matrices.clear();
+        self.skin_matrices.clear();
+        if self.skeleton.is_null() || !self.skeleton.is_loaded() {
+            return
+        }
+        let skeleton = self.skeleton.get();
+        let locals: Vec<Mat4f> = skeleton.joints.iter().map(|j| match self.pose.get(&j.name) {
+            Some(node) => trs_matrix(&node.translation.unwrap_or(j.translation), &node.rotation.unwrap_or(j.rotation), &node.scale.unwrap_or(j.scale)),
+            None => j.local_matrix(),
+        }).collect();
+        self.joint_matrices = skeleton.world_matrices(&locals);
+        self.skin_matrices = self.joint_matrices.iter().zip(skeleton.joints.iter()).map(|(m, j)| m * j.inverse_bind).collect();
+    }
+    // sets the local matrix of the animated descendants, down to the next animator
+    pub fn drive(&mut self, children: &[Rc<RefCell<Entity>>]) {
+        if self.pose.is_empty() {
+            return
+        }
+        // first time these nodes are animated, blend them from where they were
+        if self.capture_rest(children) {
+            self.evaluate();
+        }
+        self.apply_pose(children);
+    }
+    // rest of the animated descendants not seen yet, true if one was added
+    fn capture_rest(&mut self, children: &[Rc<RefCell<Entity>>]) -> bool {
+        let mut added = false;
+        for child in children.iter() {
+            let e = child.borrow();
+            if e.get_component::<AnimatorComponent>().is_some() {
+                continue
+            }
+            if self.pose.contains_key(&e.name) && !self.rest.contains_key(&e.name) {
+                if let Some(tr) = e.get_component::<TransformComponent>() {
+                    self.rest.insert(e.name.clone(), NodePose::from_matrix(&tr.local_matrix));
+                    added = true;
+                }
+            }
+            added |= self.capture_rest(&e.children);
+        }
+        added
+    }
+    fn apply_pose(&self, children: &[Rc<RefCell<Entity>>]) {
+        for child in children.iter() {
+            let mut e = child.borrow_mut();
+            if e.get_component::<AnimatorComponent>().is_some() {
+                continue
+            }
+            if let Some(node) = self.pose.get(&e.name) {
+                if let Some(tr) = e.get_component_mut::<TransformComponent>() {
+                    tr.local_matrix = node.apply(&tr.local_matrix);
+                }
+            }
+            self.apply_pose(&e.children);
+        }
+    }
+}
+
+//// exports
+
+fn animator_component_update<F: Fn(&mut AnimatorComponent)>(me: u64, f: F) -> bool
+{
+    match decode_component!(me) {
+        Some(c) => {
+            match c.as_any_mut().downcast_mut::<AnimatorComponent>() {
+                Some(animator) => {
+                    f(animator);
+                    true
+                },
+                None => false
+            }
+        },
+        None => false
+    }
+}
+// clip is a guid or a project relative path, false if it isn't valid UTF-8 or can't be resolved
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_play(me: u64, layer: u32, clip: *const c_char, fade: f32) -> bool {
+    let Ok(reference) = unsafe { CStr::from_ptr(clip) }.to_str() else { return false };
+    let Ok(guid) = ExtRes::<AnimationClip>::resolve_reference(reference) else { return false };
+    let clip = ExtRes::<AnimationClip>::load(guid, false);
+    animator_component_update(me, |animator| {
+        animator.play(layer as usize, clip.clone(), fade);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_set_graph(me: u64, graph: *const c_char) -> bool {
+    let Ok(reference) = unsafe { CStr::from_ptr(graph) }.to_str() else { return false };
+    let Ok(guid) = ExtRes::<AnimationGraph>::resolve_reference(reference) else { return false };
+    let graph = ExtRes::<AnimationGraph>::load(guid, false);
+    animator_component_update(me, |animator| {
+        animator.set_graph(graph.clone());
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_set_float(me: u64, name: *const c_char, value: f32) -> bool {
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return false };
+    if !value.is_finite() {
+        return false
+    }
+    animator_component_update(me, |animator| {
+        animator.set_parameter(name, value);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_set_bool(me: u64, name: *const c_char, value: bool) -> bool {
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return false };
+    animator_component_update(me, |animator| {
+        animator.set_bool(name, value);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_set_trigger(me: u64, name: *const c_char) -> bool {
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return false };
+    animator_component_update(me, |animator| {
+        animator.set_trigger(name);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_reset_trigger(me: u64, name: *const c_char) -> bool {
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return false };
+    animator_component_update(me, |animator| {
+        animator.reset_trigger(name);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_set_weight(me: u64, layer: u32, weight: f32) -> bool {
+    animator_component_update(me, |animator| {
+        animator.set_weight(layer as usize, weight);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn AnimatorComponent_stop(me: u64, layer: u32) -> bool {
+    animator_component_update(me, |animator| {
+        animator.stop(layer as usize);
+    })
+}
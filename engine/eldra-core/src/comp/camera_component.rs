@@ -0,0 +1,260 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::ptr::addr_of;
+use std::rc::Rc;
+use std::str::FromStr;
+use eldra_macro::{*};
+use crate::{decode_component, impl_serializable_dyn_type};
+use crate::data::bounds::{Frustum, Ray};
+use crate::entity::{*};
+use crate::reflection::{*};
+
+/**
+ * Camera looking down -Z of the world matrix of its entity, copied from its TransformComponent
+ * each tick.
+ * projections are right handed with a 0..1 depth range, or 1..0 with reverse_z. infinite_far
+ * only applies to perspective. the viewport is a rect of the render target normalized from its
+ * top left corner, and the aspect follows it unless set.
+ * screen positions are in pixels of the render target from its top left corner.
+ */
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum Projection {
+    #[default]
+    PERSPECTIVE = 0,
+    ORTHOGRAPHIC = 1,
+}
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum ClearMode {
+    #[default]
+    COLOR = 0,
+    DEPTH = 1,
+    NONE = 2,
+}
+
+#[derive(Reflection,ComponentAttr)]
+#[uuid="3e7a1c52-9b0d-4f8e-a6c4-2d5f8b1e7c93"]
+pub struct CameraComponent {
+    pub base: BaseObject,
+    #[serialize]
+    pub projection: Projection,
+    // vertical, radians
+    #[serialize]
+    pub fov_y: f32,
+    // half height of the orthographic volume
+    #[serialize]
+    pub ortho_size: f32,
+    #[serialize]
+    pub near: f32,
+    #[serialize]
+    pub far: f32,
+    #[serialize]
+    pub infinite_far: bool,
+    #[serialize]
+    pub reverse_z: bool,
+    // width over height, 0 follows the viewport
+    #[serialize]
+    pub aspect: f32,
+    // x, y, width, height
+    #[serialize]
+    pub viewport: Vec4f,
+    #[serialize]
+    pub clear: ClearMode,
+    #[serialize]
+    pub clear_color: Vec4f,
+    #[serialize]
+    pub clear_stencil: u32,
+    // drawn render layers, see RenderComponent.layers
+    #[serialize]
+    pub layer_mask: u32,
+    pub world_matrix: Mat4f,
+}
+impl Default for CameraComponent {
+    fn default() -> Self {
+        CameraComponent {
+            base: BaseObject::default(),
+            projection: Projection::PERSPECTIVE,
+            fov_y: std::f32::consts::FRAC_PI_3,
+            ortho_size: 5.0,
+            near: 0.1,
+            far: 1000.0,
+            infinite_far: false,
+            reverse_z: false,
+            aspect: 0.0,
+            viewport: Vec4f::new(0.0, 0.0, 1.0, 1.0),
+            clear: ClearMode::COLOR,
+            clear_color: Vec4f::new(0.0, 0.0, 0.0, 1.0),
+            clear_stencil: 0,
+            layer_mask: u32::MAX,
+            world_matrix: Mat4f::identity(),
+        }
+    }
+}
+impl Uniq for CameraComponent {}
+impl Component for CameraComponent {
+    // looks through the transform of its entity
+    fn post_tick(&mut self, world: Option<&Mat4f>, _children: &[Rc<RefCell<Entity>>]) {
+        if let Some(world) = world {
+            self.world_matrix = *world;
+        }
+    }
+}
+impl_serializable_dyn_type!(CameraComponent, Component);
+
+impl CameraComponent {
+    pub fn set_perspective(&mut self, fov_y: f32, near: f32, far: f32) {
+        self.projection = Projection::PERSPECTIVE;
+        (self.fov_y, self.near, self.far) = (fov_y, near, far);
+    }
+    pub fn set_orthographic(&mut self, size: f32, near: f32, far: f32) {
+        self.projection = Projection::ORTHOGRAPHIC;
+        (self.ortho_size, self.near, self.far) = (size, near, far);
+    }
+    pub fn sees(&self, layers: u32) -> bool {
+        self.layer_mask & layers != 0
+    }
+    // depth buffer value of the far plane
+    pub fn clear_depth(&self) -> f32 {
+        if self.reverse_z { 0.0 } else { 1.0 }
+    }
+    // in pixels, x, y, width, height
+    pub fn viewport_rect(&self, width: u32, height: u32) -> Vec4f {
+        let (w, h) = (width as f32, height as f32);
+        Vec4f::new(self.viewport.x * w, self.viewport.y * h, self.viewport.z * w, self.viewport.w * h)
+    }
+    pub fn aspect_ratio(&self, width: u32, height: u32) -> f32 {
+        if self.aspect > 0.0 {
+            return self.aspect
+        }
+        let rect = self.viewport_rect(width, height);
+        if rect.w > 0.0 { rect.z / rect.w } else { 1.0 }
+    }
+    pub fn view_matrix(&self) -> Mat4f {
+        self.world_matrix.try_inverse().unwrap_or(Mat4f::identity())
+    }
+    pub fn projection_matrix(&self, width: u32, height: u32) -> Mat4f {
+        let aspect = self.aspect_ratio(width, height);
+        let (n, f) = (self.near, self.far);
+        match self.projection {
+            Projection::PERSPECTIVE => {
+                let y = 1.0 / (self.fov_y * 0.5).tan();
+                let (z, w) = match (self.reverse_z, self.infinite_far) {
+                    (false, false) => (f / (n - f), n * f / (n - f)),
+                    (false, true) => (-1.0, -n),
+                    (true, false) => (n / (f - n), n * f / (f - n)),
+                    (true, true) => (0.0, n),
+                };
+                Mat4f::new(
+                    y / aspect, 0.0, 0.0, 0.0,
+                    0.0, y, 0.0, 0.0,
+                    0.0, 0.0, z, w,
+                    0.0, 0.0, -1.0, 0.0)
+            },
+            Projection::ORTHOGRAPHIC => {
+                let (h, w) = (self.ortho_size, self.ortho_size * aspect);
+                let (z, t) = if self.reverse_z { (1.0 / (f - n), f / (f - n)) } else { (1.0 / (n - f), n / (n - f)) };
+                Mat4f::new(
+                    1.0 / w, 0.0, 0.0, 0.0,
+                    0.0, 1.0 / h, 0.0, 0.0,
+                    0.0, 0.0, z, t,
+                    0.0, 0.0, 0.0, 1.0)
+            },
+        }
+    }
+    pub fn view_projection(&self, width: u32, height: u32) -> Mat4f {
+        self.projection_matrix(width, height) * self.view_matrix()
+    }
+    pub fn frustum(&self, width: u32, height: u32) -> Frustum {
+        Frustum::from_matrix(&self.view_projection(width, height))
+    }
+    // pixel x, y and depth buffer value, None behind the camera
+    pub fn world_to_screen(&self, p: &Vec3f, width: u32, height: u32) -> Option<Vec3f> {
+        let clip = self.view_projection(width, height) * Vec4f::new(p.x, p.y, p.z, 1.0);
+        if clip.w <= 0.0 {
+            return None
+        }
+        let ndc = clip.xyz() / clip.w;
+        let rect = self.viewport_rect(width, height);
+        Some(Vec3f::new(rect.x + (ndc.x + 1.0) * 0.5 * rect.z, rect.y + (1.0 - ndc.y) * 0.5 * rect.w, ndc.z))
+    }
+    pub fn screen_to_world(&self, x: f32, y: f32, depth: f32, width: u32, height: u32) -> Vec3f {
+        let rect = self.viewport_rect(width, height);
+        let ndc = Vec4f::new((x - rect.x) / rect.z * 2.0 - 1.0, 1.0 - (y - rect.y) / rect.w * 2.0, depth, 1.0);
+        let p = self.view_projection(width, height).try_inverse().unwrap_or(Mat4f::identity()) * ndc;
+        p.xyz() / p.w
+    }
+    // from the near plane through a pixel
+    pub fn screen_ray(&self, x: f32, y: f32, width: u32, height: u32) -> Ray {
+        let near = self.screen_to_world(x, y, 1.0 - self.clear_depth(), width, height);
+        // finite for infinite projections too
+        let middle = self.screen_to_world(x, y, 0.5, width, height);
+        Ray::new(near, middle - near)
+    }
+}
+
+//// exports
+
+fn camera_component_update<F: Fn(&mut CameraComponent)>(me: u64, f: F) -> bool
+{
+    match decode_component!(me) {
+        Some(c) => {
+            match c.as_any_mut().downcast_mut::<CameraComponent>() {
+                Some(camera) => {
+                    f(camera);
+                    true
+                },
+                None => false
+            }
+        },
+        None => false
+    }
+}
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_set_perspective(me: u64, fov_y: f32, near: f32, far: f32) -> bool {
+    camera_component_update(me, |camera| {
+        camera.set_perspective(fov_y, near, far);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_set_orthographic(me: u64, size: f32, near: f32, far: f32) -> bool {
+    camera_component_update(me, |camera| {
+        camera.set_orthographic(size, near, far);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_set_viewport(me: u64, x: f32, y: f32, width: f32, height: f32) -> bool {
+    camera_component_update(me, |camera| {
+        camera.viewport = Vec4f::new(x, y, width, height);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_set_clear_color(me: u64, r: f32, g: f32, b: f32, a: f32) -> bool {
+    camera_component_update(me, |camera| {
+        camera.clear = ClearMode::COLOR;
+        camera.clear_color = Vec4f::new(r, g, b, a);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_set_layer_mask(me: u64, mask: u32) -> bool {
+    camera_component_update(me, |camera| {
+        camera.layer_mask = mask;
+    })
+}
+// origin then direction into out (6 floats), for Scene_raycast. false if out is null
+#[no_mangle]
+pub extern "C"
+fn CameraComponent_screen_ray(me: u64, x: f32, y: f32, width: u32, height: u32, out: *mut f32) -> bool {
+    if out.is_null() {
+        return false
+    }
+    camera_component_update(me, |camera| {
+        let ray = camera.screen_ray(x, y, width, height);
+        let out = unsafe { std::slice::from_raw_parts_mut(out, 6) };
+        out[..3].copy_from_slice(ray.origin.as_slice());
+        out[3..].copy_from_slice(ray.direction.as_slice());
+    })
+}
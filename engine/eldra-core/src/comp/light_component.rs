@@ -0,0 +1,211 @@
+use std::any::{Any, TypeId};
+use std::str::FromStr;
+use eldra_macro::{*};
+use crate::impl_serializable_dyn_type;
+use crate::entity::{*};
+use crate::reflection::{*};
+
+/**
+ * Light sources, placed by the TransformComponent of their entity and shining down its -Z.
+ * intensities are physical : illuminance in lux for directional lights, luminous intensity in
+ * candela for point and spot lights, as KHR_lights_punctual, and luminance in nits for area
+ * lights. colors are linear and multiply the intensity.
+ * a range of 0 is infinite, the light then fades with the inverse square of the distance only.
+ * area lights are rectangles of width along +X and height along +Y, emitting toward -Z.
+ * scene::lights packs the enabled ones for the GPU.
+ */
+#[derive(Reflection,ComponentAttr)]
+#[uuid="6b1f0d4e-2c8a-4e37-9f15-a3d7c9e80b42"]
+pub struct DirectionalLight {
+    pub base: BaseObject,
+    #[serialize]
+    pub enabled: bool,
+    #[serialize]
+    pub color: Vec3f,
+    // lux
+    #[serialize]
+    pub intensity: f32,
+    #[serialize]
+    pub cast_shadows: bool,
+    // in depth, and along the normal in texels of the shadow map
+    #[serialize]
+    pub shadow_bias: f32,
+    #[serialize]
+    pub shadow_normal_bias: f32,
+    #[serialize]
+    pub shadow_resolution: u32,
+    // from the camera, covered by the cascades
+    #[serialize]
+    pub shadow_distance: f32,
+    #[serialize]
+    pub shadow_cascades: u32,
+}
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        DirectionalLight {
+            base: BaseObject::default(),
+            enabled: true,
+            color: Vec3f::repeat(1.0),
+            intensity: 100000.0,
+            cast_shadows: false,
+            shadow_bias: 0.005,
+            shadow_normal_bias: 1.0,
+            shadow_resolution: 2048,
+            shadow_distance: 100.0,
+            shadow_cascades: 4,
+        }
+    }
+}
+impl Uniq for DirectionalLight {}
+impl Component for DirectionalLight {}
+impl_serializable_dyn_type!(DirectionalLight, Component);
+
+#[derive(Reflection,ComponentAttr)]
+#[uuid="a4c93e17-5d2b-4f80-8e61-0b7f2d9c3a58"]
+pub struct PointLight {
+    pub base: BaseObject,
+    #[serialize]
+    pub enabled: bool,
+    #[serialize]
+    pub color: Vec3f,
+    // candela
+    #[serialize]
+    pub intensity: f32,
+    #[serialize]
+    pub range: f32,
+    #[serialize]
+    pub cast_shadows: bool,
+    #[serialize]
+    pub shadow_bias: f32,
+    #[serialize]
+    pub shadow_normal_bias: f32,
+    // of each cube face
+    #[serialize]
+    pub shadow_resolution: u32,
+}
+impl Default for PointLight {
+    fn default() -> Self {
+        PointLight {
+            base: BaseObject::default(),
+            enabled: true,
+            color: Vec3f::repeat(1.0),
+            intensity: 100.0,
+            range: 0.0,
+            cast_shadows: false,
+            shadow_bias: 0.005,
+            shadow_normal_bias: 1.0,
+            shadow_resolution: 512,
+        }
+    }
+}
+impl Uniq for PointLight {}
+impl Component for PointLight {}
+impl_serializable_dyn_type!(PointLight, Component);
+
+#[derive(Reflection,ComponentAttr)]
+#[uuid="d27e5b90-8f3c-4a16-b4d2-61c0e9a7f3b5"]
+pub struct SpotLight {
+    pub base: BaseObject,
+    #[serialize]
+    pub enabled: bool,
+    #[serialize]
+    pub color: Vec3f,
+    // candela
+    #[serialize]
+    pub intensity: f32,
+    #[serialize]
+    pub range: f32,
+    // from the axis to where the falloff starts and to where it ends, radians
+    #[serialize]
+    pub inner_cone_angle: f32,
+    #[serialize]
+    pub outer_cone_angle: f32,
+    #[serialize]
+    pub cast_shadows: bool,
+    #[serialize]
+    pub shadow_bias: f32,
+    #[serialize]
+    pub shadow_normal_bias: f32,
+    #[serialize]
+    pub shadow_resolution: u32,
+}
+impl Default for SpotLight {
+    fn default() -> Self {
+        SpotLight {
+            base: BaseObject::default(),
+            enabled: true,
+            color: Vec3f::repeat(1.0),
+            intensity: 100.0,
+            range: 0.0,
+            inner_cone_angle: 0.0,
+            outer_cone_angle: std::f32::consts::FRAC_PI_4,
+            cast_shadows: false,
+            shadow_bias: 0.005,
+            shadow_normal_bias: 1.0,
+            shadow_resolution: 1024,
+        }
+    }
+}
+impl Uniq for SpotLight {}
+impl Component for SpotLight {}
+impl_serializable_dyn_type!(SpotLight, Component);
+
+impl SpotLight {
+    // (scale, offset) mapping the cosine to the axis to the cone attenuation, saturated by shaders
+    pub fn cone_scale_offset(&self) -> (f32, f32) {
+        let outer = self.outer_cone_angle.clamp(0.0, std::f32::consts::FRAC_PI_2);
+        let inner = self.inner_cone_angle.clamp(0.0, outer);
+        let scale = 1.0 / (inner.cos() - outer.cos()).max(0.001);
+        (scale, -outer.cos() * scale)
+    }
+}
+
+#[derive(Reflection,ComponentAttr)]
+#[uuid="58e0a3c6-1b9d-4d72-a0f8-c4e6b3d21f97"]
+pub struct AreaLight {
+    pub base: BaseObject,
+    #[serialize]
+    pub enabled: bool,
+    #[serialize]
+    pub color: Vec3f,
+    // nits
+    #[serialize]
+    pub intensity: f32,
+    #[serialize]
+    pub range: f32,
+    #[serialize]
+    pub width: f32,
+    #[serialize]
+    pub height: f32,
+    #[serialize]
+    pub two_sided: bool,
+    #[serialize]
+    pub cast_shadows: bool,
+    #[serialize]
+    pub shadow_bias: f32,
+    #[serialize]
+    pub shadow_normal_bias: f32,
+    #[serialize]
+    pub shadow_resolution: u32,
+}
+impl Default for AreaLight {
+    fn default() -> Self {
+        AreaLight {
+            base: BaseObject::default(),
+            enabled: true,
+            color: Vec3f::repeat(1.0),
+            intensity: 1000.0,
+            range: 0.0,
+            width: 1.0,
+            height: 1.0,
+            two_sided: false,
+            cast_shadows: false,
+            shadow_bias: 0.005,
+            shadow_normal_bias: 1.0,
+            shadow_resolution: 1024,
+        }
+    }
+}
+impl Uniq for AreaLight {}
+impl Component for AreaLight {}
+impl_serializable_dyn_type!(AreaLight, Component);
@@ -1,2 +1,5 @@
 pub mod transform_component;
-pub mod render_component;
\ No newline at end of file
+pub mod render_component;
+pub mod animator_component;
+pub mod camera_component;
+pub mod light_component;
\ No newline at end of file
@@ -4,20 +4,97 @@ use std::str::FromStr;
 use eldra_macro::{*};
 use crate::data::*;
 use crate::data::skeleton::Skeleton;
-use crate::data::render_object::{RenderObject};
+use crate::data::bounds::Aabb;
+use crate::data::render_object::{Primitive, RenderObject};
 use crate::impl_serializable_dyn_type;
 use crate::entity::{*};
 use crate::reflection::{*};
 
 
-#[derive(Reflection,ComponentAttr,Default)]
+#[derive(Reflection,ComponentAttr)]
 #[uuid="f8128f7a-685e-4436-a831-3a2adab3b0dc"]
 pub struct RenderComponent {
     pub base: BaseObject,
     #[serialize]
     pub object: ExtRes<RenderObject>,
+    // part of the object drawn by this component, -1 for all of them
+    #[serialize]
+    pub part: i32,
     #[serialize]
     pub skeleton: ExtRes<Skeleton>,
+    // scales the projected size LODs are selected by, above 1 keeps the details longer
+    #[serialize]
+    pub lod_bias: f32,
+    // bits of the render layers, drawn by the cameras whose layer_mask has one of them
+    #[serialize]
+    pub layers: u32,
+    #[serialize]
+    pub cast_shadows: bool,
+}
+impl Default for RenderComponent {
+    fn default() -> Self {
+        RenderComponent {
+            base: BaseObject::default(),
+            object: ExtRes::default(),
+            part: -1,
+            skeleton: ExtRes::default(),
+            lod_bias: 1.0,
+            layers: 1,
+            cast_shadows: true,
+        }
+    }
+}
+impl RenderComponent {
+    // the skeleton comes with the mesh, unless the object is still loading
+    pub fn set_object(&mut self, object: ExtRes<RenderObject>, part: i32) {
+        self.skeleton = ExtRes::default();
+        self.object = object;
+        self.part = part;
+        if let Some(skeleton) = self.object_skeleton() {
+            self.skeleton = skeleton;
+        }
+    }
+    pub fn object(&self) -> &ExtRes<RenderObject> {
+        &self.object
+    }
+    pub fn set_skeleton(&mut self, skeleton: ExtRes<Skeleton>) {
+        self.skeleton = skeleton;
+    }
+    fn object_skeleton(&self) -> Option<ExtRes<Skeleton>> {
+        if self.object.is_null() || !self.object.is_loaded() {
+            return None
+        }
+        let object = self.object.get();
+        match object.parts.get(self.part as usize) {
+            Some(part) => Some(part.skeleton.clone()).filter(|s| !s.is_null()),
+            _ => object.skeleton().cloned(),
+        }
+    }
+    // explicit skeleton, or the one of the loaded object
+    pub fn skeleton(&self) -> Option<ExtRes<Skeleton>> {
+        if !self.skeleton.is_null() {
+            return Some(self.skeleton.clone())
+        }
+        self.object_skeleton()
+    }
+    // of the drawn parts, None until the object is loaded
+    pub fn local_bounds(&self) -> Option<Aabb> {
+        if self.object.is_null() || !self.object.is_loaded() {
+            return None
+        }
+        Some(self.object.get().bounds(self.part)).filter(|b| !b.is_empty())
+    }
+    pub fn world_bounds(&self, world: &Mat4f) -> Option<Aabb> {
+        self.local_bounds().map(|b| b.transformed(world))
+    }
+    // LOD of a primitive of the object placed by world, seen from eye
+    pub fn select_lod(&self, prim: &Primitive, world: &Mat4f, eye: &Vec3f, fov_y: f32) -> usize {
+        if prim.lods.is_empty() {
+            return 0
+        }
+        let size = prim.sphere.transformed(world).screen_size(eye, fov_y);
+        prim.select_lod(size * self.lod_bias)
+    }
 }
 impl Uniq for RenderComponent {}
 impl Component for RenderComponent {}
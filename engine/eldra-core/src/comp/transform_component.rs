@@ -35,7 +35,7 @@ impl Default for TransformComponent {
 }
 impl TransformComponent {
     pub fn translate(&mut self, v: &Vector3<f32>) {
-        let _ = self.local_matrix.append_translation(v);
+        self.local_matrix.append_translation_mut(v);
     }
 
     pub fn rotate(&mut self, angles: &Vector3<f32>) {
@@ -0,0 +1,205 @@
+use std::any::{Any, TypeId};
+use std::io::{Read, Write};
+use yaml_rust2::Yaml;
+use eldra_macro::Reflection;
+use crate::data::{build_serialized, ExtSerializable};
+use crate::data::loader::SourceData;
+use crate::impl_vec_embed_serialize;
+
+/**
+ * Keyframe animation, imported from a glTF animation.
+ * a track animates one property of one node, addressed by node name (the entity name of an
+ * imported glTF scene, or a skeleton joint name) and by glTF node index.
+ * values hold `components` floats per key : xyz for translation and scale, a quaternion
+ * xyzw for rotation, one weight per morph target. CUBICSPLINE keys store in-tangent, value
+ * and out-tangent, as in glTF.
+ */
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum Interpolation {
+    #[default]
+    LINEAR = 0,
+    STEP = 1,
+    CUBICSPLINE = 2,
+}
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum TrackPath {
+    #[default]
+    TRANSLATION = 0,
+    ROTATION = 1,
+    SCALE = 2,
+    // morph target weights
+    WEIGHTS = 3,
+}
+impl TrackPath {
+    // components per key, 0 for weights which depend on the mesh
+    pub fn components(&self) -> usize {
+        match self {
+            TrackPath::TRANSLATION | TrackPath::SCALE => 3,
+            TrackPath::ROTATION => 4,
+            TrackPath::WEIGHTS => 0,
+        }
+    }
+}
+
+#[derive(Reflection,Default,Clone)]
+pub struct AnimationTrack {
+    #[serialize]
+    pub target: String,
+    // glTF node index, -1 if unknown
+    #[serialize]
+    pub node: i32,
+    #[serialize]
+    pub path: TrackPath,
+    #[serialize]
+    pub interpolation: Interpolation,
+    #[serialize]
+    pub components: u32,
+    // seconds, increasing
+    #[serialize]
+    pub times: Vec<f32>,
+    #[serialize]
+    pub values: Vec<f32>,
+}
+impl_vec_embed_serialize!(AnimationTrack);
+
+pub(crate) fn lerp(a: &[f32], b: &[f32], s: f32, out: &mut [f32]) {
+    for i in 0..out.len() {
+        out[i] = a[i] + (b[i] - a[i]) * s;
+    }
+}
+// shortest path, xyzw
+pub(crate) fn slerp(a: &[f32], b: &[f32], s: f32, out: &mut [f32]) {
+    let mut dot: f32 = (0..4).map(|i| a[i] * b[i]).sum();
+    let sign = if dot < 0.0 { dot = -dot; -1.0 } else { 1.0 };
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - s, s * sign)
+    } else {
+        let theta = dot.acos();
+        let sin = theta.sin();
+        (((1.0 - s) * theta).sin() / sin, (s * theta).sin() / sin * sign)
+    };
+    for i in 0..4 {
+        out[i] = a[i] * wa + b[i] * wb;
+    }
+    normalize(out);
+}
+fn normalize(q: &mut [f32]) {
+    let len = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if len > 0.0 {
+        q.iter_mut().for_each(|v| *v /= len);
+    }
+}
+
+impl AnimationTrack {
+    pub fn key_count(&self) -> usize {
+        self.times.len()
+    }
+    fn key_stride(&self) -> usize {
+        if self.interpolation == Interpolation::CUBICSPLINE { 3 } else { 1 }
+    }
+    // 0 in-tangent, 1 value, 2 out-tangent for CUBICSPLINE
+    fn key_part(&self, key: usize, part: usize) -> &[f32] {
+        let n = self.components as usize;
+        let at = (key * self.key_stride() + part) * n;
+        self.values.get(at..at + n).unwrap_or(&[])
+    }
+    pub fn key_value(&self, key: usize) -> &[f32] {
+        self.key_part(key, if self.interpolation == Interpolation::CUBICSPLINE { 1 } else { 0 })
+    }
+    pub fn duration(&self) -> f32 {
+        self.times.last().cloned().unwrap_or(0.0)
+    }
+    // times and values agree
+    pub fn validate(&self) -> Result<(), String> {
+        if self.times.iter().any(|t| !t.is_finite()) || self.times.windows(2).any(|w| w[1] <= w[0]) {
+            return Err(format!("{} {:?} : times are not increasing", self.target, self.path))
+        }
+        if self.components == 0 || self.values.len() != self.times.len() * self.key_stride() * self.components as usize {
+            return Err(format!("{} {:?} : {} values for {} keys", self.target, self.path, self.values.len(), self.times.len()))
+        }
+        if self.path != TrackPath::WEIGHTS && self.components as usize != self.path.components() {
+            return Err(format!("{} {:?} : {} components", self.target, self.path, self.components))
+        }
+        Ok(())
+    }
+    // out holds `components` floats, the ends are held outside of the keys.
+    // out is left untouched if it is too short or the values don't cover the keys
+    pub fn sample(&self, time: f32, out: &mut [f32]) {
+        let n = self.components as usize;
+        let Some(out) = out.get_mut(..n) else { return };
+        if n == 0 || self.values.len() < self.times.len() * self.key_stride() * n {
+            return
+        }
+        let Some(last) = self.times.len().checked_sub(1) else { return };
+        if time <= self.times[0] || last == 0 {
+            out.copy_from_slice(self.key_value(0));
+            return
+        }
+        if time >= self.times[last] {
+            out.copy_from_slice(self.key_value(last));
+            return
+        }
+        let next = self.times.partition_point(|t| *t <= time);
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let s = (time - self.times[prev]) / dt;
+        match self.interpolation {
+            Interpolation::STEP => out.copy_from_slice(self.key_value(prev)),
+            Interpolation::LINEAR => {
+                if self.path == TrackPath::ROTATION {
+                    slerp(self.key_value(prev), self.key_value(next), s, out);
+                } else {
+                    lerp(self.key_value(prev), self.key_value(next), s, out);
+                }
+            },
+            Interpolation::CUBICSPLINE => {
+                let (p0, m0) = (self.key_part(prev, 1), self.key_part(prev, 2));
+                let (p1, m1) = (self.key_part(next, 1), self.key_part(next, 0));
+                let (s2, s3) = (s * s, s * s * s);
+                let (h00, h10, h01, h11) = (2.0 * s3 - 3.0 * s2 + 1.0, s3 - 2.0 * s2 + s, -2.0 * s3 + 3.0 * s2, s3 - s2);
+                for i in 0..n {
+                    out[i] = h00 * p0[i] + h10 * dt * m0[i] + h01 * p1[i] + h11 * dt * m1[i];
+                }
+                if self.path == TrackPath::ROTATION {
+                    normalize(out);
+                }
+            },
+        }
+    }
+}
+
+#[derive(Reflection,Default)]
+pub struct AnimationClip {
+    #[serialize]
+    pub name: String,
+    // seconds, the end of the longest track
+    #[serialize]
+    pub duration: f32,
+    #[serialize]
+    pub tracks: Vec<AnimationTrack>,
+}
+impl ExtSerializable<AnimationClip> for AnimationClip {
+    fn build_from_source(res: &mut AnimationClip, src: SourceData) -> Result<(), String> {
+        build_serialized(res, src)?;
+        res.validate()
+    }
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<AnimationClip>() + self.tracks.iter()
+            .map(|t| std::mem::size_of::<AnimationTrack>() + (t.times.len() + t.values.len()) * 4).sum::<usize>()
+    }
+}
+impl AnimationClip {
+    pub fn new() -> Box<AnimationClip> {
+        Box::new(AnimationClip::default())
+    }
+    pub fn find(&self, target: &str, path: TrackPath) -> Option<&AnimationTrack> {
+        self.tracks.iter().find(|t| t.target == target && t.path == path)
+    }
+    // every track is consistent, see AnimationTrack::validate
+    pub fn validate(&self) -> Result<(), String> {
+        self.tracks.iter().try_for_each(|t| t.validate())
+    }
+    pub fn update_duration(&mut self) {
+        self.duration = self.tracks.iter().map(|t| t.duration()).fold(0.0, f32::max);
+    }
+}
@@ -0,0 +1,264 @@
+use std::any::{Any, TypeId};
+use std::io::{Read, Write};
+use yaml_rust2::Yaml;
+use eldra_macro::Reflection;
+use crate::data::{build_serialized, ExtRes, ExtSerializable};
+use crate::data::loader::SourceData;
+use crate::data::animation_clip::AnimationClip;
+use crate::impl_vec_embed_serialize;
+use crate::reflection::Vec2f;
+
+/**
+ * Animation state machine, played by an AnimatorComponent.
+ * a state plays a clip, or a blend tree of clips placed on a line (1D, by param_x) or a plane
+ * (2D, by param_x and param_y). transitions leave a state when all their conditions on the
+ * parameters hold, after the exit time if any, and cross-fade to the next state over their
+ * duration. a transition from "*" leaves any state. parameters are floats, bools (0 or 1) and
+ * triggers, which are reset by the transition they fire.
+ */
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum ParameterType {
+    #[default]
+    FLOAT = 0,
+    BOOL = 1,
+    TRIGGER = 2,
+}
+#[derive(Reflection,Default,Clone)]
+pub struct AnimationParameter {
+    #[serialize]
+    pub name: String,
+    #[serialize]
+    pub kind: ParameterType,
+    #[serialize]
+    pub default_value: f32,
+}
+impl_vec_embed_serialize!(AnimationParameter);
+
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum StateKind {
+    #[default]
+    CLIP = 0,
+    BLEND_1D = 1,
+    BLEND_2D = 2,
+}
+#[derive(Reflection,Default,Clone)]
+pub struct BlendMotion {
+    #[serialize]
+    pub clip: ExtRes<AnimationClip>,
+    // y is ignored by 1D blend trees
+    #[serialize]
+    pub position: Vec2f,
+}
+impl_vec_embed_serialize!(BlendMotion);
+
+#[derive(Reflection,Clone)]
+pub struct AnimationState {
+    #[serialize]
+    pub name: String,
+    #[serialize]
+    pub kind: StateKind,
+    #[serialize]
+    pub clip: ExtRes<AnimationClip>,
+    #[serialize]
+    pub motions: Vec<BlendMotion>,
+    #[serialize]
+    pub param_x: String,
+    #[serialize]
+    pub param_y: String,
+    #[serialize]
+    pub speed: f32,
+    #[serialize]
+    pub looping: bool,
+}
+impl_vec_embed_serialize!(AnimationState);
+impl Default for AnimationState {
+    fn default() -> Self {
+        AnimationState {
+            name: String::new(),
+            kind: StateKind::CLIP,
+            clip: ExtRes::default(),
+            motions: Vec::new(),
+            param_x: String::new(),
+            param_y: String::new(),
+            speed: 1.0,
+            looping: true,
+        }
+    }
+}
+
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum ConditionOp {
+    #[default]
+    GREATER = 0,
+    LESS = 1,
+    EQUAL = 2,
+    NOT_EQUAL = 3,
+    // bools and triggers
+    IS_TRUE = 4,
+    IS_FALSE = 5,
+}
+#[derive(Reflection,Default,Clone)]
+pub struct TransitionCondition {
+    #[serialize]
+    pub param: String,
+    #[serialize]
+    pub op: ConditionOp,
+    #[serialize]
+    pub value: f32,
+}
+impl_vec_embed_serialize!(TransitionCondition);
+impl TransitionCondition {
+    pub fn holds(&self, value: f32) -> bool {
+        match self.op {
+            ConditionOp::GREATER => value > self.value,
+            ConditionOp::LESS => value < self.value,
+            ConditionOp::EQUAL => value == self.value,
+            ConditionOp::NOT_EQUAL => value != self.value,
+            ConditionOp::IS_TRUE => value != 0.0,
+            ConditionOp::IS_FALSE => value == 0.0,
+        }
+    }
+}
+
+#[derive(Reflection,Default,Clone)]
+pub struct AnimationTransition {
+    // state name, "*" for any state
+    #[serialize]
+    pub from: String,
+    #[serialize]
+    pub to: String,
+    #[serialize]
+    pub conditions: Vec<TransitionCondition>,
+    #[serialize]
+    pub has_exit_time: bool,
+    // normalized time of the from state, 1 is the end of its first loop
+    #[serialize]
+    pub exit_time: f32,
+    // cross-fade, seconds
+    #[serialize]
+    pub duration: f32,
+}
+impl_vec_embed_serialize!(AnimationTransition);
+impl AnimationTransition {
+    pub fn leaves(&self, state: &str) -> bool {
+        (self.from == "*" && self.to != state) || self.from == state
+    }
+}
+
+#[derive(Reflection,Default)]
+pub struct AnimationGraph {
+    #[serialize]
+    pub name: String,
+    #[serialize]
+    pub parameters: Vec<AnimationParameter>,
+    #[serialize]
+    pub states: Vec<AnimationState>,
+    #[serialize]
+    pub transitions: Vec<AnimationTransition>,
+    // first state, the first one of states if empty
+    #[serialize]
+    pub entry: String,
+}
+impl ExtSerializable<AnimationGraph> for AnimationGraph {
+    fn build_from_source(res: &mut AnimationGraph, src: SourceData) -> Result<(), String> {
+        build_serialized(res, src)?;
+        res.validate()
+    }
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<AnimationGraph>()
+            + self.parameters.len() * std::mem::size_of::<AnimationParameter>()
+            + self.states.iter().map(|s| std::mem::size_of::<AnimationState>() + s.motions.len() * std::mem::size_of::<BlendMotion>()).sum::<usize>()
+            + self.transitions.iter().map(|t| std::mem::size_of::<AnimationTransition>() + t.conditions.len() * std::mem::size_of::<TransitionCondition>()).sum::<usize>()
+    }
+}
+
+impl AnimationGraph {
+    pub fn new() -> Box<AnimationGraph> {
+        Box::new(AnimationGraph::default())
+    }
+    pub fn find_state(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|s| s.name == name)
+    }
+    pub fn find_parameter(&self, name: &str) -> Option<&AnimationParameter> {
+        self.parameters.iter().find(|p| p.name == name)
+    }
+    pub fn entry_state(&self) -> Option<usize> {
+        if self.entry.is_empty() { Some(0).filter(|_| !self.states.is_empty()) } else { self.find_state(&self.entry) }
+    }
+    // states, transitions and parameters referenced by name exist
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.entry.is_empty() && self.find_state(&self.entry).is_none() {
+            return Err(format!("{} : unknown entry state {}", self.name, self.entry))
+        }
+        for state in self.states.iter() {
+            let params = match state.kind {
+                StateKind::CLIP => vec![],
+                StateKind::BLEND_1D => vec![&state.param_x],
+                StateKind::BLEND_2D => vec![&state.param_x, &state.param_y],
+            };
+            if let Some(p) = params.iter().find(|p| self.find_parameter(p).is_none()) {
+                return Err(format!("{} : state {} blends by unknown parameter {}", self.name, state.name, p))
+            }
+        }
+        for t in self.transitions.iter() {
+            if (t.from != "*" && self.find_state(&t.from).is_none()) || self.find_state(&t.to).is_none() {
+                return Err(format!("{} : transition {} -> {} between unknown states", self.name, t.from, t.to))
+            }
+            if let Some(c) = t.conditions.iter().find(|c| self.find_parameter(&c.param).is_none()) {
+                return Err(format!("{} : transition {} -> {} tests unknown parameter {}", self.name, t.from, t.to, c.param))
+            }
+        }
+        Ok(())
+    }
+}
+
+// weights of the motions of a blend tree at point p, summing to 1
+pub fn blend_weights(kind: StateKind, motions: &[BlendMotion], p: Vec2f) -> Vec<f32> {
+    let mut weights = vec![0.0; motions.len()];
+    if motions.is_empty() {
+        return weights
+    }
+    match kind {
+        StateKind::BLEND_1D => {
+            // the two motions around p, clamped to the ends
+            let mut order: Vec<usize> = (0..motions.len()).collect();
+            order.sort_by(|a, b| motions[*a].position.x.total_cmp(&motions[*b].position.x));
+            let x = p.x;
+            let first = order[0];
+            let last = order[order.len() - 1];
+            if x <= motions[first].position.x {
+                weights[first] = 1.0;
+            } else if x >= motions[last].position.x {
+                weights[last] = 1.0;
+            } else if let Some((a, b)) = order.windows(2).map(|w| (w[0], w[1])).find(|(_, b)| x <= motions[*b].position.x) {
+                let (xa, xb) = (motions[a].position.x, motions[b].position.x);
+                let s = if xb > xa { (x - xa) / (xb - xa) } else { 0.0 };
+                weights[a] = 1.0 - s;
+                weights[b] += s;
+            } else {
+                // NaN x or positions
+                weights[first] = 1.0;
+            }
+        },
+        // inverse squared distance, a motion at p takes it all
+        _ => {
+            match motions.iter().position(|m| (m.position - p).norm_squared() < 1e-8) {
+                Some(i) => weights[i] = 1.0,
+                None => {
+                    for (w, m) in weights.iter_mut().zip(motions.iter()) {
+                        *w = 1.0 / (m.position - p).norm_squared();
+                    }
+                    let sum: f32 = weights.iter().sum();
+                    if sum.is_finite() && sum > 0.0 {
+                        weights.iter_mut().for_each(|w| *w /= sum);
+                    } else {
+                        // NaN or infinite positions, the first motion takes it all
+                        weights.fill(0.0);
+                        weights[0] = 1.0;
+                    }
+                },
+            }
+        },
+    }
+    weights
+}
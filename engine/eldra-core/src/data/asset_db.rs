@@ -0,0 +1,268 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::c_char;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use once_cell::sync::OnceCell;
+use uuid::Uuid;
+use eldra_macro::Reflection;
+use crate::data::BINARY_EXT;
+use crate::fs::vfs;
+use yaml_rust2::YamlLoader;
+use crate::{log_error, log_warn};
+use crate::reflection::{Serializable, SerializeTextWriter};
+
+/**
+ * Asset database, every asset is identified by a GUID kept in a "<asset>.meta" sidecar file.
+ * Assets are addressed by paths relative to the project root, lookup keys are
+ * case-normalized ('/' separated, lower case) so references survive case-only differences.
+ * the project root is mounted in the vfs, metas and assets are read through it, so a
+ * shipped build finds them in its packs. edits (import, rename) go to the root on disk.
+ */
+pub const META_EXT: &str = "meta";
+
+#[derive(Reflection,Default)]
+pub struct AssetMeta {
+    #[serialize]
+    pub guid: Uuid,
+    // resource type name, tells apart types sharing an extension (eg. yaml)
+    #[serialize]
+    pub type_name: String,
+}
+
+#[derive(Default)]
+pub struct AssetDatabase {
+    root: PathBuf,
+    // vfs mount of root
+    mount: u64,
+    // guid -> relative path as found on disk
+    guid_to_path: HashMap<Uuid, String>,
+    // normalized relative path -> guid
+    path_to_guid: HashMap<String, Uuid>,
+}
+static ASSET_DB : OnceCell<RwLock<AssetDatabase>> = OnceCell::new();
+#[inline]
+pub fn asset_db() -> &'static RwLock<AssetDatabase> {
+    unsafe { ASSET_DB.get_unchecked() }
+}
+pub fn init_asset_db() {
+    ASSET_DB.get_or_init(|| {
+        let mut db = AssetDatabase::default();
+        db.mount_root(&std::env::current_dir().unwrap_or_default());
+        RwLock::new(db)
+    });
+}
+
+// "<path>.<ext>", keeping the original extension
+pub fn sibling_path(path: &Path, ext: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".");
+    p.push(ext);
+    PathBuf::from(p)
+}
+pub fn meta_path(path: &Path) -> PathBuf {
+    sibling_path(path, META_EXT)
+}
+
+impl AssetDatabase {
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+    fn mount_root(&mut self, root: &Path) {
+        let mut vfs = vfs().write().unwrap();
+        vfs.unmount(self.mount);
+        self.mount = vfs.mount_dir("", root);
+        self.root = canonical(root);
+    }
+    // switch project, the index is rebuilt from the meta files under root
+    pub fn open(&mut self, root: &Path) {
+        self.mount_root(root);
+        self.guid_to_path.clear();
+        self.path_to_guid.clear();
+        self.scan();
+    }
+    // index every meta file visible in the vfs, call again after mounting a pack
+    pub fn scan(&mut self) {
+        let files = vfs().read().unwrap().files();
+        for meta in files.iter() {
+            let Some(rel) = meta.strip_suffix(&format!(".{}", META_EXT)) else { continue };
+            // only the cooked binary of an asset is shipped
+            let asset = Path::new(rel);
+            let vfs = vfs().read().unwrap();
+            if vfs.exists(asset) || vfs.exists(&sibling_path(asset, BINARY_EXT)) {
+                drop(vfs);
+                match Self::read_meta(Path::new(meta)) {
+                    Ok(m) => self.insert(m.guid, rel.to_string()),
+                    Err(e) => log_warn!("skipping {}", e),
+                }
+            }
+        }
+    }
+
+    // path relative to root, '/' separated, fails for a path outside of root
+    pub fn relative(&self, path: &Path) -> Result<String, String> {
+        let abs = canonical(&self.root.join(path));
+        let rel = abs.strip_prefix(&self.root)
+            .map_err(|_| format!("{} is outside of the project root {}", path.display(), self.root.display()))?;
+        let parts: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        Ok(parts.join("/"))
+    }
+    pub fn normalize_key(rel: &str) -> String {
+        rel.replace('\\', "/").to_lowercase()
+    }
+    pub fn absolute(&self, rel: &str) -> PathBuf {
+        self.root.join(rel)
+    }
+
+    fn insert(&mut self, guid: Uuid, rel: String) {
+        if let Some(old) = self.guid_to_path.insert(guid, rel.clone()) {
+            self.path_to_guid.remove(&Self::normalize_key(&old));
+        }
+        self.path_to_guid.insert(Self::normalize_key(&rel), guid);
+    }
+    // empty, truncated or hand broken metas are errors, not panics
+    fn read_meta(meta: &Path) -> Result<AssetMeta, String> {
+        let yaml_str = vfs().read().unwrap().read_to_string(meta).map_err(|e| format!("{} : {}", meta.display(), e))?;
+        let docs = YamlLoader::load_from_str(&yaml_str).map_err(|e| format!("{} : {}", meta.display(), e))?;
+        let Some(doc) = docs.first() else { return Err(format!("{} : empty meta", meta.display())) };
+        // deserialize_text unwraps the values, check them first
+        let guid = &doc["guid"]["value"];
+        if guid.as_str().and_then(|g| Uuid::from_str(g).ok()).is_none() {
+            return Err(format!("{} : no valid guid", meta.display()))
+        }
+        let type_name = &doc["type_name"]["value"];
+        if !type_name.is_badvalue() && !type_name.is_null() && type_name.as_str().is_none() {
+            return Err(format!("{} : invalid type_name", meta.display()))
+        }
+        let mut m = AssetMeta::default();
+        m.deserialize_text(doc);
+        Ok(m)
+    }
+    fn write_meta(meta: &Path, m: &AssetMeta) -> Result<(), String> {
+        let mut writer = SerializeTextWriter::create(meta).map_err(|e| format!("{} : {}", meta.display(), e))?;
+        m.serialize_text(&mut writer, String::new());
+        Ok(())
+    }
+
+    pub fn guid_of(&self, path: &str) -> Option<Uuid> {
+        let rel = self.relative(Path::new(path)).ok()?;
+        self.path_to_guid.get(&Self::normalize_key(&rel)).cloned()
+    }
+    // relative path of the asset, as found on disk
+    pub fn path_of(&self, guid: &Uuid) -> Option<&String> {
+        self.guid_to_path.get(guid)
+    }
+    // register an existing asset, reusing the guid of its meta file or creating one
+    pub fn import(&mut self, path: &str) -> Result<Uuid, String> {
+        if let Some(guid) = self.guid_of(path) {
+            return Ok(guid)
+        }
+        let rel = self.relative(Path::new(path))?;
+        let asset = Path::new(&rel);
+        let meta = meta_path(asset);
+        let (has_meta, has_asset) = {
+            let vfs = vfs().read().unwrap();
+            (vfs.exists(&meta), vfs.exists(asset) || vfs.exists(&sibling_path(asset, BINARY_EXT)))
+        };
+        let read = if has_meta { Some(Self::read_meta(&meta)) } else { None };
+        let guid = match read {
+            Some(Ok(m)) => m.guid,
+            // a broken meta is re-created, its old guid is lost
+            _ if has_asset => {
+                if let Some(Err(e)) = read {
+                    log_warn!("re-creating {}", e);
+                }
+                let m = AssetMeta { guid: Uuid::new_v4(), type_name: String::new() };
+                Self::write_meta(&self.absolute(&meta.to_string_lossy()), &m)?;
+                m.guid
+            },
+            Some(Err(e)) => return Err(e),
+            None => return Err(format!("{} not found", rel)),
+        };
+        self.insert(guid, rel);
+        Ok(guid)
+    }
+    // import and record the resource type in the meta file if not known yet
+    pub fn import_typed(&mut self, path: &str, type_name: &str) -> Result<Uuid, String> {
+        let guid = self.import(path)?;
+        let meta = meta_path(Path::new(self.path_of(&guid).unwrap()));
+        let mut m = Self::read_meta(&meta)?;
+        if m.type_name.is_empty() && !type_name.is_empty() {
+            m.type_name = type_name.to_string();
+            Self::write_meta(&self.absolute(&meta.to_string_lossy()), &m)?;
+        }
+        Ok(guid)
+    }
+    // resource type recorded in the meta file
+    pub fn type_of(&self, guid: &Uuid) -> Option<String> {
+        let meta = meta_path(Path::new(self.path_of(guid)?));
+        let m = Self::read_meta(&meta).ok()?;
+        if m.type_name.is_empty() { None } else { Some(m.type_name) }
+    }
+    // move/rename an asset together with its meta file, the guid is kept
+    pub fn rename(&mut self, from: &str, to: &str) -> std::io::Result<Uuid> {
+        let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+        let guid = self.import(from).map_err(invalid)?;
+        let from_rel = self.path_of(&guid).unwrap().clone();
+        let to_rel = self.relative(Path::new(to)).map_err(invalid)?;
+        let (from_abs, to_abs) = (self.absolute(&from_rel), self.absolute(&to_rel));
+        if let Some(dir) = to_abs.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::rename(&from_abs, &to_abs)?;
+        fs::rename(meta_path(&from_abs), meta_path(&to_abs))?;
+        let cooked = sibling_path(&from_abs, BINARY_EXT);
+        if cooked.is_file() {
+            fs::rename(cooked, sibling_path(&to_abs, BINARY_EXT))?;
+        }
+        self.insert(guid, to_rel);
+        Ok(guid)
+    }
+    // accepts a guid string or a project relative path
+    pub fn resolve(&mut self, reference: &str) -> Result<Uuid, String> {
+        match Uuid::from_str(reference) {
+            Ok(guid) => Ok(guid),
+            Err(_) => self.import(reference),
+        }
+    }
+}
+
+// absolute path with symlinks resolved and "." / ".." folded, the part
+// that doesn't exist yet (eg. a rename target) is folded lexically
+fn canonical(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest: Vec<Component> = Vec::new();
+    let base = loop {
+        if let Ok(p) = fs::canonicalize(existing) {
+            break p
+        }
+        let mut components = existing.components();
+        let Some(last) = components.next_back() else { break PathBuf::new() };
+        rest.push(last);
+        existing = components.as_path();
+    };
+    let mut out = base;
+    for c in rest.into_iter().rev() {
+        match c {
+            Component::CurDir => {},
+            Component::ParentDir => { out.pop(); },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+//// exports
+
+#[no_mangle]
+pub extern "C"
+fn AssetDatabase_open(root: *const c_char) {
+    let Ok(p) = unsafe { CStr::from_ptr(root) }.to_str() else {
+        log_error!("asset database root isn't valid UTF-8");
+        return
+    };
+    asset_db().write().unwrap().open(Path::new(p));
+}
@@ -0,0 +1,308 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use base64::prelude::*;
+use memmap2::Mmap;
+use yaml_rust2::Yaml;
+use crate::fs::{vfs, MappedFile};
+use crate::data::chunk::{read_chunks, write_chunks, Compression};
+use crate::reflection::{pod_as_bytes_mut, Serializable, SerializeTextWriter};
+
+/**
+ * Large binary payload (vertex/index buffer, texture pixels).
+ *
+ * binary layout : [len: i64][compression: u16][pad: u8][pad bytes][data]
+ * data is aligned to BLOB_ALIGN relative to the start of the file, so when the
+ * file is memory-mapped the blob is borrowed in place instead of being copied.
+ * a compressed blob stores a chunk stream (see chunk.rs) instead of pad + data,
+ * and is always decoded into owned memory.
+ */
+pub const BLOB_ALIGN: usize = 16;
+
+enum BlobStorage {
+    Owned(Vec<u8>),
+    Mapped { map: Arc<Mmap>, offset: usize, len: usize },
+}
+
+pub struct Blob {
+    storage: BlobStorage,
+    // applied when serializing to binary, kept from the loaded file
+    pub compression: Compression,
+}
+impl Default for Blob {
+    fn default() -> Self {
+        Blob { storage: BlobStorage::Owned(Vec::new()), compression: Compression::NONE }
+    }
+}
+impl From<Vec<u8>> for Blob {
+    fn from(v: Vec<u8>) -> Self {
+        Blob { storage: BlobStorage::Owned(v), compression: Compression::NONE }
+    }
+}
+impl Blob {
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.storage {
+            BlobStorage::Owned(v) => v.as_slice(),
+            BlobStorage::Mapped { map, offset, len } => &map[*offset..*offset + *len],
+        }
+    }
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.storage, BlobStorage::Mapped { .. })
+    }
+    // copy-on-write, a mapped blob is detached from the file before modification
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if self.is_mapped() {
+            self.storage = BlobStorage::Owned(self.as_slice().to_vec());
+        }
+        match &mut self.storage {
+            BlobStorage::Owned(v) => v,
+            BlobStorage::Mapped { .. } => unreachable!(),
+        }
+    }
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.to_mut().extend_from_slice(data);
+    }
+    // zero-copy typed view, None if the data is not aligned/sized for T
+    pub fn as_typed<T: Copy>(&self) -> Option<&[T]> {
+        let (prefix, body, suffix) = unsafe { self.as_slice().align_to::<T>() };
+        if prefix.is_empty() && suffix.is_empty() { Some(body) } else { None }
+    }
+}
+impl Deref for Blob {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+// reader address, mapping, read position and end of the mapped range
+type ReadCtx = (usize, Arc<Mmap>, Rc<Cell<usize>>, usize);
+// the active aligned writer/mapped reader of this thread, identified by address
+// so that a blob nested in another stream (eg. a compressed chunk) falls back to copying
+thread_local! {
+    static WRITE_CTX: RefCell<Option<(usize, Rc<Cell<u64>>)>> = const { RefCell::new(None) };
+    static READ_CTX: RefCell<Option<ReadCtx>> = const { RefCell::new(None) };
+    // first corrupted blob or array of the current read, Serializable has no error channel
+    static READ_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+fn stream_addr<T: ?Sized>(io: &T) -> usize {
+    io as *const T as *const u8 as usize
+}
+
+struct AlignedWriter<W: Write> {
+    inner: W,
+    pos: Rc<Cell<u64>>,
+}
+impl<W: Write> Write for AlignedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pos.set(self.pos.get() + n as u64);
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// reads [pos, end) of the mapping, a packed file is only a range of it
+struct MappedReader {
+    map: Arc<Mmap>,
+    pos: Rc<Cell<usize>>,
+    end: usize,
+}
+impl Read for MappedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let pos = self.pos.get().min(self.end);
+        let n = buf.len().min(self.end - pos);
+        buf[..n].copy_from_slice(&self.map[pos..pos + n]);
+        self.pos.set(pos + n);
+        Ok(n)
+    }
+}
+
+// serialize to file, blobs are aligned so the file can be loaded by deserialize_binary_file
+// written to a temp file then renamed over the target, a live mapping of the old file stays valid
+pub fn serialize_binary_file(obj: &dyn Serializable, path: &str) -> std::io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let result = write_binary_file(obj, &tmp).and_then(|_| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+fn write_binary_file(obj: &dyn Serializable, path: &str) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let pos = Rc::new(Cell::new(0u64));
+    let mut writer = AlignedWriter { inner: BufWriter::new(file), pos: pos.clone() };
+    WRITE_CTX.with(|ctx| { *ctx.borrow_mut() = Some((stream_addr(&writer), pos)); });
+    obj.serialize_binary(&mut writer);
+    WRITE_CTX.with(|ctx| { *ctx.borrow_mut() = None; });
+    writer.flush()?;
+    writer.inner.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+// memory-map the file, blobs inside are borrowed from the mapping instead of copied
+// the file must not be modified in place while any of its blobs are alive, serialize_binary_file replaces it
+pub fn deserialize_binary_file(obj: &mut dyn Serializable, path: &str) -> std::io::Result<()> {
+    let file = vfs().read().unwrap().map(Path::new(path))?;
+    deserialize_binary_mapped(obj, file)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+// fails if a blob of the file is corrupted, the blob is left empty
+pub fn deserialize_binary_mapped(obj: &mut dyn Serializable, file: MappedFile) -> Result<(), String> {
+    let pos = Rc::new(Cell::new(file.offset));
+    let end = file.offset + file.len;
+    let mut reader = MappedReader { map: file.map.clone(), pos: pos.clone(), end };
+    READ_CTX.with(|ctx| { *ctx.borrow_mut() = Some((stream_addr(&reader), file.map, pos, end)); });
+    READ_ERROR.with(|e| { *e.borrow_mut() = None; });
+    obj.deserialize_binary(&mut reader);
+    READ_CTX.with(|ctx| { *ctx.borrow_mut() = None; });
+    READ_ERROR.with(|e| e.borrow_mut().take()).map_or(Ok(()), Err)
+}
+fn read_failed(error: String) {
+    READ_ERROR.with(|e| { e.borrow_mut().get_or_insert(error); });
+}
+// appends len pod items read from io, a len that doesn't fit in what is left of the
+// stream fails the read instead of being allocated
+pub fn read_pod_vec<T: Copy + Default>(io: &mut dyn Read, len: i64, out: &mut Vec<T>) {
+    let size = std::mem::size_of::<T>();
+    let sizes = usize::try_from(len).ok().and_then(|n| Some((n.checked_mul(size)?, out.len().checked_add(n)?)));
+    let Some((bytes, total)) = sizes else {
+        read_failed(format!("array length {} out of range", len));
+        return
+    };
+    let addr = stream_addr(io);
+    let left = READ_CTX.with(|ctx| match ctx.borrow().as_ref() {
+        Some((a, _, pos, end)) if *a == addr => Some(*end - pos.get().min(*end)),
+        _ => None,
+    });
+    let start = out.len();
+    match left {
+        Some(left) => {
+            if bytes > left {
+                read_failed("array out of mapped range, file truncated ?".to_string());
+                return
+            }
+            out.resize(total, T::default());
+            let _ = io.read_exact(pod_as_bytes_mut(&mut out[start..]));
+        },
+        None => {
+            // sized by what the stream actually holds
+            let mut data = Vec::new();
+            let _ = io.take(bytes as u64).read_to_end(&mut data);
+            if data.len() != bytes {
+                read_failed("array stream truncated".to_string());
+                return
+            }
+            out.resize(total, T::default());
+            pod_as_bytes_mut(&mut out[start..]).copy_from_slice(&data);
+        },
+    }
+}
+
+fn align_pad(pos: u64) -> usize {
+    let rem = (pos as usize) % BLOB_ALIGN;
+    if rem == 0 { 0 } else { BLOB_ALIGN - rem }
+}
+
+impl Serializable for Blob {
+    fn is_multi_line(&self) -> bool { false }
+    fn get_type_uuid(&self) -> Option<uuid::Uuid> { None }
+    fn serialize_binary(&self, io: &mut dyn Write) {
+        let data = self.as_slice();
+        (data.len() as i64).serialize_binary(io);
+        self.compression.serialize_binary(io);
+        if self.compression != Compression::NONE {
+            write_chunks(io, data, self.compression);
+            return
+        }
+        let addr = stream_addr(io);
+        let pos = WRITE_CTX.with(|ctx| match ctx.borrow().as_ref() {
+            Some((a, pos)) if *a == addr => Some(pos.get()),
+            _ => None,
+        });
+        // pad is counted from the byte following the pad length itself
+        let pad = pos.map_or(0, |p| align_pad(p + 1));
+        (pad as u8).serialize_binary(io);
+        let _ = io.write_all(&[0u8; BLOB_ALIGN][..pad]);
+        let _ = io.write_all(data);
+    }
+
+    fn deserialize_binary(&mut self, io: &mut dyn Read) {
+        let mut len: i64 = 0;
+        len.deserialize_binary(io);
+        let len = len as usize;
+        self.compression.deserialize_binary(io);
+
+        let addr = stream_addr(io);
+        let mapped = READ_CTX.with(|ctx| match ctx.borrow().as_ref() {
+            Some((a, map, pos, end)) if *a == addr => Some((map.clone(), pos.clone(), *end)),
+            _ => None,
+        });
+        self.storage = BlobStorage::Owned(Vec::new());
+        if self.compression != Compression::NONE {
+            let data = read_chunks(io, len, self.compression, |io, stored_len| {
+                match mapped.as_ref() {
+                    Some((map, pos, end)) => {
+                        let offset = pos.get();
+                        if stored_len > *end - offset {
+                            return Err("chunk stream out of mapped range, file truncated ?".to_string())
+                        }
+                        pos.set(offset + stored_len);
+                        Ok(Cow::Borrowed(&map[offset..offset + stored_len]))
+                    },
+                    None => {
+                        // sized by what the stream actually holds, stored_len isn't checked yet
+                        let mut stored = Vec::new();
+                        let _ = io.take(stored_len as u64).read_to_end(&mut stored);
+                        Ok(Cow::Owned(stored))
+                    },
+                }
+            });
+            match data {
+                Ok(data) => self.storage = BlobStorage::Owned(data),
+                Err(e) => read_failed(e),
+            }
+            return
+        }
+
+        let mut pad: u8 = 0;
+        pad.deserialize_binary(io);
+        let mut skip = [0u8; BLOB_ALIGN];
+        let _ = io.read_exact(&mut skip[..pad as usize]);
+        match mapped {
+            Some((map, pos, end)) => {
+                let offset = pos.get();
+                if len > end - offset {
+                    read_failed("blob out of mapped range, file truncated ?".to_string());
+                    return
+                }
+                pos.set(offset + len);
+                self.storage = BlobStorage::Mapped { map, offset, len };
+            },
+            None => {
+                let mut data = Vec::new();
+                let _ = io.take(len as u64).read_to_end(&mut data);
+                if data.len() != len {
+                    read_failed("blob stream truncated".to_string());
+                    return
+                }
+                self.storage = BlobStorage::Owned(data);
+            },
+        }
+    }
+
+    fn serialize_text(&self, io: &mut SerializeTextWriter, _indent: String) {
+        let _ = io.write_all(format!("\"{}\"", BASE64_STANDARD.encode(self.as_slice())).as_bytes());
+    }
+
+    fn deserialize_text(&mut self, yaml: &Yaml) {
+        let data = BASE64_STANDARD.decode(yaml.as_str().unwrap()).expect("Malformed blob");
+        self.storage = BlobStorage::Owned(data);
+    }
+}
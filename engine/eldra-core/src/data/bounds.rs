@@ -0,0 +1,208 @@
+use std::any::{Any, TypeId};
+use eldra_macro::Reflection;
+use crate::reflection::{Mat4f, Vec3f, Vec4f};
+
+/**
+ * Bounding volumes of vertices, in the space of the positions, and the rays and frustums
+ * they are tested against.
+ * an Aabb with min above max is empty, see Aabb::empty.
+ */
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub struct Aabb {
+    #[serialize]
+    pub min: Vec3f,
+    #[serialize]
+    pub max: Vec3f,
+}
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+    pub fn empty() -> Aabb {
+        Aabb { min: Vec3f::repeat(f32::MAX), max: Vec3f::repeat(f32::MIN) }
+    }
+    pub fn from_points(points: &[Vec3f]) -> Aabb {
+        let mut aabb = Aabb::empty();
+        points.iter().for_each(|p| aabb.extend(p));
+        aabb
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+    pub fn extend(&mut self, p: &Vec3f) {
+        self.min = self.min.inf(p);
+        self.max = self.max.sup(p);
+    }
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+    pub fn half_extents(&self) -> Vec3f {
+        (self.max - self.min) * 0.5
+    }
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y && self.max.z >= other.max.z
+    }
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.min.y <= other.max.y && self.min.z <= other.max.z
+            && self.max.x >= other.min.x && self.max.y >= other.min.y && self.max.z >= other.min.z
+    }
+    // distance along the ray where it enters the box, 0 from inside
+    pub fn ray_distance(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, max_distance);
+        for i in 0..3 {
+            let inv = 1.0 / ray.direction[i];
+            let (a, b) = ((self.min[i] - ray.origin[i]) * inv, (self.max[i] - ray.origin[i]) * inv);
+            // NaN when the ray lies in a slab plane, max and min keep the other bound
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return None
+            }
+        }
+        Some(near)
+    }
+    // box of the transformed corners
+    pub fn transformed(&self, m: &Mat4f) -> Aabb {
+        if self.is_empty() {
+            return *self
+        }
+        let mut aabb = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vec3f::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z });
+            aabb.extend(&m.transform_point(&corner.into()).coords);
+        }
+        aabb
+    }
+}
+
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub struct BoundingSphere {
+    #[serialize]
+    pub center: Vec3f,
+    #[serialize]
+    pub radius: f32,
+}
+impl BoundingSphere {
+    pub fn new(center: Vec3f, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+    // Ritter's sphere, or the one around the box center when smaller
+    pub fn from_points(points: &[Vec3f]) -> BoundingSphere {
+        let Some(first) = points.first() else { return BoundingSphere::default() };
+        let farthest = |from: &Vec3f| *points.iter().max_by(|a, b| (*a - from).norm_squared().total_cmp(&(*b - from).norm_squared())).unwrap();
+        let y = farthest(first);
+        let z = farthest(&y);
+        let mut sphere = BoundingSphere::new((y + z) * 0.5, (z - y).norm() * 0.5);
+        for p in points.iter() {
+            let d = (p - sphere.center).norm();
+            if d > sphere.radius {
+                let radius = (sphere.radius + d) * 0.5;
+                sphere.center += (p - sphere.center) * ((radius - sphere.radius) / d);
+                sphere.radius = radius;
+            }
+        }
+        let center = Aabb::from_points(points).center();
+        let radius = points.iter().map(|p| (p - center).norm()).fold(0.0, f32::max);
+        if radius < sphere.radius { BoundingSphere::new(center, radius) } else { sphere }
+    }
+    pub fn contains_point(&self, p: &Vec3f) -> bool {
+        (p - self.center).norm() <= self.radius
+    }
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        (other.center - self.center).norm() <= self.radius + other.radius
+    }
+    // diameter over the viewport height, seen from eye with a vertical field of view in radians
+    pub fn screen_size(&self, eye: &Vec3f, fov_y: f32) -> f32 {
+        let distance = (self.center - eye).norm();
+        if distance <= self.radius {
+            return f32::MAX
+        }
+        self.radius / (distance * (fov_y * 0.5).tan())
+    }
+    pub fn ray_distance(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let oc = ray.origin - self.center;
+        let b = oc.dot(&ray.direction);
+        let c = oc.norm_squared() - self.radius * self.radius;
+        let d = b * b - c;
+        if d < 0.0 {
+            return None
+        }
+        let t = (-b - d.sqrt()).max(0.0);
+        if c > 0.0 && b > 0.0 || t > max_distance { None } else { Some(t) }
+    }
+    // radius scaled by the largest axis scale
+    pub fn transformed(&self, m: &Mat4f) -> BoundingSphere {
+        let scale = (0..3).map(|i| m.fixed_view::<3, 1>(0, i).norm()).fold(0.0, f32::max);
+        BoundingSphere::new(m.transform_point(&self.center.into()).coords, self.radius * scale)
+    }
+}
+
+#[derive(Default,Clone,Copy,PartialEq,Debug)]
+pub struct Ray {
+    pub origin: Vec3f,
+    // normalized
+    pub direction: Vec3f,
+}
+impl Ray {
+    pub fn new(origin: Vec3f, direction: Vec3f) -> Ray {
+        Ray { origin, direction: direction.normalize() }
+    }
+    pub fn at(&self, distance: f32) -> Vec3f {
+        self.origin + self.direction * distance
+    }
+    pub fn transformed(&self, m: &Mat4f) -> Ray {
+        Ray::new(m.transform_point(&self.origin.into()).coords, m.transform_vector(&self.direction))
+    }
+}
+
+// planes (normal, distance) facing inwards : left, right, bottom, top, near, far
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct Frustum {
+    pub planes: [Vec4f; 6],
+}
+impl Frustum {
+    // of a view projection matrix with a 0..1 depth range. the planes of an infinite or
+    // reversed depth range that degenerate accept everything
+    pub fn from_matrix(m: &Mat4f) -> Frustum {
+        let row = |i: usize| m.row(i).transpose();
+        let planes = [row(3) + row(0), row(3) - row(0), row(3) + row(1), row(3) - row(1), row(2), row(3) - row(2)];
+        Frustum { planes: planes.map(|p| {
+            let n = p.xyz().norm();
+            if n > 1e-12 { p / n } else { Vec4f::new(0., 0., 0., 1.) }
+        })}
+    }
+    fn distance(plane: &Vec4f, p: &Vec3f) -> f32 {
+        plane.xyz().dot(p) + plane.w
+    }
+    pub fn contains_point(&self, p: &Vec3f) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, p) >= 0.0)
+    }
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, &sphere.center) >= -sphere.radius)
+    }
+    // conservative, boxes across the corners may pass
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        !aabb.is_empty() && self.planes.iter().all(|plane| {
+            // corner the farthest along the normal
+            let p = Vec3f::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z });
+            Frustum::distance(plane, &p) >= 0.0
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::{Read, Write};
+use std::thread;
+use eldra_macro::Reflection;
+use crate::reflection::Serializable;
+
+/**
+ * Compressed chunk stream, used by Blob when compression is enabled.
+ *
+ * binary layout : [chunk_count: u32][chunk_count x (raw_len: u32, stored_len: u32, crc32: u32)][payloads]
+ * every chunk is compressed independently so they can be decoded in parallel,
+ * crc32 covers raw_len and the stored (compressed) bytes, a corrupted chunk is rejected
+ * before anything is allocated for it.
+ */
+pub const BLOB_CHUNK_SIZE: usize = 1 << 20;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum Compression {
+    #[default]
+    NONE = 0,
+    LZ4 = 1,
+    ZSTD = 2,
+}
+
+struct ChunkHeader {
+    raw_len: u32,
+    stored_len: u32,
+    crc: u32,
+}
+
+fn compress_chunk(data: &[u8], compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::NONE => data.to_vec(),
+        Compression::LZ4 => lz4_flex::block::compress(data),
+        Compression::ZSTD => zstd::bulk::compress(data, ZSTD_LEVEL).expect("zstd compress failed"),
+    }
+}
+fn decompress_chunk(stored: &[u8], out: &mut [u8], compression: Compression) -> Result<(), String> {
+    let n = match compression {
+        Compression::NONE if stored.len() == out.len() => { out.copy_from_slice(stored); out.len() },
+        Compression::NONE => stored.len(),
+        Compression::LZ4 => lz4_flex::block::decompress_into(stored, out).map_err(|e| format!("corrupted lz4 chunk : {}", e))?,
+        Compression::ZSTD => zstd::bulk::decompress_to_buffer(stored, out).map_err(|e| format!("corrupted zstd chunk : {}", e))?,
+    };
+    if n != out.len() {
+        return Err(format!("chunk size mismatch, {} bytes decoded instead of {}", n, out.len()))
+    }
+    Ok(())
+}
+fn chunk_crc(raw_len: u32, stored: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&raw_len.to_le_bytes());
+    hasher.update(stored);
+    hasher.finalize()
+}
+
+// run f over (input, output) pairs on all cores
+fn parallel_for<I: Sync, O: Send, F: Fn(&I, &mut O) + Sync>(inputs: &[I], outputs: &mut [O], f: F) {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(inputs.len()).max(1);
+    let per_worker = inputs.len().div_ceil(workers).max(1);
+    thread::scope(|s| {
+        for (ins, outs) in inputs.chunks(per_worker).zip(outputs.chunks_mut(per_worker)) {
+            let f = &f;
+            s.spawn(move || {
+                ins.iter().zip(outs.iter_mut()).for_each(|(i, o)| f(i, o));
+            });
+        }
+    });
+}
+
+pub fn write_chunks(io: &mut dyn Write, data: &[u8], compression: Compression) {
+    let raw: Vec<&[u8]> = data.chunks(BLOB_CHUNK_SIZE).collect();
+    let mut stored: Vec<Vec<u8>> = vec![Vec::new(); raw.len()];
+    parallel_for(&raw, &mut stored, |r, s| { *s = compress_chunk(r, compression); });
+
+    (raw.len() as u32).serialize_binary(io);
+    for (r, s) in raw.iter().zip(stored.iter()) {
+        (r.len() as u32).serialize_binary(io);
+        (s.len() as u32).serialize_binary(io);
+        chunk_crc(r.len() as u32, s).serialize_binary(io);
+    }
+    for s in stored.iter() {
+        let _ = io.write_all(s);
+    }
+}
+
+// read the chunk table of a blob of raw_total bytes, payload bytes are pulled through
+// read_payload so a mapped stream can hand out its memory directly instead of copying
+pub fn read_chunks<'a, F>(io: &mut dyn Read, raw_total: usize, compression: Compression, read_payload: F) -> Result<Vec<u8>, String>
+    where F: FnOnce(&mut dyn Read, usize) -> Result<std::borrow::Cow<'a, [u8]>, String>
+{
+    let mut count = [0u8; 4];
+    io.read_exact(&mut count).map_err(|_| "chunk table truncated".to_string())?;
+    let count = u32::from_le_bytes(count) as usize;
+    if count != raw_total.div_ceil(BLOB_CHUNK_SIZE) {
+        return Err(format!("{} chunks for {} bytes, corrupted data ?", count, raw_total))
+    }
+    // count is bounded by the blob length, which isn't checksummed
+    let mut headers = Vec::with_capacity(count.min(1024));
+    for _i in 0..count {
+        let mut h = [0u8; 12];
+        io.read_exact(&mut h).map_err(|_| "chunk table truncated".to_string())?;
+        let u32_at = |o: usize| u32::from_le_bytes(h[o..o + 4].try_into().unwrap());
+        headers.push(ChunkHeader { raw_len: u32_at(0), stored_len: u32_at(4), crc: u32_at(8) });
+    }
+    let stored_total: usize = headers.iter().map(|h| h.stored_len as usize).sum();
+    let payload = read_payload(io, stored_total)?;
+    if payload.len() != stored_total {
+        return Err("chunk stream truncated".to_string())
+    }
+
+    let mut inputs = Vec::with_capacity(headers.len());
+    let mut stored_offset = 0usize;
+    for (index, h) in headers.iter().enumerate() {
+        let stored = &payload[stored_offset..stored_offset + h.stored_len as usize];
+        if chunk_crc(h.raw_len, stored) != h.crc {
+            return Err(format!("checksum mismatch in chunk {}, corrupted data ?", index))
+        }
+        inputs.push(stored);
+        stored_offset += h.stored_len as usize;
+    }
+    // write_chunks splits at BLOB_CHUNK_SIZE, any other layout is corrupted
+    let sizes_valid = headers.iter().enumerate()
+        .all(|(i, h)| h.raw_len as usize == BLOB_CHUNK_SIZE.min(raw_total - i * BLOB_CHUNK_SIZE));
+    if !sizes_valid {
+        return Err(format!("chunk sizes don't add up to {} bytes", raw_total))
+    }
+
+    let mut out = vec![0u8; raw_total];
+    let mut outputs = Vec::with_capacity(headers.len());
+    let mut rest = out.as_mut_slice();
+    for h in headers.iter() {
+        let (head, tail) = rest.split_at_mut(h.raw_len as usize);
+        outputs.push((head, Ok(())));
+        rest = tail;
+    }
+    parallel_for(&inputs, &mut outputs, |s, (o, result)| *result = decompress_chunk(s, o, compression));
+    outputs.into_iter().map(|(_, result)| result).collect::<Result<Vec<_>, _>>()?;
+    Ok(out)
+}
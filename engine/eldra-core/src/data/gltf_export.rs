@@ -0,0 +1,525 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::io::{Cursor, Write};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::rc::Rc;
+use base64::prelude::*;
+use gltf_json as json;
+use gltf_json::validation::{Checked::Valid, USize64};
+use gltf_json::{Index, Value};
+use uuid::Uuid;
+use crate::comp::render_component::RenderComponent;
+use crate::comp::transform_component::TransformComponent;
+use crate::data::ExtRes;
+use crate::data::material::{AlphaMode, Material, ShadingModel};
+use crate::data::render_object::{BufferType, BufferView, ComponentType, DataType, Primitive, PrimitiveMode, RenderObject};
+use crate::data::skeleton::Skeleton;
+use crate::data::vertex_accessor::{component_count, element_size, VertexAccessor};
+use crate::data::texture::{FilterMode, Sampler, Texture, WrapMode, PIXEL_FORMAT_RGBA8};
+use crate::entity::{entity_cast, Entity};
+use crate::log_error;
+use crate::reflection::Mat4f;
+
+/**
+ * glTF 2.0 export built on gltf-json.
+ * every accessor gets its own buffer view, repacked into a single buffer with vertex elements
+ * aligned to 4 bytes. textures are written as png images stored in the buffer.
+ * .glb files carry the buffer in their BIN chunk, .gltf files embed it as a data uri.
+ */
+const SPECULAR_GLOSSINESS_EXT: &str = "KHR_materials_pbrSpecularGlossiness";
+
+pub struct GltfExport {
+    root: json::Root,
+    bin: Vec<u8>,
+    scene_nodes: Vec<Index<json::Node>>,
+    // root joints, after the other scene nodes
+    joint_roots: Vec<Index<json::Node>>,
+    meshes: HashMap<Uuid, Vec<Index<json::Mesh>>>,
+    materials: HashMap<Uuid, Index<json::Material>>,
+    textures: HashMap<Uuid, Index<json::Texture>>,
+    skins: HashMap<Uuid, Index<json::Skin>>,
+}
+
+fn json_component_type(t: &ComponentType) -> json::accessor::ComponentType {
+    match t {
+        ComponentType::S8 => json::accessor::ComponentType::I8,
+        ComponentType::U8 => json::accessor::ComponentType::U8,
+        ComponentType::S16 => json::accessor::ComponentType::I16,
+        ComponentType::U16 => json::accessor::ComponentType::U16,
+        ComponentType::U32 => json::accessor::ComponentType::U32,
+        ComponentType::F32 => json::accessor::ComponentType::F32,
+    }
+}
+fn json_type(t: &DataType) -> json::accessor::Type {
+    match t {
+        DataType::SCALA => json::accessor::Type::Scalar,
+        DataType::VEC2 => json::accessor::Type::Vec2,
+        DataType::VEC3 => json::accessor::Type::Vec3,
+        DataType::VEC4 => json::accessor::Type::Vec4,
+        DataType::MAT2 => json::accessor::Type::Mat2,
+        DataType::MAT3 => json::accessor::Type::Mat3,
+        DataType::MAT4 => json::accessor::Type::Mat4,
+    }
+}
+fn json_mode(mode: &PrimitiveMode) -> json::mesh::Mode {
+    match mode {
+        PrimitiveMode::POINTS => json::mesh::Mode::Points,
+        PrimitiveMode::LINES => json::mesh::Mode::Lines,
+        PrimitiveMode::LINE_LOOP => json::mesh::Mode::LineLoop,
+        PrimitiveMode::LINE_STRIP => json::mesh::Mode::LineStrip,
+        PrimitiveMode::TRIANGLES => json::mesh::Mode::Triangles,
+        PrimitiveMode::TRIANGLE_STRIP => json::mesh::Mode::TriangleStrip,
+        PrimitiveMode::TRIANGLE_FAN => json::mesh::Mode::TriangleFan,
+    }
+}
+// BViewIterator attribute names
+fn semantic(name: &str) -> Result<json::mesh::Semantic, String> {
+    use json::mesh::Semantic;
+    let set = |prefix: &str| name.strip_prefix(prefix).and_then(|n| n.parse::<u32>().ok());
+    Ok(match name {
+        "POSITION" => Semantic::Positions,
+        "NORMAL" => Semantic::Normals,
+        "TANGENT" => Semantic::Tangents,
+        _ => if let Some(n) = set("TEXCOORD_") { Semantic::TexCoords(n) }
+            else if let Some(n) = set("COLOR_") { Semantic::Colors(n) }
+            else if let Some(n) = set("JOINTS_") { Semantic::Joints(n) }
+            else if let Some(n) = set("WEIGHTS_") { Semantic::Weights(n) }
+            else { return Err(format!("unknown vertex attribute {}", name)) },
+    })
+}
+fn wrap_mode(mode: WrapMode) -> json::texture::WrappingMode {
+    match mode {
+        WrapMode::REPEAT => json::texture::WrappingMode::Repeat,
+        WrapMode::CLAMP_TO_EDGE => json::texture::WrappingMode::ClampToEdge,
+        WrapMode::MIRRORED_REPEAT => json::texture::WrappingMode::MirroredRepeat,
+    }
+}
+fn json_sampler(s: &Sampler) -> json::texture::Sampler {
+    use json::texture::{MagFilter, MinFilter};
+    let mag = if s.mag_filter == FilterMode::NEAREST { MagFilter::Nearest } else { MagFilter::Linear };
+    let min = match (s.mipmaps, s.min_filter, s.mip_filter) {
+        (false, FilterMode::NEAREST, _) => MinFilter::Nearest,
+        (false, FilterMode::LINEAR, _) => MinFilter::Linear,
+        (true, FilterMode::NEAREST, FilterMode::NEAREST) => MinFilter::NearestMipmapNearest,
+        (true, FilterMode::LINEAR, FilterMode::NEAREST) => MinFilter::LinearMipmapNearest,
+        (true, FilterMode::NEAREST, FilterMode::LINEAR) => MinFilter::NearestMipmapLinear,
+        (true, FilterMode::LINEAR, FilterMode::LINEAR) => MinFilter::LinearMipmapLinear,
+    };
+    json::texture::Sampler {
+        mag_filter: Some(Valid(mag)),
+        min_filter: Some(Valid(min)),
+        wrap_s: Valid(wrap_mode(s.wrap_u)),
+        wrap_t: Valid(wrap_mode(s.wrap_v)),
+        ..Default::default()
+    }
+}
+fn texture_info(index: Option<Index<json::Texture>>) -> Option<json::texture::Info> {
+    index.map(|index| json::texture::Info { index, tex_coord: 0, extensions: None, extras: Default::default() })
+}
+
+impl Default for GltfExport {
+    fn default() -> Self {
+        GltfExport::new()
+    }
+}
+impl GltfExport {
+    pub fn new() -> GltfExport {
+        let mut root = json::Root::default();
+        root.asset.generator = Some("Eldra".to_string());
+        GltfExport {
+            root,
+            bin: Vec::new(),
+            scene_nodes: Vec::new(),
+            joint_roots: Vec::new(),
+            meshes: HashMap::new(),
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+            skins: HashMap::new(),
+        }
+    }
+
+    fn push_view(&mut self, data: &[u8], stride: Option<usize>, target: Option<json::buffer::Target>) -> Index<json::buffer::View> {
+        self.bin.resize(self.bin.len().div_ceil(4) * 4, 0);
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(data);
+        self.root.push(json::buffer::View {
+            buffer: Index::new(0),
+            byte_length: USize64::from(data.len()),
+            byte_offset: Some(USize64::from(offset)),
+            byte_stride: stride.map(json::buffer::Stride),
+            name: None,
+            target: target.map(Valid),
+            extensions: None,
+            extras: Default::default(),
+        })
+    }
+    // copies the view out of the object buffer, sparse views are written dense.
+    // min/max are required for POSITION
+    fn push_accessor(&mut self, buffer: &[u8], view: &BufferView, bounds: bool) -> Result<Index<json::Accessor>, String> {
+        let elem = element_size(view);
+        let src = VertexAccessor::new(buffer, view)?.to_bytes();
+        let vertex = matches!(view.buffer_type, BufferType::VERTEX);
+        // index views are tightly packed and have no byteStride
+        let stride = if vertex { elem.div_ceil(4) * 4 } else { elem };
+        let mut data = vec![0_u8; stride * view.count as usize];
+        for i in 0..view.count as usize {
+            data[i * stride..i * stride + elem].copy_from_slice(&src[i * elem..(i + 1) * elem]);
+        }
+        let (min, max) = if bounds { Self::bounds(&data, stride, view) } else { (None, None) };
+        let target = if vertex { json::buffer::Target::ArrayBuffer } else { json::buffer::Target::ElementArrayBuffer };
+        let buffer_view = self.push_view(&data, (stride != elem).then_some(stride), Some(target));
+        Ok(self.root.push(json::Accessor {
+            buffer_view: Some(buffer_view),
+            byte_offset: None,
+            count: USize64::from(view.count as usize),
+            component_type: Valid(json::accessor::GenericComponentType(json_component_type(&view.component_type))),
+            extensions: None,
+            extras: Default::default(),
+            type_: Valid(json_type(&view.data_type)),
+            min,
+            max,
+            name: None,
+            normalized: view.normalized,
+            sparse: None,
+        }))
+    }
+    fn bounds(data: &[u8], stride: usize, view: &BufferView) -> (Option<Value>, Option<Value>) {
+        if !matches!(view.component_type, ComponentType::F32) || view.count == 0 {
+            return (None, None)
+        }
+        let n = component_count(&view.data_type);
+        let mut min = vec![f32::MAX; n];
+        let mut max = vec![f32::MIN; n];
+        for i in 0..view.count as usize {
+            for c in 0..n {
+                let at = i * stride + c * 4;
+                let v = f32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+                min[c] = min[c].min(v);
+                max[c] = max[c].max(v);
+            }
+        }
+        (Some(Value::from(min)), Some(Value::from(max)))
+    }
+    fn push_matrices(&mut self, matrices: &[Mat4f]) -> Index<json::Accessor> {
+        let data: Vec<u8> = matrices.iter().flat_map(|m| m.as_slice().iter().flat_map(|f| f.to_le_bytes())).collect();
+        let buffer_view = self.push_view(&data, None, None);
+        self.root.push(json::Accessor {
+            buffer_view: Some(buffer_view),
+            byte_offset: None,
+            count: USize64::from(matrices.len()),
+            component_type: Valid(json::accessor::GenericComponentType(json::accessor::ComponentType::F32)),
+            extensions: None,
+            extras: Default::default(),
+            type_: Valid(json::accessor::Type::Mat4),
+            min: None,
+            max: None,
+            name: None,
+            normalized: false,
+            sparse: None,
+        })
+    }
+
+    fn add_texture(&mut self, tex: &ExtRes<Texture>) -> Option<Index<json::Texture>> {
+        if tex.is_null() || !tex.is_loaded() {
+            return None
+        }
+        if let Some(index) = self.textures.get(tex.guid()) {
+            return Some(*index)
+        }
+        let guid = *tex.guid();
+        let tex = tex.get();
+        if tex.pixel_format != PIXEL_FORMAT_RGBA8 || tex.pixels.is_empty() {
+            return None
+        }
+        let image = image::RgbaImage::from_raw(tex.width, tex.height, tex.pixels.as_slice().to_vec())?;
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).ok()?;
+        let buffer_view = self.push_view(png.get_ref(), None, None);
+        let source = self.root.push(json::Image {
+            buffer_view: Some(buffer_view),
+            mime_type: Some(json::image::MimeType("image/png".to_string())),
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default(),
+        });
+        let sampler = self.root.push(json_sampler(&tex.sampler));
+        let index = self.root.push(json::Texture {
+            name: None,
+            sampler: Some(sampler),
+            source,
+            extensions: None,
+            extras: Default::default(),
+        });
+        self.textures.insert(guid, index);
+        Some(index)
+    }
+    fn add_material(&mut self, res: &ExtRes<Material>) -> Option<Index<json::Material>> {
+        if res.is_null() {
+            return None
+        }
+        if let Some(index) = self.materials.get(res.guid()) {
+            return Some(*index)
+        }
+        let mat = res.get();
+        let mut m = json::Material {
+            name: (!mat.name.is_empty()).then(|| mat.name.clone()),
+            alpha_mode: Valid(match mat.alpha_mode {
+                AlphaMode::OPAQUE => json::material::AlphaMode::Opaque,
+                AlphaMode::MASK => json::material::AlphaMode::Mask,
+                AlphaMode::BLEND => json::material::AlphaMode::Blend,
+            }),
+            // only valid with MASK
+            alpha_cutoff: (mat.alpha_mode == AlphaMode::MASK).then_some(json::material::AlphaCutoff(mat.alpha_cutoff)),
+            double_sided: mat.double_sided,
+            emissive_factor: json::material::EmissiveFactor(mat.emissive.into()),
+            ..Default::default()
+        };
+        let base_color = self.add_texture(&mat.tex);
+        m.pbr_metallic_roughness.base_color_factor = json::material::PbrBaseColorFactor(mat.base_color.into());
+        match mat.shading {
+            ShadingModel::METALLIC_ROUGHNESS => {
+                m.pbr_metallic_roughness.base_color_texture = texture_info(base_color);
+                m.pbr_metallic_roughness.metallic_factor = json::material::StrengthFactor(mat.metallic);
+                m.pbr_metallic_roughness.roughness_factor = json::material::StrengthFactor(mat.roughness);
+                m.pbr_metallic_roughness.metallic_roughness_texture = texture_info(self.add_texture(&mat.metallic_roughness_tex));
+            },
+            ShadingModel::SPECULAR_GLOSSINESS => {
+                use json::extensions::material::{PbrDiffuseFactor, PbrSpecularFactor, PbrSpecularGlossiness};
+                let sg = PbrSpecularGlossiness {
+                    diffuse_factor: PbrDiffuseFactor(mat.base_color.into()),
+                    diffuse_texture: texture_info(base_color),
+                    specular_factor: PbrSpecularFactor(mat.specular.into()),
+                    glossiness_factor: json::material::StrengthFactor(mat.glossiness),
+                    specular_glossiness_texture: texture_info(self.add_texture(&mat.specular_glossiness_tex)),
+                    ..Default::default()
+                };
+                // the other KHR_materials features of gltf-json add fields
+                #[allow(clippy::needless_update)]
+                let extensions = json::extensions::material::Material { pbr_specular_glossiness: Some(sg), ..Default::default() };
+                m.extensions = Some(extensions);
+                if !self.root.extensions_used.iter().any(|e| e == SPECULAR_GLOSSINESS_EXT) {
+                    self.root.extensions_used.push(SPECULAR_GLOSSINESS_EXT.to_string());
+                }
+            },
+        }
+        m.normal_texture = self.add_texture(&mat.normal_tex).map(|index| json::material::NormalTexture {
+            index, scale: mat.normal_scale, tex_coord: 0, extensions: None, extras: Default::default(),
+        });
+        m.occlusion_texture = self.add_texture(&mat.occlusion_tex).map(|index| json::material::OcclusionTexture {
+            index, strength: json::material::StrengthFactor(mat.occlusion_strength), tex_coord: 0, extensions: None, extras: Default::default(),
+        });
+        m.emissive_texture = texture_info(self.add_texture(&mat.emissive_tex));
+        let index = self.root.push(m);
+        self.materials.insert(*res.guid(), index);
+        Some(index)
+    }
+    // joint nodes, the roots are added to the scene
+    fn add_skin(&mut self, res: &ExtRes<Skeleton>) -> Option<Index<json::Skin>> {
+        if res.is_null() || !res.is_loaded() {
+            return None
+        }
+        if let Some(index) = self.skins.get(res.guid()) {
+            return Some(*index)
+        }
+        let skeleton = res.get();
+        let joints: Vec<Index<json::Node>> = skeleton.joints.iter().map(|j| self.root.push(json::Node {
+            name: Some(j.name.clone()),
+            translation: Some(j.translation.into()),
+            rotation: Some(json::scene::UnitQuaternion(j.rotation.into())),
+            scale: Some(j.scale.into()),
+            ..Default::default()
+        })).collect();
+        let mut roots = Vec::new();
+        for (i, j) in skeleton.joints.iter().enumerate() {
+            if j.parent < 0 {
+                roots.push(joints[i]);
+            } else {
+                let parent = &mut self.root.nodes[joints[j.parent as usize].value()];
+                parent.children.get_or_insert_with(Vec::new).push(joints[i]);
+            }
+        }
+        let inverse_binds: Vec<Mat4f> = skeleton.joints.iter().map(|j| j.inverse_bind).collect();
+        let inverse_bind_matrices = Some(self.push_matrices(&inverse_binds));
+        let index = self.root.push(json::Skin {
+            extensions: None,
+            extras: Default::default(),
+            inverse_bind_matrices,
+            joints,
+            name: (!skeleton.name.is_empty()).then(|| skeleton.name.clone()),
+            skeleton: if roots.len() == 1 { Some(roots[0]) } else { None },
+        });
+        self.joint_roots.extend(roots);
+        self.skins.insert(*res.guid(), index);
+        Some(index)
+    }
+    fn push_optional(&mut self, buffer: &[u8], view: Option<&BufferView>, bounds: bool) -> Result<Option<Index<json::Accessor>>, String> {
+        view.map(|view| self.push_accessor(buffer, view, bounds)).transpose()
+    }
+    fn add_primitive(&mut self, buffer: &[u8], prim: &Primitive) -> Result<json::mesh::Primitive, String> {
+        let mut attributes = BTreeMap::new();
+        for info in prim.vertex_iter() {
+            let name = info.attr_name.as_str();
+            attributes.insert(Valid(semantic(name)?), self.push_accessor(buffer, info.buffer_view, name == "POSITION")?);
+        }
+        let mut targets = Vec::new();
+        for t in prim.targets.iter() {
+            targets.push(json::mesh::MorphTarget {
+                positions: self.push_optional(buffer, t.position.as_ref(), true)?,
+                normals: self.push_optional(buffer, t.normal.as_ref(), false)?,
+                tangents: self.push_optional(buffer, t.tangent.as_ref(), false)?,
+            });
+        }
+        Ok(json::mesh::Primitive {
+            attributes,
+            extensions: None,
+            extras: Default::default(),
+            indices: self.push_optional(buffer, prim.indice.as_ref(), false)?,
+            material: self.add_material(&prim.material),
+            mode: Valid(json_mode(&prim.mode)),
+            targets: (!targets.is_empty()).then_some(targets),
+        })
+    }
+    // one mesh per part. an error leaves the export half written, drop it
+    pub fn add_object(&mut self, obj: &RenderObject) -> Result<Vec<Index<json::Mesh>>, String> {
+        let buffer = obj.buffer.as_slice();
+        obj.parts.iter().map(|part| {
+            let primitives = part.primitives.iter().map(|prim| self.add_primitive(buffer, prim)).collect::<Result<_, _>>()?;
+            Ok(self.root.push(json::Mesh {
+                extensions: None,
+                extras: Default::default(),
+                name: (!part.name.is_empty()).then(|| part.name.clone()),
+                primitives,
+                weights: (!part.weights.is_empty()).then(|| part.weights.clone()),
+            }))
+        }).collect()
+    }
+    fn add_object_res(&mut self, res: &ExtRes<RenderObject>) -> Result<Vec<Index<json::Mesh>>, String> {
+        if res.is_null() || !res.is_loaded() {
+            return Ok(Vec::new())
+        }
+        if let Some(meshes) = self.meshes.get(res.guid()) {
+            return Ok(meshes.clone())
+        }
+        let meshes = self.add_object(&res.get())?;
+        self.meshes.insert(*res.guid(), meshes.clone());
+        Ok(meshes)
+    }
+    fn part_node(&mut self, name: &str, mesh: Index<json::Mesh>, skeleton: &ExtRes<Skeleton>) -> Index<json::Node> {
+        let skin = self.add_skin(skeleton);
+        self.root.push(json::Node {
+            name: (!name.is_empty()).then(|| name.to_string()),
+            mesh: Some(mesh),
+            skin,
+            ..Default::default()
+        })
+    }
+    // one scene node per part
+    pub fn add_object_nodes(&mut self, obj: &RenderObject) -> Result<(), String> {
+        let meshes = self.add_object(obj)?;
+        for (part, mesh) in obj.parts.iter().zip(meshes) {
+            let node = self.part_node(&part.name, mesh, &part.skeleton);
+            self.scene_nodes.push(node);
+        }
+        Ok(())
+    }
+    fn entity_node(&mut self, entity: &Entity) -> Result<Index<json::Node>, String> {
+        let mut node = json::Node { name: Some(entity.name.clone()), ..Default::default() };
+        if let Some(tr) = entity.get_component::<TransformComponent>() {
+            if tr.local_matrix != Mat4f::identity() {
+                node.matrix = Some(tr.local_matrix.as_slice().try_into().unwrap());
+            }
+        }
+        let mut children = Vec::new();
+        if let Some(render) = entity.get_component::<RenderComponent>() {
+            let meshes = self.add_object_res(render.object())?;
+            let skeleton = render.skeleton().unwrap_or_default();
+            let object = render.object().get();
+            match meshes.get(render.part as usize) {
+                Some(mesh) => {
+                    node.mesh = Some(*mesh);
+                    node.skin = self.add_skin(&skeleton);
+                },
+                // all parts, as children
+                None => for (part, mesh) in object.parts.iter().zip(meshes) {
+                    let skeleton = if part.skeleton.is_null() { &skeleton } else { &part.skeleton };
+                    children.push(self.part_node(&part.name, mesh, skeleton));
+                },
+            }
+        }
+        for child in entity.children.iter() {
+            children.push(self.entity_node(&child.borrow())?);
+        }
+        node.children = (!children.is_empty()).then_some(children);
+        Ok(self.root.push(node))
+    }
+    // the entity and its children become a scene node tree
+    pub fn add_entity(&mut self, entity: &Rc<RefCell<Entity>>) -> Result<(), String> {
+        let node = self.entity_node(&entity.borrow())?;
+        self.scene_nodes.push(node);
+        Ok(())
+    }
+
+    fn finish(mut self, glb: bool) -> (json::Root, Vec<u8>) {
+        self.bin.resize(self.bin.len().div_ceil(4) * 4, 0);
+        if !self.bin.is_empty() {
+            let uri = (!glb).then(|| format!("data:application/octet-stream;base64,{}", BASE64_STANDARD.encode(&self.bin)));
+            self.root.push(json::Buffer {
+                byte_length: USize64::from(self.bin.len()),
+                name: None,
+                uri,
+                extensions: None,
+                extras: Default::default(),
+            });
+        }
+        self.scene_nodes.append(&mut self.joint_roots);
+        let scene = self.root.push(json::Scene {
+            extensions: None,
+            extras: Default::default(),
+            name: None,
+            nodes: self.scene_nodes,
+        });
+        self.root.scene = Some(scene);
+        (self.root, self.bin)
+    }
+    pub fn write_gltf(self, io: &mut dyn Write) -> Result<(), String> {
+        let (root, _) = self.finish(false);
+        let json = json::serialize::to_vec_pretty(&root).map_err(|e| e.to_string())?;
+        io.write_all(&json).map_err(|e| e.to_string())
+    }
+    pub fn write_glb(self, io: &mut dyn Write) -> Result<(), String> {
+        let (root, bin) = self.finish(true);
+        let json = json::serialize::to_vec(&root).map_err(|e| e.to_string())?;
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header { magic: *b"glTF", version: 2, length: 0 },
+            json: json.into(),
+            bin: (!bin.is_empty()).then(|| bin.into()),
+        };
+        glb.to_writer(io).map_err(|e| e.to_string())
+    }
+    // binary for a .glb path
+    pub fn save(self, path: &Path) -> Result<(), String> {
+        let mut file = std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("glb")) {
+            self.write_glb(&mut file)
+        } else {
+            self.write_gltf(&mut file)
+        }
+    }
+}
+
+//// exports
+
+#[no_mangle]
+pub extern "C"
+fn Entity_export_gltf(addr: u64, path: *const c_char) -> bool {
+    let Some(entity) = entity_cast(&addr) else { return false };
+    let Ok(p) = unsafe { CStr::from_ptr(path) }.to_str() else { return false };
+    let mut export = GltfExport::new();
+    export.add_entity(&entity)
+        .and_then(|_| export.save(Path::new(p)))
+        .map_err(|e| log_error!("gltf export failed : {}", e))
+        .is_ok()
+}
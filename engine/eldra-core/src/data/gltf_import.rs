@@ -0,0 +1,344 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use base64::prelude::*;
+use gltf::Document;
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Source;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use uuid::Uuid;
+use crate::data::{ExtRes, Resource};
+use crate::data::animation_clip::{AnimationClip, AnimationTrack, Interpolation, TrackPath};
+use crate::data::asset_db::asset_db;
+use crate::data::material::{AlphaMode, Material, ShadingModel};
+use crate::data::skeleton::{decompose_matrix, Joint, Skeleton};
+use crate::data::texture::{FilterMode, Sampler, Texture, WrapMode};
+use crate::fs::vfs;
+use crate::reflection::{Mat4f, Serializable, SerializeTextWriter, Vec3f, Vec4f};
+
+/**
+ * glTF file import, shared by the importers of the resources found in a glTF file.
+ *
+ * buffers are read through the vfs. materials, textures and embedded images are extracted
+ * as regular assets into "<file>.import/" next to the glTF file (material_N.yaml,
+ * texture_N.yaml, image_N.png), so they are referenced, cooked and packed like any other.
+ * extract writes them, offline (cooker, editor import), loading a glTF only looks them up.
+ * they are written again on every extract, edits belong in the glTF file.
+ */
+pub const IMPORT_DIR_EXT: &str = "import";
+
+pub struct GltfImport {
+    pub path: PathBuf,
+    pub document: Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+}
+
+// "%20" escapes of relative uris
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' { uri.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()) } else { None };
+        match hex {
+            Some(b) => { out.push(b); i += 3; },
+            None => { out.push(bytes[i]); i += 1; },
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+// mime type and payload of a "data:" uri
+fn decode_data_uri(uri: &str) -> Option<(String, Vec<u8>)> {
+    let (header, data) = uri.strip_prefix("data:")?.split_once(";base64,")?;
+    Some((header.to_string(), BASE64_STANDARD.decode(data).ok()?))
+}
+fn image_ext(mime: &str) -> &'static str {
+    if mime == "image/jpeg" { "jpg" } else { "png" }
+}
+
+// entity, joint and animation target name of a node
+pub(crate) fn node_name(node: &gltf::Node) -> String {
+    node.name().map(|n| n.to_string()).unwrap_or_else(|| format!("node_{}", node.index()))
+}
+
+fn wrap_mode(mode: WrappingMode) -> WrapMode {
+    match mode {
+        WrappingMode::ClampToEdge => WrapMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => WrapMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => WrapMode::REPEAT,
+    }
+}
+fn sampler(s: &gltf::texture::Sampler) -> Sampler {
+    let mut sampler = Sampler { wrap_u: wrap_mode(s.wrap_s()), wrap_v: wrap_mode(s.wrap_t()), ..Default::default() };
+    if s.mag_filter() == Some(MagFilter::Nearest) {
+        sampler.mag_filter = FilterMode::NEAREST;
+    }
+    // unspecified : let the renderer pick, mipmapped linear
+    let (min, mip, mipmaps) = match s.min_filter() {
+        None => (FilterMode::LINEAR, FilterMode::LINEAR, true),
+        Some(MinFilter::Nearest) => (FilterMode::NEAREST, FilterMode::NEAREST, false),
+        Some(MinFilter::Linear) => (FilterMode::LINEAR, FilterMode::LINEAR, false),
+        Some(MinFilter::NearestMipmapNearest) => (FilterMode::NEAREST, FilterMode::NEAREST, true),
+        Some(MinFilter::LinearMipmapNearest) => (FilterMode::LINEAR, FilterMode::NEAREST, true),
+        Some(MinFilter::NearestMipmapLinear) => (FilterMode::NEAREST, FilterMode::LINEAR, true),
+        Some(MinFilter::LinearMipmapLinear) => (FilterMode::LINEAR, FilterMode::LINEAR, true),
+    };
+    sampler.min_filter = min;
+    sampler.mip_filter = mip;
+    sampler.mipmaps = mipmaps;
+    sampler
+}
+
+impl GltfImport {
+    // like gltf::import, but external buffers are read through the vfs relative to the glTF file
+    pub fn open(path: &Path) -> Result<GltfImport, String> {
+        let err = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let bytes = vfs().read().unwrap().read(path).map_err(|e| err(&e))?;
+        let gltf = gltf::Gltf::from_slice(&bytes).map_err(|e| err(&e))?;
+        let mut blob = gltf.blob;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut buffers = Vec::new();
+        for buffer in gltf.document.buffers() {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Uri(uri) if !uri.contains(':') => {
+                    let mut data = vfs().read().unwrap().read(&base.join(decode_uri(uri))).map_err(|e| err(&e))?;
+                    data.resize(data.len().div_ceil(4) * 4, 0);
+                    gltf::buffer::Data(data)
+                },
+                source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob).map_err(|e| err(&e))?,
+            };
+            if data.len() < buffer.length() {
+                return Err(format!("{}: buffer {} is shorter than its byteLength", path.display(), buffer.index()))
+            }
+            buffers.push(data);
+        }
+        Ok(GltfImport { path: path.to_path_buf(), document: gltf.document, buffers })
+    }
+
+    // project relative directory of the extracted assets
+    pub fn import_dir(&self) -> Result<String, String> {
+        let rel = asset_db().read().unwrap().relative(&self.path)?;
+        Ok(format!("{}.{}", rel, IMPORT_DIR_EXT))
+    }
+    fn create_import_dir(&self) -> Result<PathBuf, String> {
+        let dir = asset_db().read().unwrap().absolute(&self.import_dir()?);
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(dir)
+    }
+    // the guid is kept across imports by the meta file
+    fn write_asset(&self, name: &str, obj: &dyn Serializable, type_name: &str) -> Result<Uuid, String> {
+        let rel = format!("{}/{}", self.import_dir()?, name);
+        let path = asset_db().read().unwrap().absolute(&rel);
+        {
+            let mut writer = SerializeTextWriter::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            obj.serialize_text(&mut writer, String::new());
+        }
+        asset_db().write().unwrap().import_typed(&rel, type_name)
+    }
+
+    // image paths relative to the import dir, embedded images are written into it
+    fn extract_images(&self, dir: &Path) -> Result<Vec<String>, String> {
+        self.document.images().map(|img| {
+            let (mime, data) = match img.source() {
+                Source::View { view, mime_type } => {
+                    let buffer = &self.buffers[view.buffer().index()];
+                    (mime_type.to_string(), buffer[view.offset()..view.offset() + view.length()].to_vec())
+                },
+                Source::Uri { uri, mime_type } => match decode_data_uri(uri) {
+                    Some((header, data)) => (mime_type.unwrap_or(&header).to_string(), data),
+                    // external file next to the glTF
+                    None => return Ok(format!("../{}", decode_uri(uri))),
+                },
+            };
+            let name = format!("image_{}.{}", img.index(), image_ext(&mime));
+            fs::write(dir.join(&name), data).map_err(|e| format!("{}: {}", dir.join(&name).display(), e))?;
+            Ok(name)
+        }).collect()
+    }
+    // write every extracted asset, the guids are kept across extracts by the meta files
+    pub fn extract(&self) -> Result<(), String> {
+        let textures = self.extract_textures()?;
+        self.extract_materials(&textures)?;
+        self.extract_skeletons()?;
+        self.extract_animations()
+    }
+    // an extracted asset, fails if the glTF wasn't extracted
+    fn extracted<T: Resource>(&self, name: String) -> Result<ExtRes<T>, String> {
+        let rel = format!("{}/{}", self.import_dir()?, name);
+        let guid = asset_db().read().unwrap().guid_of(&rel)
+            .ok_or_else(|| format!("{}: {} is missing, import the file first", self.path.display(), rel))?;
+        Ok(ExtRes::<T>::load(guid, false))
+    }
+    // document order, for the lookups below
+    pub fn materials(&self) -> Result<Vec<ExtRes<Material>>, String> {
+        self.document.materials().filter_map(|m| m.index())
+            .map(|i| self.extracted(format!("material_{}.yaml", i))).collect()
+    }
+    pub fn skeletons(&self) -> Result<Vec<ExtRes<Skeleton>>, String> {
+        self.document.skins().map(|skin| self.extracted(format!("skeleton_{}.yaml", skin.index()))).collect()
+    }
+    pub fn animations(&self) -> Result<Vec<ExtRes<AnimationClip>>, String> {
+        self.document.animations().map(|anim| self.extracted(format!("animation_{}.yaml", anim.index()))).collect()
+    }
+
+    // one Texture asset per glTF texture, in document order
+    fn extract_textures(&self) -> Result<Vec<Uuid>, String> {
+        if self.document.textures().len() == 0 {
+            return Ok(Vec::new())
+        }
+        let dir = self.create_import_dir()?;
+        let images = self.extract_images(&dir)?;
+        self.document.textures().map(|t| {
+            let tex = Texture { image: images[t.source().index()].clone(), sampler: sampler(&t.sampler()), ..Default::default() };
+            self.write_asset(&format!("texture_{}.yaml", t.index()), &tex, "Texture")
+        }).collect()
+    }
+    // one Material asset per glTF material, in document order
+    fn extract_materials(&self, textures: &[Uuid]) -> Result<(), String> {
+        if self.document.materials().len() == 0 {
+            return Ok(())
+        }
+        self.create_import_dir()?;
+        let tex = |t: gltf::Texture| ExtRes::<Texture>::load(textures[t.index()], false);
+        self.document.materials().filter(|m| m.index().is_some()).try_for_each(|m| {
+            let mut mat = Material { name: m.name().unwrap_or_default().to_string(), ..Default::default() };
+            match m.pbr_specular_glossiness() {
+                Some(sg) => {
+                    mat.shading = ShadingModel::SPECULAR_GLOSSINESS;
+                    mat.base_color = Vec4f::from(sg.diffuse_factor());
+                    mat.specular = Vec3f::from(sg.specular_factor());
+                    mat.glossiness = sg.glossiness_factor();
+                    if let Some(info) = sg.diffuse_texture() {
+                        mat.tex = tex(info.texture());
+                    }
+                    if let Some(info) = sg.specular_glossiness_texture() {
+                        mat.specular_glossiness_tex = tex(info.texture());
+                    }
+                },
+                None => {
+                    let mr = m.pbr_metallic_roughness();
+                    mat.base_color = Vec4f::from(mr.base_color_factor());
+                    mat.metallic = mr.metallic_factor();
+                    mat.roughness = mr.roughness_factor();
+                    if let Some(info) = mr.base_color_texture() {
+                        mat.tex = tex(info.texture());
+                    }
+                    if let Some(info) = mr.metallic_roughness_texture() {
+                        mat.metallic_roughness_tex = tex(info.texture());
+                    }
+                },
+            }
+            if let Some(normal) = m.normal_texture() {
+                mat.normal_tex = tex(normal.texture());
+                mat.normal_scale = normal.scale();
+            }
+            if let Some(occlusion) = m.occlusion_texture() {
+                mat.occlusion_tex = tex(occlusion.texture());
+                mat.occlusion_strength = occlusion.strength();
+            }
+            mat.emissive = Vec3f::from(m.emissive_factor());
+            if let Some(info) = m.emissive_texture() {
+                mat.emissive_tex = tex(info.texture());
+            }
+            mat.alpha_mode = match m.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::OPAQUE,
+                gltf::material::AlphaMode::Mask => AlphaMode::MASK,
+                gltf::material::AlphaMode::Blend => AlphaMode::BLEND,
+            };
+            mat.alpha_cutoff = m.alpha_cutoff().unwrap_or(0.5);
+            mat.double_sided = m.double_sided();
+            self.write_asset(&format!("material_{}.yaml", m.index().unwrap()), &mat, "Material").map(|_| ())
+        })
+    }
+
+    // parent node index of every node
+    pub fn node_parents(&self) -> Vec<Option<usize>> {
+        let mut parents = vec![None; self.document.nodes().len()];
+        for node in self.document.nodes() {
+            for child in node.children() {
+                parents[child.index()] = Some(node.index());
+            }
+        }
+        parents
+    }
+    // one Skeleton asset per glTF skin, in document order
+    fn extract_skeletons(&self) -> Result<(), String> {
+        if self.document.skins().len() == 0 {
+            return Ok(())
+        }
+        self.create_import_dir()?;
+        let parents = self.node_parents();
+        let nodes: Vec<gltf::Node> = self.document.nodes().collect();
+        let local = |n: usize| Mat4f::from(nodes[n].transform().matrix());
+        self.document.skins().try_for_each(|skin| {
+            let joint_nodes: Vec<usize> = skin.joints().map(|n| n.index()).collect();
+            let reader = skin.reader(|b| Some(&self.buffers[b.index()]));
+            let inverse_binds: Vec<Mat4f> = match reader.read_inverse_bind_matrices() {
+                Some(iter) => iter.map(Mat4f::from).collect(),
+                None => vec![Mat4f::identity(); joint_nodes.len()],
+            };
+            if inverse_binds.len() < joint_nodes.len() {
+                return Err(format!("{}: skin {} has fewer inverse bind matrices than joints", self.path.display(), skin.index()))
+            }
+            let mut skeleton = Skeleton { name: skin.name().unwrap_or_default().to_string(), ..Default::default() };
+            for (i, &node) in joint_nodes.iter().enumerate() {
+                let mut joint = Joint { name: node_name(&nodes[node]), inverse_bind: inverse_binds[i], ..Default::default() };
+                // nearest ancestor in the skin, the nodes in between are folded into the rest pose
+                let mut matrix = local(node);
+                let mut ancestor = parents[node];
+                while let Some(a) = ancestor {
+                    if let Some(p) = joint_nodes.iter().position(|&j| j == a) {
+                        joint.parent = p as i32;
+                        break
+                    }
+                    matrix = local(a) * matrix;
+                    ancestor = parents[a];
+                }
+                if joint.parent < 0 {
+                    // a root keeps only its own transform, the skinned mesh is placed by its entity
+                    matrix = local(node);
+                }
+                (joint.translation, joint.rotation, joint.scale) = decompose_matrix(&matrix);
+                skeleton.joints.push(joint);
+            }
+            self.write_asset(&format!("skeleton_{}.yaml", skin.index()), &skeleton, "Skeleton").map(|_| ())
+        })
+    }
+    // one AnimationClip asset per glTF animation, in document order
+    fn extract_animations(&self) -> Result<(), String> {
+        if self.document.animations().len() == 0 {
+            return Ok(())
+        }
+        self.create_import_dir()?;
+        self.document.animations().try_for_each(|anim| {
+            let mut clip = AnimationClip { name: anim.name().unwrap_or_default().to_string(), ..Default::default() };
+            for channel in anim.channels() {
+                let node = channel.target().node();
+                let interpolation = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Linear => Interpolation::LINEAR,
+                    gltf::animation::Interpolation::Step => Interpolation::STEP,
+                    gltf::animation::Interpolation::CubicSpline => Interpolation::CUBICSPLINE,
+                };
+                let mut track = AnimationTrack { target: node_name(&node), node: node.index() as i32, interpolation, ..Default::default() };
+                let reader = channel.reader(|b| Some(&self.buffers[b.index()]));
+                let err = || format!("{}: animation {} channel {} has no data", self.path.display(), anim.index(), channel.index());
+                track.times = reader.read_inputs().ok_or_else(err)?.collect();
+                let outputs = reader.read_outputs().ok_or_else(err)?;
+                (track.path, track.values) = match outputs {
+                    ReadOutputs::Translations(v) => (TrackPath::TRANSLATION, v.flatten().collect()),
+                    ReadOutputs::Rotations(v) => (TrackPath::ROTATION, v.into_f32().flatten().collect()),
+                    ReadOutputs::Scales(v) => (TrackPath::SCALE, v.flatten().collect()),
+                    ReadOutputs::MorphTargetWeights(v) => (TrackPath::WEIGHTS, v.into_f32().collect()),
+                };
+                let keys = track.times.len() * if track.interpolation == Interpolation::CUBICSPLINE { 3 } else { 1 };
+                track.components = match track.path {
+                    TrackPath::WEIGHTS => track.values.len().checked_div(keys).unwrap_or(0) as u32,
+                    path => path.components() as u32,
+                };
+                track.validate().map_err(|e| format!("{}: animation {}: {}", self.path.display(), anim.index(), e))?;
+                clip.tracks.push(track);
+            }
+            clip.update_duration();
+            self.write_asset(&format!("animation_{}.yaml", anim.index()), &clip, "AnimationClip").map(|_| ())
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::comp::animator_component::AnimatorComponent;
+use crate::comp::light_component::{DirectionalLight, PointLight, SpotLight};
+use crate::comp::render_component::RenderComponent;
+use crate::comp::transform_component::TransformComponent;
+use crate::data::{res_mgr, ExtRes};
+use crate::data::asset_db::asset_db;
+use crate::data::gltf_import::{node_name, GltfImport};
+use crate::data::loader::LoadState;
+use crate::data::render_object::RenderObject;
+use crate::data::skeleton::Skeleton;
+use crate::engine::engine_pin;
+use crate::entity::Entity;
+use crate::log_error;
+use crate::reflection::{Mat4f, Serializable, SerializeTextWriter, Vec3f};
+
+/**
+ * glTF node hierarchy import.
+ * one Entity per node of the default scene, with a TransformComponent from the node TRS or
+ * matrix and a RenderComponent drawing the node mesh, which is the part of the same index
+ * in the RenderObject of the glTF file. skinned nodes get the skeleton of their skin.
+ * KHR_lights_punctual lights become light components, with the same units.
+ * the root plays the first animation of the file with an AnimatorComponent.
+ * the tree can be saved as a yaml prefab, and loaded back with Entity_deserialize_yaml.
+ */
+struct SceneBuilder<'a> {
+    object: &'a ExtRes<RenderObject>,
+    skeletons: Vec<ExtRes<Skeleton>>,
+}
+impl SceneBuilder<'_> {
+    fn build(&self, node: gltf::Node) -> Rc<RefCell<Entity>> {
+        let entity = Entity::new();
+        let mut e = entity.borrow_mut();
+        e.name = node_name(&node);
+        let mut transform = TransformComponent::default();
+        transform.local_matrix = Mat4f::from(node.transform().matrix());
+        e.add_component(transform);
+        if let Some(mesh) = node.mesh() {
+            let mut render = RenderComponent::default();
+            render.set_object(self.object.clone(), mesh.index() as i32);
+            if let Some(skin) = node.skin() {
+                render.set_skeleton(self.skeletons[skin.index()].clone());
+            }
+            e.add_component(render);
+        }
+        if let Some(light) = node.light() {
+            add_light(&mut e, &light);
+        }
+        for child in node.children() {
+            e.add_child(self.build(child));
+        }
+        drop(e);
+        entity
+    }
+}
+
+fn add_light(e: &mut Entity, light: &gltf::khr_lights_punctual::Light) {
+    use gltf::khr_lights_punctual::Kind;
+    let color = Vec3f::from(light.color());
+    // no range is infinite
+    let range = light.range().unwrap_or(0.0);
+    match light.kind() {
+        Kind::Directional => {
+            e.add_component(DirectionalLight { color, intensity: light.intensity(), ..Default::default() });
+        },
+        Kind::Point => {
+            e.add_component(PointLight { color, intensity: light.intensity(), range, ..Default::default() });
+        },
+        Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+            e.add_component(SpotLight { color, intensity: light.intensity(), range, inner_cone_angle, outer_cone_angle, ..Default::default() });
+        },
+    };
+}
+
+// the returned root is neither pinned nor attached, see Entity::pinned
+pub fn import_scene(path: &Path) -> Result<Rc<RefCell<Entity>>, String> {
+    let import = GltfImport::open(path)?;
+    let rel = asset_db().read().unwrap().relative(path)?;
+    let guid = res_mgr().import_source(&rel)?;
+    let object = ExtRes::<RenderObject>::load(guid, false);
+    if object.state() != LoadState::Loaded {
+        return Err(format!("{}: {}", rel, object.handle().error().unwrap_or_default()))
+    }
+    let skeletons = import.skeletons()?;
+    let builder = SceneBuilder { object: &object, skeletons };
+
+    let root = Entity::new();
+    root.borrow_mut().name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    root.borrow_mut().add_component(TransformComponent::default());
+    let animations = &object.get().animations;
+    if !animations.is_empty() {
+        let mut animator = AnimatorComponent { skeleton: object.get().skeleton().cloned().unwrap_or_default(), ..Default::default() };
+        animator.play(0, animations[0].clone(), 0.0);
+        root.borrow_mut().add_component(animator);
+    }
+    match import.document.default_scene().or_else(|| import.document.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                root.borrow_mut().add_child(builder.build(node));
+            }
+        },
+        // no scene : every top level node
+        None => {
+            let parents = import.node_parents();
+            for node in import.document.nodes().filter(|n| parents[n.index()].is_none()) {
+                root.borrow_mut().add_child(builder.build(node));
+            }
+        },
+    }
+    Ok(root)
+}
+pub fn save_prefab(entity: &Rc<RefCell<Entity>>, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{} : {}", dir.display(), e))?;
+    }
+    let mut writer = SerializeTextWriter::create(path).map_err(|e| format!("{} : {}", path.display(), e))?;
+    entity.borrow().serialize_text(&mut writer, String::new());
+    Ok(())
+}
+
+//// exports
+
+// pinned root entity of the glTF scene, 0 on failure (logged). saved as a yaml prefab if prefab is not null
+#[no_mangle]
+pub extern "C"
+fn Entity_import_gltf(path: *const c_char, prefab: *const c_char) -> u64 {
+    let Ok(p) = unsafe { CStr::from_ptr(path) }.to_str() else { return 0 };
+    let prefab = if prefab.is_null() { None } else {
+        let Ok(prefab) = unsafe { CStr::from_ptr(prefab) }.to_str() else { return 0 };
+        Some(prefab)
+    };
+    let root = match import_scene(Path::new(p)) {
+        Ok(root) => root,
+        Err(e) => {
+            log_error!("gltf scene import failed : {}", e);
+            return 0
+        },
+    };
+    if let Some(prefab) = prefab {
+        if let Err(e) = save_prefab(&root, Path::new(prefab)) {
+            log_error!("gltf prefab save failed : {}", e);
+            return 0
+        }
+    }
+    let id = root.borrow().instance_id;
+    let addr = root.borrow().marker_address();
+    engine_pin(id, unsafe { Pin::new_unchecked(root) });
+    addr
+}
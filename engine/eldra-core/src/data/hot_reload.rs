@@ -0,0 +1,187 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+use crate::fs::vfs;
+use crate::log_warn;
+
+/**
+ * Hot reload of loaded resources, source files are polled for modification.
+ *
+ * the source file of a resource is watched, even when it was loaded from its cooked binary.
+ * a changed resource is rebuilt and swapped into its shared ResHandle, so every ExtRes
+ * holding it sees the new data. resources referencing it (directly or not) are rebuilt
+ * after it, references first, and reported as dependency changes so the renderer/editor
+ * can refresh what they derived from it.
+ */
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ReloadCause {
+    Changed,
+    DependencyChanged,
+}
+#[derive(Clone,Debug)]
+pub struct ReloadEvent {
+    pub guid: Uuid,
+    pub type_name: &'static str,
+    pub cause: ReloadCause,
+}
+
+// rebuilds the resource in place, Ok(false) when the resource is gone
+pub type Reloader = Rc<dyn Fn() -> Result<bool, String>>;
+
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    type_name: &'static str,
+    reload: Reloader,
+}
+
+pub type ReloadListener = Rc<dyn Fn(&ReloadEvent)>;
+pub struct HotReload {
+    pub enabled: bool,
+    pub poll_interval: Duration,
+    last_poll: Option<Instant>,
+    watched: HashMap<Uuid, Watched>,
+    // guid -> resources it references
+    dependencies: HashMap<Uuid, HashSet<Uuid>>,
+    listeners: Vec<(u64, ReloadListener)>,
+    next_listener: u64,
+    // replaced values are kept one more update so references taken before the swap stay valid
+    retired: Vec<Rc<dyn Any>>,
+}
+impl Default for HotReload {
+    fn default() -> Self {
+        HotReload {
+            enabled: false,
+            poll_interval: Duration::from_millis(500),
+            last_poll: None,
+            watched: HashMap::new(),
+            dependencies: HashMap::new(),
+            listeners: Vec::new(),
+            next_listener: 0,
+            retired: Vec::new(),
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    vfs().read().unwrap().modified(path)
+}
+
+impl HotReload {
+    pub fn watch(&mut self, guid: Uuid, path: PathBuf, type_name: &'static str, reload: Reloader) {
+        let modified = modified_time(&path);
+        self.watched.insert(guid, Watched { path, modified, type_name, reload });
+    }
+    pub fn unwatch(&mut self, guid: &Uuid) {
+        self.watched.remove(guid);
+        self.dependencies.remove(guid);
+    }
+    pub fn is_watched(&self, guid: &Uuid) -> bool {
+        self.watched.contains_key(guid)
+    }
+    pub fn add_dependency(&mut self, guid: Uuid, dependency: Uuid) {
+        self.dependencies.entry(guid).or_default().insert(dependency);
+    }
+    pub fn clear_dependencies(&mut self, guid: &Uuid) {
+        self.dependencies.remove(guid);
+    }
+    pub fn add_listener(&mut self, listener: ReloadListener) -> u64 {
+        self.next_listener += 1;
+        self.listeners.push((self.next_listener, listener));
+        self.next_listener
+    }
+    pub fn remove_listener(&mut self, id: u64) {
+        self.listeners.retain(|l| l.0 != id);
+    }
+    pub fn retire(&mut self, value: Rc<dyn Any>) {
+        self.retired.push(value);
+    }
+    // every resource referencing guid, directly or through other resources
+    // ordered so that a resource comes after the ones it references
+    pub fn dependents_of(&self, guid: &Uuid) -> Vec<Uuid> {
+        let mut found: Vec<Uuid> = Vec::new();
+        let mut stack = vec![*guid];
+        while let Some(g) = stack.pop() {
+            for (parent, deps) in self.dependencies.iter() {
+                if deps.contains(&g) && *parent != *guid && !found.contains(parent) {
+                    found.push(*parent);
+                    stack.push(*parent);
+                }
+            }
+        }
+        let mut ordered = Vec::with_capacity(found.len());
+        while !found.is_empty() {
+            let pending = found.clone();
+            let (ready, rest): (Vec<Uuid>, Vec<Uuid>) = found.into_iter().partition(|d| {
+                self.dependencies.get(d).is_none_or(|deps| !pending.iter().any(|p| deps.contains(p)))
+            });
+            if ready.is_empty() {
+                // reference cycle, keep discovery order
+                ordered.extend(rest);
+                break
+            }
+            ordered.extend(ready);
+            found = rest;
+        }
+        ordered
+    }
+    // called once per update, returns the resources whose file changed
+    fn poll(&mut self) -> Vec<(Uuid, Reloader)> {
+        self.retired.clear();
+        if !self.enabled {
+            return Vec::new()
+        }
+        let now = Instant::now();
+        if self.last_poll.is_some_and(|t| now.duration_since(t) < self.poll_interval) {
+            return Vec::new()
+        }
+        self.last_poll = Some(now);
+        let mut changed = Vec::new();
+        for (guid, w) in self.watched.iter_mut() {
+            let modified = modified_time(&w.path);
+            if modified.is_some() && modified != w.modified {
+                w.modified = modified;
+                changed.push((*guid, w.reload.clone()));
+            }
+        }
+        changed
+    }
+}
+
+// rebuild a resource, false if it failed or is gone
+fn rebuild(hot: &std::cell::RefCell<HotReload>, guid: Uuid, reload: &Reloader) -> bool {
+    // rebuilding records them again
+    hot.borrow_mut().clear_dependencies(&guid);
+    match reload() {
+        Ok(true) => true,
+        Ok(false) => { hot.borrow_mut().unwatch(&guid); false },
+        Err(e) => { log_warn!("hot reload of {} failed : {}", guid, e); false },
+    }
+}
+
+// main thread, the RefCell is released while reloading and notifying since both may load resources
+pub fn update_hot_reload(hot: &std::cell::RefCell<HotReload>) {
+    let changed = hot.borrow_mut().poll();
+    for (guid, reload) in changed {
+        // collected first, a failed rebuild keeps the references of the old value
+        let dependents = hot.borrow().dependents_of(&guid);
+        if !rebuild(hot, guid, &reload) {
+            continue
+        }
+        let type_name_of = |g: &Uuid| hot.borrow().watched.get(g).map_or("", |w| w.type_name);
+        let mut events = vec![ReloadEvent { guid, type_name: type_name_of(&guid), cause: ReloadCause::Changed }];
+        for d in dependents {
+            let Some(reload) = hot.borrow().watched.get(&d).map(|w| w.reload.clone()) else { continue };
+            if rebuild(hot, d, &reload) {
+                events.push(ReloadEvent { guid: d, type_name: type_name_of(&d), cause: ReloadCause::DependencyChanged });
+            }
+        }
+        let listeners: Vec<_> = hot.borrow().listeners.iter().map(|l| l.1.clone()).collect();
+        for e in events.iter() {
+            listeners.iter().for_each(|l| l(e));
+        }
+    }
+}
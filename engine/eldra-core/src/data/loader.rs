@@ -0,0 +1,403 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use uuid::Uuid;
+use yaml_rust2::{Yaml, YamlLoader};
+use crate::data::asset_db::asset_db;
+use crate::fs::{vfs, MappedFile};
+use crate::log_error;
+
+/**
+ * Background resource loading.
+ *
+ * loading is split in two phases because resources are Rc based and can't cross threads :
+ * - worker threads do file IO and format parsing into a SourceData (mapped file, yaml doc, gltf import...)
+ * - the main thread builds the resource from it in ResourceMgr::update, resolving nested ExtRes
+ *   as dependencies, a resource completes only after all its dependencies did.
+ *   a resource that would wait on itself, directly or through its dependencies, fails instead.
+ */
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum LoadState {
+    Pending,
+    Loaded,
+    Failed,
+}
+
+// parsed but not yet built resource, produced by worker threads
+pub enum SourceData {
+    Binary(MappedFile),
+    Yaml(Yaml),
+    Custom(Box<dyn Any + Send>),
+}
+pub type SourceReader = fn(&Path, bool) -> Result<SourceData, String>;
+// main thread, fills the resource from its source data
+pub type SourceBuilder<T> = fn(&mut T, SourceData) -> Result<(), String>;
+
+pub fn read_binary_source(path: &Path) -> Result<SourceData, String> {
+    let file = vfs().read().unwrap().map(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(SourceData::Binary(file))
+}
+pub fn read_yaml_source(path: &Path) -> Result<SourceData, String> {
+    let yaml_str = vfs().read().unwrap().read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut docs = YamlLoader::load_from_str(&yaml_str).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if docs.is_empty() {
+        return Err(format!("{}: empty yaml", path.display()))
+    }
+    Ok(SourceData::Yaml(docs.swap_remove(0)))
+}
+
+// called once with the final state
+pub type LoadCallback = Box<dyn FnOnce(LoadState)>;
+// shared by every ExtRes of the same asset
+pub struct ResHandle<T> {
+    state: Cell<LoadState>,
+    value: RefCell<Rc<T>>, // default value until loaded
+    error: RefCell<Option<String>>,
+    callbacks: RefCell<Vec<LoadCallback>>,
+    // ExtRes referencing the handle, loader jobs and caches hold it too
+    users: Cell<usize>,
+}
+impl<T> ResHandle<T> {
+    pub fn pending(placeholder: Rc<T>) -> Self {
+        ResHandle {
+            state: Cell::new(LoadState::Pending),
+            value: RefCell::new(placeholder),
+            error: RefCell::new(None),
+            callbacks: RefCell::new(Vec::new()),
+            users: Cell::new(0),
+        }
+    }
+    pub fn loaded(value: Rc<T>) -> Self {
+        let h = Self::pending(value);
+        h.state.set(LoadState::Loaded);
+        h
+    }
+    pub fn state(&self) -> LoadState {
+        self.state.get()
+    }
+    pub fn users(&self) -> usize {
+        self.users.get()
+    }
+    pub(crate) fn add_user(&self) {
+        self.users.set(self.users.get() + 1);
+    }
+    // returns the users left
+    pub(crate) fn remove_user(&self) -> usize {
+        self.users.set(self.users.get() - 1);
+        self.users.get()
+    }
+    pub fn error(&self) -> Option<String> {
+        self.error.borrow().clone()
+    }
+    pub fn value(&self) -> Rc<T> {
+        self.value.borrow().clone()
+    }
+    // swap in a reloaded value, returns the previous one
+    pub fn replace(&self, value: Rc<T>) -> Rc<T> {
+        self.state.set(LoadState::Loaded);
+        *self.error.borrow_mut() = None;
+        std::mem::replace(&mut *self.value.borrow_mut(), value)
+    }
+    // fired on the main thread when the load completes, immediately if it already did
+    pub fn on_complete(&self, f: LoadCallback) {
+        match self.state.get() {
+            LoadState::Pending => self.callbacks.borrow_mut().push(f),
+            state => f(state),
+        }
+    }
+    pub fn complete(&self, result: Result<Rc<T>, String>) {
+        match result {
+            Ok(v) => {
+                *self.value.borrow_mut() = v;
+                self.state.set(LoadState::Loaded);
+            },
+            Err(e) => {
+                log_error!("resource load failed : {}", e);
+                *self.error.borrow_mut() = Some(e);
+                self.state.set(LoadState::Failed);
+            },
+        }
+        let callbacks = std::mem::take(&mut *self.callbacks.borrow_mut());
+        let state = self.state.get();
+        callbacks.into_iter().for_each(|f| f(state));
+    }
+}
+// type erased view used for dependency tracking
+pub trait LoadStatus {
+    fn load_state(&self) -> LoadState;
+}
+impl<T> LoadStatus for ResHandle<T> {
+    fn load_state(&self) -> LoadState { self.state.get() }
+}
+
+// resources being built on this thread, innermost last
+// while a background load builds its resource, nested ExtRes are requested
+// asynchronously and collected as its dependencies
+struct BuildCtx {
+    guid: Uuid,
+    async_deps: Option<Vec<(Uuid, Rc<dyn LoadStatus>)>>,
+    refs: Vec<Uuid>,
+    // first error reported while building, fails the build
+    error: Option<String>,
+}
+thread_local! {
+    static BUILD_CTX: RefCell<Vec<BuildCtx>> = const { RefCell::new(Vec::new()) };
+}
+pub fn building_resource() -> Option<Uuid> {
+    BUILD_CTX.with(|c| c.borrow().last().map(|ctx| ctx.guid))
+}
+pub fn is_building_async() -> bool {
+    BUILD_CTX.with(|c| c.borrow().last().is_some_and(|ctx| ctx.async_deps.is_some()))
+}
+pub fn add_build_dependency(guid: Uuid, dep: Rc<dyn LoadStatus>) {
+    BUILD_CTX.with(|c| {
+        if let Some(deps) = c.borrow_mut().last_mut().and_then(|ctx| ctx.async_deps.as_mut()) {
+            deps.push((guid, dep));
+        }
+    });
+}
+// fails the resource being built, nested loads have no other error channel
+pub fn fail_build(error: String) {
+    BUILD_CTX.with(|c| {
+        if let Some(ctx) = c.borrow_mut().last_mut() {
+            ctx.error.get_or_insert(error);
+        }
+    });
+}
+// chain from the resource being built back to itself if loading guid would make it wait on
+// itself : guid is being built on this thread, or a background load of guid waits on it
+pub fn dependency_cycle(guid: Uuid, loader: &RefCell<AsyncLoader>) -> Option<Vec<Uuid>> {
+    let (building, async_mode) = BUILD_CTX.with(|c| {
+        let c = c.borrow();
+        (c.iter().map(|ctx| ctx.guid).collect::<Vec<Uuid>>(), c.last().is_some_and(|ctx| ctx.async_deps.is_some()))
+    });
+    let current = *building.last()?;
+    if let Some(i) = building.iter().position(|g| *g == guid) {
+        return Some([&building[i..], &[guid]].concat())
+    }
+    if !async_mode {
+        return None
+    }
+    let chain = loader.borrow().wait_chain(guid, current)?;
+    Some([&[current], chain.as_slice()].concat())
+}
+pub fn format_chain(chain: &[Uuid]) -> String {
+    let db = asset_db().read().unwrap();
+    let names: Vec<String> = chain.iter().map(|g| db.path_of(g).cloned().unwrap_or_else(|| g.to_string())).collect();
+    names.join(" -> ")
+}
+// an asset referenced by the resource being built, returns that resource
+pub fn add_build_reference(guid: Uuid) -> Option<Uuid> {
+    BUILD_CTX.with(|c| c.borrow_mut().last_mut().map(|ctx| {
+        if !ctx.refs.contains(&guid) {
+            ctx.refs.push(guid);
+        }
+        ctx.guid
+    }))
+}
+pub struct Built {
+    pub result: Result<(), String>,
+    // still loading dependencies, async mode only
+    pub deps: Vec<(Uuid, Rc<dyn LoadStatus>)>,
+    // every asset referenced while building
+    pub refs: Vec<Uuid>,
+}
+pub fn build_in_context<F: FnOnce() -> Result<(), String>>(guid: Uuid, async_mode: bool, f: F) -> Built {
+    BUILD_CTX.with(|c| c.borrow_mut().push(BuildCtx {
+        guid,
+        async_deps: if async_mode { Some(Vec::new()) } else { None },
+        refs: Vec::new(),
+        error: None,
+    }));
+    let result = catch_unwind(AssertUnwindSafe(f)).map_err(|e| {
+        e.downcast_ref::<String>().cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| "panic while building resource".to_string())
+    }).and_then(|r| r);
+    let ctx = BUILD_CTX.with(|c| c.borrow_mut().pop()).unwrap();
+    let result = result.and_then(|_| ctx.error.map_or(Ok(()), Err));
+    Built { result, deps: ctx.async_deps.unwrap_or_default(), refs: ctx.refs }
+}
+
+struct Job {
+    id: u64,
+    path: PathBuf,
+    bin: bool,
+    read: SourceReader,
+}
+type JobResult = (u64, Result<SourceData, String>);
+// main thread part of a job : build the resource from its source
+pub type Builder = Box<dyn FnOnce(Result<SourceData, String>) -> Waiting>;
+// built resource waiting for its dependencies, a failed dependency doesn't fail its dependents
+pub struct Waiting {
+    guid: Uuid,
+    deps: Vec<(Uuid, Rc<dyn LoadStatus>)>,
+    finish: Box<dyn FnOnce() -> LoadState>,
+}
+impl Waiting {
+    fn ready(&self) -> bool {
+        self.deps.iter().all(|(_, d)| d.load_state() != LoadState::Pending)
+    }
+}
+pub fn build_async<T: Default + 'static>(guid: Uuid, handle: Rc<ResHandle<T>>, source: Result<SourceData, String>,
+                                         build: SourceBuilder<T>) -> Waiting {
+    if handle.state() != LoadState::Pending {
+        // completed by a synchronous load meanwhile
+        return Waiting { guid, deps: Vec::new(), finish: Box::new(move || handle.state()) }
+    }
+    let mut obj = T::default();
+    let (result, deps) = match source {
+        Ok(src) => {
+            let built = build_in_context(guid, true, || build(&mut obj, src));
+            (built.result, built.deps)
+        },
+        Err(e) => (Err(e), Vec::new()),
+    };
+    Waiting {
+        guid,
+        deps,
+        finish: Box::new(move || {
+            handle.complete(result.map(|_| Rc::new(obj)));
+            handle.state()
+        }),
+    }
+}
+
+#[derive(Default,Clone,Copy,Debug)]
+pub struct LoadProgress {
+    pub requested: u32,
+    pub loaded: u32,
+    pub failed: u32,
+}
+impl LoadProgress {
+    pub fn pending(&self) -> u32 {
+        self.requested - self.loaded - self.failed
+    }
+    pub fn fraction(&self) -> f32 {
+        if self.requested == 0 { 1.0 } else { (self.loaded + self.failed) as f32 / self.requested as f32 }
+    }
+}
+
+#[derive(Default)]
+pub struct AsyncLoader {
+    next_id: u64,
+    job_tx: Option<mpsc::Sender<Job>>,
+    done_rx: Option<mpsc::Receiver<JobResult>>,
+    builders: HashMap<u64, Builder>,
+    waiting: Vec<Waiting>,
+    progress: LoadProgress,
+}
+impl AsyncLoader {
+    fn start_workers(&mut self) {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (done_tx, done_rx) = mpsc::channel::<JobResult>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = thread::available_parallelism().map_or(2, |n| n.get()).saturating_sub(1).max(1);
+        for i in 0..workers {
+            let job_rx = job_rx.clone();
+            let done_tx = done_tx.clone();
+            let _ = thread::Builder::new().name(format!("eldra-loader-{}", i)).spawn(move || {
+                loop {
+                    let job = match job_rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let result = catch_unwind(|| (job.read)(&job.path, job.bin))
+                        .unwrap_or_else(|_| Err(format!("{}: reader panicked", job.path.display())));
+                    if done_tx.send((job.id, result)).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+        self.job_tx = Some(job_tx);
+        self.done_rx = Some(done_rx);
+    }
+    pub fn submit(&mut self, path: PathBuf, bin: bool, read: SourceReader, builder: Builder) {
+        if self.job_tx.is_none() {
+            self.start_workers();
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        self.builders.insert(id, builder);
+        self.progress.requested += 1;
+        let _ = self.job_tx.as_ref().unwrap().send(Job { id, path, bin, read });
+    }
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+    pub fn is_idle(&self) -> bool {
+        self.builders.is_empty() && self.waiting.is_empty()
+    }
+    pub fn reset_progress(&mut self) {
+        let pending = self.progress.pending();
+        self.progress = LoadProgress { requested: pending, loaded: 0, failed: 0 };
+    }
+    // from, the resources it waits on, ... to, if from waits on to
+    pub fn wait_chain(&self, from: Uuid, to: Uuid) -> Option<Vec<Uuid>> {
+        let mut chain = vec![from];
+        let mut visited = HashSet::new();
+        self.find_wait(to, &mut chain, &mut visited).then_some(chain)
+    }
+    fn find_wait(&self, to: Uuid, chain: &mut Vec<Uuid>, visited: &mut HashSet<Uuid>) -> bool {
+        let guid = *chain.last().unwrap();
+        if !visited.insert(guid) {
+            return false
+        }
+        let Some(w) = self.waiting.iter().find(|w| w.guid == guid) else { return false };
+        for (dep, status) in w.deps.iter() {
+            if status.load_state() != LoadState::Pending {
+                continue
+            }
+            chain.push(*dep);
+            if *dep == to || self.find_wait(to, chain, visited) {
+                return true
+            }
+            chain.pop();
+        }
+        false
+    }
+}
+
+// main thread pump, the loader is not borrowed while running builders and
+// callbacks so they are free to request more resources
+pub fn update_loader(loader: &RefCell<AsyncLoader>) {
+    let done: Vec<JobResult> = match loader.borrow().done_rx.as_ref() {
+        Some(rx) => rx.try_iter().collect(),
+        None => Vec::new(),
+    };
+    for (id, result) in done {
+        let builder = loader.borrow_mut().builders.remove(&id);
+        if let Some(builder) = builder {
+            let waiting = builder(result);
+            loader.borrow_mut().waiting.push(waiting);
+        }
+    }
+    // finish in dependency order, repeat while something completes
+    loop {
+        let ready: Vec<Waiting> = {
+            let mut l = loader.borrow_mut();
+            let (ready, rest): (Vec<Waiting>, Vec<Waiting>) =
+                std::mem::take(&mut l.waiting).into_iter().partition(|w| w.ready());
+            l.waiting = rest;
+            ready
+        };
+        if ready.is_empty() {
+            break
+        }
+        for w in ready {
+            let state = (w.finish)();
+            let mut l = loader.borrow_mut();
+            match state {
+                LoadState::Loaded => l.progress.loaded += 1,
+                LoadState::Failed => l.progress.failed += 1,
+                LoadState::Pending => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::any::{Any, TypeId};
+use std::io::{Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+use yaml_rust2::Yaml;
+use eldra_macro::Reflection;
+use crate::impl_vec_embed_serialize;
+use crate::fs::vfs;
+use crate::reflection::{load_from_yaml, Serializable, SerializeTextWriter};
+
+/**
+ * Cook manifest, written at the project root by the offline cooker.
+ * maps every asset guid to its cooked binary, with the hashes used for incremental rebuilds.
+ */
+pub const MANIFEST_FILE: &str = "cook_manifest.yaml";
+
+#[derive(Reflection,Default,Clone)]
+pub struct CookedAsset {
+    #[serialize]
+    pub guid: Uuid,
+    #[serialize]
+    pub type_name: String,
+    // paths relative to the project root
+    #[serialize]
+    pub source: String,
+    #[serialize]
+    pub cooked: String,
+    // hash of the source file content
+    #[serialize]
+    pub source_hash: String,
+    // hash of the source hashes of the dependencies
+    #[serialize]
+    pub deps_hash: String,
+    // assets referenced by this one
+    #[serialize]
+    pub dependencies: Vec<Uuid>,
+}
+impl_vec_embed_serialize!(CookedAsset);
+
+#[derive(Reflection,Default)]
+pub struct CookManifest {
+    #[serialize]
+    pub assets: Vec<CookedAsset>,
+}
+impl CookManifest {
+    // empty manifest if the project was never cooked
+    pub fn load(path: &Path) -> CookManifest {
+        let mut m = CookManifest::default();
+        if let Ok(yaml_str) = vfs().read().unwrap().read_to_string(path) {
+            load_from_yaml(&mut m, &yaml_str);
+        }
+        m
+    }
+    pub fn save(&self, path: &Path) {
+        let mut writer = SerializeTextWriter::new(path.to_str().unwrap());
+        self.serialize_text(&mut writer, String::new());
+    }
+    pub fn find(&self, guid: &Uuid) -> Option<&CookedAsset> {
+        self.assets.iter().find(|a| a.guid == *guid)
+    }
+}
@@ -3,17 +3,102 @@ use std::io::*;
 use eldra_macro::{*};
 use crate::data::{ExtRes, ExtSerializable};
 use crate::data::texture::Texture;
-use crate::reflection::Serializable;
+use crate::reflection::{Vec3f, Vec4f};
 use crate::impl_vec_embed_serialize;
 use yaml_rust2::Yaml;
 
-#[derive(Reflection,Default)]
+/**
+ * PBR material, glTF 2.0 model.
+ * factors multiply the texture samples, a null texture samples as white.
+ */
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum ShadingModel {
+    #[default]
+    METALLIC_ROUGHNESS = 0,
+    // KHR_materials_pbrSpecularGlossiness
+    SPECULAR_GLOSSINESS = 1,
+}
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum AlphaMode {
+    #[default]
+    OPAQUE = 0,
+    // alpha tested against alpha_cutoff
+    MASK = 1,
+    BLEND = 2,
+}
+
+#[derive(Reflection)]
 pub struct Material
 {
     #[serialize]
     pub name: String,
     #[serialize]
+    pub shading: ShadingModel,
+    // base color, or diffuse for specular-glossiness
+    #[serialize]
+    pub base_color: Vec4f,
+    #[serialize]
     pub tex: ExtRes<Texture>,
+    #[serialize]
+    pub metallic: f32,
+    #[serialize]
+    pub roughness: f32,
+    // metallic in B, roughness in G
+    #[serialize]
+    pub metallic_roughness_tex: ExtRes<Texture>,
+    #[serialize]
+    pub specular: Vec3f,
+    #[serialize]
+    pub glossiness: f32,
+    // specular in RGB, glossiness in A
+    #[serialize]
+    pub specular_glossiness_tex: ExtRes<Texture>,
+    #[serialize]
+    pub normal_tex: ExtRes<Texture>,
+    #[serialize]
+    pub normal_scale: f32,
+    // occlusion in R
+    #[serialize]
+    pub occlusion_tex: ExtRes<Texture>,
+    #[serialize]
+    pub occlusion_strength: f32,
+    #[serialize]
+    pub emissive: Vec3f,
+    #[serialize]
+    pub emissive_tex: ExtRes<Texture>,
+    #[serialize]
+    pub alpha_mode: AlphaMode,
+    #[serialize]
+    pub alpha_cutoff: f32,
+    #[serialize]
+    pub double_sided: bool,
 }
 impl_vec_embed_serialize!(Material);
-impl ExtSerializable<Material> for Material {}
\ No newline at end of file
+impl ExtSerializable<Material> for Material {}
+
+// glTF defaults
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            shading: ShadingModel::METALLIC_ROUGHNESS,
+            base_color: Vec4f::new(1.0, 1.0, 1.0, 1.0),
+            tex: ExtRes::default(),
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_tex: ExtRes::default(),
+            specular: Vec3f::new(1.0, 1.0, 1.0),
+            glossiness: 1.0,
+            specular_glossiness_tex: ExtRes::default(),
+            normal_tex: ExtRes::default(),
+            normal_scale: 1.0,
+            occlusion_tex: ExtRes::default(),
+            occlusion_strength: 1.0,
+            emissive: Vec3f::zeros(),
+            emissive_tex: ExtRes::default(),
+            alpha_mode: AlphaMode::OPAQUE,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
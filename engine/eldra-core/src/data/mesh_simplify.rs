@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use nalgebra::{Matrix4, Vector3, Vector4};
+use crate::data::bounds::BoundingSphere;
+use crate::data::mesh_tools::optimize_vertex_cache;
+use crate::data::render_object::{PrimitiveLod, RenderObject};
+use crate::data::vertex_accessor::write_indices;
+use crate::reflection::Vec3f;
+
+/**
+ * Quadric error metric simplification, after Garland and Heckbert.
+ * edges collapse onto one of their vertices, so a simplified mesh only needs new indices and
+ * levels of detail share the vertex streams of their primitive. border vertices, which include
+ * the attribute seams of unwelded meshes, never move. collapses that flip a triangle are
+ * rejected.
+ * a LOD is drawn while the projected size of the primitive, its bounding sphere diameter over
+ * the viewport height, is below its screen_size threshold.
+ */
+// projected error allowed for a LOD, as a fraction of the viewport height, about a pixel at 1080p
+pub const MAX_SCREEN_ERROR: f32 = 0.001;
+
+#[derive(Clone,Copy,Default)]
+struct Quadric {
+    m: Matrix4<f64>,
+    weight: f64,
+}
+impl Quadric {
+    fn plane(a: &Vector3<f64>, b: &Vector3<f64>, c: &Vector3<f64>) -> Quadric {
+        let n = (b - a).cross(&(c - a));
+        let area = n.norm() * 0.5;
+        let Some(n) = n.try_normalize(0.0) else { return Quadric::default() };
+        let p = Vector4::new(n.x, n.y, n.z, -n.dot(a));
+        Quadric { m: p * p.transpose() * area, weight: area }
+    }
+    fn add(&self, other: &Quadric) -> Quadric {
+        Quadric { m: self.m + other.m, weight: self.weight + other.weight }
+    }
+    // mean squared distance to the planes
+    fn error(&self, v: &Vector3<f64>) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0
+        }
+        let p = Vector4::new(v.x, v.y, v.z, 1.0);
+        (p.transpose() * self.m * p)[0].max(0.0) / self.weight
+    }
+}
+
+// collapsing u onto v keeps the orientation of the other triangles around u
+fn flips(u: u32, v: u32, triangles: &[u32], indices: &[u32], positions: &[Vector3<f64>]) -> bool {
+    triangles.iter().any(|t| {
+        let tri = &indices[*t as usize * 3..*t as usize * 3 + 3];
+        if tri.contains(&v) {
+            return false
+        }
+        let p = |i: u32| positions[i as usize];
+        let q = |i: u32| if i == u { positions[v as usize] } else { positions[i as usize] };
+        let before = (p(tri[1]) - p(tri[0])).cross(&(p(tri[2]) - p(tri[0])));
+        let after = (q(tri[1]) - q(tri[0])).cross(&(q(tri[2]) - q(tri[0])));
+        after.dot(&before) <= 0.0 || after.norm_squared() < before.norm_squared() * 1e-6
+    })
+}
+
+// simplified triangle list of at most target_count indices when the error allows it,
+// and its error, a distance in the space of the positions
+pub fn simplify(indices: &[u32], positions: &[Vec3f], target_count: usize, max_error: f32) -> (Vec<u32>, f32) {
+    let n = positions.len();
+    let pos: Vec<Vector3<f64>> = positions.iter().map(|p| p.cast::<f64>()).collect();
+    let mut indices: Vec<u32> = indices.chunks_exact(3).filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).flatten().cloned().collect();
+    let mut quadrics = vec![Quadric::default(); n];
+    for t in indices.chunks_exact(3) {
+        let q = Quadric::plane(&pos[t[0] as usize], &pos[t[1] as usize], &pos[t[2] as usize]);
+        t.iter().for_each(|v| quadrics[*v as usize] = quadrics[*v as usize].add(&q));
+    }
+    // an edge without its opposite is on a border
+    let edges: HashSet<(u32, u32)> = indices.chunks_exact(3).flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])]).collect();
+    let mut locked = vec![false; n];
+    for (a, b) in edges.iter().filter(|(a, b)| !edges.contains(&(*b, *a))) {
+        locked[*a as usize] = true;
+        locked[*b as usize] = true;
+    }
+    let max_error = (max_error as f64) * (max_error as f64);
+    let mut error = 0.0f64;
+    while indices.len() > target_count {
+        let mut vertex_tris: Vec<Vec<u32>> = vec![Vec::new(); n];
+        for (t, tri) in indices.chunks_exact(3).enumerate() {
+            tri.iter().for_each(|v| vertex_tris[*v as usize].push(t as u32));
+        }
+        let mut candidates: Vec<(f64, u32, u32)> = indices.chunks_exact(3)
+            .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .filter(|(u, _)| !locked[*u as usize])
+            .map(|(u, v)| (quadrics[u as usize].add(&quadrics[v as usize]).error(&pos[v as usize]), u, v))
+            .filter(|(cost, _, _)| *cost <= max_error)
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+        // one collapse per neighbourhood and pass, the flip tests stay valid
+        let mut touched = vec![false; n];
+        let mut remap: Vec<u32> = (0..n as u32).collect();
+        let mut triangle_count = indices.len() / 3;
+        for (cost, u, v) in candidates {
+            if triangle_count * 3 <= target_count {
+                break
+            }
+            if touched[u as usize] || touched[v as usize] || flips(u, v, &vertex_tris[u as usize], &indices, &pos) {
+                continue
+            }
+            remap[u as usize] = v;
+            quadrics[v as usize] = quadrics[v as usize].add(&quadrics[u as usize]);
+            error = error.max(cost);
+            for t in vertex_tris[u as usize].iter().chain(vertex_tris[v as usize].iter()) {
+                indices[*t as usize * 3..*t as usize * 3 + 3].iter().for_each(|i| touched[*i as usize] = true);
+            }
+            triangle_count -= vertex_tris[u as usize].iter().filter(|t| vertex_tris[v as usize].contains(t)).count();
+        }
+        if remap.iter().enumerate().all(|(i, r)| i as u32 == *r) {
+            break
+        }
+        indices = indices.chunks_exact(3).map(|t| t.iter().map(|i| remap[*i as usize]).collect::<Vec<u32>>())
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).flatten().collect();
+    }
+    (indices, error.sqrt() as f32)
+}
+
+impl RenderObject {
+    // a LOD per ratio of the triangle count, simplified from the primitive until the error
+    // relative to the bounding radius reaches max_error. returns the LOD count
+    pub fn generate_lods(&mut self, part: usize, prim: usize, ratios: &[f32], max_error: f32) -> Result<usize, String> {
+        let (_, p) = self.primitive_mut(part, prim)?;
+        p.lods.clear();
+        let p = &self.parts[part].primitives[prim];
+        let base: Vec<u32> = self.triangles(p)?.into_iter().flatten().collect();
+        let positions = self.read_attribute::<Vec3f>(p, "POSITION")?;
+        let radius = match p.sphere.radius {
+            r if r > 0.0 => r,
+            _ => BoundingSphere::from_points(&positions).radius,
+        };
+        let mut lods = Vec::new();
+        let (mut count, mut error, mut screen_size) = (base.len(), 0.0f32, f32::MAX);
+        for ratio in ratios.iter() {
+            let target = (base.len() as f32 * ratio) as usize / 3 * 3;
+            let (indices, e) = simplify(&base, &positions, target, max_error * radius);
+            if indices.len() >= count {
+                break
+            }
+            count = indices.len();
+            error = error.max(e);
+            if error > 0.0 {
+                screen_size = screen_size.min(2.0 * radius * MAX_SCREEN_ERROR / error);
+            }
+            lods.push((optimize_vertex_cache(&indices, positions.len()), error, screen_size));
+        }
+        let (buffer, p) = self.primitive_mut(part, prim)?;
+        for (indices, error, screen_size) in lods {
+            p.lods.push(PrimitiveLod { indice: write_indices(buffer, &indices), screen_size, error });
+        }
+        let count = p.lods.len();
+        self.compact_buffer();
+        Ok(count)
+    }
+}
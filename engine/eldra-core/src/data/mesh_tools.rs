@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use crate::data::bounds::{Aabb, BoundingSphere};
+use crate::data::mikktspace;
+use crate::data::render_object::{BufferType, BufferView, ComponentType, DataType, Primitive, PrimitiveMode, RenderObject, RenderPart};
+use crate::data::vertex_accessor::{gather_view, views_mut, write_view};
+use crate::reflection::{Vec2f, Vec3f, Vec4f};
+
+/**
+ * CPU processing of the primitives of a RenderObject.
+ * triangle tools expect TRIANGLES primitives, see RenderObject::triangulate. operations that
+ * change the vertex count rewrite every vertex stream, morph targets included, and always
+ * leave an index buffer.
+ * tangents follow MikkTSpace, see mikktspace.rs, with the bitangent sign in w. vertices whose
+ * corners get different tangents are split.
+ * vertex cache ordering is Tom Forsyth's linear-speed algorithm, overdraw ordering sorts the
+ * cache clusters front facing outwards first, as in Sander et al.
+ */
+const CACHE_SIZE: usize = 32;
+
+// list indices of a strip, fan or loop, as (LINES or TRIANGLES, indices)
+pub fn list_indices(mode: &PrimitiveMode, indices: &[u32]) -> (PrimitiveMode, Vec<u32>) {
+    let n = indices.len();
+    match mode {
+        PrimitiveMode::LINE_STRIP | PrimitiveMode::LINE_LOOP => {
+            let mut lines: Vec<u32> = indices.windows(2).flatten().cloned().collect();
+            if matches!(mode, PrimitiveMode::LINE_LOOP) && n > 2 {
+                lines.extend([indices[n - 1], indices[0]]);
+            }
+            (PrimitiveMode::LINES, lines)
+        },
+        PrimitiveMode::TRIANGLE_STRIP | PrimitiveMode::TRIANGLE_FAN => {
+            let fan = matches!(mode, PrimitiveMode::TRIANGLE_FAN);
+            let triangles = (0..n.saturating_sub(2)).map(|i| match (fan, i % 2) {
+                (true, _) => [indices[i + 1], indices[i + 2], indices[0]],
+                (false, 0) => [indices[i], indices[i + 1], indices[i + 2]],
+                (false, _) => [indices[i], indices[i + 2], indices[i + 1]],
+            });
+            // strips restart with degenerate triangles
+            (PrimitiveMode::TRIANGLES, triangles.filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).flatten().collect())
+        },
+        PrimitiveMode::POINTS => (PrimitiveMode::POINTS, indices.to_vec()),
+        PrimitiveMode::LINES => (PrimitiveMode::LINES, indices.to_vec()),
+        PrimitiveMode::TRIANGLES => (PrimitiveMode::TRIANGLES, indices.to_vec()),
+    }
+}
+// average cache miss ratio of a FIFO cache, misses per triangle
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let mut cache: Vec<u32> = Vec::with_capacity(cache_size);
+    let mut misses = 0;
+    for i in indices.iter() {
+        if !cache.contains(i) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.remove(0);
+            }
+            cache.push(*i);
+        }
+    }
+    misses as f32 / (indices.len() / 3).max(1) as f32
+}
+
+fn forsyth_score(position: Option<usize>, valence: usize) -> f32 {
+    if valence == 0 {
+        return -1.0
+    }
+    let cache = match position {
+        Some(p) if p < 3 => 0.75,
+        Some(p) => (1.0 - (p - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+    cache + 2.0 * (valence as f32).powf(-0.5)
+}
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let tri_count = indices.len() / 3;
+    let mut vertex_tris: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for t in 0..tri_count {
+        for c in 0..3 {
+            vertex_tris[indices[t * 3 + c] as usize].push(t);
+        }
+    }
+    let mut valence: Vec<usize> = vertex_tris.iter().map(|t| t.len()).collect();
+    let mut position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_score: Vec<f32> = (0..vertex_count).map(|v| forsyth_score(None, valence[v])).collect();
+    let tri_score = |t: usize, vertex_score: &[f32]| (0..3).map(|c| vertex_score[indices[t * 3 + c] as usize]).sum::<f32>();
+    let mut scores: Vec<f32> = (0..tri_count).map(|t| tri_score(t, &vertex_score)).collect();
+    let mut emitted = vec![false; tri_count];
+    let mut cache: Vec<u32> = Vec::new();
+    let mut out = Vec::with_capacity(indices.len());
+    let mut best: Option<usize> = None;
+    let mut next_unemitted = 0;
+    for _ in 0..tri_count {
+        let t = match best {
+            Some(t) => t,
+            None => {
+                // full scan when the cache has no candidate
+                let t = (next_unemitted..tri_count).filter(|t| !emitted[*t])
+                    .max_by(|a, b| scores[*a].total_cmp(&scores[*b]).then(b.cmp(a))).unwrap();
+                while next_unemitted < tri_count && emitted[next_unemitted] { next_unemitted += 1; }
+                t
+            },
+        };
+        emitted[t] = true;
+        let tri = [indices[t * 3], indices[t * 3 + 1], indices[t * 3 + 2]];
+        out.extend(tri);
+        for v in tri.iter() {
+            valence[*v as usize] -= 1;
+            vertex_tris[*v as usize].retain(|x| *x != t);
+            cache.retain(|c| c != v);
+        }
+        let evicted: Vec<u32> = cache.drain(CACHE_SIZE.saturating_sub(3).min(cache.len())..).collect();
+        cache.splice(0..0, tri.iter().cloned());
+        for v in evicted.iter() {
+            position[*v as usize] = None;
+        }
+        // rescore the vertices that moved and their triangles
+        let mut touched: Vec<usize> = Vec::new();
+        for v in cache.iter().chain(evicted.iter()) {
+            let v = *v as usize;
+            position[v] = cache.iter().position(|c| *c as usize == v);
+            vertex_score[v] = forsyth_score(position[v], valence[v]);
+            touched.extend(vertex_tris[v].iter().cloned());
+        }
+        best = None;
+        for t in touched {
+            scores[t] = tri_score(t, &vertex_score);
+            if best.is_none_or(|b| scores[t] > scores[b]) {
+                best = Some(t);
+            }
+        }
+    }
+    out
+}
+// triangles are kept in their cache order within clusters starting at full cache misses
+pub fn optimize_overdraw(indices: &[u32], positions: &[Vec3f]) -> Vec<u32> {
+    let tri_count = indices.len() / 3;
+    let mut clusters: Vec<(usize, usize)> = Vec::new();
+    let mut cache: Vec<u32> = Vec::new();
+    let mut start = 0;
+    for t in 0..tri_count {
+        let tri = &indices[t * 3..t * 3 + 3];
+        if t > start && tri.iter().all(|v| !cache.contains(v)) {
+            clusters.push((start, t));
+            start = t;
+        }
+        for v in tri.iter() {
+            if !cache.contains(v) {
+                if cache.len() == CACHE_SIZE {
+                    cache.remove(0);
+                }
+                cache.push(*v);
+            }
+        }
+    }
+    if start < tri_count {
+        clusters.push((start, tri_count));
+    }
+    let face = |t: usize| {
+        let p = |c: usize| positions[indices[t * 3 + c] as usize];
+        ((p(1) - p(0)).cross(&(p(2) - p(0))), (p(0) + p(1) + p(2)) / 3.0)
+    };
+    let mut mesh_area = 0.0;
+    let mut mesh_center = Vec3f::zeros();
+    for t in 0..tri_count {
+        let (n, c) = face(t);
+        mesh_area += n.norm();
+        mesh_center += c * n.norm();
+    }
+    if mesh_area > 0.0 {
+        mesh_center /= mesh_area;
+    }
+    let mut scored: Vec<(f32, (usize, usize))> = clusters.into_iter().map(|(a, b)| {
+        let (mut normal, mut center, mut area) = (Vec3f::zeros(), Vec3f::zeros(), 0.0);
+        for t in a..b {
+            let (n, c) = face(t);
+            normal += n;
+            center += c * n.norm();
+            area += n.norm();
+        }
+        let center = if area > 0.0 { center / area } else { center };
+        ((center - mesh_center).dot(&normal.try_normalize(0.0).unwrap_or_default()), (a, b))
+    }).collect();
+    // stable, equal scores keep the cache order
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.iter().flat_map(|(_, (a, b))| indices[a * 3..b * 3].iter().cloned()).collect()
+}
+
+fn vec3_view() -> BufferView {
+    BufferView { buffer_type: BufferType::VERTEX, data_type: DataType::VEC3, component_type: ComponentType::F32, ..Default::default() }
+}
+fn views(prim: &Primitive) -> Vec<&BufferView> {
+    let mut views: Vec<&BufferView> = prim.vertex_iter().map(|info| info.buffer_view).collect();
+    for t in prim.targets.iter() {
+        views.extend(t.position.iter().chain(t.normal.iter()).chain(t.tangent.iter()));
+    }
+    views
+}
+// the triangle count drops when welding merges corners
+fn remove_degenerate(indices: &mut Vec<u32>) {
+    let kept: Vec<u32> = indices.chunks_exact(3).filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).flatten().cloned().collect();
+    *indices = kept;
+}
+
+impl RenderObject {
+    fn primitive(&self, part: usize, prim: usize) -> Result<&Primitive, String> {
+        self.parts.get(part).and_then(|p| p.primitives.get(prim)).ok_or(format!("no primitive {} in part {}", prim, part))
+    }
+    // indices or 0..vertex count
+    pub fn element_indices(&self, prim: &Primitive) -> Result<Vec<u32>, String> {
+        match self.read_indices(prim)? {
+            Some(indices) => Ok(indices),
+            None => Ok((0..prim.position.count).collect()),
+        }
+    }
+    pub fn triangles(&self, prim: &Primitive) -> Result<Vec<[u32; 3]>, String> {
+        if !matches!(prim.mode, PrimitiveMode::TRIANGLES) {
+            return Err("not a triangle list, triangulate it first".to_string())
+        }
+        Ok(self.element_indices(prim)?.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect())
+    }
+    // strips, fans and loops to lists
+    pub fn triangulate(&mut self, part: usize, prim: usize) -> Result<(), String> {
+        let p = self.primitive(part, prim)?;
+        let (mode, list) = list_indices(&p.mode, &self.element_indices(p)?);
+        self.write_indices(part, prim, &list)?;
+        self.parts[part].primitives[prim].mode = mode;
+        Ok(())
+    }
+    // vertex i becomes the old vertex remap[i] in every stream
+    pub fn remap_vertices(&mut self, part: usize, prim: usize, remap: &[u32], indices: &[u32]) -> Result<(), String> {
+        let (buffer, p) = self.primitive_mut(part, prim)?;
+        // the LODs index the old vertices
+        p.lods.clear();
+        for view in views_mut(p, false) {
+            gather_view(buffer, view, remap)?;
+        }
+        self.write_indices(part, prim, indices)
+    }
+    // flat normals split the vertices per triangle, smooth ones are area weighted and shared
+    // by the vertices of a same position
+    pub fn generate_normals(&mut self, part: usize, prim: usize, smooth: bool) -> Result<(), String> {
+        let triangles = self.triangles(self.primitive(part, prim)?)?;
+        let normals: Vec<Vec3f> = if smooth {
+            let p = self.primitive(part, prim)?;
+            let positions = self.read_attribute::<Vec3f>(p, "POSITION")?;
+            let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+            let group: Vec<usize> = positions.iter().map(|p| {
+                let n = groups.len();
+                *groups.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert(n)
+            }).collect();
+            let mut sums = vec![Vec3f::zeros(); groups.len()];
+            for t in triangles.iter() {
+                let [a, b, c] = t.map(|v| positions[v as usize]);
+                let n = (b - a).cross(&(c - a));
+                t.iter().for_each(|v| sums[group[*v as usize]] += n);
+            }
+            group.iter().map(|g| sums[*g].try_normalize(0.0).unwrap_or_default()).collect()
+        } else {
+            let remap: Vec<u32> = triangles.iter().flatten().cloned().collect();
+            let indices: Vec<u32> = (0..remap.len() as u32).collect();
+            self.remap_vertices(part, prim, &remap, &indices)?;
+            let positions = self.read_attribute::<Vec3f>(self.primitive(part, prim)?, "POSITION")?;
+            positions.chunks_exact(3).flat_map(|t| {
+                let n = (t[1] - t[0]).cross(&(t[2] - t[0])).try_normalize(0.0).unwrap_or_default();
+                [n, n, n]
+            }).collect()
+        };
+        let (buffer, p) = self.primitive_mut(part, prim)?;
+        write_view(buffer, p.normal.get_or_insert_with(vec3_view), &normals)?;
+        self.compact_buffer();
+        Ok(())
+    }
+    // from NORMAL and TEXCOORD_0, written as a VEC4 F32 TANGENT. MikkTSpace gives a tangent per
+    // triangle corner, a vertex whose corners disagree is split with a copy per tangent
+    pub fn generate_tangents(&mut self, part: usize, prim: usize) -> Result<(), String> {
+        let p = self.primitive(part, prim)?;
+        let triangles = self.triangles(p)?;
+        let positions = self.read_attribute::<Vec3f>(p, "POSITION")?;
+        let normals = self.read_attribute::<Vec3f>(p, "NORMAL").map_err(|e| format!("tangents need normals : {}", e))?;
+        let uvs = self.read_attribute::<Vec2f>(p, "TEXCOORD_0").map_err(|e| format!("tangents need texcoords : {}", e))?;
+        let corners = mikktspace::generate_tangents(&positions, &normals, &uvs, &triangles).ok_or("no triangle to compute tangents from")?;
+        // the first tangent of a vertex keeps it, the others get copies at the end.
+        // vertices no triangle uses get the x axis
+        let mut tangents: Vec<Option<Vec4f>> = vec![None; positions.len()];
+        let mut remap: Vec<u32> = (0..positions.len() as u32).collect();
+        let mut split: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+        let indices: Vec<u32> = triangles.iter().flatten().zip(corners.iter()).map(|(v, t)| {
+            *split.entry((*v, [t.x.to_bits(), t.y.to_bits(), t.z.to_bits(), t.w.to_bits()])).or_insert_with(|| {
+                if tangents[*v as usize].is_none() {
+                    tangents[*v as usize] = Some(*t);
+                    return *v
+                }
+                remap.push(*v);
+                tangents.push(Some(*t));
+                remap.len() as u32 - 1
+            })
+        }).collect();
+        if remap.len() > positions.len() {
+            self.remap_vertices(part, prim, &remap, &indices)?;
+        }
+        let tangents: Vec<Vec4f> = tangents.iter().map(|t| t.unwrap_or(Vec4f::new(1., 0., 0., 1.))).collect();
+        let (buffer, p) = self.primitive_mut(part, prim)?;
+        let view = p.tangent.get_or_insert_with(|| BufferView { data_type: DataType::VEC4, ..vec3_view() });
+        write_view(buffer, view, &tangents)?;
+        self.compact_buffer();
+        Ok(())
+    }
+    // merges the vertices equal in every stream, returns the vertex count
+    pub fn deduplicate_vertices(&mut self, part: usize, prim: usize) -> Result<usize, String> {
+        let p = self.primitive(part, prim)?;
+        let streams = views(p).iter().map(|v| Ok((self.accessor(v)?.to_bytes(), v.count as usize)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let count = p.position.count as usize;
+        let mut unique: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut remap = Vec::new();
+        let new_index: Vec<u32> = (0..count).map(|v| {
+            let key: Vec<u8> = streams.iter().flat_map(|(bytes, n)| {
+                let elem = bytes.len() / (*n).max(1);
+                bytes[v * elem..(v + 1) * elem].iter().cloned()
+            }).collect();
+            *unique.entry(key).or_insert_with(|| {
+                remap.push(v as u32);
+                remap.len() as u32 - 1
+            })
+        }).collect();
+        let indices: Vec<u32> = self.element_indices(p)?.iter().map(|i| new_index[*i as usize]).collect();
+        self.remap_vertices(part, prim, &remap, &indices)?;
+        Ok(remap.len())
+    }
+    // merges the vertices closer than tolerance, keeping the streams of the first one.
+    // triangles left degenerate are removed
+    pub fn weld_vertices(&mut self, part: usize, prim: usize, tolerance: f32) -> Result<usize, String> {
+        let p = self.primitive(part, prim)?;
+        let positions = self.read_attribute::<Vec3f>(p, "POSITION")?;
+        let cell = |p: &Vec3f| p.map(|c| (c / tolerance.max(f32::EPSILON)).floor() as i64);
+        let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut remap: Vec<u32> = Vec::new();
+        let new_index: Vec<u32> = positions.iter().enumerate().map(|(v, pos)| {
+            let c = cell(pos);
+            let near = (0..27).filter_map(|n| {
+                let key = [c.x + n % 3 - 1, c.y + n / 3 % 3 - 1, c.z + n / 9 - 1];
+                grid.get(&key)?.iter().find(|r| (positions[remap[**r as usize] as usize] - pos).norm() <= tolerance).cloned()
+            }).next();
+            near.unwrap_or_else(|| {
+                remap.push(v as u32);
+                let r = remap.len() as u32 - 1;
+                grid.entry([c.x, c.y, c.z]).or_default().push(r);
+                r
+            })
+        }).collect();
+        let mut indices: Vec<u32> = self.element_indices(p)?.iter().map(|i| new_index[*i as usize]).collect();
+        if matches!(p.mode, PrimitiveMode::TRIANGLES) {
+            remove_degenerate(&mut indices);
+        }
+        self.remap_vertices(part, prim, &remap, &indices)?;
+        Ok(remap.len())
+    }
+    pub fn optimize_vertex_cache(&mut self, part: usize, prim: usize) -> Result<(), String> {
+        let p = self.primitive(part, prim)?;
+        let indices: Vec<u32> = self.triangles(p)?.into_iter().flatten().collect();
+        let ordered = optimize_vertex_cache(&indices, p.position.count as usize);
+        self.write_indices(part, prim, &ordered)
+    }
+    // after optimize_vertex_cache, whose clusters it reorders
+    pub fn optimize_overdraw(&mut self, part: usize, prim: usize) -> Result<(), String> {
+        let p = self.primitive(part, prim)?;
+        let indices: Vec<u32> = self.triangles(p)?.into_iter().flatten().collect();
+        let positions = self.read_attribute::<Vec3f>(p, "POSITION")?;
+        let ordered = optimize_overdraw(&indices, &positions);
+        self.write_indices(part, prim, &ordered)
+    }
+    pub fn compute_bounds(&mut self, part: usize, prim: usize) -> Result<(), String> {
+        let positions = self.read_attribute::<Vec3f>(self.primitive(part, prim)?, "POSITION")?;
+        let (_, p) = self.primitive_mut(part, prim)?;
+        p.aabb = Aabb::from_points(&positions);
+        p.sphere = BoundingSphere::from_points(&positions);
+        Ok(())
+    }
+    // a part, all of them for -1 as in RenderComponent
+    pub(crate) fn drawn_parts(&self, part: i32) -> &[RenderPart] {
+        match self.parts.get(part as usize) {
+            Some(p) if part >= 0 => std::slice::from_ref(p),
+            _ => &self.parts[..],
+        }
+    }
+    // of the stored primitive bounds. bind pose, morph targets and skinning are not accounted for
+    pub fn bounds(&self, part: i32) -> Aabb {
+        self.drawn_parts(part).iter().flat_map(|p| p.primitives.iter()).fold(Aabb::empty(), |aabb, p| aabb.merge(&p.aabb))
+    }
+    pub fn bounding_sphere(&self, part: i32) -> BoundingSphere {
+        let aabb = self.bounds(part);
+        if aabb.is_empty() {
+            return BoundingSphere::default()
+        }
+        let center = aabb.center();
+        let radius = self.drawn_parts(part).iter().flat_map(|p| p.primitives.iter())
+            .filter(|p| !p.aabb.is_empty())
+            .map(|p| (p.sphere.center - center).norm() + p.sphere.radius)
+            .fold(0.0, f32::max);
+        // never larger than the one around the box
+        BoundingSphere::new(center, radius.min(aabb.half_extents().norm()))
+    }
+}
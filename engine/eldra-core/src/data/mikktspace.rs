@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use crate::reflection::{Vec2f, Vec3f, Vec4f};
+
+/**
+ * MikkTSpace tangent generation, a port of Morten S. Mikkelsen's reference implementation.
+ * tangents are computed per triangle corner. a vertex is identified by its position, normal and
+ * uv, the triangles around it are grouped by connectivity and uv orientation, and each group
+ * gets the angle weighted average of its triangle tangents projected on the vertex normal.
+ * corners of a same vertex can get different tangents, see RenderObject::generate_tangents.
+ * only triangles are handled, the quad paths of the reference are left out.
+ */
+// cos of the 180 degrees default angular threshold, every subgroup is merged
+const THRESHOLD_COS: f32 = -1.0;
+
+#[derive(Clone, Copy)]
+struct TSpace {
+    os: Vec3f,
+    mag_s: f32,
+    ot: Vec3f,
+    mag_t: f32,
+    orient: bool,
+}
+impl Default for TSpace {
+    fn default() -> Self {
+        TSpace { os: Vec3f::x(), mag_s: 1.0, ot: Vec3f::y(), mag_t: 1.0, orient: false }
+    }
+}
+
+#[derive(Clone, Default)]
+struct TriInfo {
+    // across the edge from corner i to i + 1
+    neighbors: [Option<usize>; 3],
+    groups: [Option<usize>; 3],
+    os: Vec3f,
+    ot: Vec3f,
+    mag_s: f32,
+    mag_t: f32,
+    // input triangle, its corners are 3 * face..3 * face + 3
+    face: usize,
+    degenerate: bool,
+    // no usable uv gradient, joins the group of any neighbor
+    group_with_any: bool,
+    orient_preserving: bool,
+}
+
+struct Group {
+    faces: Vec<usize>,
+    vertex: usize,
+    orient_preserving: bool,
+}
+
+fn not_zero(x: f32) -> bool {
+    x.abs() > f32::MIN_POSITIVE
+}
+fn normalized(v: Vec3f) -> Vec3f {
+    if v.iter().any(|c| not_zero(*c)) { v * (1.0 / v.norm()) } else { v }
+}
+// v without its component along n
+fn project(v: &Vec3f, n: &Vec3f) -> Vec3f {
+    normalized(v - n * n.dot(v))
+}
+
+struct Mesh<'a> {
+    positions: &'a [Vec3f],
+    normals: &'a [Vec3f],
+    uvs: &'a [Vec2f],
+    triangles: &'a [[u32; 3]],
+}
+impl Mesh<'_> {
+    fn vertex(&self, corner: usize) -> usize {
+        self.triangles[corner / 3][corner % 3] as usize
+    }
+    fn position(&self, corner: usize) -> Vec3f {
+        self.positions[self.vertex(corner)]
+    }
+    fn normal(&self, corner: usize) -> Vec3f {
+        self.normals[self.vertex(corner)]
+    }
+    fn uv(&self, corner: usize) -> Vec2f {
+        self.uvs[self.vertex(corner)]
+    }
+    // first corner of the same position, normal and uv for every corner
+    fn shared_corners(&self) -> Vec<usize> {
+        // +0.0 folds -0.0 into 0.0, they compare equal in the reference
+        let bits = |c: &[f32]| c.iter().map(|x| (x + 0.0).to_bits()).collect::<Vec<u32>>();
+        let mut first: HashMap<Vec<u32>, usize> = HashMap::new();
+        (0..self.triangles.len() * 3).map(|c| {
+            let key = [bits(self.position(c).as_slice()), bits(self.normal(c).as_slice()), bits(self.uv(c).as_slice())].concat();
+            *first.entry(key).or_insert(c)
+        }).collect()
+    }
+}
+
+// degenerate triangles go after the good ones, in the same swaps as the reference
+fn move_degenerate_last(infos: &mut [TriInfo], list: &mut [usize], good: usize) {
+    let mut next_good = 1;
+    for t in 0..good {
+        if !infos[t].degenerate {
+            next_good = next_good.max(t + 2);
+            continue
+        }
+        while next_good < infos.len() && infos[next_good].degenerate {
+            next_good += 1;
+        }
+        if next_good >= infos.len() {
+            break
+        }
+        for i in 0..3 {
+            list.swap(t * 3 + i, next_good * 3 + i);
+        }
+        infos.swap(t, next_good);
+        next_good += 1;
+    }
+}
+
+fn init_tri_info(mesh: &Mesh, infos: &mut [TriInfo], list: &[usize]) {
+    for (t, info) in infos.iter_mut().enumerate() {
+        let [p1, p2, p3] = [0, 1, 2].map(|i| mesh.position(list[t * 3 + i]));
+        let [t1, t2, t3] = [0, 1, 2].map(|i| mesh.uv(list[t * 3 + i]));
+        let (t21, t31) = (t2 - t1, t3 - t1);
+        let (d1, d2) = (p2 - p1, p3 - p1);
+        let area = t21.x * t31.y - t21.y * t31.x;
+        let os = d1 * t31.y - d2 * t21.y;
+        let ot = d2 * t21.x - d1 * t31.x;
+        info.orient_preserving = area > 0.0;
+        info.group_with_any = true;
+        if not_zero(area) {
+            let sign = if info.orient_preserving { 1.0 } else { -1.0 };
+            let (len_os, len_ot) = (os.norm(), ot.norm());
+            if not_zero(len_os) {
+                info.os = os * (sign / len_os);
+            }
+            if not_zero(len_ot) {
+                info.ot = ot * (sign / len_ot);
+            }
+            info.mag_s = len_os / area.abs();
+            info.mag_t = len_ot / area.abs();
+            info.group_with_any = !(not_zero(info.mag_s) && not_zero(info.mag_t));
+        }
+    }
+    build_neighbors(infos, list);
+}
+
+// triangles sharing an edge in opposite directions are neighbors, the first free one in
+// (vertex, vertex, triangle) order when the edge is shared by more than two
+fn build_neighbors(infos: &mut [TriInfo], list: &[usize]) {
+    let mut edges: Vec<(usize, usize, usize)> = (0..infos.len() * 3).map(|e| {
+        let (i0, i1) = (list[e], list[e / 3 * 3 + (e % 3 + 1) % 3]);
+        (i0.min(i1), i0.max(i1), e / 3)
+    }).collect();
+    edges.sort_unstable();
+    // corner where the edge starts, and the edge in the triangle winding
+    let edge_of = |t: usize, a: usize, b: usize| {
+        let v = &list[t * 3..t * 3 + 3];
+        let e = (0..3).find(|e| (v[*e] == a || v[*e] == b) && (v[(e + 1) % 3] == a || v[(e + 1) % 3] == b)).unwrap();
+        (e, v[e], v[(e + 1) % 3])
+    };
+    for i in 0..edges.len() {
+        let (a, b, f) = edges[i];
+        let (edge_a, i0_a, i1_a) = edge_of(f, a, b);
+        if infos[f].neighbors[edge_a].is_some() {
+            continue
+        }
+        let found = edges[i + 1..].iter().take_while(|e| e.0 == a && e.1 == b).find_map(|&(_, _, t)| {
+            let (edge_b, i0_b, i1_b) = edge_of(t, a, b);
+            (i0_a == i1_b && i1_a == i0_b && infos[t].neighbors[edge_b].is_none()).then_some((t, edge_b))
+        });
+        if let Some((t, edge_b)) = found {
+            infos[f].neighbors[edge_a] = Some(t);
+            infos[t].neighbors[edge_b] = Some(f);
+        }
+    }
+}
+
+// corner of triangle t on the given shared vertex
+fn corner_of(list: &[usize], t: usize, vertex: usize) -> usize {
+    (0..3).find(|i| list[t * 3 + i] == vertex).unwrap_or(0)
+}
+
+// the triangles around a vertex connected by edges and with the same orientation
+fn build_groups(infos: &mut [TriInfo], list: &[usize]) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    for f in 0..infos.len() {
+        for i in 0..3 {
+            if infos[f].group_with_any || infos[f].groups[i].is_some() {
+                continue
+            }
+            let g = groups.len();
+            groups.push(Group { faces: vec![f], vertex: list[f * 3 + i], orient_preserving: infos[f].orient_preserving });
+            infos[f].groups[i] = Some(g);
+            let mut stack: Vec<usize> = Vec::new();
+            stack.extend(infos[f].neighbors[i]);
+            stack.extend(infos[f].neighbors[(i + 2) % 3]);
+            while let Some(t) = stack.pop() {
+                let c = corner_of(list, t, groups[g].vertex);
+                if infos[t].groups[c].is_some() {
+                    continue
+                }
+                if infos[t].group_with_any && infos[t].groups.iter().all(|g| g.is_none()) {
+                    infos[t].orient_preserving = groups[g].orient_preserving;
+                }
+                if infos[t].orient_preserving != groups[g].orient_preserving {
+                    continue
+                }
+                groups[g].faces.push(t);
+                infos[t].groups[c] = Some(g);
+                stack.extend(infos[t].neighbors[c]);
+                stack.extend(infos[t].neighbors[(c + 2) % 3]);
+            }
+        }
+    }
+    groups
+}
+
+// angle weighted average over the members, at the corner of the group vertex
+fn eval_tspace(mesh: &Mesh, infos: &[TriInfo], list: &[usize], members: &[usize], vertex: usize) -> TSpace {
+    let mut res = TSpace { os: Vec3f::zeros(), mag_s: 0.0, ot: Vec3f::zeros(), mag_t: 0.0, orient: false };
+    let mut angle_sum = 0.0;
+    for &f in members.iter().filter(|f| !infos[**f].group_with_any) {
+        let i = corner_of(list, f, vertex);
+        let n = mesh.normal(list[f * 3 + i]);
+        let (os, ot) = (project(&infos[f].os, &n), project(&infos[f].ot, &n));
+        let p0 = mesh.position(list[f * 3 + (i + 2) % 3]);
+        let p1 = mesh.position(list[f * 3 + i]);
+        let p2 = mesh.position(list[f * 3 + (i + 1) % 3]);
+        let (v1, v2) = (project(&(p0 - p1), &n), project(&(p2 - p1), &n));
+        let angle = (v1.dot(&v2).clamp(-1.0, 1.0) as f64).acos() as f32;
+        res.os += os * angle;
+        res.ot += ot * angle;
+        res.mag_s += infos[f].mag_s * angle;
+        res.mag_t += infos[f].mag_t * angle;
+        angle_sum += angle;
+    }
+    res.os = normalized(res.os);
+    res.ot = normalized(res.ot);
+    if angle_sum > 0.0 {
+        res.mag_s /= angle_sum;
+        res.mag_t /= angle_sum;
+    }
+    res
+}
+
+fn generate_tspaces(mesh: &Mesh, infos: &[TriInfo], list: &[usize], groups: &[Group], tspaces: &mut [TSpace]) {
+    for (g, group) in groups.iter().enumerate() {
+        // members sorted, with their tangent space
+        let mut subgroups: Vec<(Vec<usize>, TSpace)> = Vec::new();
+        for &f in group.faces.iter() {
+            let i = (0..3).find(|i| infos[f].groups[*i] == Some(g)).unwrap();
+            let n = mesh.normal(list[f * 3 + i]);
+            let (os, ot) = (project(&infos[f].os, &n), project(&infos[f].ot, &n));
+            let mut members: Vec<usize> = group.faces.iter().cloned().filter(|&t| {
+                let (os2, ot2) = (project(&infos[t].os, &n), project(&infos[t].ot, &n));
+                infos[f].group_with_any || infos[t].group_with_any || t == f
+                    || (os.dot(&os2) > THRESHOLD_COS && ot.dot(&ot2) > THRESHOLD_COS)
+            }).collect();
+            members.sort_unstable();
+            let k = match subgroups.iter().position(|s| s.0 == members) {
+                Some(k) => k,
+                None => {
+                    let tspace = eval_tspace(mesh, infos, list, &members, group.vertex);
+                    subgroups.push((members, tspace));
+                    subgroups.len() - 1
+                },
+            };
+            tspaces[infos[f].face * 3 + i] = TSpace { orient: group.orient_preserving, ..subgroups[k].1 };
+        }
+    }
+}
+
+// per corner tangents of the triangles, xyz and the bitangent sign in w. None without a
+// non degenerate triangle
+pub fn generate_tangents(positions: &[Vec3f], normals: &[Vec3f], uvs: &[Vec2f], triangles: &[[u32; 3]]) -> Option<Vec<Vec4f>> {
+    let mesh = Mesh { positions, normals, uvs, triangles };
+    let mut list = mesh.shared_corners();
+    let mut infos: Vec<TriInfo> = (0..triangles.len()).map(|t| {
+        let [p0, p1, p2] = [0, 1, 2].map(|i| mesh.position(list[t * 3 + i]));
+        TriInfo { face: t, degenerate: p0 == p1 || p0 == p2 || p1 == p2, ..Default::default() }
+    }).collect();
+    let good = infos.iter().filter(|t| !t.degenerate).count();
+    if good == 0 {
+        return None
+    }
+    move_degenerate_last(&mut infos, &mut list, good);
+    init_tri_info(&mesh, &mut infos[..good], &list[..good * 3]);
+    let groups = build_groups(&mut infos[..good], &list[..good * 3]);
+    let mut tspaces = vec![TSpace::default(); triangles.len() * 3];
+    generate_tspaces(&mesh, &infos[..good], &list[..good * 3], &groups, &mut tspaces);
+    // degenerate corners copy a good corner of the same vertex
+    for t in good..infos.len() {
+        for i in 0..3 {
+            if let Some(j) = list[..good * 3].iter().position(|c| *c == list[t * 3 + i]) {
+                tspaces[infos[t].face * 3 + i] = tspaces[infos[j / 3].face * 3 + j % 3];
+            }
+        }
+    }
+    Some(tspaces.iter().map(|ts| Vec4f::new(ts.os.x, ts.os.y, ts.os.z, if ts.orient { 1.0 } else { -1.0 })).collect())
+}
@@ -1,31 +1,61 @@
-use std::collections::HashMap;
-use std::fs;
-use std::fs::File;
-use std::io::{BufReader, Read, Write};
-use std::ops::Deref;
-use std::rc::Rc;
-use std::sync::RwLock;
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::io::{Read, Write};
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::str::FromStr;
 use once_cell::sync::OnceCell;
 use uuid::Uuid;
-use yaml_rust2::{Yaml, YamlLoader};
+use yaml_rust2::Yaml;
+use crate::data::asset_db::{asset_db, sibling_path};
+use crate::data::blob::deserialize_binary_mapped;
+use crate::data::hot_reload::{update_hot_reload, HotReload, ReloadCause, ReloadEvent};
+use crate::data::loader::{*};
 use crate::data::material::Material;
+use crate::data::registry::{ResMap, ResourceRegistry};
 use crate::data::render_object::RenderObject;
+use crate::data::residency::{collect_unused, enforce_budget, Residency, ResidencyStats, ResidentOps};
 use crate::data::skeleton::Skeleton;
+use crate::data::animation_clip::AnimationClip;
+use crate::data::animation_graph::AnimationGraph;
 use crate::data::texture::Texture;
 use crate::reflection::{Serializable};
+use crate::fs::vfs;
+use crate::{log_error, log_warn};
 
+pub mod animation_clip;
+pub mod animation_graph;
+pub mod asset_db;
+pub mod blob;
+pub mod bounds;
+pub mod chunk;
+pub mod gltf_export;
+pub mod gltf_import;
+pub mod gltf_scene;
+pub mod hot_reload;
+pub mod loader;
+pub mod manifest;
 pub mod skeleton;
 pub mod material;
+pub mod mesh_simplify;
+pub mod mesh_tools;
+pub mod mikktspace;
+pub mod registry;
 pub mod render_object;
+pub mod residency;
 pub mod texture;
+pub mod vertex_accessor;
 
 #[derive(Default)]
 pub struct ResourceMgr
 {
-    pub RenderObject: RwLock<HashMap<String, Rc<RenderObject>>>,
-    pub Material: RwLock<HashMap<String, Rc<Material>>>,
-    pub Texture: RwLock<HashMap<String, Rc<Texture>>>,
-    pub Skeleton: RwLock<HashMap<String, Rc<Skeleton>>>,
+    pub registry: ResourceRegistry,
+    pub loader: RefCell<AsyncLoader>,
+    pub hot_reload: RefCell<HotReload>,
+    pub residency: RefCell<Residency>,
 }
 static mut RESOURCE_MGR : OnceCell<ResourceMgr> = OnceCell::new();
 #[inline]
@@ -33,85 +63,447 @@ pub fn res_mgr() -> &'static ResourceMgr {
     unsafe { RESOURCE_MGR.get_unchecked() }
 }
 pub unsafe fn init_resource_mgr() {
-    RESOURCE_MGR.get_or_init (|| { ResourceMgr::default() });
+    RESOURCE_MGR.get_or_init (|| {
+        let mgr = ResourceMgr::default();
+        mgr.registry.register::<RenderObject>();
+        mgr.registry.register::<Material>();
+        mgr.registry.register::<Texture>();
+        mgr.registry.register::<Skeleton>();
+        mgr.registry.register::<AnimationClip>();
+        mgr.registry.register::<AnimationGraph>();
+        mgr
+    });
 }
+impl ResourceMgr {
+    // make T loadable through ExtRes<T>, plugins register their own types after engine_init
+    pub fn register<T: Resource>(&self) {
+        self.registry.register::<T>();
+    }
+    // editor import of a source file, registers it and extracts what it embeds (glTF materials...)
+    pub fn import_source(&self, path: &str) -> Result<Uuid, String> {
+        let (guid, abs) = {
+            let mut db = asset_db().write().unwrap();
+            let guid = db.import(path)?;
+            (guid, db.absolute(db.path_of(&guid).unwrap()))
+        };
+        let ext = abs.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        for type_id in self.registry.types_for_extension(&ext) {
+            self.registry.import_source(type_id, &abs)?;
+        }
+        Ok(guid)
+    }
+    // cached handles of T
+    pub fn handles<T: Resource>(&self) -> &ResMap<T> {
+        &self.registry.cache::<T>().map
+    }
+    // main thread, builds finished background loads and fires completion callbacks
+    pub fn update(&self) {
+        update_loader(&self.loader);
+        update_hot_reload(&self.hot_reload);
+        enforce_budget(&self.residency);
+    }
+    pub fn progress(&self) -> LoadProgress {
+        self.loader.borrow().progress()
+    }
+    pub fn is_idle(&self) -> bool {
+        self.loader.borrow().is_idle()
+    }
+    // start counting from zero for the next loading screen
+    pub fn reset_progress(&self) {
+        self.loader.borrow_mut().reset_progress();
+    }
+    // poll source files of loaded resources in update, changed ones are reloaded in place
+    pub fn set_hot_reload(&self, enabled: bool) {
+        self.hot_reload.borrow_mut().enabled = enabled;
+    }
+    pub fn add_reload_listener<F: Fn(&ReloadEvent) + 'static>(&self, f: F) -> u64 {
+        self.hot_reload.borrow_mut().add_listener(Rc::new(f))
+    }
+    pub fn remove_reload_listener(&self, id: u64) {
+        self.hot_reload.borrow_mut().remove_listener(id);
+    }
+    // bytes kept resident before unused assets get evicted, unlimited by default, 0 frees on last release
+    pub fn set_budget(&self, bytes: usize) {
+        self.residency.borrow_mut().budget = bytes;
+        enforce_budget(&self.residency);
+    }
+    // free every asset no ExtRes refers to, returns how many were freed
+    pub fn collect(&self) -> usize {
+        collect_unused(&self.residency)
+    }
+    pub fn stats(&self) -> ResidencyStats {
+        self.residency.borrow().stats()
+    }
+    // last user of the asset went away
+    fn release(&self, guid: &Uuid) {
+        self.residency.borrow_mut().touch(guid);
+        enforce_budget(&self.residency);
+    }
+}
+
 pub trait ExtSerializable<T> where T : Serializable + Sized {
     fn text_ext() -> &'static str { "yaml" }
-    fn deserialize_from_text_file(res: &mut T, respath: &String) {
-        let yaml_str = fs::read_to_string(respath).unwrap();
-        let docs = YamlLoader::load_from_str(&yaml_str).unwrap();
-        let doc = &docs[0];
-        res.deserialize_text(doc);
+    // every source file extension the type is imported from
+    fn source_exts() -> Vec<&'static str> { vec![Self::text_ext()] }
+    // worker thread, file IO and parsing
+    fn read_source(respath: &Path, bin: bool) -> Result<SourceData, String> {
+        if bin { read_binary_source(respath) } else { read_yaml_source(respath) }
+    }
+    // main thread, build the resource from what read_source produced
+    fn build_from_source(res: &mut T, src: SourceData) -> Result<(), String> {
+        build_serialized(res, src)
     }
+    // resident memory, for budgets and stats
+    fn memory_size(&self) -> usize { std::mem::size_of_val(self) }
+    // offline (cooker, editor import), write the assets embedded in the source file,
+    // read_source and build_from_source only read them
+    fn import_source(_respath: &Path) -> Result<(), String> { Ok(()) }
 }
+pub fn build_serialized(res: &mut dyn Serializable, src: SourceData) -> Result<(), String> {
+    match src {
+        SourceData::Binary(map) => deserialize_binary_mapped(res, map)?,
+        SourceData::Yaml(doc) => res.deserialize_text(&doc),
+        SourceData::Custom(_) => return Err("custom source data needs a specific build_from_source".to_string()),
+    }
+    Ok(())
+}
+// a type ResourceMgr can manage, it still has to be registered
+pub trait Resource : Default + Serializable + ExtSerializable<Self> + 'static {
+    fn res_map() -> &'static ResMap<Self> {
+        res_mgr().handles::<Self>()
+    }
+}
+impl<T: Default + Serializable + ExtSerializable<T> + 'static> Resource for T {}
 
-#[derive(Default)]
+// cooked binary of an asset is "<source path>.bin"
+pub const BINARY_EXT: &str = "bin";
+
+// reference to an asset by guid, the guid is what gets serialized
+// so renaming or moving the source file keeps the reference valid
 pub struct ExtRes<T> {
-    path: String,
-    value: Rc<T>,
-}
-macro_rules! impl_ext_ref {
-    ( $t:ident ) => {
-        impl Serializable for ExtRes<$t> {
-            fn is_multi_line(&self) -> bool { false }
-            fn get_type_uuid(&self) -> Option<Uuid> { None }
-            fn serialize_binary(&self, io: &mut dyn Write) {
-                self.path.serialize_binary(io);
-            }
-            fn deserialize_binary(&mut self, io: &mut dyn Read) {
-                self.path.deserialize_binary(io);
-                self.value = self.load_ext_res(true, &res_mgr().$t);
-            }
-            fn serialize_text(&self, io: &mut crate::reflection::SerializeTextWriter, indent: String) {
-                self.path.serialize_text(io, indent.clone());
-            }
-            fn deserialize_text(&mut self, yaml: &Yaml) {
-                self.path.deserialize_text(yaml);
-                self.value = self.load_ext_res(false, &res_mgr().$t);
-            }
+    guid: Uuid,
+    // released in drop before notifying ResourceMgr
+    handle: ManuallyDrop<Rc<ResHandle<T>>>,
+}
+impl<T> ExtRes<T> {
+    fn with_handle(guid: Uuid, handle: Rc<ResHandle<T>>) -> Self {
+        handle.add_user();
+        ExtRes { guid, handle: ManuallyDrop::new(handle) }
+    }
+}
+impl<T> Clone for ExtRes<T> {
+    fn clone(&self) -> Self {
+        Self::with_handle(self.guid, Rc::clone(&self.handle))
+    }
+}
+impl<T: Default> Default for ExtRes<T> {
+    fn default() -> Self {
+        Self::with_handle(Uuid::nil(), Rc::new(ResHandle::loaded(Rc::new(T::default()))))
+    }
+}
+fn users_of<T: Resource>(guid: &Uuid) -> usize {
+    T::res_map().read().unwrap().get(guid).map_or(0, |h| h.users())
+}
+fn bytes_of<T: Resource>(guid: &Uuid) -> usize {
+    T::res_map().read().unwrap().get(guid).map_or(0, |h| h.value().memory_size())
+}
+fn state_of<T: Resource>(guid: &Uuid) -> Option<LoadState> {
+    T::res_map().read().unwrap().get(guid).map(|h| h.state())
+}
+fn evict_of<T: Resource>(guid: &Uuid) -> Option<Rc<dyn Any>> {
+    T::res_map().write().unwrap().remove(guid).map(|h| h as Rc<dyn Any>)
+}
+fn resident_ops<T: Resource>() -> ResidentOps {
+    ResidentOps {
+        type_name: res_mgr().registry.name_of::<T>(),
+        users: users_of::<T>,
+        bytes: bytes_of::<T>,
+        state: state_of::<T>,
+        evict: evict_of::<T>,
+    }
+}
+impl<T: Resource> Serializable for ExtRes<T> {
+    fn is_multi_line(&self) -> bool { false }
+    fn get_type_uuid(&self) -> Option<Uuid> { None }
+    fn serialize_binary(&self, io: &mut dyn Write) {
+        self.guid.serialize_binary(io);
+    }
+    fn deserialize_binary(&mut self, io: &mut dyn Read) {
+        let mut guid = Uuid::nil();
+        guid.deserialize_binary(io);
+        *self = Self::load(guid, true);
+    }
+    fn serialize_text(&self, io: &mut crate::reflection::SerializeTextWriter, indent: String) {
+        self.guid.serialize_text(io, indent.clone());
+    }
+    fn deserialize_text(&mut self, yaml: &Yaml) {
+        // hand written yaml may reference the asset by path
+        let mut reference = String::new();
+        reference.deserialize_text(yaml);
+        let guid = Self::resolve_reference(&reference).unwrap_or_else(|e| {
+            log_warn!("unresolved reference {} : {}", reference, e);
+            Uuid::nil()
+        });
+        *self = Self::load(guid, false);
+    }
+}
+// list of references, one guid per line
+impl<T: Resource> Serializable for Vec<ExtRes<T>> {
+    fn is_multi_line(&self) -> bool { !self.is_empty() }
+    fn get_type_uuid(&self) -> Option<Uuid> { None }
+    fn serialize_binary(&self, io: &mut dyn Write) {
+        (self.len() as i64).serialize_binary(io);
+        for v in self.iter() {
+            v.serialize_binary(io);
+        }
+    }
+    fn deserialize_binary(&mut self, io: &mut dyn Read) {
+        let mut len: i64 = 0;
+        len.deserialize_binary(io);
+        for _i in 0..len {
+            let mut item = ExtRes::<T>::default();
+            item.deserialize_binary(io);
+            self.push(item);
+        }
+    }
+    fn serialize_text(&self, io: &mut crate::reflection::SerializeTextWriter, indent: String) {
+        if self.is_empty() {
+            let _ = io.write_all("[]".as_bytes());
+            return
+        }
+        for item in self.iter() {
+            let _ = io.write_all(format!("{}- ", indent).as_bytes());
+            item.serialize_text(io, indent.clone() + "  ");
+            io.newline();
+        }
+    }
+    fn deserialize_text(&mut self, data: &Yaml) {
+        for yaml in data.as_vec().unwrap() {
+            let mut item = ExtRes::<T>::default();
+            item.deserialize_text(yaml);
+            self.push(item);
         }
     }
 }
-impl_ext_ref!(RenderObject);
-impl_ext_ref!(Material);
-impl_ext_ref!(Texture);
-impl_ext_ref!(Skeleton);
 impl<T> ExtRes<T> {
-    pub fn load_ext_res(&mut self, bin:bool, resmap_rw: &RwLock<HashMap<String, Rc<T>>>) -> Rc<T>
-    where T : Default + Serializable + ExtSerializable<T>
-    {
-        {
-            let resmap = resmap_rw.read().unwrap();
-            let res_opt = resmap.get(&self.path);
-            if res_opt.is_some() {
-                return res_opt.unwrap().clone()
+    pub fn guid(&self) -> &Uuid {
+        &self.guid
+    }
+    pub fn is_null(&self) -> bool {
+        self.guid.is_nil()
+    }
+    pub fn state(&self) -> LoadState {
+        self.handle.state()
+    }
+    pub fn is_loaded(&self) -> bool {
+        self.handle.state() == LoadState::Loaded
+    }
+    pub fn get(&self) -> Rc<T> {
+        self.handle.value()
+    }
+    pub fn handle(&self) -> &Rc<ResHandle<T>> {
+        &self.handle
+    }
+    // fired on the main thread once loaded or failed
+    pub fn on_complete<F: FnOnce(LoadState) + 'static>(&self, f: F) {
+        self.handle.on_complete(Box::new(f));
+    }
+}
+// source file, or its cooked binary
+pub fn res_path(guid: &Uuid, bin: bool) -> Option<PathBuf> {
+    let db = asset_db().read().unwrap();
+    let rel = db.path_of(guid)?;
+    // vfs path, loose project files or a mounted pack
+    let path = PathBuf::from(rel);
+    if bin { Some(sibling_path(&path, BINARY_EXT)) } else { Some(path) }
+}
+impl<T: Resource> ExtRes<T> {
+    pub fn resolve_reference(reference: &str) -> Result<Uuid, String> {
+        if reference.is_empty() {
+            return Ok(Uuid::nil())
+        }
+        if let Ok(guid) = Uuid::from_str(reference) {
+            return Ok(guid)
+        }
+        let mut db = asset_db().write().unwrap();
+        let type_name = res_mgr().registry.name_of::<T>();
+        if Path::new(reference).extension().is_none() {
+            // first registered extension with an existing file
+            let candidates: Vec<String> = res_mgr().registry.extensions_of::<T>().iter()
+                .map(|ext| format!("{}.{}", reference, ext)).collect();
+            let found = candidates.iter().find(|c| db.guid_of(c).is_some()
+                || db.relative(Path::new(c)).is_ok_and(|rel| vfs().read().unwrap().exists(Path::new(&rel))));
+            return db.import_typed(found.or(candidates.first()).map_or(reference, |c| c.as_str()), type_name)
+        }
+        db.import_typed(reference, type_name)
+    }
+    // synchronous, except while a background load builds its resource,
+    // then nested references become async dependencies of it.
+    // a reference closing a dependency cycle fails the resource being built
+    pub fn load(guid: Uuid, bin: bool) -> Self {
+        if !guid.is_nil() {
+            if let Some(chain) = dependency_cycle(guid, &res_mgr().loader) {
+                fail_build(format!("dependency cycle {}", format_chain(&chain)));
+                return Self::default()
+            }
+            if let Some(parent) = add_build_reference(guid) {
+                res_mgr().hot_reload.borrow_mut().add_dependency(parent, guid);
             }
         }
-        // resource not found, load
-        let mut obj = T::default();
-        let mut resmap = resmap_rw.write().unwrap();
-        // w-locked, try again
-        let res_opt = resmap.get(&self.path);
-        if res_opt.is_some() {
-            return res_opt.unwrap().clone()
-        }
-        {
-            let respath = if bin { self.path.clone() + T::text_ext() } else { self.path.clone() + ".bin" };
-            if bin {
-                let mut file = BufReader::new(File::open(respath).unwrap());
-                obj.deserialize_binary(&mut file);
-            } else {
-                T::deserialize_from_text_file(&mut obj, &respath);
+        if is_building_async() { Self::load_async(guid, bin) } else { Self::load_sync(guid, bin) }
+    }
+    pub fn load_sync(guid: Uuid, bin: bool) -> Self {
+        if guid.is_nil() {
+            return Self::default()
+        }
+        let cached = T::res_map().read().unwrap().get(&guid).cloned();
+        if let Some(handle) = cached.as_ref() {
+            if handle.state() != LoadState::Pending {
+                res_mgr().residency.borrow_mut().touch(&guid);
+                return Self::with_handle(guid, handle.clone())
             }
         }
-        let refer: Rc<T> = Rc::from(obj);
-        resmap.insert(self.path.clone(), refer.clone());
-        refer
+        let registered = cached.is_some();
+        // not loaded yet, or still in flight : load now, the background result will be dropped
+        let handle = cached.unwrap_or_else(|| Rc::new(ResHandle::pending(Rc::new(T::default()))));
+        handle.complete(Self::build_sync(guid, bin));
+        T::res_map().write().unwrap().insert(guid, handle.clone());
+        if !registered {
+            res_mgr().residency.borrow_mut().register(guid, resident_ops::<T>());
+        }
+        Self::watch(guid, &handle);
+        Self::with_handle(guid, handle)
+    }
+    fn build_sync(guid: Uuid, bin: bool) -> Result<Rc<T>, String> {
+        let respath = res_path(&guid, bin).ok_or_else(|| format!("asset {} not found in asset database", guid))?;
+        let cache = res_mgr().registry.cache::<T>();
+        let src = (cache.reader())(&respath, bin)?;
+        let build = cache.builder();
+        let mut obj = T::default();
+        build_in_context(guid, false, || build(&mut obj, src)).result?;
+        Ok(Rc::new(obj))
+    }
+    // reload from the source file when it changes, the handle is shared so every ExtRes sees the new value
+    // a cooked binary is stale once its source changed, the source is watched and rebuilt from
+    fn watch(guid: Uuid, handle: &Rc<ResHandle<T>>) {
+        let Some(path) = res_path(&guid, false) else { return };
+        let weak: Weak<ResHandle<T>> = Rc::downgrade(handle);
+        let type_name = res_mgr().registry.name_of::<T>();
+        res_mgr().hot_reload.borrow_mut().watch(guid, path, type_name, Rc::new(move || {
+            let Some(handle) = weak.upgrade() else { return Ok(false) };
+            let old = handle.replace(Self::build_sync(guid, false)?);
+            res_mgr().hot_reload.borrow_mut().retire(old);
+            Ok(true)
+        }));
+    }
+    // file IO and parsing happen on worker threads, the value is available
+    // after a later ResourceMgr::update, see state()/on_complete()
+    pub fn load_async(guid: Uuid, bin: bool) -> Self {
+        if guid.is_nil() {
+            return Self::default()
+        }
+        let cached = T::res_map().read().unwrap().get(&guid).cloned();
+        let handle = match cached {
+            Some(handle) => {
+                res_mgr().residency.borrow_mut().touch(&guid);
+                handle
+            },
+            None => {
+                let handle = Rc::new(ResHandle::pending(Rc::new(T::default())));
+                T::res_map().write().unwrap().insert(guid, handle.clone());
+                let target = handle.clone();
+                let cache = res_mgr().registry.cache::<T>();
+                let build = cache.builder();
+                let builder: Builder = Box::new(move |src| build_async(guid, target, src, build));
+                let path = res_path(&guid, bin).unwrap_or_default();
+                res_mgr().loader.borrow_mut().submit(path, bin, cache.reader(), builder);
+                res_mgr().residency.borrow_mut().register(guid, resident_ops::<T>());
+                Self::watch(guid, &handle);
+                handle
+            },
+        };
+        if is_building_async() {
+            add_build_dependency(guid, handle.clone());
+        }
+        Self::with_handle(guid, handle)
+    }
+}
+impl<T> Drop for ExtRes<T> {
+    fn drop(&mut self) {
+        let last = self.handle.remove_user() == 0 && !self.guid.is_nil();
+        unsafe { ManuallyDrop::drop(&mut self.handle); }
+        if last {
+            res_mgr().release(&self.guid);
+        }
     }
 }
-impl<T> Deref for ExtRes<T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        self.value.as_ref()
+
+//// exports
+
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_update() {
+    res_mgr().update();
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_progress() -> f32 {
+    res_mgr().progress().fraction()
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_pending_count() -> u32 {
+    res_mgr().progress().pending()
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_reset_progress() {
+    res_mgr().reset_progress();
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_set_budget(bytes: u64) {
+    res_mgr().set_budget(bytes as usize);
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_collect() -> u32 {
+    res_mgr().collect() as u32
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_resident_bytes() -> u64 {
+    res_mgr().stats().total_bytes as u64
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_set_hot_reload(enabled: bool) {
+    res_mgr().set_hot_reload(enabled);
+}
+// callback receives the guid string of each reloaded resource and whether only a dependency changed
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_add_reload_callback(cb: extern "C" fn(*const c_char, bool)) -> u64 {
+    res_mgr().add_reload_listener(move |e| {
+        let guid = CString::new(e.guid.to_string()).unwrap();
+        cb(guid.as_ptr(), e.cause == ReloadCause::DependencyChanged);
+    })
+}
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_remove_reload_callback(id: u64) {
+    res_mgr().remove_reload_listener(id);
+}
+// see ResourceMgr::import_source, false on failure (logged)
+#[no_mangle]
+pub extern "C"
+fn ResourceMgr_import(path: *const c_char) -> bool {
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return false };
+    match res_mgr().import_source(path) {
+        Ok(_) => true,
+        Err(e) => {
+            log_error!("import failed : {}", e);
+            false
+        },
     }
 }
@@ -0,0 +1,134 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::RwLock;
+use uuid::Uuid;
+use crate::data::blob::serialize_binary_file;
+use crate::data::loader::{build_in_context, ResHandle, SourceBuilder, SourceReader};
+use crate::data::{res_mgr, Resource};
+
+/**
+ * Resource types known to ResourceMgr, keyed by TypeId.
+ *
+ * each registered type owns its cache and loader (source reader + builder, by default the
+ * ones of its ExtSerializable impl) and the source file extensions it is imported from.
+ * types from plugins register at runtime, ExtRes<T> works for any registered T.
+ */
+pub type ResMap<T> = RwLock<HashMap<Uuid, Rc<ResHandle<T>>>>;
+
+pub struct ResCache<T> {
+    pub map: ResMap<T>,
+    loader: RwLock<(SourceReader, SourceBuilder<T>)>,
+}
+impl<T> ResCache<T> {
+    pub fn reader(&self) -> SourceReader {
+        self.loader.read().unwrap().0
+    }
+    pub fn builder(&self) -> SourceBuilder<T> {
+        self.loader.read().unwrap().1
+    }
+}
+
+pub struct ResourceType {
+    pub type_id: TypeId,
+    pub type_uuid: Option<Uuid>,
+    pub name: &'static str,
+    // source extensions without the dot, the first one is used for new assets
+    pub extensions: Vec<String>,
+    // Box<ResCache<T>>, never removed so references to it stay valid
+    cache: Box<dyn Any>,
+    cook: CookFn,
+    import: ImportFn,
+}
+// imports the source file and writes the binary form, returns the referenced assets
+pub type CookFn = fn(&Uuid, &Path, &Path) -> Result<Vec<Uuid>, String>;
+// writes the assets embedded in the source file, see ExtSerializable::import_source
+pub type ImportFn = fn(&Path) -> Result<(), String>;
+fn cook_resource<T: Resource>(guid: &Uuid, source: &Path, cooked: &Path) -> Result<Vec<Uuid>, String> {
+    T::import_source(source)?;
+    let cache = res_mgr().registry.cache::<T>();
+    let src = (cache.reader())(source, false)?;
+    let build = cache.builder();
+    let mut obj = T::default();
+    let built = build_in_context(*guid, false, || build(&mut obj, src));
+    built.result?;
+    serialize_binary_file(&obj, &cooked.to_string_lossy()).map_err(|e| format!("{}: {}", cooked.display(), e))?;
+    Ok(built.refs)
+}
+
+#[derive(Default)]
+pub struct ResourceRegistry {
+    types: RwLock<HashMap<TypeId, ResourceType>>,
+}
+impl ResourceRegistry {
+    // register with the loader and extension of T's ExtSerializable impl
+    pub fn register<T: Resource>(&self) {
+        self.register_with::<T>(&T::source_exts(), T::read_source, T::build_from_source);
+    }
+    // registering again replaces the loader and extensions, cached resources are kept
+    pub fn register_with<T: Resource>(&self, extensions: &[&str], read: SourceReader, build: SourceBuilder<T>) {
+        let mut types = self.types.write().unwrap();
+        let extensions: Vec<String> = extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect();
+        if let Some(t) = types.get_mut(&TypeId::of::<T>()) {
+            let cache = t.cache.downcast_ref::<ResCache<T>>().unwrap();
+            *cache.loader.write().unwrap() = (read, build);
+            t.extensions = extensions;
+            return
+        }
+        types.insert(TypeId::of::<T>(), ResourceType {
+            type_id: TypeId::of::<T>(),
+            type_uuid: T::default().get_type_uuid(),
+            name: std::any::type_name::<T>().rsplit("::").next().unwrap_or_default(),
+            extensions,
+            cache: Box::new(ResCache::<T> { map: RwLock::new(HashMap::new()), loader: RwLock::new((read, build)) }),
+            cook: cook_resource::<T>,
+            import: T::import_source,
+        });
+    }
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.types.read().unwrap().contains_key(&TypeId::of::<T>())
+    }
+    pub fn cache<T: Resource>(&self) -> &ResCache<T> {
+        let types = self.types.read().unwrap();
+        let t = types.get(&TypeId::of::<T>())
+            .unwrap_or_else(|| panic!("resource type {} is not registered", std::any::type_name::<T>()));
+        let cache = t.cache.downcast_ref::<ResCache<T>>().unwrap();
+        // the boxed cache is never dropped or moved while the registry lives
+        unsafe { &*(cache as *const ResCache<T>) }
+    }
+    pub fn name_of<T: 'static>(&self) -> &'static str {
+        self.types.read().unwrap().get(&TypeId::of::<T>()).map_or("", |t| t.name)
+    }
+    pub fn extensions_of<T: 'static>(&self) -> Vec<String> {
+        self.types.read().unwrap().get(&TypeId::of::<T>()).map_or(Vec::new(), |t| t.extensions.clone())
+    }
+    // resource types imported from files with this extension
+    pub fn types_for_extension(&self, ext: &str) -> Vec<TypeId> {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        self.types.read().unwrap().values().filter(|t| t.extensions.contains(&ext)).map(|t| t.type_id).collect()
+    }
+    pub fn type_for_name(&self, name: &str) -> Option<TypeId> {
+        self.types.read().unwrap().values().find(|t| t.name == name).map(|t| t.type_id)
+    }
+    pub fn type_name(&self, type_id: TypeId) -> Option<&'static str> {
+        self.types.read().unwrap().get(&type_id).map(|t| t.name)
+    }
+    // offline import of a source file of the given type into its cooked binary
+    pub fn cook(&self, type_id: TypeId, guid: &Uuid, source: &Path, cooked: &Path) -> Result<Vec<Uuid>, String> {
+        let cook = self.types.read().unwrap().get(&type_id).map(|t| t.cook).ok_or("resource type is not registered")?;
+        cook(guid, source, cooked)
+    }
+    pub fn import_source(&self, type_id: TypeId, source: &Path) -> Result<(), String> {
+        let import = self.types.read().unwrap().get(&type_id).map(|t| t.import).ok_or("resource type is not registered")?;
+        import(source)
+    }
+    pub fn type_for_uuid(&self, type_uuid: &Uuid) -> Option<TypeId> {
+        self.types.read().unwrap().values().find(|t| t.type_uuid.as_ref() == Some(type_uuid)).map(|t| t.type_id)
+    }
+    pub fn type_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.types.read().unwrap().values().map(|t| t.name).collect();
+        names.sort();
+        names
+    }
+}
@@ -3,17 +3,24 @@ use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::ops::Deref;
-use gltf::{Accessor, Document, Semantic};
+use std::path::Path;
+use gltf::{Accessor, Semantic};
 use nalgebra::{*};
-use base64::prelude::*;
 use gltf::accessor::Dimensions;
 use gltf::mesh::Mode;
 use gltf_json::accessor::Type;
 use eldra_macro::{Reflection};
-use crate::data::{ExtRes, ExtSerializable};
+use crate::data::{build_serialized, ExtRes, ExtSerializable};
+use crate::data::loader::{read_binary_source, SourceData};
+use crate::data::blob::Blob;
+use crate::data::bounds::{Aabb, BoundingSphere};
+use crate::data::animation_clip::AnimationClip;
+use crate::data::gltf_export::GltfExport;
+use crate::data::gltf_import::GltfImport;
 use crate::data::material::Material;
+use crate::data::skeleton::Skeleton;
 use crate::{impl_option_embed_serialize, impl_vec_embed_serialize};
-use crate::reflection::{Serializable};
+use crate::reflection::{Serializable, Vec3f, Vec4f};
 use yaml_rust2::Yaml;
 use crate::data::render_object::BufferType::{INDEX, VERTEX};
 
@@ -30,12 +37,6 @@ pub enum BufferType
     INDEX = 1,
 }
 impl BufferType {
-    fn to_gltf(&self) -> i32 {
-        match self {
-            BufferType::VERTEX => 34962,// ARRAY_BUFFER
-            BufferType::INDEX => 34963, // ELEMENT_ARRAY_BUFFER
-        }
-    }
     fn decode_gltf(&mut self, v: i32) {
         match v {
             34962 => { *self = BufferType::VERTEX; },
@@ -73,16 +74,6 @@ pub enum ComponentType {
     S16 = 4, U16 = 5,
 }
 impl ComponentType {
-    fn to_gltf(&self) -> i32 {
-        match self {
-            ComponentType::S8 => 5120,
-            ComponentType::U8 => 5121,
-            ComponentType::S16 => 5122,
-            ComponentType::U16 => 5123,
-            ComponentType::U32 => 5125,
-            ComponentType::F32 => 5126,
-        }
-    }
     fn decode_gltf(&mut self, v: gltf_json::accessor::ComponentType) {
         match v {
             gltf_json::accessor::ComponentType::I8 => { *self = ComponentType::S8; },
@@ -137,9 +128,30 @@ pub struct BufferView
     pub component_type: ComponentType,
     #[serialize]
     pub normalized: bool,
+    #[serialize]
+    pub sparse: Option<SparseView>,
 }
 impl_vec_embed_serialize!(BufferView);
 impl_option_embed_serialize!(BufferView);
+impl BufferView {
+    // false for a sparse view without base data, which reads zeros outside of its sparse values
+    pub fn has_data(&self) -> bool {
+        self.byte_size > 0
+    }
+}
+// elements replacing those of the base view, values tightly packed in the view format
+#[derive(Reflection,Default)]
+pub struct SparseView {
+    #[serialize]
+    pub count: u32,
+    #[serialize]
+    pub indices_offset: u32,
+    #[serialize]
+    pub index_type: ComponentType,
+    #[serialize]
+    pub values_offset: u32,
+}
+impl_option_embed_serialize!(SparseView);
 #[derive(Reflection,Default)]
 pub struct SkinDataVec4 {
     #[serialize]
@@ -148,6 +160,35 @@ pub struct SkinDataVec4 {
     pub weights: BufferView,
 }
 impl_vec_embed_serialize!(SkinDataVec4);
+// per vertex displacements, VEC3 F32
+#[derive(Reflection,Default)]
+pub struct MorphTarget {
+    #[serialize]
+    pub position: Option<BufferView>,
+    #[serialize]
+    pub normal: Option<BufferView>,
+    #[serialize]
+    pub tangent: Option<BufferView>,
+}
+impl_vec_embed_serialize!(MorphTarget);
+// positions and normals after morphing
+#[derive(Default,Debug)]
+pub struct MorphedVertices {
+    pub positions: Vec<Vec3f>,
+    pub normals: Vec<Vec3f>,
+}
+// coarser indices over the vertices of a primitive, see RenderObject::generate_lods
+#[derive(Reflection,Default)]
+pub struct PrimitiveLod {
+    #[serialize]
+    pub indice: BufferView,
+    // drawn while the projected size is at most this
+    #[serialize]
+    pub screen_size: f32,
+    #[serialize]
+    pub error: f32,
+}
+impl_vec_embed_serialize!(PrimitiveLod);
 #[derive(Reflection,Default)]
 pub struct Primitive {
     #[serialize]
@@ -162,6 +203,8 @@ pub struct Primitive {
     pub color: Vec<BufferView>,
     #[serialize]
     pub skin: Vec<SkinDataVec4>,
+    #[serialize]
+    pub targets: Vec<MorphTarget>,
 
     #[serialize]
     pub indice: Option<BufferView>,
@@ -169,8 +212,28 @@ pub struct Primitive {
     pub material: ExtRes<Material>,
     #[serialize]
     pub mode: PrimitiveMode,
+    // of the positions, see RenderObject::compute_bounds
+    #[serialize]
+    pub aabb: Aabb,
+    #[serialize]
+    pub sphere: BoundingSphere,
+    // from the finest, thresholds decreasing
+    #[serialize]
+    pub lods: Vec<PrimitiveLod>,
 }
 impl_vec_embed_serialize!(Primitive);
+impl Primitive {
+    // 0 is the primitive itself, the coarsest LOD whose threshold the projected size is under
+    pub fn select_lod(&self, screen_size: f32) -> usize {
+        self.lods.iter().rposition(|l| screen_size <= l.screen_size).map_or(0, |i| i + 1)
+    }
+    pub fn lod_indices(&self, lod: usize) -> Option<&BufferView> {
+        match lod {
+            0 => self.indice.as_ref(),
+            _ => self.lods.get(lod - 1).map(|l| &l.indice),
+        }
+    }
+}
 #[derive(Reflection,Default)]
 pub struct RenderPart
 {
@@ -178,29 +241,38 @@ pub struct RenderPart
     pub name: String,
     #[serialize]
     pub primitives: Vec<Primitive>,
+    // joints the JOINTS_n attributes index into, null if the part is not skinned
+    #[serialize]
+    pub skeleton: ExtRes<Skeleton>,
+    // morph target weights when not animated, one per target of the primitives
+    #[serialize]
+    pub weights: Vec<f32>,
 }
 impl_vec_embed_serialize!(RenderPart);
 #[derive(Reflection,Default)]
 pub struct RenderObject
 {
     #[serialize]
-    pub buffer: Vec<u8>,
+    pub buffer: Blob,
     #[serialize]
     pub parts: Vec<RenderPart>,
+    // clips of the glTF file
+    #[serialize]
+    pub animations: Vec<ExtRes<AnimationClip>>,
 }
 impl_vec_embed_serialize!(RenderObject);
 
 #[derive(Clone,Copy,PartialEq)]
-enum BufferLoopOrder
+pub(crate) enum BufferLoopOrder
 {
     POS, NORMAL, TANGENT, TEXCOORD, COLOR, SKIN
 }
 #[derive(Clone)]
-struct BViewInfo<'a> {
+pub(crate) struct BViewInfo<'a> {
     pub buffer_view: &'a BufferView,
     pub attr_name: String,
 }
-struct BViewIterator<'a> {
+pub(crate) struct BViewIterator<'a> {
     pub curr_mesh: &'a Primitive,
     pub buffer_loop_order: BufferLoopOrder, // pos(0)->normal(1)->tangent(2)->texcoord(3)->color(4)->skin(5)
     pub buffer_vec_index: isize, // index in texcoord_n(3)/color_n(4)/skin_n(5)
@@ -316,7 +388,7 @@ impl<'a> Iterator for BViewIterator<'a> {
     }
 }
 impl Primitive {
-    fn vertex_iter(&self) -> BViewIterator {
+    pub(crate) fn vertex_iter(&self) -> BViewIterator<'_> {
         BViewIterator {
             curr_mesh: self,
             buffer_loop_order: BufferLoopOrder::POS,
@@ -328,10 +400,30 @@ impl Primitive {
 
 impl ExtSerializable<RenderObject> for RenderObject {
     fn text_ext() -> &'static str { "gltf" }
-    fn deserialize_from_text_file(res: &mut RenderObject, respath: &String) {
-        let result = gltf::import(respath);
-        let (document, buffers, images) = result.unwrap();
-        res.read_gltf(&document, &buffers, &images);
+    fn source_exts() -> Vec<&'static str> { vec!["gltf", "glb"] }
+    fn read_source(respath: &Path, bin: bool) -> Result<SourceData, String> {
+        if bin {
+            return read_binary_source(respath)
+        }
+        Ok(SourceData::Custom(Box::new(GltfImport::open(respath)?)))
+    }
+    fn build_from_source(res: &mut RenderObject, src: SourceData) -> Result<(), String> {
+        match src {
+            SourceData::Custom(data) => {
+                let import = *data.downcast::<GltfImport>().unwrap();
+                res.read_gltf(&import)
+            },
+            src => build_serialized(res, src),
+        }
+    }
+    // vertex/index buffer plus the primitive descriptions
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<RenderObject>() + self.buffer.len() + self.parts.iter()
+            .map(|p| std::mem::size_of::<RenderPart>() + p.primitives.len() * std::mem::size_of::<Primitive>()).sum::<usize>()
+    }
+    // materials, textures, skeletons and animations are separate assets, see GltfImport
+    fn import_source(respath: &Path) -> Result<(), String> {
+        GltfImport::open(respath)?.extract()
     }
 }
 impl RenderObject {
@@ -339,150 +431,79 @@ impl RenderObject {
         Box::new(RenderObject::default())
     }
 
-    pub fn write_gltf(&self, io: &mut dyn Write) {
-        // header
-        {
-            let _ = io.write(r#"{
-    "asset": {
-        "generator": "Eldra",
-        "version": "2.0"
-    },"#.as_bytes());
-        }
+    // one scene node per part, see GltfExport
+    pub fn write_gltf(&self, io: &mut dyn Write) -> Result<(), String> {
+        let mut export = GltfExport::new();
+        export.add_object_nodes(self)?;
+        export.write_gltf(io)
+    }
+    pub fn write_glb(&self, io: &mut dyn Write) -> Result<(), String> {
+        let mut export = GltfExport::new();
+        export.add_object_nodes(self)?;
+        export.write_glb(io)
+    }
 
-        // buffer
-        {
-            let _ = io.write(format!(r#"
-    "buffers": [
-        {{
-            "byteLength": {},
-            "uri": "data:application/octet-stream;base64,{}"
-        }}
-    ],"#, self.buffer.len(), BASE64_STANDARD.encode(self.buffer.as_slice())).as_bytes());
+    // joint indices of a VEC4 U8/U16 view
+    pub fn read_joints(&self, view: &BufferView) -> Result<Vec<[u16; 4]>, String> {
+        if !matches!(view.component_type, ComponentType::U8 | ComponentType::U16) {
+            return Err("invalid joint component type, U8 or U16 expected".to_string())
         }
-
-        // buffer view
-        {
-            let _ = io.write(format!(r#"
-    "bufferViews": ["#).as_bytes());
-            for part in self.parts.iter() {
-                for prim in part.primitives.iter() {
-                    match prim.indice.as_ref() {
-                        Some(view) => {
-                            let _ = io.write(format!(r#"
-        "{{
-            "buffer": 0,
-            "byteOffset": {},
-            "byteLength": {},
-            "byteStride": {},
-            "target": {},
-        }},"#,              view.offset, view.byte_size, view.vertex_stride,
-                            view.buffer_type.to_gltf()).as_bytes());
-                        }
-                        None => {}
-                    }
-                    for info in prim.vertex_iter() {
-                        let view = info.buffer_view;
-                        let _ = io.write(format!(r#"
-        "{{
-            "buffer": 0,
-            "byteOffset": {},
-            "byteLength": {},
-            "byteStride": {},
-            "target": {},
-        }},"#,          view.offset, view.byte_size, view.vertex_stride,
-                        view.buffer_type.to_gltf()).as_bytes());
-                    }
-                }
-            }
-            let _ = io.write(format!(r#"
-    "],"#).as_bytes());
+        let joints = self.accessor(view).and_then(|a| a.read::<Vec4f>())?;
+        Ok(joints.iter().map(|j| [j.x as u16, j.y as u16, j.z as u16, j.w as u16]).collect())
+    }
+    // VEC3 view, positions, normals or morph displacements
+    pub fn read_vec3(&self, view: &BufferView) -> Result<Vec<Vec3f>, String> {
+        if !matches!(view.data_type, DataType::VEC3) {
+            return Err("invalid vec3 view".to_string())
         }
-
-        // accessors
-        {
-            let mut view_index = 0;
-            let _ = io.write(format!(r#"
-    "accessors": ["#).as_bytes());
-            for part in self.parts.iter() {
-                for prim in part.primitives.iter() {
-                    match prim.indice.as_ref() {
-                        Some(view) => {
-                            let _ = io.write(format!(r#"
-        "{{
-            "bufferView": {},
-            "byteOffset": 0,
-            "componentType": {},
-            "count": {},
-            "type": "{}",
-        }},"#,              view_index, view.component_type.to_gltf(),
-                            view.count, view.data_type.to_string()).as_bytes());
-                            view_index += 1;
-                        },
-                        None => {},
-                    };
-                    for info in prim.vertex_iter() {
-                        let view = info.buffer_view;
-                        let _ = io.write(format!(r#"
-        "{{
-            "bufferView": {},
-            "byteOffset": 0,
-            "componentType": {},
-            "count": {},
-            "type": "{}",
-        }},"#,          view_index, view.component_type.to_gltf(),
-                        view.count, view.data_type.to_string()).as_bytes());
-                        view_index += 1;
-                    }
-                }
+        self.accessor(view).and_then(|a| a.read::<Vec3f>())
+    }
+    // weighted sum of the target displacements, weights past the targets are ignored
+    pub fn morph(&self, prim: &Primitive, weights: &[f32]) -> Result<MorphedVertices, String> {
+        let mut positions = self.read_vec3(&prim.position)?;
+        let mut normals = prim.normal.as_ref().map(|n| self.read_vec3(n)).transpose()?.unwrap_or_default();
+        for (target, w) in prim.targets.iter().zip(weights.iter()).filter(|(_, w)| **w != 0.0) {
+            if let Some(view) = &target.position {
+                positions.iter_mut().zip(self.read_vec3(view)?).for_each(|(p, d)| *p += d * *w);
+            }
+            if let (Some(view), false) = (&target.normal, normals.is_empty()) {
+                normals.iter_mut().zip(self.read_vec3(view)?).for_each(|(n, d)| *n += d * *w);
             }
-            let _ = io.write(format!(r#"
-    "],"#).as_bytes());
         }
-
-        // meshes
-        {
-            let mut view_index = 0;
-            let _ = io.write(format!(r#"
-    "meshes": ["#).as_bytes());
-            for part in self.parts.iter() {
-                let _ = io.write(format!(r#"
-    {{
-        "name": "{}",
-        "primitives": ["#, part.name).as_bytes());
-                for prim in part.primitives.iter() {
-                    let _ = io.write(r#"
-        {{"#.as_bytes());
-                    match prim.indice.as_ref() {
-                        Some(_view) => {
-                            let _ = io.write(format!(r#"
-            "indices": {},
-            "mode": {},
-            "attributes": {{
-            "#,             view_index, prim.mode.to_i32()).as_bytes());
-                            view_index += 1;
-                        },
-                        None => {},
-                    }
-                    for info in prim.vertex_iter() {
-                        let _view = info.buffer_view;
-                        let _ = io.write(format!(r#"
-                "{}": {},"#, info.attr_name, view_index).as_bytes());
-                        view_index += 1;
-                    }
-                    let _ = io.write(r#"
-            }},
-        }},"#.as_bytes());
+        normals.iter_mut().for_each(|n| { n.try_normalize_mut(0.0); });
+        Ok(MorphedVertices { positions, normals })
+    }
+    // every joint index of the part is below joint_count
+    pub fn validate_skin(&self, part: &RenderPart, joint_count: usize) -> Result<(), String> {
+        for (p, prim) in part.primitives.iter().enumerate() {
+            for (s, skin) in prim.skin.iter().enumerate() {
+                let end = skin.joints.offset as usize + skin.joints.byte_size as usize;
+                if end > self.buffer.len() {
+                    return Err(format!("part {} primitive {} JOINTS_{} is out of the buffer", part.name, p, s))
+                }
+                let joints = self.read_joints(&skin.joints).map_err(|e| format!("part {} primitive {} JOINTS_{}: {}", part.name, p, s, e))?;
+                if let Some(j) = joints.iter().flatten().find(|&&j| j as usize >= joint_count) {
+                    return Err(format!("part {} primitive {} JOINTS_{} references joint {} of {}", part.name, p, s, j, joint_count))
                 }
-                let _ = io.write(format!(r#"
-        "],"#).as_bytes());
             }
-            let _ = io.write(format!(r#"
-    "],"#).as_bytes());
         }
+        Ok(())
     }
 
-    pub fn read_gltf(&mut self, doc:&Document,
-             buffers:&Vec<gltf::buffer::Data>, images:&Vec<gltf::image::Data>) {
+    // the assets extracted from the file must exist, see GltfImport::extract
+    pub fn read_gltf(&mut self, import: &GltfImport) -> Result<(), String> {
+        let doc = &import.document;
+        let buffers = &import.buffers;
+        let materials = import.materials()?;
+        let skeletons = import.skeletons()?;
+        self.animations = import.animations()?;
+        // skin of the first node instancing each mesh
+        let mut mesh_skins: Vec<Option<gltf::Skin>> = vec![None; doc.meshes().len()];
+        for node in doc.nodes() {
+            if let (Some(mesh), Some(skin)) = (node.mesh(), node.skin()) {
+                mesh_skins[mesh.index()].get_or_insert(skin);
+            }
+        }
         let mut buffer_offset_fix:Vec<u32> = Vec::new();
         {
             let mut offset = 0_u32;
@@ -494,26 +515,48 @@ impl RenderObject {
                 self.buffer.extend_from_slice(data);
             }
         }
+        let buffer_offset = |gltf_view: &gltf::buffer::View, offset: usize| {
+            (gltf_view.offset() + offset) as u32 + buffer_offset_fix[gltf_view.buffer().index()]
+        };
         let fn_view_from_accessor = |accessor:Accessor, indice:bool| -> BufferView {
-            let gltf_view = accessor.view().unwrap();
             let mut view = BufferView::default();
             view.count = accessor.count() as u32;
-            view.offset = (gltf_view.offset() + accessor.offset()) as u32 + buffer_offset_fix[gltf_view.index()];
+            // a sparse accessor may have no base view
+            if let Some(gltf_view) = accessor.view() {
+                view.offset = buffer_offset(&gltf_view, accessor.offset());
+                view.vertex_stride = gltf_view.stride().unwrap_or(0) as u32;
+                view.byte_size = gltf_view.length() as u32;
+            }
             view.component_type.decode_gltf(accessor.data_type());
             view.normalized = accessor.normalized();
-            view.vertex_stride = gltf_view.stride().unwrap_or(0) as u32;
-            view.byte_size = gltf_view.length() as u32;
             view.data_type.decode_gltf(accessor.dimensions());
+            if let Some(sparse) = accessor.sparse() {
+                let mut index_type = ComponentType::default();
+                index_type.decode_gltf(match sparse.indices().index_type() {
+                    gltf::accessor::sparse::IndexType::U8 => gltf_json::accessor::ComponentType::U8,
+                    gltf::accessor::sparse::IndexType::U16 => gltf_json::accessor::ComponentType::U16,
+                    gltf::accessor::sparse::IndexType::U32 => gltf_json::accessor::ComponentType::U32,
+                });
+                view.sparse = Some(SparseView {
+                    count: sparse.count() as u32,
+                    indices_offset: buffer_offset(&sparse.indices().view(), sparse.indices().offset()),
+                    index_type,
+                    values_offset: buffer_offset(&sparse.values().view(), sparse.values().offset()),
+                });
+            }
             view.buffer_type = if indice { INDEX } else { VERTEX };
             let _ = accessor.size();
             let _ = accessor.name();
             view
         };
         for gltf_mesh in doc.meshes() {
-            let mut part = RenderPart::default();
+            let mut part = RenderPart { name: gltf_mesh.name().unwrap_or_default().to_string(), ..Default::default() };
             for gltf_prim in gltf_mesh.primitives() {
                 let mut primitive = Primitive::default();
                 primitive.mode.decode_gltf(gltf_prim.mode());
+                if let Some(index) = gltf_prim.material().index() {
+                    primitive.material = materials[index].clone();
+                }
                 // indice
                 match gltf_prim.indices() {
                     Some(accessor) => { primitive.indice = Some(fn_view_from_accessor(accessor, true)); },
@@ -537,34 +580,47 @@ impl RenderObject {
                         Semantic::Weights(i) => { weight_map.insert(i, fn_view_from_accessor(accessor, false)); },
                     }
                 }
-                // sort vectors
+                // sort vectors, the sets are numbered from 0 without gaps
+                let missing = |name: &str, i: u32| format!("{}: mesh {} has no {}_{}", import.path.display(), gltf_mesh.index(), name, i);
                 for i in 0_u32..color_map.len() as u32 {
-                    primitive.color.push(color_map.remove(&i).unwrap());
+                    primitive.color.push(color_map.remove(&i).ok_or_else(|| missing("COLOR", i))?);
                 }
                 for i in 0_u32..texcoord_map.len() as u32 {
-                    primitive.texcoord.push(texcoord_map.remove(&i).unwrap());
+                    primitive.texcoord.push(texcoord_map.remove(&i).ok_or_else(|| missing("TEXCOORD", i))?);
                 }
                 for i in 0_u32..joint_map.len() as u32 {
                     primitive.skin.push(SkinDataVec4 {
-                        joints: joint_map.remove(&i).unwrap(),
-                        weights: weight_map.remove(&i).unwrap(),
+                        joints: joint_map.remove(&i).ok_or_else(|| missing("JOINTS", i))?,
+                        weights: weight_map.remove(&i).ok_or_else(|| missing("WEIGHTS", i))?,
                     });
                 }
+                for gltf_target in gltf_prim.morph_targets() {
+                    primitive.targets.push(MorphTarget {
+                        position: gltf_target.positions().map(|a| fn_view_from_accessor(a, false)),
+                        normal: gltf_target.normals().map(|a| fn_view_from_accessor(a, false)),
+                        tangent: gltf_target.tangents().map(|a| fn_view_from_accessor(a, false)),
+                    });
+                }
+                if let Ok(positions) = self.accessor(&primitive.position).and_then(|a| a.read::<Vec3f>()) {
+                    primitive.aabb = Aabb::from_points(&positions);
+                    primitive.sphere = BoundingSphere::from_points(&positions);
+                }
                 // gen part
                 part.primitives.push(primitive);
             }
-            self.parts.push(part);
-        }
-        for skin in doc.skins() {
-
-        }
-        {
-            // this is just for debug purpose of standalone viewer
-            for mat in doc.materials() {
-                let metallic_roughness = mat.pbr_metallic_roughness();
-                let specular_glossiness = mat.pbr_specular_glossiness();
+            let targets = part.primitives.iter().map(|p| p.targets.len()).max().unwrap_or(0);
+            part.weights = gltf_mesh.weights().map(|w| w.to_vec()).unwrap_or_default();
+            part.weights.resize(targets, 0.0);
+            if let Some(skin) = &mesh_skins[gltf_mesh.index()] {
+                part.skeleton = skeletons[skin.index()].clone();
+                self.validate_skin(&part, skin.joints().len()).map_err(|e| format!("{}: {}", import.path.display(), e))?;
             }
-            for tex in doc.images() {}
+            self.parts.push(part);
         }
+        Ok(())
+    }
+    // first skeleton of the parts, the one a RenderComponent animates
+    pub fn skeleton(&self) -> Option<&ExtRes<Skeleton>> {
+        self.parts.iter().map(|p| &p.skeleton).find(|s| !s.is_null())
     }
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use uuid::Uuid;
+use crate::data::loader::LoadState;
+
+/**
+ * Tracks what ResourceMgr keeps resident and frees what nobody uses anymore.
+ *
+ * an asset is referenced while an ExtRes (or a clone of its handle) is alive, once the last one
+ * is dropped it stays cached until the memory budget is exceeded, then unreferenced assets are
+ * evicted least recently used first. a budget of 0 frees assets as soon as the last user goes away.
+ */
+// type erased access to the ResourceMgr map holding the asset
+#[derive(Clone,Copy)]
+pub struct ResidentOps {
+    pub type_name: &'static str,
+    pub users: fn(&Uuid) -> usize,
+    pub bytes: fn(&Uuid) -> usize,
+    pub state: fn(&Uuid) -> Option<LoadState>,
+    // removes the asset from its map, the value is returned so it gets dropped outside any lock
+    pub evict: fn(&Uuid) -> Option<Rc<dyn Any>>,
+}
+
+struct Resident {
+    ops: ResidentOps,
+    last_used: u64,
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ResidentReason {
+    // used by at least one ExtRes
+    Referenced,
+    // unused, kept while within budget
+    Cached,
+    // still being loaded
+    Loading,
+}
+#[derive(Clone,Debug)]
+pub struct ResidentInfo {
+    pub guid: Uuid,
+    pub type_name: &'static str,
+    pub bytes: usize,
+    pub users: usize,
+    pub reason: ResidentReason,
+}
+#[derive(Default,Clone,Copy,Debug)]
+pub struct TypeStats {
+    pub count: usize,
+    pub bytes: usize,
+}
+#[derive(Default,Clone,Debug)]
+pub struct ResidencyStats {
+    pub budget: usize,
+    pub total_bytes: usize,
+    pub cached_bytes: usize,
+    pub per_type: BTreeMap<&'static str, TypeStats>,
+    pub resident: Vec<ResidentInfo>,
+}
+
+pub struct Residency {
+    pub budget: usize,
+    clock: u64,
+    entries: HashMap<Uuid, Resident>,
+}
+impl Default for Residency {
+    fn default() -> Self {
+        Residency { budget: usize::MAX, clock: 0, entries: HashMap::new() }
+    }
+}
+impl Residency {
+    pub fn register(&mut self, guid: Uuid, ops: ResidentOps) {
+        self.clock += 1;
+        self.entries.insert(guid, Resident { ops, last_used: self.clock });
+    }
+    pub fn touch(&mut self, guid: &Uuid) {
+        self.clock += 1;
+        if let Some(r) = self.entries.get_mut(guid) {
+            r.last_used = self.clock;
+        }
+    }
+    fn info(guid: &Uuid, r: &Resident) -> Option<ResidentInfo> {
+        let state = (r.ops.state)(guid)?;
+        let users = (r.ops.users)(guid);
+        let reason = if state == LoadState::Pending {
+            ResidentReason::Loading
+        } else if users > 0 {
+            ResidentReason::Referenced
+        } else {
+            ResidentReason::Cached
+        };
+        Some(ResidentInfo { guid: *guid, type_name: r.ops.type_name, bytes: (r.ops.bytes)(guid), users, reason })
+    }
+    pub fn stats(&self) -> ResidencyStats {
+        let mut stats = ResidencyStats { budget: self.budget, ..Default::default() };
+        for (guid, r) in self.entries.iter() {
+            let Some(info) = Self::info(guid, r) else { continue };
+            stats.total_bytes += info.bytes;
+            if info.reason == ResidentReason::Cached {
+                stats.cached_bytes += info.bytes;
+            }
+            let t = stats.per_type.entry(info.type_name).or_default();
+            t.count += 1;
+            t.bytes += info.bytes;
+            stats.resident.push(info);
+        }
+        stats.resident.sort_by_key(|info| std::cmp::Reverse(info.bytes));
+        stats
+    }
+    // unreferenced assets to evict, least recently used first, all of them if budget is None
+    fn victims(&mut self, budget: Option<usize>) -> Vec<Uuid> {
+        let mut total = 0usize;
+        let mut cached: Vec<(u64, Uuid, usize)> = Vec::new();
+        let mut gone: Vec<Uuid> = Vec::new();
+        for (guid, r) in self.entries.iter() {
+            match Self::info(guid, r) {
+                Some(info) => {
+                    total += info.bytes;
+                    if info.reason == ResidentReason::Cached {
+                        cached.push((r.last_used, *guid, info.bytes));
+                    }
+                },
+                None => gone.push(*guid),
+            }
+        }
+        gone.iter().for_each(|g| { self.entries.remove(g); });
+        cached.sort_by_key(|c| c.0);
+        let mut victims = Vec::new();
+        for (_, guid, bytes) in cached {
+            if budget.is_some_and(|b| total <= b) {
+                break
+            }
+            total -= bytes;
+            victims.push(guid);
+        }
+        victims
+    }
+}
+
+// evicts without holding the RefCell, dropping an asset may release the assets it references,
+// which re-enters here. returns how many assets were freed
+fn evict(residency: &RefCell<Residency>, all: bool) -> usize {
+    let mut freed = 0;
+    loop {
+        let victims: Vec<(Uuid, ResidentOps)> = {
+            let mut r = residency.borrow_mut();
+            let budget = if all { None } else { Some(r.budget) };
+            r.victims(budget).into_iter().filter_map(|g| r.entries.remove(&g).map(|e| (g, e.ops))).collect()
+        };
+        if victims.is_empty() {
+            return freed
+        }
+        for (guid, ops) in victims {
+            if (ops.evict)(&guid).is_some() {
+                freed += 1;
+            }
+        }
+    }
+}
+pub fn enforce_budget(residency: &RefCell<Residency>) -> usize {
+    evict(residency, false)
+}
+pub fn collect_unused(residency: &RefCell<Residency>) -> usize {
+    evict(residency, true)
+}
@@ -1,16 +1,144 @@
 use std::any::{Any, TypeId};
+use std::io::{Read, Write};
+use nalgebra::{Matrix3, Quaternion, Rotation3, Translation3, UnitQuaternion};
+use yaml_rust2::Yaml;
 use eldra_macro::Reflection;
-use crate::data::ExtSerializable;
+use crate::data::{build_serialized, ExtSerializable};
+use crate::data::loader::SourceData;
+use crate::impl_vec_embed_serialize;
+use crate::reflection::{Mat4f, Vec3f, Vec4f};
+
+/**
+ * Joint hierarchy of a skinned mesh, imported from a glTF skin.
+ * joints keep the glTF skin order, the JOINTS_n vertex attributes index into it.
+ * a parent may come after its children.
+ */
+#[derive(Reflection,Clone)]
+pub struct Joint {
+    #[serialize]
+    pub name: String,
+    // index in Skeleton.joints, -1 for a root
+    #[serialize]
+    pub parent: i32,
+    // rest pose, relative to the parent
+    #[serialize]
+    pub translation: Vec3f,
+    // quaternion x, y, z, w
+    #[serialize]
+    pub rotation: Vec4f,
+    #[serialize]
+    pub scale: Vec3f,
+    // mesh space to joint space at bind time
+    #[serialize]
+    pub inverse_bind: Mat4f,
+}
+impl_vec_embed_serialize!(Joint);
+impl Default for Joint {
+    fn default() -> Self {
+        Joint {
+            name: String::new(),
+            parent: -1,
+            translation: Vec3f::zeros(),
+            rotation: Vec4f::new(0.0, 0.0, 0.0, 1.0),
+            scale: Vec3f::new(1.0, 1.0, 1.0),
+            inverse_bind: Mat4f::identity(),
+        }
+    }
+}
+impl Joint {
+    pub fn local_matrix(&self) -> Mat4f {
+        trs_matrix(&self.translation, &self.rotation, &self.scale)
+    }
+}
+pub fn trs_matrix(t: &Vec3f, r: &Vec4f, s: &Vec3f) -> Mat4f {
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(r.w, r.x, r.y, r.z));
+    Translation3::from(*t).to_homogeneous() * rotation.to_homogeneous() * Mat4f::new_nonuniform_scaling(s)
+}
+// translation, rotation, scale of an affine matrix without shear
+pub fn decompose_matrix(m: &Mat4f) -> (Vec3f, Vec4f, Vec3f) {
+    let t = Vec3f::new(m[(0, 3)], m[(1, 3)], m[(2, 3)]);
+    let basis: Matrix3<f32> = m.fixed_view::<3, 3>(0, 0).into();
+    let mut s = Vec3f::new(basis.column(0).norm(), basis.column(1).norm(), basis.column(2).norm());
+    if basis.determinant() < 0.0 {
+        s.x = -s.x;
+    }
+    let mut r = basis;
+    for i in 0..3 {
+        if s[i] != 0.0 {
+            r.column_mut(i).unscale_mut(s[i]);
+        }
+    }
+    let q = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(r));
+    (t, Vec4f::new(q.i, q.j, q.k, q.w), s)
+}
 
 #[derive(Reflection,Default)]
 pub struct Skeleton {
     #[serialize]
-    pub bone_count: u16,
+    pub name: String,
+    #[serialize]
+    pub joints: Vec<Joint>,
+}
+impl ExtSerializable<Skeleton> for Skeleton {
+    fn build_from_source(res: &mut Skeleton, src: SourceData) -> Result<(), String> {
+        build_serialized(res, src)?;
+        res.validate()
+    }
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Skeleton>() + self.joints.len() * std::mem::size_of::<Joint>()
+    }
 }
-impl ExtSerializable<Skeleton> for Skeleton {}
 
 impl Skeleton {
     pub fn new() -> Box<Skeleton> {
         Box::new(Skeleton::default())
     }
-}
\ No newline at end of file
+    pub fn joint_count(&self) -> usize {
+        self.joints.len()
+    }
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|j| j.name == name)
+    }
+    // parents in range and no cycle
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, joint) in self.joints.iter().enumerate() {
+            let mut parent = joint.parent;
+            let mut depth = 0;
+            while parent >= 0 {
+                if parent as usize >= self.joints.len() {
+                    return Err(format!("joint {} ({}) has invalid parent {}", i, joint.name, parent))
+                }
+                depth += 1;
+                if depth > self.joints.len() {
+                    return Err(format!("joint {} ({}) is part of a cycle", i, joint.name))
+                }
+                parent = self.joints[parent as usize].parent;
+            }
+        }
+        Ok(())
+    }
+    // model space matrices of the given local ones, in joint order
+    pub fn world_matrices(&self, locals: &[Mat4f]) -> Vec<Mat4f> {
+        let mut world: Vec<Option<Mat4f>> = vec![None; self.joints.len()];
+        for i in 0..self.joints.len() {
+            self.resolve_world(i, locals, &mut world, 0);
+        }
+        world.into_iter().map(|m| m.unwrap()).collect()
+    }
+    // a parent out of range or a cycle (see validate) makes the joint a root instead of recursing forever
+    fn resolve_world(&self, i: usize, locals: &[Mat4f], world: &mut Vec<Option<Mat4f>>, depth: usize) -> Mat4f {
+        if let Some(m) = world[i] {
+            return m
+        }
+        let m = match usize::try_from(self.joints[i].parent) {
+            Ok(p) if p < self.joints.len() && depth < self.joints.len() => self.resolve_world(p, locals, world, depth + 1) * locals[i],
+            _ => locals[i],
+        };
+        world[i] = Some(m);
+        m
+    }
+    pub fn rest_world_matrices(&self) -> Vec<Mat4f> {
+        let locals: Vec<Mat4f> = self.joints.iter().map(|j| j.local_matrix()).collect();
+        self.world_matrices(&locals)
+    }
+}
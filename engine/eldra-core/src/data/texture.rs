@@ -1,16 +1,140 @@
 use std::any::{Any, TypeId};
+use std::path::{Path, PathBuf};
 use eldra_macro::{*};
-use crate::data::ExtSerializable;
+use yaml_rust2::Yaml;
+use crate::data::{build_serialized, ExtSerializable};
+use crate::data::asset_db::asset_db;
+use crate::data::blob::Blob;
+use crate::data::loader::{add_build_reference, read_binary_source, read_yaml_source, SourceData};
+use crate::fs::{normalize, vfs};
+use crate::reflection::Serializable;
+
+// pixel_format values
+pub const PIXEL_FORMAT_RGBA8: u8 = 1;
+
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum WrapMode {
+    #[default]
+    REPEAT = 0,
+    CLAMP_TO_EDGE = 1,
+    MIRRORED_REPEAT = 2,
+}
+#[derive(Reflection,Default,Clone,Copy,PartialEq,Debug)]
+pub enum FilterMode {
+    #[default]
+    LINEAR = 0,
+    NEAREST = 1,
+}
+
+#[derive(Reflection,Clone)]
+pub struct Sampler {
+    #[serialize]
+    pub wrap_u: WrapMode,
+    #[serialize]
+    pub wrap_v: WrapMode,
+    #[serialize]
+    pub mag_filter: FilterMode,
+    #[serialize]
+    pub min_filter: FilterMode,
+    // filter between mip levels, unused without mipmaps
+    #[serialize]
+    pub mip_filter: FilterMode,
+    #[serialize]
+    pub mipmaps: bool,
+}
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler {
+            wrap_u: WrapMode::REPEAT,
+            wrap_v: WrapMode::REPEAT,
+            mag_filter: FilterMode::LINEAR,
+            min_filter: FilterMode::LINEAR,
+            mip_filter: FilterMode::LINEAR,
+            mipmaps: true,
+        }
+    }
+}
 
 #[derive(Reflection,Default)]
 pub struct Texture
 {
     #[serialize]
     pub pixel_format: u8,
+    #[serialize]
+    pub width: u32,
+    #[serialize]
+    pub height: u32,
+    #[serialize]
+    pub pixels: Blob,
+    #[serialize]
+    pub sampler: Sampler,
+    // image file the pixels are decoded from, relative to the texture file (yaml textures only)
+    #[serialize]
+    pub image: String,
+}
+
+// decoded on the worker thread
+struct ImageSource {
+    // settings of a yaml texture
+    settings: Option<Yaml>,
+    image_path: PathBuf,
+    image: image::RgbaImage,
+}
+fn decode_image(path: &Path) -> Result<image::RgbaImage, String> {
+    let data = vfs().read().unwrap().read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let img = image::load_from_memory(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(img.to_rgba8())
+}
+
+impl ExtSerializable<Texture> for Texture {
+    fn source_exts() -> Vec<&'static str> { vec!["yaml", "png", "jpg", "jpeg"] }
+    fn read_source(respath: &Path, bin: bool) -> Result<SourceData, String> {
+        if bin {
+            return read_binary_source(respath)
+        }
+        if respath.extension().is_some_and(|e| e.eq_ignore_ascii_case("yaml")) {
+            let src = read_yaml_source(respath)?;
+            let SourceData::Yaml(doc) = &src else { return Ok(src) };
+            let image = doc["image"]["value"].as_str().unwrap_or_default();
+            if image.is_empty() {
+                return Ok(src)
+            }
+            let image_path = respath.parent().unwrap_or(Path::new("")).join(image);
+            let image = decode_image(&image_path)?;
+            let SourceData::Yaml(doc) = src else { unreachable!() };
+            return Ok(SourceData::Custom(Box::new(ImageSource { settings: Some(doc), image_path, image })))
+        }
+        let image = decode_image(respath)?;
+        Ok(SourceData::Custom(Box::new(ImageSource { settings: None, image_path: respath.to_path_buf(), image })))
+    }
+    fn build_from_source(res: &mut Texture, src: SourceData) -> Result<(), String> {
+        match src {
+            SourceData::Custom(data) => {
+                let src = *data.downcast::<ImageSource>().unwrap();
+                if let Some(doc) = src.settings.as_ref() {
+                    res.deserialize_text(doc);
+                    // the cooker rebuilds the texture when its image changes, the cooker imports the
+                    // image, loading only looks its guid up
+                    let db = asset_db().read().unwrap();
+                    if let Some(guid) = db.guid_of(&normalize(&db.relative(&src.image_path)?)) {
+                        add_build_reference(guid);
+                    }
+                }
+                res.pixel_format = PIXEL_FORMAT_RGBA8;
+                res.width = src.image.width();
+                res.height = src.image.height();
+                res.pixels = Blob::from(src.image.into_raw());
+                Ok(())
+            },
+            src => build_serialized(res, src),
+        }
+    }
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Texture>() + self.pixels.len()
+    }
 }
-impl ExtSerializable<Texture> for Texture {}
 impl Texture {
     pub fn new() -> Box<Texture> {
         Box::new(Texture::default())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,385 @@
+use crate::data::blob::Blob;
+use crate::data::render_object::{BufferType, BufferView, ComponentType, DataType, Primitive, RenderObject};
+use crate::reflection::{Vec2f, Vec3f, Vec4f};
+
+/**
+ * Typed reads and writes of the BufferViews of a RenderObject.
+ * a VertexAccessor checks the view against the buffer once, then decodes elements of any
+ * component type to f32 vectors or u32, normalized integers mapped to [0, 1] or [-1, 1] as in
+ * glTF. sparse views read their base (zeros without one) with the sparse values substituted.
+ * writers encode back to the view format, in place when the count is unchanged, otherwise the
+ * stream is re-packed at the end of the buffer. the RenderObject writers then compact the buffer,
+ * so the streams they replace don't pile up over repeated edits.
+ */
+pub fn component_size(t: &ComponentType) -> usize {
+    match t {
+        ComponentType::S8 | ComponentType::U8 => 1,
+        ComponentType::S16 | ComponentType::U16 => 2,
+        ComponentType::F32 | ComponentType::U32 => 4,
+    }
+}
+pub fn component_count(t: &DataType) -> usize {
+    match t {
+        DataType::SCALA => 1,
+        DataType::VEC2 => 2,
+        DataType::VEC3 => 3,
+        DataType::VEC4 | DataType::MAT2 => 4,
+        DataType::MAT3 => 9,
+        DataType::MAT4 => 16,
+    }
+}
+// bytes of one element, without stride padding
+pub fn element_size(view: &BufferView) -> usize {
+    component_count(&view.data_type) * component_size(&view.component_type)
+}
+
+fn decode(data: &[u8], at: usize, t: &ComponentType, normalized: bool) -> f64 {
+    let b = &data[at..at + component_size(t)];
+    match (t, normalized) {
+        (ComponentType::F32, _) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        (ComponentType::U32, _) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        (ComponentType::U8, false) => b[0] as f64,
+        (ComponentType::U8, true) => b[0] as f64 / 255.0,
+        (ComponentType::S8, false) => b[0] as i8 as f64,
+        (ComponentType::S8, true) => (b[0] as i8 as f64 / 127.0).max(-1.0),
+        (ComponentType::U16, false) => u16::from_le_bytes([b[0], b[1]]) as f64,
+        (ComponentType::U16, true) => u16::from_le_bytes([b[0], b[1]]) as f64 / 65535.0,
+        (ComponentType::S16, false) => i16::from_le_bytes([b[0], b[1]]) as f64,
+        (ComponentType::S16, true) => (i16::from_le_bytes([b[0], b[1]]) as f64 / 32767.0).max(-1.0),
+    }
+}
+fn encode(v: f64, t: &ComponentType, normalized: bool, out: &mut [u8]) {
+    match (t, normalized) {
+        (ComponentType::F32, _) => out.copy_from_slice(&(v as f32).to_le_bytes()),
+        (ComponentType::U32, _) => out.copy_from_slice(&(v.round() as u32).to_le_bytes()),
+        (ComponentType::U8, false) => out[0] = v.round() as u8,
+        (ComponentType::U8, true) => out[0] = (v.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (ComponentType::S8, false) => out[0] = v.round() as i8 as u8,
+        (ComponentType::S8, true) => out[0] = (v.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8,
+        (ComponentType::U16, false) => out.copy_from_slice(&(v.round() as u16).to_le_bytes()),
+        (ComponentType::U16, true) => out.copy_from_slice(&((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes()),
+        (ComponentType::S16, false) => out.copy_from_slice(&(v.round() as i16).to_le_bytes()),
+        (ComponentType::S16, true) => out.copy_from_slice(&((v.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes()),
+    }
+}
+
+// element type of a typed read, the leading components of the view element
+pub trait VertexElement: Copy {
+    const COMPONENTS: usize;
+    fn from_components(c: &[f64]) -> Self;
+    fn components(&self) -> [f64; 4];
+}
+impl VertexElement for f32 {
+    const COMPONENTS: usize = 1;
+    fn from_components(c: &[f64]) -> Self { c[0] as f32 }
+    fn components(&self) -> [f64; 4] { [*self as f64, 0.0, 0.0, 0.0] }
+}
+impl VertexElement for u32 {
+    const COMPONENTS: usize = 1;
+    fn from_components(c: &[f64]) -> Self { c[0] as u32 }
+    fn components(&self) -> [f64; 4] { [*self as f64, 0.0, 0.0, 0.0] }
+}
+impl VertexElement for Vec2f {
+    const COMPONENTS: usize = 2;
+    fn from_components(c: &[f64]) -> Self { Vec2f::new(c[0] as f32, c[1] as f32) }
+    fn components(&self) -> [f64; 4] { [self.x as f64, self.y as f64, 0.0, 0.0] }
+}
+impl VertexElement for Vec3f {
+    const COMPONENTS: usize = 3;
+    fn from_components(c: &[f64]) -> Self { Vec3f::new(c[0] as f32, c[1] as f32, c[2] as f32) }
+    fn components(&self) -> [f64; 4] { [self.x as f64, self.y as f64, self.z as f64, 0.0] }
+}
+impl VertexElement for Vec4f {
+    const COMPONENTS: usize = 4;
+    fn from_components(c: &[f64]) -> Self { Vec4f::new(c[0] as f32, c[1] as f32, c[2] as f32, c[3] as f32) }
+    fn components(&self) -> [f64; 4] { [self.x as f64, self.y as f64, self.z as f64, self.w as f64] }
+}
+
+pub struct VertexAccessor<'a> {
+    data: &'a [u8],
+    view: &'a BufferView,
+    stride: usize,
+    // sorted element indices replaced by the sparse values
+    sparse_indices: Vec<u32>,
+}
+impl<'a> VertexAccessor<'a> {
+    pub fn new(data: &'a [u8], view: &'a BufferView) -> Result<VertexAccessor<'a>, String> {
+        let elem = element_size(view);
+        let stride = if view.vertex_stride == 0 { elem } else { view.vertex_stride as usize };
+        if stride < elem {
+            return Err(format!("stride {} is below the element size {}", stride, elem))
+        }
+        let count = view.count as usize;
+        if view.has_data() && count > 0 && view.offset as usize + (count - 1) * stride + elem > data.len() {
+            return Err(format!("{} elements at {} are out of the buffer of {} bytes", count, view.offset, data.len()))
+        }
+        let mut sparse_indices = Vec::new();
+        if let Some(sparse) = &view.sparse {
+            let n = sparse.count as usize;
+            let index_size = component_size(&sparse.index_type);
+            if sparse.indices_offset as usize + n * index_size > data.len() || sparse.values_offset as usize + n * elem > data.len() {
+                return Err(format!("{} sparse elements are out of the buffer of {} bytes", n, data.len()))
+            }
+            sparse_indices = (0..n).map(|i| decode(data, sparse.indices_offset as usize + i * index_size, &sparse.index_type, false) as u32).collect();
+            if sparse_indices.windows(2).any(|w| w[1] <= w[0]) || sparse_indices.last().is_some_and(|i| *i as usize >= count) {
+                return Err("sparse indices are not increasing or out of the view".to_string())
+            }
+        }
+        Ok(VertexAccessor { data, view, stride, sparse_indices })
+    }
+    pub fn count(&self) -> usize {
+        self.view.count as usize
+    }
+    pub fn components(&self) -> usize {
+        component_count(&self.view.data_type)
+    }
+    fn element_at(&self, i: usize) -> Option<usize> {
+        match self.sparse_indices.binary_search(&(i as u32)) {
+            Ok(k) => Some(self.view.sparse.as_ref().unwrap().values_offset as usize + k * element_size(self.view)),
+            Err(_) if self.view.has_data() => Some(self.view.offset as usize + i * self.stride),
+            Err(_) => None,
+        }
+    }
+    // all the components of element i
+    pub fn get_components(&self, i: usize, out: &mut [f64]) {
+        let size = component_size(&self.view.component_type);
+        match self.element_at(i) {
+            Some(at) => {
+                for (c, v) in out.iter_mut().enumerate().take(self.components()) {
+                    *v = decode(self.data, at + c * size, &self.view.component_type, self.view.normalized);
+                }
+            },
+            None => out.iter_mut().for_each(|v| *v = 0.0),
+        }
+    }
+    pub fn get<T: VertexElement>(&self, i: usize) -> T {
+        let mut c = [0.0; 16];
+        self.get_components(i, &mut c);
+        T::from_components(&c)
+    }
+    pub fn iter<T: VertexElement>(&self) -> Result<impl Iterator<Item = T> + '_, String> {
+        if T::COMPONENTS > self.components() {
+            return Err(format!("{} components read from a {} components view", T::COMPONENTS, self.components()))
+        }
+        Ok((0..self.count()).map(|i| self.get::<T>(i)))
+    }
+    pub fn read<T: VertexElement>(&self) -> Result<Vec<T>, String> {
+        Ok(self.iter::<T>()?.collect())
+    }
+    // dense copy of the elements in the view format, tightly packed
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let elem = element_size(self.view);
+        let mut bytes = vec![0_u8; elem * self.count()];
+        for i in 0..self.count() {
+            if let Some(at) = self.element_at(i) {
+                bytes[i * elem..(i + 1) * elem].copy_from_slice(&self.data[at..at + elem]);
+            }
+        }
+        bytes
+    }
+}
+
+// encodes values in the view format : in place if the count is unchanged, otherwise
+// appended to the buffer, 4 aligned and tightly packed. the view is dense afterwards
+pub fn write_view<T: VertexElement>(buffer: &mut Blob, view: &mut BufferView, values: &[T]) -> Result<(), String> {
+    let n = component_count(&view.data_type);
+    if T::COMPONENTS != n {
+        return Err(format!("{} components written to a {} components view", T::COMPONENTS, n))
+    }
+    let (elem, size) = (element_size(view), component_size(&view.component_type));
+    let in_place = values.len() == view.count as usize && view.has_data() && view.sparse.is_none()
+        && VertexAccessor::new(buffer.as_slice(), view).is_ok();
+    if !in_place {
+        let data = buffer.to_mut();
+        data.resize(data.len().div_ceil(4) * 4, 0);
+        view.offset = data.len() as u32;
+        view.vertex_stride = 0;
+        view.count = values.len() as u32;
+        view.byte_size = (elem * values.len()) as u32;
+        view.sparse = None;
+        data.resize(data.len() + elem * values.len(), 0);
+    }
+    let stride = if view.vertex_stride == 0 { elem } else { view.vertex_stride as usize };
+    let data = buffer.to_mut();
+    for (i, v) in values.iter().enumerate() {
+        let at = view.offset as usize + i * stride;
+        for (c, x) in v.components().iter().enumerate().take(n) {
+            encode(*x, &view.component_type, view.normalized, &mut data[at + c * size..at + (c + 1) * size]);
+        }
+    }
+    Ok(())
+}
+// new stream of the elements at remap, appended to the buffer
+pub fn gather_view(buffer: &mut Blob, view: &mut BufferView, remap: &[u32]) -> Result<(), String> {
+    let elem = element_size(view);
+    let src = VertexAccessor::new(buffer.as_slice(), view)?.to_bytes();
+    let mut bytes = Vec::with_capacity(elem * remap.len());
+    for i in remap.iter().map(|i| *i as usize) {
+        let at = src.get(i * elem..(i + 1) * elem).ok_or(format!("element {} out of {}", i, view.count))?;
+        bytes.extend_from_slice(at);
+    }
+    let data = buffer.to_mut();
+    data.resize(data.len().div_ceil(4) * 4, 0);
+    view.offset = data.len() as u32;
+    view.vertex_stride = 0;
+    view.count = remap.len() as u32;
+    view.byte_size = bytes.len() as u32;
+    view.sparse = None;
+    data.extend_from_slice(&bytes);
+    Ok(())
+}
+// byte ranges of the buffer a view reads, its elements then its sparse indices and values
+fn view_ranges(view: &BufferView) -> Vec<(usize, usize)> {
+    let elem = element_size(view);
+    let stride = if view.vertex_stride == 0 { elem } else { view.vertex_stride as usize };
+    let mut ranges = Vec::new();
+    if view.has_data() && view.count > 0 {
+        ranges.push((view.offset as usize, view.offset as usize + (view.count as usize - 1) * stride + elem));
+    }
+    if let Some(sparse) = view.sparse.as_ref().filter(|s| s.count > 0) {
+        let n = sparse.count as usize;
+        ranges.push((sparse.indices_offset as usize, sparse.indices_offset as usize + n * component_size(&sparse.index_type)));
+        ranges.push((sparse.values_offset as usize, sparse.values_offset as usize + n * elem));
+    }
+    ranges
+}
+// every vertex stream, morph targets included, then the indices and LOD indices if asked
+pub(crate) fn views_mut(prim: &mut Primitive, indices: bool) -> Vec<&mut BufferView> {
+    let Primitive { position, normal, tangent, texcoord, color, skin, targets, indice, lods, .. } = prim;
+    let mut views = vec![position];
+    views.extend(normal.as_mut());
+    views.extend(tangent.as_mut());
+    views.extend(texcoord.iter_mut());
+    views.extend(color.iter_mut());
+    for s in skin.iter_mut() {
+        views.push(&mut s.joints);
+        views.push(&mut s.weights);
+    }
+    for t in targets.iter_mut() {
+        views.extend(t.position.as_mut());
+        views.extend(t.normal.as_mut());
+        views.extend(t.tangent.as_mut());
+    }
+    if indices {
+        views.extend(indice.as_mut());
+        views.extend(lods.iter_mut().map(|l| &mut l.indice));
+    }
+    views
+}
+// index view of the smallest type holding the indices
+pub fn write_indices(buffer: &mut Blob, indices: &[u32]) -> BufferView {
+    let component_type = if indices.iter().all(|i| *i <= u16::MAX as u32) { ComponentType::U16 } else { ComponentType::U32 };
+    let mut view = BufferView { buffer_type: BufferType::INDEX, data_type: DataType::SCALA, component_type, ..Default::default() };
+    // appended as the count differs from the empty view
+    write_view(buffer, &mut view, indices).unwrap();
+    view
+}
+
+impl Primitive {
+    // view of a vertex_iter attribute name, POSITION, TEXCOORD_1, JOINTS_0...
+    pub fn attribute(&self, name: &str) -> Option<&BufferView> {
+        self.vertex_iter().find(|info| info.attr_name == name).map(|info| info.buffer_view)
+    }
+    pub fn attribute_mut(&mut self, name: &str) -> Option<&mut BufferView> {
+        let (semantic, set) = match name.rsplit_once('_') {
+            Some((s, i)) if i.parse::<usize>().is_ok() => (s, i.parse::<usize>().unwrap()),
+            _ => (name, 0),
+        };
+        match semantic {
+            "POSITION" => Some(&mut self.position),
+            "NORMAL" => self.normal.as_mut(),
+            "TANGENT" => self.tangent.as_mut(),
+            "TEXCOORD" => self.texcoord.get_mut(set),
+            "COLOR" => self.color.get_mut(set),
+            "JOINTS" => self.skin.get_mut(set).map(|s| &mut s.joints),
+            "WEIGHTS" => self.skin.get_mut(set).map(|s| &mut s.weights),
+            _ => None,
+        }
+    }
+}
+impl RenderObject {
+    pub fn accessor<'a>(&'a self, view: &'a BufferView) -> Result<VertexAccessor<'a>, String> {
+        VertexAccessor::new(self.buffer.as_slice(), view)
+    }
+    pub fn read_attribute<T: VertexElement>(&self, prim: &Primitive, name: &str) -> Result<Vec<T>, String> {
+        let view = prim.attribute(name).ok_or(format!("no {} attribute", name))?;
+        self.accessor(view)?.read::<T>()
+    }
+    pub fn read_indices(&self, prim: &Primitive) -> Result<Option<Vec<u32>>, String> {
+        prim.indice.as_ref().map(|view| self.accessor(view)?.read::<u32>()).transpose()
+    }
+    // the buffer and a primitive, borrowed together for writes
+    pub fn primitive_mut(&mut self, part: usize, prim: usize) -> Result<(&mut Blob, &mut Primitive), String> {
+        let RenderObject { buffer, parts, .. } = self;
+        let prim = parts.get_mut(part).and_then(|p| p.primitives.get_mut(prim)).ok_or(format!("no primitive {} in part {}", prim, part))?;
+        Ok((buffer, prim))
+    }
+    pub fn write_attribute<T: VertexElement>(&mut self, part: usize, prim: usize, name: &str, values: &[T]) -> Result<(), String> {
+        let (buffer, prim) = self.primitive_mut(part, prim)?;
+        let view = prim.attribute_mut(name).ok_or(format!("no {} attribute", name))?;
+        write_view(buffer, view, values)?;
+        self.compact_buffer();
+        Ok(())
+    }
+    pub fn write_indices(&mut self, part: usize, prim: usize, indices: &[u32]) -> Result<(), String> {
+        let (buffer, prim) = self.primitive_mut(part, prim)?;
+        prim.indice = Some(write_indices(buffer, indices));
+        self.compact_buffer();
+        Ok(())
+    }
+    // drops the bytes no view reads anymore and returns their count. overlapping views (interleaved
+    // or shared) move together and offsets keep their alignment to 4. nothing moves while a view
+    // is out of the buffer
+    pub fn compact_buffer(&mut self) -> usize {
+        let RenderObject { buffer, parts, .. } = self;
+        let mut ranges = Vec::new();
+        for prim in parts.iter_mut().flat_map(|p| p.primitives.iter_mut()) {
+            for view in views_mut(prim, true) {
+                if VertexAccessor::new(buffer.as_slice(), view).is_err() {
+                    return 0
+                }
+                ranges.extend(view_ranges(view));
+            }
+        }
+        ranges.sort();
+        // merged live ranges as [start, end, new start]
+        let mut live: Vec<[usize; 3]> = Vec::new();
+        for (start, end) in ranges {
+            match live.last_mut() {
+                Some(last) if start <= last[1] => last[1] = last[1].max(end),
+                _ => {
+                    let at = live.last().map_or(0, |l| l[2] + l[1] - l[0]);
+                    live.push([start, end, at + (start % 4 + 4 - at % 4) % 4]);
+                },
+            }
+        }
+        let size = live.last().map_or(0, |l| l[2] + l[1] - l[0]);
+        if size == buffer.len() {
+            return 0
+        }
+        let mut data = vec![0_u8; size];
+        for [start, end, at] in live.iter() {
+            data[*at..*at + end - start].copy_from_slice(&buffer.as_slice()[*start..*end]);
+        }
+        let moved = |offset: u32| {
+            let [start, _, at] = live[live.partition_point(|l| l[0] <= offset as usize) - 1];
+            (at + offset as usize - start) as u32
+        };
+        for prim in parts.iter_mut().flat_map(|p| p.primitives.iter_mut()) {
+            for view in views_mut(prim, true) {
+                if view.has_data() && view.count > 0 {
+                    view.offset = moved(view.offset);
+                }
+                if let Some(sparse) = view.sparse.as_mut().filter(|s| s.count > 0) {
+                    sparse.indices_offset = moved(sparse.indices_offset);
+                    sparse.values_offset = moved(sparse.values_offset);
+                }
+            }
+        }
+        let freed = buffer.len() - size;
+        let compression = buffer.compression;
+        *buffer = Blob::from(data);
+        buffer.compression = compression;
+        freed
+    }
+}
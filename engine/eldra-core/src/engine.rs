@@ -7,12 +7,16 @@ use std::ffi::CString;
 use std::os::raw::c_char;
 use std::rc::Rc;
 use crate::data::init_resource_mgr;
+use crate::data::asset_db::init_asset_db;
 use crate::reflection::init_reflection;
+use crate::fs::init_vfs;
 
 pub fn engine_init(drop_callback: ObjDropCallback) {
     unsafe {
         engine_init_once__(drop_callback);
         init_reflection();
+        init_vfs();
+        init_asset_db();
         init_resource_mgr();
     }
 }
@@ -1,4 +1,4 @@
-use std::io::{BufWriter,BufReader,Read,Write};
+use std::io::{BufReader,Read,Write};
 use std::ops::DerefMut;
 use std::os::raw::c_char;
 use std::ffi::CStr;
@@ -10,8 +10,7 @@ use std::ptr::{addr_of};
 use std::rc::{Rc, Weak};
 use std::marker::PhantomPinned;
 use std::any::type_name;
-use std::fs;
-use std::fs::File;
+use std::path::Path;
 use std::str::FromStr;
 use uuid::Uuid;
 use yaml_rust2::Yaml;
@@ -19,7 +18,9 @@ use eldra_macro::{ComponentAttr, DropNotify, Reflection};
 use crate::engine::{*};
 use crate::reflection::{*};
 use crate::comp::transform_component::TransformComponent;
-use crate::impl_vec_concrete_serialize;
+use crate::data::blob::serialize_binary_file;
+use crate::fs::vfs;
+use crate::{impl_vec_concrete_serialize, log_warn};
 
 #[derive(Debug,Reflection)]
 pub struct BaseObject
@@ -43,6 +44,10 @@ pub trait ComponentAttr {
 pub trait Component : Reflectable + ComponentAttr + Serializable {
     fn tick(&mut self, _delta: f32, _ancestor: &Option<&Components>) {
     }
+    // once every component of the entity ticked, before the children tick.
+    // world is the matrix of the entity's TransformComponent, if it has one
+    fn post_tick(&mut self, _world: Option<&Mat4f>, _children: &[Rc<RefCell<Entity>>]) {
+    }
 }
 #[derive(Default,Reflection,ComponentAttr)]
 pub struct DummyComponent {
@@ -79,6 +84,22 @@ impl Components {
             Some(&self.multi_comp[self.multi_comp.len() - 1])
         }
     }
+    // like create_component, with an already set up component
+    pub fn add_component<T>(&mut self, comp: T) -> bool
+        where T: Component + Uniq + 'static
+    {
+        if T::is_uniq() {
+            if self.uniq_comp.contains_key(&TypeId::of::<T>()) {
+                log_warn!("{} is already there, can't duplicate uniq component", type_name::<T>());
+                return false
+            }
+            self.uniq_comp.insert(TypeId::of::<T>(), Box::new(comp));
+        }
+        else {
+            self.multi_comp.push(Box::new(comp));
+        }
+        true
+    }
     pub fn remove_component(&mut self, candidate: &Box<dyn Component>) -> bool {
         if candidate.is_comp_uniq() {
             if self.uniq_comp.remove(&candidate.real_type_id()).is_some() {
@@ -118,6 +139,26 @@ impl Components {
             }
         }
     }
+    pub fn get_component_mut<T: Component + Uniq + 'static>(&mut self) -> Option<&mut T> where {
+        match T::is_uniq() {
+            true => {
+                match self.uniq_comp.get_mut(&TypeId::of::<T>()) {
+                    Some(cc) => {
+                        cc.as_any_mut().downcast_mut::<T>()
+                    },
+                    None => None,
+                }
+            },
+            false => {
+                for c in self.multi_comp.iter_mut() {
+                    if c.as_any().is::<T>() {
+                        return c.as_any_mut().downcast_mut::<T>()
+                    }
+                }
+                None
+            }
+        }
+    }
 }
 
 #[derive(Reflection,DropNotify)]
@@ -220,6 +261,10 @@ impl Entity {
         }
     }
 
+    // address handed to scripting
+    pub fn marker_address(&self) -> u64 {
+        self.marker_address
+    }
     pub fn has_parent(&self) -> bool {
         self.get_parent().is_some()
     }
@@ -229,6 +274,12 @@ impl Entity {
     pub fn get_component<T: Component + Uniq + 'static>(& self) -> Option<&T> where {
         self.components.get_component::<T>()
     }
+    pub fn get_component_mut<T: Component + Uniq + 'static>(&mut self) -> Option<&mut T> where {
+        self.components.get_component_mut::<T>()
+    }
+    pub fn add_component<T: Component + Uniq + 'static>(&mut self, comp: T) -> bool {
+        self.components.add_component(comp)
+    }
     pub fn tick(&mut self, delta: f32, parent: &Option<&Components>) {
         for c in self.components.uniq_comp.iter_mut() {
             c.1.tick(delta, parent);
@@ -236,6 +287,13 @@ impl Entity {
         for c in self.components.multi_comp.iter_mut() {
             c.tick(delta, parent);
         }
+        let world = self.components.get_component::<TransformComponent>().map(|t| t.world_matrix);
+        for c in self.components.uniq_comp.iter_mut() {
+            c.1.post_tick(world.as_ref(), &self.children);
+        }
+        for c in self.components.multi_comp.iter_mut() {
+            c.post_tick(world.as_ref(), &self.children);
+        }
         for c in self.children.iter_mut() {
             c.borrow_mut().tick(delta, &Some(&self.components));
         }
@@ -368,9 +426,7 @@ pub extern "C"
 fn Entity_serialize_binary(addr: u64, path: *const c_char) {
     entity_update(&addr, |entity| {
         let p = unsafe { CStr::from_ptr(path) }.to_str().unwrap();
-        let mut file = BufWriter::new(File::create(p).unwrap());
-        entity.borrow().serialize_binary(&mut file);
-        let _ = file.flush();
+        serialize_binary_file(&*entity.borrow(), p).unwrap();
     });
 }
 #[no_mangle]
@@ -378,7 +434,7 @@ pub extern "C"
 fn Entity_deserialize_binary(addr: u64, path: *const c_char) {
     entity_update(&addr, |entity| {      
         let p = unsafe { CStr::from_ptr(path) }.to_str().unwrap();
-        let file = File::open(p).unwrap();
+        let file = vfs().read().unwrap().open(Path::new(p)).unwrap();
         entity.borrow_mut().deserialize_binary(&mut BufReader::new(file)); 
     });
 }
@@ -396,7 +452,7 @@ pub extern "C"
 fn Entity_deserialize_yaml(addr: u64, path: *const c_char) {
     entity_update(&addr, |entity| {      
         let yaml_path = unsafe { CStr::from_ptr(path) }.to_str().unwrap();
-        let yaml_str = fs::read_to_string(yaml_path).unwrap();
+        let yaml_str = vfs().read().unwrap().read_to_string(Path::new(yaml_path)).unwrap();
         let mut e = entity.borrow_mut();
         load_from_yaml(e.deref_mut(), &yaml_str);
     });
@@ -0,0 +1,277 @@
+use core::ffi::c_char;
+use std::ffi::CStr;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use memmap2::Mmap;
+use once_cell::sync::OnceCell;
+use crate::fs::pack::PackArchive;
+use crate::log_error;
+
+pub mod pack;
+
+/**
+ * Virtual file system, read-only.
+ *
+ * relative paths ('/' separated) are looked up in the mounts, highest priority first,
+ * newest first on equal priority. a loose directory mounted above a pack overrides
+ * the packed files, which is how development builds patch shipped data.
+ * absolute paths, and relative ones found in no mount, go to the disk (tools, cooker, tests).
+ */
+// packed data, below loose files
+pub const PACK_PRIORITY: i32 = 0;
+pub const LOOSE_PRIORITY: i32 = 100;
+
+pub trait VfsRead: Read + Seek + Send {}
+impl<T: Read + Seek + Send> VfsRead for T {}
+pub type VfsFile = Box<dyn VfsRead>;
+
+// mapped file content, a packed file is a range of the mapped archive
+#[derive(Clone)]
+pub struct MappedFile {
+    pub map: Arc<Mmap>,
+    pub offset: usize,
+    pub len: usize,
+}
+impl MappedFile {
+    pub fn as_slice(&self) -> &[u8] {
+        &self.map[self.offset..self.offset + self.len]
+    }
+}
+
+pub trait MountSource: Send + Sync {
+    fn exists(&self, path: &str) -> bool;
+    fn open(&self, path: &str) -> std::io::Result<VfsFile>;
+    fn map(&self, path: &str) -> std::io::Result<MappedFile>;
+    fn modified(&self, path: &str) -> Option<SystemTime>;
+    // every file, relative to the mount
+    fn files(&self) -> Vec<String>;
+}
+
+fn not_found(path: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, format!("{}: not found in vfs", path))
+}
+
+// '/' separated, "." and ".." folded, no leading '/'
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for p in path.split(['/', '\\']) {
+        match p {
+            "" | "." => {},
+            ".." if parts.last().is_some_and(|l| *l != "..") => { parts.pop(); },
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
+fn map_file(path: &Path) -> std::io::Result<MappedFile> {
+    let file = File::open(path)?;
+    let map = Arc::new(unsafe { Mmap::map(&file)? });
+    let len = map.len();
+    Ok(MappedFile { map, offset: 0, len })
+}
+
+pub struct DirMount {
+    root: PathBuf,
+}
+impl DirMount {
+    pub fn new(root: &Path) -> Self {
+        DirMount { root: root.to_path_buf() }
+    }
+    fn collect(&self, dir: &Path, prefix: &str, out: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rel = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let path = entry.path();
+            if path.is_dir() {
+                self.collect(&path, &rel, out);
+            } else {
+                out.push(rel);
+            }
+        }
+    }
+}
+impl MountSource for DirMount {
+    fn exists(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+    fn open(&self, path: &str) -> std::io::Result<VfsFile> {
+        Ok(Box::new(File::open(self.root.join(path))?))
+    }
+    fn map(&self, path: &str) -> std::io::Result<MappedFile> {
+        map_file(&self.root.join(path))
+    }
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        fs::metadata(self.root.join(path)).and_then(|m| m.modified()).ok()
+    }
+    fn files(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        self.collect(&self.root, "", &mut out);
+        out
+    }
+}
+
+enum Location<'a> {
+    Disk(&'a Path),
+    Mounted(&'a Mount, String),
+}
+
+struct Mount {
+    id: u64,
+    point: String,
+    priority: i32,
+    source: Box<dyn MountSource>,
+}
+
+#[derive(Default)]
+pub struct Vfs {
+    next_id: u64,
+    // sorted by lookup order
+    mounts: Vec<Mount>,
+}
+static VFS : OnceCell<RwLock<Vfs>> = OnceCell::new();
+// also used before engine_init by tools, so created on first use
+#[inline]
+pub fn vfs() -> &'static RwLock<Vfs> {
+    VFS.get_or_init(|| RwLock::new(Vfs::default()))
+}
+pub fn init_vfs() {
+    VFS.get_or_init(|| RwLock::new(Vfs::default()));
+}
+
+impl Vfs {
+    // files of source appear under point ("" for the root), returns the id to unmount
+    pub fn mount(&mut self, point: &str, source: Box<dyn MountSource>, priority: i32) -> u64 {
+        self.next_id += 1;
+        let mount = Mount { id: self.next_id, point: normalize(point), priority, source };
+        let at = self.mounts.iter().position(|m| m.priority <= priority).unwrap_or(self.mounts.len());
+        self.mounts.insert(at, mount);
+        self.next_id
+    }
+    pub fn mount_dir(&mut self, point: &str, dir: &Path) -> u64 {
+        self.mount(point, Box::new(DirMount::new(dir)), LOOSE_PRIORITY)
+    }
+    pub fn mount_pack(&mut self, point: &str, pack: &Path) -> std::io::Result<u64> {
+        Ok(self.mount(point, Box::new(PackArchive::open(pack)?), PACK_PRIORITY))
+    }
+    pub fn unmount(&mut self, id: u64) {
+        self.mounts.retain(|m| m.id != id);
+    }
+
+    // first mount holding the file, with the path relative to it
+    fn find(&self, path: &str) -> Option<(&Mount, String)> {
+        let path = normalize(path);
+        // outside of every mount
+        if path == ".." || path.starts_with("../") {
+            return None
+        }
+        self.mounts.iter().find_map(|m| {
+            let rel = if m.point.is_empty() {
+                path.clone()
+            } else {
+                path.strip_prefix(&m.point)?.strip_prefix('/')?.to_string()
+            };
+            if m.source.exists(&rel) { Some((m, rel)) } else { None }
+        })
+    }
+    fn locate<'a>(&'a self, path: &'a Path) -> Option<Location<'a>> {
+        if path.is_absolute() {
+            return Some(Location::Disk(path))
+        }
+        match self.find(&path.to_string_lossy()) {
+            Some((m, rel)) => Some(Location::Mounted(m, rel)),
+            None if path.is_file() => Some(Location::Disk(path)),
+            None => None,
+        }
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        match self.locate(path) {
+            Some(Location::Disk(p)) => p.is_file(),
+            Some(Location::Mounted(..)) => true,
+            None => false,
+        }
+    }
+    pub fn open(&self, path: &Path) -> std::io::Result<VfsFile> {
+        match self.locate(path) {
+            Some(Location::Disk(p)) => Ok(Box::new(File::open(p)?)),
+            Some(Location::Mounted(m, rel)) => m.source.open(&rel),
+            None => Err(not_found(&path.to_string_lossy())),
+        }
+    }
+    // zero-copy access, see blob.rs
+    pub fn map(&self, path: &Path) -> std::io::Result<MappedFile> {
+        match self.locate(path) {
+            Some(Location::Disk(p)) => map_file(p),
+            Some(Location::Mounted(m, rel)) => m.source.map(&rel),
+            None => Err(not_found(&path.to_string_lossy())),
+        }
+    }
+    pub fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+    pub fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+    pub fn modified(&self, path: &Path) -> Option<SystemTime> {
+        match self.locate(path)? {
+            Location::Disk(p) => fs::metadata(p).and_then(|m| m.modified()).ok(),
+            Location::Mounted(m, rel) => m.source.modified(&rel),
+        }
+    }
+    // every visible file once, sorted
+    pub fn files(&self) -> Vec<String> {
+        let mut out: Vec<String> = self.mounts.iter().flat_map(|m| {
+            m.source.files().into_iter().map(|f| if m.point.is_empty() { f } else { format!("{}/{}", m.point, f) })
+        }).collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+}
+
+// writes go straight to the disk, the vfs is read-only
+pub fn write_file(path: *const c_char, data: &[u8])
+{
+    let p = unsafe { CStr::from_ptr(path) }.to_string_lossy();
+    if let Err(e) = fs::write(p.as_ref(), data) {
+        log_error!("write {} failed : {}", p, e);
+    }
+}
+
+//// exports
+
+// 0 if a path isn't valid UTF-8
+#[no_mangle]
+pub extern "C"
+fn Vfs_mount_dir(point: *const c_char, dir: *const c_char) -> u64 {
+    let Ok(point) = unsafe { CStr::from_ptr(point) }.to_str() else { return 0 };
+    let Ok(dir) = unsafe { CStr::from_ptr(dir) }.to_str() else { return 0 };
+    vfs().write().unwrap().mount_dir(point, Path::new(dir))
+}
+// 0 if a path isn't valid UTF-8 or the archive can't be opened (logged)
+#[no_mangle]
+pub extern "C"
+fn Vfs_mount_pack(point: *const c_char, pack: *const c_char) -> u64 {
+    let Ok(point) = unsafe { CStr::from_ptr(point) }.to_str() else { return 0 };
+    let Ok(pack) = unsafe { CStr::from_ptr(pack) }.to_str() else { return 0 };
+    match vfs().write().unwrap().mount_pack(point, Path::new(pack)) {
+        Ok(id) => id,
+        Err(e) => {
+            log_error!("mount {} failed : {}", pack, e);
+            0
+        },
+    }
+}
+#[no_mangle]
+pub extern "C"
+fn Vfs_unmount(id: u64) {
+    vfs().write().unwrap().unmount(id);
+}
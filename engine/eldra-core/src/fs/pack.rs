@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use memmap2::Mmap;
+use crate::data::blob::BLOB_ALIGN;
+use crate::fs::{normalize, MappedFile, MountSource, VfsFile};
+
+/**
+ * Pack archive, every file of a shipped build in one memory-mapped file.
+ *
+ * layout : [magic][version: u32][count: u32][index offset: u64][pad]
+ *          [file data, each aligned to BLOB_ALIGN]
+ *          [index : count * ([path len: u32][path][offset: u64][len: u64][crc32: u32])]
+ * file data keeps the alignment it had on disk, so cooked blobs are still borrowed in place.
+ */
+pub const PACK_MAGIC: &[u8; 4] = b"EPAK";
+pub const PACK_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 20;
+
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+fn aligned(pos: u64) -> u64 {
+    let align = BLOB_ALIGN as u64;
+    pos.div_ceil(align) * align
+}
+
+#[derive(Clone,Copy)]
+struct PackEntry {
+    offset: usize,
+    len: usize,
+    crc: u32,
+}
+
+pub struct PackArchive {
+    map: Arc<Mmap>,
+    entries: HashMap<String, PackEntry>,
+    modified: Option<SystemTime>,
+}
+impl PackArchive {
+    pub fn open(path: &Path) -> std::io::Result<PackArchive> {
+        let file = File::open(path)?;
+        let modified = file.metadata().and_then(|m| m.modified()).ok();
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let err = |what: &str| invalid(format!("{}: {}", path.display(), what));
+        if map.len() < HEADER_SIZE || &map[0..4] != PACK_MAGIC {
+            return Err(err("not a pack archive"))
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(map[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(map[at..at + 8].try_into().unwrap()) as usize;
+        if u32_at(4) != PACK_VERSION {
+            return Err(err("unsupported pack version"))
+        }
+        let count = u32_at(8) as usize;
+        let mut pos = u64_at(12);
+        // offsets come from the file, an end past the mapping (or past usize) is corrupted
+        let end = |at: usize, len: usize| at.checked_add(len).filter(|&end| end <= map.len());
+        // every entry takes at least 24 bytes, don't trust count for the allocation
+        let mut entries = HashMap::with_capacity(count.min(map.len() / 24));
+        for _ in 0..count {
+            let Some(name_at) = end(pos, 4) else { return Err(err("truncated index")) };
+            let name_len = u32_at(pos) as usize;
+            let Some(next) = name_at.checked_add(name_len).and_then(|at| end(at, 20)) else {
+                return Err(err("truncated index"))
+            };
+            let name = String::from_utf8_lossy(&map[name_at..name_at + name_len]).to_string();
+            pos = name_at + name_len;
+            let entry = PackEntry { offset: u64_at(pos), len: u64_at(pos + 8), crc: u32_at(pos + 16) };
+            pos = next;
+            if end(entry.offset, entry.len).is_none() {
+                return Err(err("entry out of range"))
+            }
+            entries.insert(name, entry);
+        }
+        Ok(PackArchive { map, entries, modified })
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    // check every file against its checksum, returns the corrupted ones
+    pub fn verify(&self) -> Vec<String> {
+        let mut bad: Vec<String> = self.entries.iter()
+            .filter(|(_, e)| crc32fast::hash(&self.map[e.offset..e.offset + e.len]) != e.crc)
+            .map(|(name, _)| name.clone()).collect();
+        bad.sort();
+        bad
+    }
+}
+
+// reads one packed file out of the shared mapping
+struct PackFileReader {
+    map: Arc<Mmap>,
+    start: usize,
+    len: usize,
+    pos: usize,
+}
+impl Read for PackFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len {
+            return Ok(0)
+        }
+        let n = buf.len().min(self.len - self.pos);
+        let at = self.start + self.pos;
+        buf[..n].copy_from_slice(&self.map[at..at + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+impl Seek for PackFileReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => usize::try_from(p).ok(),
+            SeekFrom::End(d) => self.len.checked_add_signed(d as isize),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d as isize),
+        };
+        let Some(target) = target else {
+            return Err(invalid("seek out of range".to_string()))
+        };
+        self.pos = target;
+        Ok(self.pos as u64)
+    }
+}
+
+impl MountSource for PackArchive {
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+    fn open(&self, path: &str) -> std::io::Result<VfsFile> {
+        let e = self.entries.get(path).ok_or_else(|| invalid(format!("{}: not in pack", path)))?;
+        Ok(Box::new(PackFileReader { map: self.map.clone(), start: e.offset, len: e.len, pos: 0 }))
+    }
+    fn map(&self, path: &str) -> std::io::Result<MappedFile> {
+        let e = self.entries.get(path).ok_or_else(|| invalid(format!("{}: not in pack", path)))?;
+        Ok(MappedFile { map: self.map.clone(), offset: e.offset, len: e.len })
+    }
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        if self.entries.contains_key(path) { self.modified } else { None }
+    }
+    fn files(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+}
+
+pub struct PackWriter {
+    writer: BufWriter<File>,
+    pos: u64,
+    index: Vec<(String, u64, u64, u32)>,
+}
+impl PackWriter {
+    pub fn create(path: &Path) -> std::io::Result<PackWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // header is rewritten by finish
+        writer.write_all(&[0u8; HEADER_SIZE])?;
+        Ok(PackWriter { writer, pos: HEADER_SIZE as u64, index: Vec::new() })
+    }
+    // path is the vfs path of the file inside the pack
+    pub fn add(&mut self, path: &str, data: &[u8]) -> std::io::Result<()> {
+        let path = normalize(path);
+        if self.index.iter().any(|e| e.0 == path) {
+            return Err(invalid(format!("{}: already in pack", path)))
+        }
+        let start = aligned(self.pos);
+        self.writer.write_all(&[0u8; BLOB_ALIGN][..(start - self.pos) as usize])?;
+        self.writer.write_all(data)?;
+        self.pos = start + data.len() as u64;
+        self.index.push((path, start, data.len() as u64, crc32fast::hash(data)));
+        Ok(())
+    }
+    pub fn add_file(&mut self, path: &str, disk_path: &Path) -> std::io::Result<()> {
+        let data = fs::read(disk_path)?;
+        self.add(path, &data)
+    }
+    pub fn finish(mut self) -> std::io::Result<()> {
+        let index_offset = self.pos;
+        for (path, offset, len, crc) in self.index.iter() {
+            self.writer.write_all(&(path.len() as u32).to_le_bytes())?;
+            self.writer.write_all(path.as_bytes())?;
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&len.to_le_bytes())?;
+            self.writer.write_all(&crc.to_le_bytes())?;
+        }
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(PACK_MAGIC)?;
+        self.writer.write_all(&PACK_VERSION.to_le_bytes())?;
+        self.writer.write_all(&(self.index.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
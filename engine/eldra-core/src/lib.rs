@@ -2,13 +2,18 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 #![allow(dead_code)]
+// the C exports dereference the raw pointers given by the host
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
 
 //extern crate wgpu;
 extern crate wgpu_native;
 
 pub mod engine;
+pub mod log;
+pub mod fs;
 pub mod entity;
 pub mod data;
 pub mod comp;
 pub mod reflection;
 pub mod shader;
+pub mod scene;
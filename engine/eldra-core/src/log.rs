@@ -0,0 +1,93 @@
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+
+/**
+ * Engine log, what the engine reports without failing a call (reload errors, skipped data...).
+ *
+ * messages go to the sink set by the host (Log_set_callback) or stderr when there is none,
+ * those below the minimum level are dropped. worker threads log too, sinks must be Send + Sync.
+ * use the log_error!/log_warn!/log_info! macros, they take format! arguments.
+ */
+#[repr(u32)]
+#[derive(Clone,Copy,PartialEq,PartialOrd,Debug)]
+pub enum LogLevel {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+impl LogLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+pub type LogSink = Arc<dyn Fn(LogLevel, &str) + Send + Sync>;
+pub type LogCallback = unsafe extern "C" fn(level: u32, message: *const c_char);
+
+static LOG_LEVEL: AtomicU32 = AtomicU32::new(LogLevel::Info as u32);
+static LOG_SINK: RwLock<Option<LogSink>> = RwLock::new(None);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u32, Ordering::Relaxed);
+}
+// None restores stderr, returns the previous sink
+pub fn set_log_sink(sink: Option<LogSink>) -> Option<LogSink> {
+    std::mem::replace(&mut *LOG_SINK.write().unwrap(), sink)
+}
+pub fn log(level: LogLevel, message: &str) {
+    if (level as u32) < LOG_LEVEL.load(Ordering::Relaxed) {
+        return
+    }
+    // called outside of the lock, a sink may log or replace itself
+    let sink = LOG_SINK.read().unwrap().clone();
+    match sink {
+        Some(sink) => sink(level, message),
+        None => eprintln!("[{}] {}", level.name(), message),
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Error, &format!($($arg)*)) };
+}
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Warn, &format!($($arg)*)) };
+}
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Info, &format!($($arg)*)) };
+}
+
+//// exports
+
+// null restores stderr, the message is only valid during the call
+#[no_mangle]
+pub extern "C"
+fn Log_set_callback(callback: Option<LogCallback>) {
+    set_log_sink(callback.map(|callback| -> LogSink {
+        Arc::new(move |level, message| {
+            let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+            unsafe { callback(level as u32, message.as_ptr()) }
+        })
+    }));
+}
+// messages below level are dropped, see LogLevel
+#[no_mangle]
+pub extern "C"
+fn Log_set_level(level: u32) {
+    set_log_level(match level {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Info,
+        2 => LogLevel::Warn,
+        _ => LogLevel::Error,
+    });
+}
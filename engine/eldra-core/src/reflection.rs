@@ -2,6 +2,7 @@ use std::any::{Any, TypeId};
 use std::fs::File;
 use std::collections::HashMap;
 use std::io::{Read, Write, BufWriter};
+use std::path::Path;
 use std::ptr::addr_of_mut;
 use std::rc::Rc;
 use std::str::FromStr;
@@ -11,6 +12,9 @@ use once_cell::sync::OnceCell;
 use uuid::Uuid;
 use yaml_rust2::{Yaml, YamlLoader};
 use std::mem::MaybeUninit;
+use crate::comp::animator_component::AnimatorComponent;
+use crate::comp::camera_component::CameraComponent;
+use crate::comp::light_component::{AreaLight, DirectionalLight, PointLight, SpotLight};
 use crate::comp::render_component::RenderComponent;
 use crate::comp::transform_component::TransformComponent;
 use crate::entity::Component;
@@ -37,6 +41,12 @@ pub unsafe fn init_reflection() {
     let reg = &mut DYN_NEW_REG.get_mut().unwrap_unchecked().Component;
     register_serializable_type!(reg, TransformComponent);
     register_serializable_type!(reg, RenderComponent);
+    register_serializable_type!(reg, AnimatorComponent);
+    register_serializable_type!(reg, CameraComponent);
+    register_serializable_type!(reg, DirectionalLight);
+    register_serializable_type!(reg, PointLight);
+    register_serializable_type!(reg, SpotLight);
+    register_serializable_type!(reg, AreaLight);
     register_shader_graph_components(reg);
 }
 
@@ -84,11 +94,14 @@ pub struct SerializeTextWriter {
 }
 impl SerializeTextWriter {
     pub fn new(filepath: &str) -> SerializeTextWriter {
-        let file = File::create(filepath).unwrap();
-        SerializeTextWriter {
+        Self::create(Path::new(filepath)).unwrap()
+    }
+    pub fn create(filepath: &Path) -> std::io::Result<SerializeTextWriter> {
+        let file = File::create(filepath)?;
+        Ok(SerializeTextWriter {
             writer: BufWriter::new(file),
             newline: false,
-        }
+        })
     }
     pub fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
         self.newline = false;
@@ -120,7 +133,7 @@ impl Serializable for bool {
     fn deserialize_binary(&mut self, io: &mut dyn Read) {
         let mut d: [u8; 1] = [0];
         let _ = io.read_exact(&mut d);
-        unsafe { *addr_of_mut!(*self) = d[0] == 0; };
+        unsafe { *addr_of_mut!(*self) = d[0] != 0; };
     }
 
     fn serialize_text(&self, io: &mut SerializeTextWriter, _indent: String) {
@@ -187,7 +200,8 @@ macro_rules! impl_primitive_serialize {
             fn deserialize_text(&mut self, yaml: &Yaml) {
                 match yaml.$yamlconv() {
                     Some(v) => { *self = v as $x; },
-                    None => {},
+                    // integral floats are written without a fraction ("1")
+                    None => if let Some(v) = yaml.as_i64() { *self = v as $x; },
                 }
             }
         }
@@ -206,14 +220,20 @@ impl<T, R, C, S> Serializable for Matrix<T, R, C, S> where T: Serializable + Def
     fn is_multi_line(&self) -> bool { false }
     fn get_type_uuid(&self) -> Option<uuid::Uuid> { None }
     fn serialize_binary(&self, io: &mut dyn Write) {
+        // gather elements and issue a single write
+        let mut buf = Vec::<u8>::with_capacity(self.len() * std::mem::size_of::<T>());
         self.iter().for_each(|e| {
-            e.serialize_binary(io);
+            e.serialize_binary(&mut buf);
         });
+        let _ = io.write_all(buf.as_slice());
     }
 
     fn deserialize_binary(&mut self, io: &mut dyn Read) {
+        let mut buf = vec![0u8; self.len() * std::mem::size_of::<T>()];
+        let _ = io.read_exact(buf.as_mut_slice());
+        let mut bytes = buf.as_slice();
         self.iter_mut().for_each(|e| {
-            e.deserialize_binary(io);
+            e.deserialize_binary(&mut bytes);
         });
     }
 
@@ -373,6 +393,72 @@ macro_rules! impl_vec_embed_serialize {
                 }
                 else {
                     for item in self.iter() {
+                        if !item.is_multi_line() {
+                            // scalar items inline
+                            let _ = io.write_all(format!("{}- ", indent.clone()).as_bytes());
+                            item.serialize_text(io, indent.clone() + "  ");
+                            io.newline();
+                            continue
+                        }
+                        let _ = io.write_all(format!("{}- array_item :", indent.clone()).as_bytes());
+                        io.newline();
+                        item.serialize_text(io, indent.clone() + "  ");
+                        io.newline();
+                    }
+                }
+            }
+
+            fn deserialize_text(&mut self, data: &Yaml) {
+                let arr = data.as_vec().unwrap();
+                self.reserve(arr.len());
+                for yaml in arr {
+                    let mut item = $x::default();
+                    item.deserialize_text(yaml);
+                    self.push(item);
+                }
+            }
+        }
+    }
+}
+// plain-old-data vector, binary form is the raw little-endian bytes written in one go
+#[cfg(target_endian = "big")]
+compile_error!("binary serialization assumes a little-endian target");
+pub fn pod_as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+pub fn pod_as_bytes_mut<T: Copy>(data: &mut [T]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, std::mem::size_of_val(data)) }
+}
+#[macro_export]
+macro_rules! impl_vec_pod_serialize {
+    ( $x:ident ) => {
+        impl $crate::reflection::Serializable for Vec<$x> {
+            fn is_multi_line(&self) -> bool { !self.is_empty() }
+            fn get_type_uuid(&self) -> Option<uuid::Uuid> { None }
+            fn serialize_binary(&self, io: &mut dyn Write) {
+                (self.len() as i64).serialize_binary(io);
+                let _ = io.write_all($crate::reflection::pod_as_bytes(self.as_slice()));
+            }
+
+            fn deserialize_binary(&mut self, io: &mut dyn Read) {
+                let mut len: i64 = 0;
+                len.deserialize_binary(io);
+                $crate::data::blob::read_pod_vec(io, len, self);
+            }
+
+            fn serialize_text(&self, io: &mut $crate::reflection::SerializeTextWriter, indent: String) {
+                if self.is_empty() {
+                    let _ = io.write_all("[]".as_bytes());
+                }
+                else {
+                    for item in self.iter() {
+                        if !item.is_multi_line() {
+                            // scalar items inline
+                            let _ = io.write_all(format!("{}- ", indent.clone()).as_bytes());
+                            item.serialize_text(io, indent.clone() + "  ");
+                            io.newline();
+                            continue
+                        }
                         let _ = io.write_all(format!("{}- array_item :", indent.clone()).as_bytes());
                         io.newline();
                         item.serialize_text(io, indent.clone() + "  ");
@@ -393,18 +479,20 @@ macro_rules! impl_vec_embed_serialize {
         }
     }
 }
-impl_vec_embed_serialize!(u8);
-impl_vec_embed_serialize!(i8);
-impl_vec_embed_serialize!(u16);
-impl_vec_embed_serialize!(i16);
-impl_vec_embed_serialize!(u32);
-impl_vec_embed_serialize!(i32);
-impl_vec_embed_serialize!(i64);
-impl_vec_embed_serialize!(f32);
-impl_vec_embed_serialize!(f64);
-impl_vec_embed_serialize!(Vec2f);
-impl_vec_embed_serialize!(Vec3f);
-impl_vec_embed_serialize!(Vec4f);
+impl_vec_pod_serialize!(u8);
+impl_vec_pod_serialize!(i8);
+impl_vec_pod_serialize!(u16);
+impl_vec_pod_serialize!(i16);
+impl_vec_pod_serialize!(u32);
+impl_vec_pod_serialize!(i32);
+impl_vec_pod_serialize!(i64);
+impl_vec_pod_serialize!(f32);
+impl_vec_pod_serialize!(f64);
+impl_vec_pod_serialize!(Vec2f);
+impl_vec_pod_serialize!(Vec3f);
+impl_vec_pod_serialize!(Vec4f);
+impl_vec_embed_serialize!(String);
+impl_vec_embed_serialize!(Uuid);
 
 macro_rules! impl_ptr_serialize {
     ( $x:ident,$y:ident ) => {
@@ -0,0 +1,285 @@
+use crate::data::bounds::{Aabb, Frustum, Ray};
+use crate::reflection::Vec3f;
+
+/**
+ * Dynamic bounding volume hierarchy, as Box2D's dynamic tree.
+ * leaves hold the boxes of proxies enlarged by a margin, so small moves keep them in place.
+ * a leaf is inserted next to the sibling of least surface area cost and the tree is kept
+ * balanced by rotations on the way up. queries test the enlarged boxes, callers filter with
+ * the exact ones.
+ */
+const NULL: usize = usize::MAX;
+
+#[derive(Clone)]
+struct BvhNode<T> {
+    aabb: Aabb,
+    parent: usize,
+    left: usize,
+    right: usize,
+    // leaves are 0, free nodes -1
+    height: i32,
+    data: Option<T>,
+}
+impl<T> BvhNode<T> {
+    fn is_leaf(&self) -> bool {
+        self.left == NULL
+    }
+}
+
+pub struct DynamicBvh<T> {
+    nodes: Vec<BvhNode<T>>,
+    free: Vec<usize>,
+    root: usize,
+    pub margin: f32,
+}
+impl<T: Copy> Default for DynamicBvh<T> {
+    fn default() -> Self {
+        DynamicBvh::new(0.1)
+    }
+}
+impl<T: Copy> DynamicBvh<T> {
+    pub fn new(margin: f32) -> DynamicBvh<T> {
+        DynamicBvh { nodes: Vec::new(), free: Vec::new(), root: NULL, margin }
+    }
+    fn allocate(&mut self) -> usize {
+        let node = BvhNode { aabb: Aabb::empty(), parent: NULL, left: NULL, right: NULL, height: 0, data: None };
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+    fn release(&mut self, i: usize) {
+        self.nodes[i].height = -1;
+        self.nodes[i].data = None;
+        self.free.push(i);
+    }
+    fn fatten(&self, aabb: &Aabb) -> Aabb {
+        let margin = Vec3f::repeat(self.margin);
+        Aabb::new(aabb.min - margin, aabb.max + margin)
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.iter().filter(|n| n.height == 0).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.root == NULL
+    }
+    pub fn height(&self) -> i32 {
+        if self.root == NULL { 0 } else { self.nodes[self.root].height }
+    }
+    pub fn insert(&mut self, aabb: &Aabb, data: T) -> usize {
+        let leaf = self.allocate();
+        self.nodes[leaf].aabb = self.fatten(aabb);
+        self.nodes[leaf].data = Some(data);
+        self.insert_leaf(leaf);
+        leaf
+    }
+    pub fn remove(&mut self, proxy: usize) {
+        assert!(self.nodes.get(proxy).is_some_and(|n| n.height == 0), "invalid bvh proxy {}", proxy);
+        self.remove_leaf(proxy);
+        self.release(proxy);
+    }
+    // reinserts the proxy when its box left the enlarged one, returns whether it did
+    pub fn update(&mut self, proxy: usize, aabb: &Aabb) -> bool {
+        assert!(self.nodes.get(proxy).is_some_and(|n| n.height == 0), "invalid bvh proxy {}", proxy);
+        if self.nodes[proxy].aabb.contains(aabb) {
+            return false
+        }
+        self.remove_leaf(proxy);
+        self.nodes[proxy].aabb = self.fatten(aabb);
+        self.insert_leaf(proxy);
+        true
+    }
+    pub fn fat_aabb(&self, proxy: usize) -> &Aabb {
+        &self.nodes[proxy].aabb
+    }
+    pub fn data(&self, proxy: usize) -> Option<T> {
+        self.nodes.get(proxy).and_then(|n| n.data)
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return
+        }
+        // descend while it is cheaper than making a sibling here
+        let leaf_aabb = self.nodes[leaf].aabb;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+            let area = node.aabb.surface_area();
+            let combined = node.aabb.merge(&leaf_aabb).surface_area();
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - area);
+            let child_cost = |c: usize| {
+                let child = &self.nodes[c];
+                let merged = child.aabb.merge(&leaf_aabb).surface_area();
+                if child.is_leaf() { merged + inheritance } else { merged - child.aabb.surface_area() + inheritance }
+            };
+            let (left, right) = (node.left, node.right);
+            let (cost_left, cost_right) = (child_cost(left), child_cost(right));
+            if cost < cost_left && cost < cost_right {
+                break
+            }
+            index = if cost_left < cost_right { left } else { right };
+        }
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate();
+        self.nodes[parent].parent = old_parent;
+        self.nodes[parent].aabb = leaf_aabb.merge(&self.nodes[sibling].aabb);
+        self.nodes[parent].height = self.nodes[sibling].height + 1;
+        self.nodes[parent].left = sibling;
+        self.nodes[parent].right = leaf;
+        self.nodes[sibling].parent = parent;
+        self.nodes[leaf].parent = parent;
+        if old_parent == NULL {
+            self.root = parent;
+        } else if self.nodes[old_parent].left == sibling {
+            self.nodes[old_parent].left = parent;
+        } else {
+            self.nodes[old_parent].right = parent;
+        }
+        self.fix_upwards(parent);
+    }
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return
+        }
+        let parent = self.nodes[leaf].parent;
+        let grand_parent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf { self.nodes[parent].right } else { self.nodes[parent].left };
+        self.release(parent);
+        self.nodes[sibling].parent = grand_parent;
+        if grand_parent == NULL {
+            self.root = sibling;
+            return
+        }
+        if self.nodes[grand_parent].left == parent {
+            self.nodes[grand_parent].left = sibling;
+        } else {
+            self.nodes[grand_parent].right = sibling;
+        }
+        self.fix_upwards(grand_parent);
+    }
+    // balances and refits from a node to the root
+    fn fix_upwards(&mut self, mut index: usize) {
+        while index != NULL {
+            index = self.balance(index);
+            let (left, right) = (self.nodes[index].left, self.nodes[index].right);
+            self.nodes[index].height = 1 + self.nodes[left].height.max(self.nodes[right].height);
+            self.nodes[index].aabb = self.nodes[left].aabb.merge(&self.nodes[right].aabb);
+            index = self.nodes[index].parent;
+        }
+    }
+    // rotates the higher child of a up when the heights differ by more than 1, returns the
+    // node now in place of a
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a
+        }
+        let (b, c) = (self.nodes[a].left, self.nodes[a].right);
+        let balance = self.nodes[c].height - self.nodes[b].height;
+        let (up, other, up_is_right) = match balance {
+            _ if balance > 1 => (c, b, true),
+            _ if balance < -1 => (b, c, false),
+            _ => return a,
+        };
+        let (f, g) = (self.nodes[up].left, self.nodes[up].right);
+        // up takes the place of a, a becomes its left child
+        self.nodes[up].left = a;
+        self.nodes[up].parent = self.nodes[a].parent;
+        self.nodes[a].parent = up;
+        let parent = self.nodes[up].parent;
+        if parent == NULL {
+            self.root = up;
+        } else if self.nodes[parent].left == a {
+            self.nodes[parent].left = up;
+        } else {
+            self.nodes[parent].right = up;
+        }
+        // the higher grandchild stays with up, the other goes to a in place of up
+        let (keep, give) = if self.nodes[f].height > self.nodes[g].height { (f, g) } else { (g, f) };
+        self.nodes[up].right = keep;
+        if up_is_right {
+            self.nodes[a].right = give;
+        } else {
+            self.nodes[a].left = give;
+        }
+        self.nodes[give].parent = a;
+        self.nodes[a].aabb = self.nodes[other].aabb.merge(&self.nodes[give].aabb);
+        self.nodes[a].height = 1 + self.nodes[other].height.max(self.nodes[give].height);
+        self.nodes[up].aabb = self.nodes[a].aabb.merge(&self.nodes[keep].aabb);
+        self.nodes[up].height = 1 + self.nodes[a].height.max(self.nodes[keep].height);
+        up
+    }
+
+    // proxies whose enlarged box passes the test, the test prunes the subtrees it fails
+    pub fn query<F: Fn(&Aabb) -> bool>(&self, test: F) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(i) = stack.pop() {
+            if i == NULL || !test(&self.nodes[i].aabb) {
+                continue
+            }
+            if self.nodes[i].is_leaf() {
+                found.push(i);
+            } else {
+                stack.extend([self.nodes[i].left, self.nodes[i].right]);
+            }
+        }
+        found
+    }
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+        self.query(|b| b.intersects(aabb))
+    }
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(|b| frustum.intersects_aabb(b))
+    }
+    // (distance to the enlarged box, proxy), nearest first
+    pub fn query_ray(&self, ray: &Ray, max_distance: f32) -> Vec<(f32, usize)> {
+        let mut found: Vec<(f32, usize)> = self.query(|b| b.ray_distance(ray, max_distance).is_some()).into_iter()
+            .map(|p| (self.nodes[p].aabb.ray_distance(ray, max_distance).unwrap(), p)).collect();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found
+    }
+
+    // links, heights and boxes are consistent
+    pub fn validate(&self) -> Result<(), String> {
+        if self.root == NULL {
+            return Ok(())
+        }
+        if self.nodes[self.root].parent != NULL {
+            return Err("root has a parent".to_string())
+        }
+        let mut stack = vec![self.root];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.is_leaf() {
+                if node.height != 0 || node.data.is_none() {
+                    return Err(format!("bad leaf {}", i))
+                }
+                continue
+            }
+            let (l, r) = (&self.nodes[node.left], &self.nodes[node.right]);
+            if l.parent != i || r.parent != i {
+                return Err(format!("bad parent links under {}", i))
+            }
+            if node.height != 1 + l.height.max(r.height) {
+                return Err(format!("bad height at {}", i))
+            }
+            if node.aabb != l.aabb.merge(&r.aabb) {
+                return Err(format!("bad box at {}", i))
+            }
+            stack.extend([node.left, node.right]);
+        }
+        Ok(())
+    }
+}
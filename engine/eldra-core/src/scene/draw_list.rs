@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use uuid::Uuid;
+use crate::comp::animator_component::AnimatorComponent;
+use crate::comp::camera_component::CameraComponent;
+use crate::comp::render_component::RenderComponent;
+use crate::comp::transform_component::TransformComponent;
+use crate::data::ExtRes;
+use crate::data::bounds::Frustum;
+use crate::data::material::{AlphaMode, Material};
+use crate::data::render_object::RenderObject;
+use crate::entity::Entity;
+use crate::reflection::{Mat4f, Vec3f};
+use crate::scene::Scene;
+
+/**
+ * Render extraction of the primitives a camera draws.
+ * the BVH of the scene gives the entities in the frustum, then each primitive of their
+ * RenderComponent is culled by its own world bounds and the layers the camera sees.
+ * every draw carries its world matrix, LOD, material and skin palette, so the lists can be
+ * recorded without touching the entities again. materials and palettes are shared by index.
+ * opaque draws are sorted by material then front to back, alpha tested ones after the others,
+ * transparent draws back to front. shadow draws are the casters in a light view, or in the
+ * camera view without one, with the LODs of the camera.
+ * skinned primitives are culled with their bind pose bounds, as the scene.
+ */
+// where the draws are seen from
+pub struct RenderView {
+    pub eye: Vec3f,
+    // normalized, depths are along it
+    pub forward: Vec3f,
+    pub fov_y: f32,
+    pub frustum: Frustum,
+    pub layer_mask: u32,
+}
+impl RenderView {
+    pub fn from_camera(camera: &CameraComponent, width: u32, height: u32) -> RenderView {
+        RenderView {
+            eye: camera.world_matrix.column(3).xyz(),
+            forward: (-camera.world_matrix.column(2).xyz()).try_normalize(0.0).unwrap_or(-Vec3f::z()),
+            fov_y: camera.fov_y,
+            frustum: camera.frustum(width, height),
+            layer_mask: camera.layer_mask,
+        }
+    }
+    pub fn depth(&self, p: &Vec3f) -> f32 {
+        (p - self.eye).dot(&self.forward)
+    }
+}
+
+pub struct DrawItem {
+    pub entity: i64,
+    pub object: ExtRes<RenderObject>,
+    pub part: u32,
+    pub primitive: u32,
+    // see Primitive::lod_indices
+    pub lod: u32,
+    pub world: Mat4f,
+    // in DrawLists.materials
+    pub material: u32,
+    // joints in DrawLists.palettes, 0 long when not skinned
+    pub palette_offset: u32,
+    pub palette_len: u32,
+    // of the bounding sphere center in the view of the list
+    pub depth: f32,
+    pub sort_key: u64,
+}
+
+// material then depth, alpha tested last
+pub fn opaque_key(material: u32, depth: f32, alpha_tested: bool) -> u64 {
+    ((alpha_tested as u64) << 63) | ((material as u64 & 0x7fff_ffff) << 32) | depth.max(0.0).to_bits() as u64
+}
+// depth, farthest first, then material
+pub fn transparent_key(material: u32, depth: f32) -> u64 {
+    ((!depth.max(0.0).to_bits() as u64) << 32) | material as u64
+}
+
+#[derive(Default)]
+pub struct DrawLists {
+    pub opaque: Vec<DrawItem>,
+    pub transparent: Vec<DrawItem>,
+    pub shadow: Vec<DrawItem>,
+    pub materials: Vec<ExtRes<Material>>,
+    // skinning matrices of the draws
+    pub palettes: Vec<Mat4f>,
+}
+
+// skinning matrices of the animator driving the skeleton of the component, on the entity or an
+// ancestor, the bind pose without one. empty when the component is not skinned
+pub fn skin_palette(entity: &Entity, render: &RenderComponent) -> Vec<Mat4f> {
+    let Some(skeleton) = render.skeleton().filter(|s| s.is_loaded()) else { return Vec::new() };
+    let animated = |e: &Entity| e.get_component::<AnimatorComponent>()
+        .filter(|a| a.skeleton.guid() == skeleton.guid() && !a.skin_matrices.is_empty())
+        .map(|a| a.skin_matrices.clone());
+    if let Some(palette) = animated(entity) {
+        return palette
+    }
+    let mut parent = entity.get_parent();
+    while let Some(p) = parent {
+        if let Some(palette) = animated(&p.borrow()) {
+            return palette
+        }
+        parent = p.borrow().get_parent();
+    }
+    let skeleton = skeleton.get();
+    skeleton.rest_world_matrices().iter().zip(skeleton.joints.iter()).map(|(m, j)| m * j.inverse_bind).collect()
+}
+
+struct Extraction<'a> {
+    lists: DrawLists,
+    view: &'a RenderView,
+    materials: HashMap<Uuid, u32>,
+    // offset and length by instance id
+    palettes: HashMap<i64, (u32, u32)>,
+}
+impl Extraction<'_> {
+    fn material(&mut self, material: &ExtRes<Material>) -> u32 {
+        let count = self.lists.materials.len() as u32;
+        let index = *self.materials.entry(*material.guid()).or_insert(count);
+        if index == count {
+            self.lists.materials.push(material.clone());
+        }
+        index
+    }
+    fn palette(&mut self, entity: &Entity, render: &RenderComponent) -> (u32, u32) {
+        if let Some(p) = self.palettes.get(&entity.instance_id) {
+            return *p
+        }
+        let palette = skin_palette(entity, render);
+        let p = (self.lists.palettes.len() as u32, palette.len() as u32);
+        self.lists.palettes.extend(palette);
+        self.palettes.insert(entity.instance_id, p);
+        p
+    }
+    // the primitives of the entity in the frustum of cull, into the camera lists or the shadow one
+    fn visit(&mut self, entity: &Rc<RefCell<Entity>>, cull: &RenderView, shadow: bool) {
+        let e = entity.borrow();
+        let Some(render) = e.get_component::<RenderComponent>() else { return };
+        if self.view.layer_mask & render.layers == 0 || (shadow && !render.cast_shadows) {
+            return
+        }
+        if render.object.is_null() || !render.object.is_loaded() {
+            return
+        }
+        let world = e.get_component::<TransformComponent>().map_or(Mat4f::identity(), |t| t.world_matrix);
+        let object = render.object.get();
+        let first_part = if render.part >= 0 && (render.part as usize) < object.parts.len() { render.part as usize } else { 0 };
+        for (pi, part) in object.drawn_parts(render.part).iter().enumerate() {
+            for (ri, prim) in part.primitives.iter().enumerate() {
+                if !prim.aabb.is_empty() && !cull.frustum.intersects_aabb(&prim.aabb.transformed(&world)) {
+                    continue
+                }
+                let alpha = if prim.material.is_loaded() { prim.material.get().alpha_mode } else { AlphaMode::OPAQUE };
+                if shadow && alpha == AlphaMode::BLEND {
+                    continue
+                }
+                let material = self.material(&prim.material);
+                let (palette_offset, palette_len) = if prim.skin.is_empty() { (0, 0) } else { self.palette(&e, render) };
+                let depth = cull.depth(&prim.sphere.transformed(&world).center);
+                let sort_key = match alpha {
+                    AlphaMode::BLEND if !shadow => transparent_key(material, depth),
+                    _ => opaque_key(material, depth, alpha == AlphaMode::MASK),
+                };
+                let draw = DrawItem {
+                    entity: e.instance_id,
+                    object: render.object.clone(),
+                    part: (first_part + pi) as u32,
+                    primitive: ri as u32,
+                    lod: render.select_lod(prim, &world, &self.view.eye, self.view.fov_y) as u32,
+                    world,
+                    material,
+                    palette_offset,
+                    palette_len,
+                    depth,
+                    sort_key,
+                };
+                match alpha {
+                    _ if shadow => self.lists.shadow.push(draw),
+                    AlphaMode::BLEND => self.lists.transparent.push(draw),
+                    _ => self.lists.opaque.push(draw),
+                }
+            }
+        }
+    }
+}
+
+impl DrawLists {
+    // from the bounds of the last Scene::update
+    pub fn extract(scene: &Scene, view: &RenderView, shadow_view: Option<&RenderView>) -> DrawLists {
+        let mut extraction = Extraction { lists: DrawLists::default(), view, materials: HashMap::new(), palettes: HashMap::new() };
+        for entity in scene.query_frustum(&view.frustum) {
+            extraction.visit(&entity, view, false);
+        }
+        let shadow_view = shadow_view.unwrap_or(view);
+        for entity in scene.query_frustum(&shadow_view.frustum) {
+            extraction.visit(&entity, shadow_view, true);
+        }
+        let mut lists = extraction.lists;
+        for list in [&mut lists.opaque, &mut lists.transparent, &mut lists.shadow] {
+            list.sort_by_key(|d| d.sort_key);
+        }
+        lists
+    }
+    pub fn from_camera(scene: &Scene, camera: &CameraComponent, width: u32, height: u32) -> DrawLists {
+        DrawLists::extract(scene, &RenderView::from_camera(camera, width, height), None)
+    }
+    pub fn len(&self) -> usize {
+        self.opaque.len() + self.transparent.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::comp::light_component::{AreaLight, DirectionalLight, PointLight, SpotLight};
+use crate::comp::transform_component::TransformComponent;
+use crate::entity::Entity;
+use crate::reflection::{Mat4f, Vec3f};
+use crate::scene::with_scene;
+
+/**
+ * Render extraction of the lights of an entity tree.
+ * every enabled light component becomes a GpuLight in world space, grouped by kind in the
+ * order of LightKind so shaders can loop over each kind, and in tree order within a kind.
+ * colors are premultiplied by the intensity, in the unit of the kind.
+ * the packed bytes are a 16 bytes header of the 4 counts followed by the lights, laid out
+ * for a std430 storage buffer, then the shadow casters.
+ */
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum LightKind {
+    DIRECTIONAL = 0,
+    POINT = 1,
+    SPOT = 2,
+    AREA = 3,
+}
+// above the kind in GpuLight.kind
+pub const LIGHT_FLAG_TWO_SIDED: u32 = 1 << 8;
+
+#[repr(C)]
+#[derive(Clone,Copy,Default,PartialEq,Debug)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    // 0 is infinite
+    pub range: f32,
+    // normalized -Z of the light
+    pub direction: [f32; 3],
+    // LightKind, with the LIGHT_FLAG_ bits
+    pub kind: u32,
+    pub color: [f32; 3],
+    // in LightData.shadows, -1 without shadows
+    pub shadow: i32,
+    // half the width of area lights along their X
+    pub right: [f32; 3],
+    // cone attenuation is saturate(cos * spot_scale + spot_offset), 1 for other kinds
+    pub spot_scale: f32,
+    // half the height of area lights along their Y
+    pub up: [f32; 3],
+    pub spot_offset: f32,
+}
+
+#[repr(C)]
+#[derive(Clone,Copy,Default,PartialEq,Debug)]
+pub struct GpuShadow {
+    // in LightData.lights
+    pub light: u32,
+    pub resolution: u32,
+    pub bias: f32,
+    pub normal_bias: f32,
+}
+
+#[derive(Default)]
+pub struct LightData {
+    pub lights: Vec<GpuLight>,
+    // by LightKind
+    pub counts: [u32; 4],
+    pub shadows: Vec<GpuShadow>,
+    // of each light
+    pub entities: Vec<i64>,
+}
+
+struct Extracted {
+    kind: LightKind,
+    light: GpuLight,
+    shadow: Option<GpuShadow>,
+    entity: i64,
+}
+struct ShadowSettings {
+    cast: bool,
+    resolution: u32,
+    bias: f32,
+    normal_bias: f32,
+}
+impl ShadowSettings {
+    fn gpu(&self) -> Option<GpuShadow> {
+        if self.cast { Some(GpuShadow { light: 0, resolution: self.resolution, bias: self.bias, normal_bias: self.normal_bias }) } else { None }
+    }
+}
+
+fn light(world: &Mat4f, kind: LightKind, color: &Vec3f, intensity: f32, range: f32) -> GpuLight {
+    let direction = -world.column(2).xyz();
+    GpuLight {
+        position: world.column(3).xyz().into(),
+        range,
+        direction: direction.try_normalize(0.0).unwrap_or(-Vec3f::z()).into(),
+        kind: kind as u32,
+        color: (color * intensity).into(),
+        shadow: -1,
+        right: [0.0; 3],
+        spot_scale: 0.0,
+        up: [0.0; 3],
+        spot_offset: 1.0,
+    }
+}
+
+fn visit(entity: &Rc<RefCell<Entity>>, found: &mut Vec<Extracted>) {
+    let e = entity.borrow();
+    let world = e.get_component::<TransformComponent>().map_or(Mat4f::identity(), |t| t.world_matrix);
+    let mut push = |kind, light, shadows: ShadowSettings| {
+        found.push(Extracted { kind, light, shadow: shadows.gpu(), entity: e.instance_id });
+    };
+    if let Some(l) = e.get_component::<DirectionalLight>().filter(|l| l.enabled) {
+        let mut gpu = light(&world, LightKind::DIRECTIONAL, &l.color, l.intensity, 0.0);
+        gpu.position = [0.0; 3];
+        push(LightKind::DIRECTIONAL, gpu, ShadowSettings { cast: l.cast_shadows, resolution: l.shadow_resolution, bias: l.shadow_bias, normal_bias: l.shadow_normal_bias });
+    }
+    if let Some(l) = e.get_component::<PointLight>().filter(|l| l.enabled) {
+        let gpu = light(&world, LightKind::POINT, &l.color, l.intensity, l.range);
+        push(LightKind::POINT, gpu, ShadowSettings { cast: l.cast_shadows, resolution: l.shadow_resolution, bias: l.shadow_bias, normal_bias: l.shadow_normal_bias });
+    }
+    if let Some(l) = e.get_component::<SpotLight>().filter(|l| l.enabled) {
+        let mut gpu = light(&world, LightKind::SPOT, &l.color, l.intensity, l.range);
+        (gpu.spot_scale, gpu.spot_offset) = l.cone_scale_offset();
+        push(LightKind::SPOT, gpu, ShadowSettings { cast: l.cast_shadows, resolution: l.shadow_resolution, bias: l.shadow_bias, normal_bias: l.shadow_normal_bias });
+    }
+    if let Some(l) = e.get_component::<AreaLight>().filter(|l| l.enabled) {
+        let mut gpu = light(&world, LightKind::AREA, &l.color, l.intensity, l.range);
+        // the world scale stretches the rectangle
+        gpu.right = (world.column(0).xyz() * l.width * 0.5).into();
+        gpu.up = (world.column(1).xyz() * l.height * 0.5).into();
+        if l.two_sided {
+            gpu.kind |= LIGHT_FLAG_TWO_SIDED;
+        }
+        push(LightKind::AREA, gpu, ShadowSettings { cast: l.cast_shadows, resolution: l.shadow_resolution, bias: l.shadow_bias, normal_bias: l.shadow_normal_bias });
+    }
+    for c in e.children.iter() {
+        visit(c, found);
+    }
+}
+
+// from the world matrices of the last tick
+pub fn extract_lights(root: &Rc<RefCell<Entity>>) -> LightData {
+    let mut found = Vec::new();
+    visit(root, &mut found);
+    // stable, tree order is kept within a kind
+    found.sort_by_key(|l| l.kind as u32);
+    let mut data = LightData::default();
+    for (i, mut l) in found.into_iter().enumerate() {
+        if let Some(mut shadow) = l.shadow {
+            shadow.light = i as u32;
+            l.light.shadow = data.shadows.len() as i32;
+            data.shadows.push(shadow);
+        }
+        data.counts[l.kind as usize] += 1;
+        data.lights.push(l.light);
+        data.entities.push(l.entity);
+    }
+    data
+}
+
+impl LightData {
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+    pub fn of_kind(&self, kind: LightKind) -> &[GpuLight] {
+        let start: u32 = self.counts[..kind as usize].iter().sum();
+        &self.lights[start as usize..(start + self.counts[kind as usize]) as usize]
+    }
+    // byte offset of the shadows in as_bytes
+    pub fn shadows_offset(&self) -> usize {
+        std::mem::size_of::<[u32; 4]>() + self.lights.len() * std::mem::size_of::<GpuLight>()
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.shadows_offset() + self.shadows.len() * std::mem::size_of::<GpuShadow>());
+        for c in self.counts {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        // plain f32, u32 and i32 fields without padding
+        bytes.extend_from_slice(unsafe { std::slice::from_raw_parts(self.lights.as_ptr() as *const u8, self.lights.len() * std::mem::size_of::<GpuLight>()) });
+        bytes.extend_from_slice(unsafe { std::slice::from_raw_parts(self.shadows.as_ptr() as *const u8, self.shadows.len() * std::mem::size_of::<GpuShadow>()) });
+        bytes
+    }
+}
+
+//// exports
+
+// packed lights of the scene root into out, returns the byte size, written only if it fits
+#[no_mangle]
+pub extern "C"
+fn Scene_extract_lights(id: u64, out: *mut u8, capacity: u32) -> u32 {
+    let bytes = with_scene(id, |s| extract_lights(&s.root).as_bytes()).unwrap_or_default();
+    if !out.is_null() && bytes.len() <= capacity as usize {
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    }
+    bytes.len() as u32
+}
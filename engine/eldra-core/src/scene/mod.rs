@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+use crate::comp::render_component::RenderComponent;
+use crate::comp::transform_component::TransformComponent;
+use crate::data::bounds::{Aabb, Frustum, Ray};
+use crate::engine::engine_next_global_id;
+use crate::entity::{entity_cast, Entity};
+use crate::reflection::Mat4f;
+use crate::scene::bvh::DynamicBvh;
+
+pub mod bvh;
+pub mod draw_list;
+pub mod lights;
+pub mod picking;
+
+/**
+ * Spatial view of an entity tree, for culling and picking.
+ * every entity with a loaded RenderComponent gets a BVH proxy from its world bounds, the
+ * bounds of its RenderObject part moved by TransformComponent.world_matrix. update follows the
+ * entities after they tick, refitting only those whose matrix or object changed.
+ */
+struct SceneProxy {
+    proxy: usize,
+    entity: Weak<RefCell<Entity>>,
+    world: Mat4f,
+    local: Aabb,
+    bounds: Aabb,
+}
+
+pub struct Scene {
+    pub root: Rc<RefCell<Entity>>,
+    pub bvh: DynamicBvh<i64>,
+    // by instance id
+    proxies: HashMap<i64, SceneProxy>,
+}
+impl Scene {
+    pub fn new(root: Rc<RefCell<Entity>>) -> Scene {
+        Scene { root, bvh: DynamicBvh::default(), proxies: HashMap::new() }
+    }
+    // returns the count of proxies inserted, moved or removed
+    pub fn update(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        let mut changes = 0;
+        let root = self.root.clone();
+        self.visit(&root, &mut seen, &mut changes);
+        let gone: Vec<i64> = self.proxies.keys().filter(|id| !seen.contains(*id)).cloned().collect();
+        for id in gone {
+            let proxy = self.proxies.remove(&id).unwrap();
+            self.bvh.remove(proxy.proxy);
+            changes += 1;
+        }
+        changes
+    }
+    fn visit(&mut self, entity: &Rc<RefCell<Entity>>, seen: &mut HashSet<i64>, changes: &mut usize) {
+        let e = entity.borrow();
+        let local = e.get_component::<RenderComponent>().and_then(|r| r.local_bounds());
+        if let Some(local) = local {
+            let world = e.get_component::<TransformComponent>().map_or(Mat4f::identity(), |t| t.world_matrix);
+            seen.insert(e.instance_id);
+            match self.proxies.get_mut(&e.instance_id) {
+                Some(p) if p.world == world && p.local == local => {},
+                Some(p) => {
+                    p.bounds = local.transformed(&world);
+                    (p.world, p.local) = (world, local);
+                    if self.bvh.update(p.proxy, &p.bounds) {
+                        *changes += 1;
+                    }
+                },
+                None => {
+                    let bounds = local.transformed(&world);
+                    let proxy = self.bvh.insert(&bounds, e.instance_id);
+                    self.proxies.insert(e.instance_id, SceneProxy { proxy, entity: Rc::downgrade(entity), world, local, bounds });
+                    *changes += 1;
+                },
+            }
+        }
+        for c in e.children.iter() {
+            self.visit(c, seen, changes);
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.proxies.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.proxies.is_empty()
+    }
+    // world bounds as of the last update
+    pub fn world_bounds(&self, instance_id: i64) -> Option<Aabb> {
+        self.proxies.get(&instance_id).map(|p| p.bounds)
+    }
+    fn found(&self, proxies: Vec<usize>, exact: impl Fn(&Aabb) -> bool) -> Vec<Rc<RefCell<Entity>>> {
+        proxies.into_iter().filter_map(|p| self.proxies.get(&self.bvh.data(p)?))
+            .filter(|p| exact(&p.bounds))
+            .filter_map(|p| p.entity.upgrade()).collect()
+    }
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<Rc<RefCell<Entity>>> {
+        self.found(self.bvh.query_frustum(frustum), |b| frustum.intersects_aabb(b))
+    }
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<Rc<RefCell<Entity>>> {
+        self.found(self.bvh.query_aabb(aabb), |b| b.intersects(aabb))
+    }
+    // entities whose bounds the ray enters, with the distance, nearest first
+    pub fn query_ray(&self, ray: &Ray, max_distance: f32) -> Vec<(f32, Rc<RefCell<Entity>>)> {
+        let mut found: Vec<(f32, Rc<RefCell<Entity>>)> = self.bvh.query_ray(ray, max_distance).into_iter()
+            .filter_map(|(_, p)| self.proxies.get(&self.bvh.data(p)?))
+            .filter_map(|p| Some((p.bounds.ray_distance(ray, max_distance)?, p.entity.upgrade()?)))
+            .collect();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found
+    }
+}
+
+thread_local! {
+    static SCENES: RefCell<HashMap<u64, Scene>> = RefCell::new(HashMap::new());
+}
+// runs f on a scene created by Scene_new
+pub fn with_scene<T, F: FnOnce(&mut Scene) -> T>(id: u64, f: F) -> Option<T> {
+    SCENES.with(|s| s.borrow_mut().get_mut(&id).map(f))
+}
+
+//// exports
+
+#[no_mangle]
+pub extern "C"
+fn Scene_new(root: u64) -> u64 {
+    match entity_cast(&root) {
+        Some(root) => {
+            let id = engine_next_global_id() as u64;
+            SCENES.with(|s| s.borrow_mut().insert(id, Scene::new(root)));
+            id
+        },
+        None => 0,
+    }
+}
+#[no_mangle]
+pub extern "C"
+fn Scene_update(id: u64) -> u32 {
+    with_scene(id, |s| s.update() as u32).unwrap_or(0)
+}
+#[no_mangle]
+pub extern "C"
+fn Scene_destroy(id: u64) -> bool {
+    SCENES.with(|s| s.borrow_mut().remove(&id).is_some())
+}
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::comp::render_component::RenderComponent;
+use crate::comp::transform_component::TransformComponent;
+use crate::data::bounds::Ray;
+use crate::data::mesh_tools::list_indices;
+use crate::data::render_object::{Primitive, PrimitiveMode, RenderObject};
+use crate::entity::Entity;
+use crate::reflection::{Mat4f, Vec3f};
+use crate::scene::{with_scene, Scene};
+
+/**
+ * Ray picking against the triangles of the scene.
+ * the BVH gives the entities whose world bounds the ray enters, nearest first, then their
+ * triangles are tested in local space until the bounds are farther than the best hit.
+ * the local ray keeps the scale of the world matrix, so distances stay in world units.
+ * meshes are tested in their bind pose with their default morph weights, points and lines
+ * are never hit.
+ */
+pub struct RayHit {
+    pub entity: Rc<RefCell<Entity>>,
+    pub part: usize,
+    pub primitive: usize,
+    // in the triangle list of the primitive, strips and fans counted as listed
+    pub triangle: usize,
+    // weights of the 3 vertices of the triangle
+    pub barycentrics: Vec3f,
+    pub distance: f32,
+    pub point: Vec3f,
+    // world space geometric normal, facing the ray origin
+    pub normal: Vec3f,
+}
+
+// Möller-Trumbore, both faces, as (distance, u, v)
+pub fn ray_triangle(ray: &Ray, a: &Vec3f, b: &Vec3f, c: &Vec3f) -> Option<(f32, f32, f32)> {
+    let (e1, e2) = (b - a, c - a);
+    let p = ray.direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < 1e-12 {
+        return None
+    }
+    let inv = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None
+    }
+    let q = s.cross(&e1);
+    let v = ray.direction.dot(&q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None
+    }
+    let t = e2.dot(&q) * inv;
+    if t < 0.0 { None } else { Some((t, u, v)) }
+}
+
+struct LocalHit {
+    part: usize,
+    primitive: usize,
+    triangle: usize,
+    distance: f32,
+    u: f32,
+    v: f32,
+    normal: Vec3f,
+}
+fn triangle_list(object: &RenderObject, prim: &Primitive) -> Option<Vec<u32>> {
+    let indices = object.element_indices(prim).ok()?;
+    match list_indices(&prim.mode, &indices) {
+        (PrimitiveMode::TRIANGLES, list) => Some(list),
+        _ => None,
+    }
+}
+// nearest triangle of the drawn parts within max_distance
+fn raycast_object(object: &RenderObject, part: i32, ray: &Ray, max_distance: f32) -> Option<LocalHit> {
+    let mut best: Option<LocalHit> = None;
+    let first_part = if part >= 0 && (part as usize) < object.parts.len() { part as usize } else { 0 };
+    for (pi, p) in object.drawn_parts(part).iter().enumerate() {
+        for (ri, prim) in p.primitives.iter().enumerate() {
+            let limit = best.as_ref().map_or(max_distance, |b| b.distance);
+            if !prim.aabb.is_empty() && prim.aabb.ray_distance(ray, limit).is_none() {
+                continue
+            }
+            let Some(list) = triangle_list(object, prim) else { continue };
+            let positions = if prim.targets.is_empty() {
+                match object.read_attribute::<Vec3f>(prim, "POSITION") {
+                    Ok(positions) => positions,
+                    Err(_) => continue,
+                }
+            } else {
+                match object.morph(prim, &p.weights) {
+                    Ok(morphed) => morphed.positions,
+                    Err(_) => continue,
+                }
+            };
+            for (ti, t) in list.chunks_exact(3).enumerate() {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| positions.get(i as usize).cloned());
+                let (Some(a), Some(b), Some(c)) = (a, b, c) else { continue };
+                match ray_triangle(ray, &a, &b, &c) {
+                    Some((distance, u, v)) if distance <= best.as_ref().map_or(max_distance, |b| b.distance) => {
+                        best = Some(LocalHit { part: first_part + pi, primitive: ri, triangle: ti, distance, u, v, normal: (b - a).cross(&(c - a)) });
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+    best
+}
+
+impl Scene {
+    // nearest hit of the entities the filter accepts, from the last update.
+    // the entity isn't borrowed while the filter runs, it may borrow it mutably
+    pub fn raycast<F: Fn(&Rc<RefCell<Entity>>) -> bool>(&self, origin: Vec3f, direction: Vec3f, filter: F) -> Option<RayHit> {
+        let ray = Ray::new(origin, direction);
+        let mut best: Option<RayHit> = None;
+        for (bounds_distance, entity) in self.query_ray(&ray, f32::MAX) {
+            if best.as_ref().is_some_and(|b| b.distance < bounds_distance) {
+                break
+            }
+            if !filter(&entity) {
+                continue
+            }
+            let e = entity.borrow();
+            let Some(render) = e.get_component::<RenderComponent>() else { continue };
+            if render.object.is_null() || !render.object.is_loaded() {
+                continue
+            }
+            let world = e.get_component::<TransformComponent>().map_or(Mat4f::identity(), |t| t.world_matrix);
+            let Some(inverse) = world.try_inverse() else { continue };
+            let local = Ray { origin: inverse.transform_point(&origin.into()).coords, direction: inverse.transform_vector(&ray.direction) };
+            let limit = best.as_ref().map_or(f32::MAX, |b| b.distance);
+            let Some(hit) = raycast_object(&render.object.get(), render.part, &local, limit) else { continue };
+            let mut normal = inverse.transpose().transform_vector(&hit.normal).try_normalize(0.0).unwrap_or_default();
+            if normal.dot(&ray.direction) > 0.0 {
+                normal = -normal;
+            }
+            drop(e);
+            best = Some(RayHit {
+                entity,
+                part: hit.part,
+                primitive: hit.primitive,
+                triangle: hit.triangle,
+                barycentrics: Vec3f::new(1.0 - hit.u - hit.v, hit.u, hit.v),
+                distance: hit.distance,
+                point: ray.at(hit.distance),
+                normal,
+            });
+        }
+        best
+    }
+}
+
+//// exports
+
+#[repr(C)]
+#[derive(Default)]
+pub struct SceneRayHit {
+    pub entity: u64,
+    pub part: u32,
+    pub primitive: u32,
+    pub triangle: u32,
+    pub barycentrics: [f32; 3],
+    pub distance: f32,
+    pub point: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+// filter receives the entity address and returns whether it can be hit, null accepts all
+#[no_mangle]
+pub extern "C"
+fn Scene_raycast(id: u64, ox: f32, oy: f32, oz: f32, dx: f32, dy: f32, dz: f32,
+                 filter: Option<extern "C" fn(u64) -> bool>, hit: *mut SceneRayHit) -> bool {
+    let found = with_scene(id, |s| {
+        s.raycast(Vec3f::new(ox, oy, oz), Vec3f::new(dx, dy, dz), |e| filter.is_none_or(|f| {
+            let address = e.borrow().marker_address();
+            f(address)
+        }))
+    }).flatten();
+    match found {
+        Some(h) if !hit.is_null() => {
+            let out = unsafe { &mut *hit };
+            *out = SceneRayHit {
+                entity: h.entity.borrow().marker_address(),
+                part: h.part as u32,
+                primitive: h.primitive as u32,
+                triangle: h.triangle as u32,
+                barycentrics: h.barycentrics.into(),
+                distance: h.distance,
+                point: h.point.into(),
+                normal: h.normal.into(),
+            };
+            true
+        },
+        Some(_) => true,
+        None => false,
+    }
+}
@@ -2,7 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::Write;
 use std::io::Read;
-use std::fs;
+use std::path::Path;
 use std::any::{Any, TypeId};
 use std::rc::{Rc, Weak};
 use nalgebra::Vector2;
@@ -11,6 +11,7 @@ use yaml_rust2::{Yaml, YamlLoader};
 use crate::{impl_map_concrete_serialize, impl_vec_concrete_serialize, impl_vec_embed_serialize};
 use crate::entity::{Component, DummyComponent};
 use crate::reflection::Serializable;
+use crate::fs::vfs;
 use crate::shader::{*};
 
 #[derive(Default,Reflection)]
@@ -131,7 +132,7 @@ impl ShaderGraph {
         ShaderGraph::default()
     }
     pub fn load_from_file(&mut self, yaml_path: &str) {
-        let yaml_str = fs::read_to_string(yaml_path).unwrap();
+        let yaml_str = vfs().read().unwrap().read_to_string(Path::new(yaml_path)).unwrap();
         let docs = YamlLoader::load_from_str(&yaml_str).unwrap();
         let doc = &docs[0];
         self.deserialize_text(doc);
@@ -4,6 +4,20 @@ use eldra::{*};
 use eldra::engine::{*};
 use eldra::entity::{*};
 use eldra::comp::transform_component::{*};
+use eldra::data::blob::{*};
+use eldra::data::chunk::{Compression, BLOB_CHUNK_SIZE};
+use eldra::data::asset_db::{*};
+use eldra::data::texture::Texture;
+use eldra::data::{*};
+use eldra::data::loader::LoadState;
+use eldra::data::hot_reload::ReloadCause;
+use eldra::data::residency::ResidentReason;
+use eldra::data::material::Material;
+use std::cell::RefCell;
+use std::rc::Rc;
+use yaml_rust2::Yaml;
+use eldra::data::render_object::RenderObject;
+use eldra::data::gltf_scene::Entity_import_gltf;
 use eldra::reflection::{*};
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
@@ -62,6 +76,16 @@ fn test_transform_component() -> u64 {
     c1
 }
 
+fn test_transform_translate() {
+    let mut transform = TransformComponent::default();
+    transform.scale(&Vector3::new(2., 2., 2.));
+    transform.translate(&Vector3::new(1., 2., 3.));
+    transform.translate(&Vector3::new(1., 0., 0.));
+    // appended after the scale, in parent space
+    assert_eq!(transform.local_matrix.column(3).xyz(), Vector3::new(2., 2., 3.));
+    assert_eq!(transform.local_matrix.transform_point(&Point3::new(1., 1., 1.)), Point3::new(4., 4., 5.));
+}
+
 fn test_serialize_yaml(entity_uuid: u64) { 
     // serialize
     let output_path = "../../bin/test.yaml";
@@ -94,6 +118,2189 @@ fn test_serialize_binary(entity_uuid: u64) {
     println!("deserialize binary done");
     drop_c_str(output_path_c);
 }
+fn test_blob_mapped() {
+    let output_path = "../../bin/test_mesh.bin";
+    let mut mesh = RenderObject::default();
+    let data: Vec<u8> = (0..4096_u32).map(|i| (i % 251) as u8).collect();
+    mesh.buffer = Blob::from(data.clone());
+    serialize_binary_file(&mesh, output_path).unwrap();
+
+    let mut loaded = RenderObject::default();
+    deserialize_binary_file(&mut loaded, output_path).unwrap();
+    assert!(loaded.buffer.is_mapped());
+    assert_eq!(loaded.buffer.as_ptr() as usize % BLOB_ALIGN, 0);
+    assert_eq!(loaded.buffer.as_slice(), data.as_slice());
+    assert_eq!(loaded.buffer.as_typed::<f32>().unwrap().len(), data.len() / 4);
+    // overwriting the file leaves the live mapping untouched
+    let other = RenderObject { buffer: Blob::from(vec![7u8; 64]), ..Default::default() };
+    serialize_binary_file(&other, output_path).unwrap();
+    assert_eq!(loaded.buffer.as_slice(), data.as_slice());
+    // copy-on-write detaches from the mapping
+    loaded.buffer.extend_from_slice(&[1, 2, 3]);
+    assert!(!loaded.buffer.is_mapped());
+    assert_eq!(loaded.buffer.len(), data.len() + 3);
+
+    // pod arrays : a corrupted length fails the read instead of allocating it
+    let array_path = "../../bin/test_array.bin";
+    let floats = vec![1f32, 2., 3.];
+    serialize_binary_file(&floats, array_path).unwrap();
+    let mut loaded = Vec::<f32>::new();
+    deserialize_binary_file(&mut loaded, array_path).unwrap();
+    assert_eq!(loaded, floats);
+    let mut bytes = std::fs::read(array_path).unwrap();
+    for (len, error) in [(4_i64, "out of mapped range"), (i64::MAX / 2, "out of mapped range"), (-1, "out of range")] {
+        bytes[..8].copy_from_slice(&len.to_le_bytes());
+        std::fs::write(array_path, &bytes).unwrap();
+        let mut loaded = Vec::<f32>::new();
+        assert!(deserialize_binary_file(&mut loaded, array_path).unwrap_err().to_string().contains(error));
+        assert!(loaded.is_empty());
+    }
+    // and from a plain stream
+    let mut loaded = Vec::<f32>::new();
+    loaded.deserialize_binary(&mut &bytes[..]);
+    assert!(loaded.is_empty());
+}
+fn test_blob_compressed() {
+    let output_path = "../../bin/test_mesh_compressed.bin";
+    // a bit more than 2 chunks, compressible
+    let data: Vec<u8> = (0..(BLOB_CHUNK_SIZE * 2 + 100)).map(|i| ((i / 7) % 13) as u8).collect();
+    for compression in [Compression::LZ4, Compression::ZSTD] {
+        let mut mesh = RenderObject { buffer: Blob::from(data.clone()), ..Default::default() };
+        mesh.buffer.compression = compression;
+        serialize_binary_file(&mesh, output_path).unwrap();
+        assert!(std::fs::metadata(output_path).unwrap().len() < data.len() as u64);
+
+        let mut loaded = RenderObject::default();
+        deserialize_binary_file(&mut loaded, output_path).unwrap();
+        assert_eq!(loaded.buffer.compression, compression);
+        assert_eq!(loaded.buffer.as_slice(), data.as_slice());
+    }
+    // corrupt the last payload byte, checksum must reject it. the blob is the first field of
+    // the object, [len: i64][compression: u16] then its chunk table, see chunk.rs
+    let mut bytes = std::fs::read(output_path).unwrap();
+    let u32_at = |o: usize| u32::from_le_bytes(bytes[o..o + 4].try_into().unwrap()) as usize;
+    let table = 8 + 2;
+    let count = u32_at(table);
+    let stored: usize = (0..count).map(|i| u32_at(table + 4 + i * 12 + 4)).sum();
+    let last = table + 4 + count * 12 + stored - 1;
+    bytes[last] ^= 0xff;
+    std::fs::write(output_path, &bytes).unwrap();
+    let mut loaded = RenderObject::default();
+    let err = deserialize_binary_file(&mut loaded, output_path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("checksum mismatch in chunk"));
+    assert!(loaded.buffer.is_empty());
+    // raw_len is covered by the checksum, a huge one is rejected before allocating
+    bytes[last] ^= 0xff;
+    bytes[table + 4..table + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(output_path, &bytes).unwrap();
+    let mut loaded = RenderObject::default();
+    assert!(deserialize_binary_file(&mut loaded, output_path).is_err());
+}
+fn test_asset_db() {
+    let root = current_dir().unwrap().join("../../bin/assets");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("textures")).unwrap();
+    {
+        let tex = Texture { pixel_format: 7, ..Default::default() };
+        let mut writer = SerializeTextWriter::new(root.join("textures/Albedo.yaml").to_str().unwrap());
+        tex.serialize_text(&mut writer, String::new());
+    }
+    asset_db().write().unwrap().open(&root);
+    let guid = asset_db().write().unwrap().import("textures/Albedo.yaml").unwrap();
+    // case-normalized, relative to root
+    assert_eq!(asset_db().read().unwrap().guid_of("./Textures/albedo.YAML"), Some(guid));
+    assert_eq!(asset_db().read().unwrap().relative(&root.join("textures/../textures/Albedo.yaml")).unwrap(), "textures/Albedo.yaml");
+    // outside of root, or missing : no meta file is created
+    assert!(asset_db().read().unwrap().relative(std::path::Path::new("../outside.yaml")).is_err());
+    assert!(asset_db().write().unwrap().import("textures/../../outside.yaml").is_err());
+    assert!(asset_db().write().unwrap().import("textures/missing.yaml").is_err());
+    assert!(!root.join("textures/missing.yaml.meta").exists());
+    // guid comes back from the meta file when the project is reopened
+    asset_db().write().unwrap().open(&root);
+    assert_eq!(asset_db().read().unwrap().guid_of("textures/Albedo.yaml"), Some(guid));
+
+    // reference by path without extension, serialized as guid
+    let mut tex_ref = ExtRes::<Texture>::default();
+    tex_ref.deserialize_text(&Yaml::String("textures/Albedo".to_string()));
+    assert_eq!(*tex_ref.guid(), guid);
+    assert_eq!(tex_ref.get().pixel_format, 7);
+
+    // moving the file keeps the guid valid
+    asset_db().write().unwrap().rename("textures/Albedo.yaml", "moved/albedo_v2.yaml").unwrap();
+    assert_eq!(asset_db().read().unwrap().path_of(&guid).unwrap(), "moved/albedo_v2.yaml");
+    let mut moved_ref = ExtRes::<Texture>::default();
+    moved_ref.deserialize_text(&Yaml::String(guid.to_string()));
+    assert_eq!(moved_ref.get().pixel_format, 7);
+    // a dangling path is logged and leaves a nil reference
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    let mut dangling = ExtRes::<Texture>::default();
+    dangling.deserialize_text(&Yaml::String("textures/nowhere".to_string()));
+    eldra::log::set_log_sink(None);
+    assert!(dangling.guid().is_nil());
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("unresolved reference textures/nowhere")));
+
+    // broken metas are skipped by a scan and re-created by an import
+    for (name, meta) in [("empty", ""), ("garbage", "guid : [unclosed"), ("bad_guid", "guid :\n  value : \"nope\"\n")] {
+        std::fs::write(root.join(format!("textures/{}.yaml", name)), "").unwrap();
+        std::fs::write(root.join(format!("textures/{}.yaml.meta", name)), meta).unwrap();
+    }
+    logged.lock().unwrap().clear();
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    asset_db().write().unwrap().open(&root);
+    let fresh = asset_db().write().unwrap().import("textures/garbage.yaml");
+    eldra::log::set_log_sink(None);
+    assert_eq!(asset_db().read().unwrap().guid_of("moved/albedo_v2.yaml"), Some(guid));
+    assert!(asset_db().read().unwrap().guid_of("textures/empty.yaml").is_none());
+    assert_eq!(logged.lock().unwrap().iter().filter(|m| m.starts_with("skipping")).count(), 3);
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("re-creating")));
+    let fresh = fresh.unwrap();
+    asset_db().write().unwrap().open(&root);
+    assert_eq!(asset_db().read().unwrap().guid_of("textures/garbage.yaml"), Some(fresh));
+    // a meta that can't be written is an error
+    std::fs::write(root.join("textures/locked.yaml"), "").unwrap();
+    std::fs::create_dir_all(root.join("textures/locked.yaml.meta")).unwrap();
+    assert!(asset_db().write().unwrap().import("textures/locked.yaml").is_err());
+    std::fs::remove_dir(root.join("textures/locked.yaml.meta")).unwrap();
+}
+fn test_async_load() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    std::fs::create_dir_all(root.join("materials")).unwrap();
+    {
+        let tex = Texture { pixel_format: 9, ..Default::default() };
+        let mut writer = SerializeTextWriter::new(root.join("textures/async.yaml").to_str().unwrap());
+        tex.serialize_text(&mut writer, String::new());
+    }
+    std::fs::write(root.join("materials/async.yaml"), r#"
+name :
+  field_type : "String"
+  value : "async"
+tex :
+  field_type : "ExtRes<Texture>"
+  value : "textures/async"
+"#).unwrap();
+    let mat_guid = asset_db().write().unwrap().import("materials/async.yaml").unwrap();
+    let tex_guid = asset_db().write().unwrap().import("textures/async.yaml").unwrap();
+
+    res_mgr().reset_progress();
+    let mat = ExtRes::<Material>::load_async(mat_guid, false);
+    assert_eq!(mat.state(), LoadState::Pending);
+    let completed = Rc::new(RefCell::new(false));
+    let completed_cb = completed.clone();
+    mat.on_complete(move |state| {
+        assert_eq!(state, LoadState::Loaded);
+        // dependency completes first
+        let tex = res_mgr().handles::<Texture>().read().unwrap().get(&tex_guid).cloned().unwrap();
+        assert_eq!(tex.state(), LoadState::Loaded);
+        *completed_cb.borrow_mut() = true;
+    });
+    let mut frames = 0;
+    while !res_mgr().is_idle() {
+        res_mgr().update();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        frames += 1;
+        assert!(frames < 5000, "async load timed out");
+    }
+    assert!(*completed.borrow());
+    assert_eq!(mat.get().name, "async");
+    assert_eq!(mat.get().tex.get().pixel_format, 9);
+    assert_eq!(res_mgr().progress().fraction(), 1.0);
+
+    // missing file fails without taking the engine down, the error goes to the log
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    let missing = ExtRes::<Texture>::load_async(uuid::Uuid::new_v4(), false);
+    while !res_mgr().is_idle() {
+        res_mgr().update();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    eldra::log::set_log_sink(None);
+    assert_eq!(missing.state(), LoadState::Failed);
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("resource load failed")));
+}
+fn test_hot_reload() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let mat_guid = asset_db().read().unwrap().guid_of("materials/async.yaml").unwrap();
+    let tex_guid = asset_db().read().unwrap().guid_of("textures/async.yaml").unwrap();
+    let mat = ExtRes::<Material>::load(mat_guid, false);
+    assert_eq!(mat.get().tex.get().pixel_format, 9);
+
+    res_mgr().set_hot_reload(true);
+    res_mgr().hot_reload.borrow_mut().poll_interval = std::time::Duration::ZERO;
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events_cb = events.clone();
+    let listener = res_mgr().add_reload_listener(move |e| events_cb.borrow_mut().push(e.clone()));
+    res_mgr().update();
+    assert!(events.borrow().is_empty());
+
+    let mat_value = mat.get();
+    let tex_path = root.join("textures/async.yaml");
+    {
+        let tex = Texture { pixel_format: 11, ..Default::default() };
+        let mut writer = SerializeTextWriter::new(tex_path.to_str().unwrap());
+        tex.serialize_text(&mut writer, String::new());
+    }
+    // don't depend on the file system timestamp resolution
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(2);
+    std::fs::File::options().write(true).open(&tex_path).unwrap().set_modified(later).unwrap();
+    res_mgr().update();
+
+    // reloaded in place, the material sees the new texture through its shared handle
+    assert_eq!(mat.get().tex.get().pixel_format, 11);
+    assert_eq!(ExtRes::<Texture>::load(tex_guid, false).get().pixel_format, 11);
+    let events = events.borrow();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().any(|e| e.guid == tex_guid && e.cause == ReloadCause::Changed && e.type_name == "Texture"));
+    assert!(events.iter().any(|e| e.guid == mat_guid && e.cause == ReloadCause::DependencyChanged));
+    // the material is rebuilt after its texture
+    assert!(!Rc::ptr_eq(&mat_value, &mat.get()));
+    res_mgr().remove_reload_listener(listener);
+
+    // loaded from its cooked binary, rebuilt from the source
+    let src_path = root.join("textures/hot_cooked.yaml");
+    let write_source = |pixel_format: u8, seconds: u64| {
+        let tex = Texture { pixel_format, ..Default::default() };
+        let mut writer = SerializeTextWriter::new(src_path.to_str().unwrap());
+        tex.serialize_text(&mut writer, String::new());
+        drop(writer);
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(seconds);
+        std::fs::File::options().write(true).open(&src_path).unwrap().set_modified(later).unwrap();
+        tex
+    };
+    let tex = write_source(5, 0);
+    let guid = asset_db().write().unwrap().import("textures/hot_cooked.yaml").unwrap();
+    serialize_binary_file(&tex, root.join("textures/hot_cooked.yaml.bin").to_str().unwrap()).unwrap();
+    let cooked = ExtRes::<Texture>::load(guid, true);
+    assert_eq!(cooked.get().pixel_format, 5);
+    write_source(6, 2);
+    res_mgr().update();
+    assert_eq!(cooked.get().pixel_format, 6);
+    // a broken source is logged, the previous value stays
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    std::fs::write(&src_path, "pixel_format: [").unwrap();
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(4);
+    std::fs::File::options().write(true).open(&src_path).unwrap().set_modified(later).unwrap();
+    res_mgr().update();
+    eldra::log::set_log_sink(None);
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with(&format!("hot reload of {} failed", guid))));
+    assert_eq!(cooked.get().pixel_format, 6);
+    res_mgr().set_hot_reload(false);
+}
+fn test_residency() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    // assets of the previous tests are unused by now, the material releases its texture when freed
+    assert!(res_mgr().collect() >= 2);
+    assert_eq!(res_mgr().stats().resident.len(), 0);
+
+    let mut guids = Vec::new();
+    for name in ["big_a", "big_b"] {
+        let tex = Texture { pixels: Blob::from(vec![1u8; 4096]), ..Default::default() };
+        let path = format!("textures/{}.yaml", name);
+        let mut writer = SerializeTextWriter::new(root.join(&path).to_str().unwrap());
+        tex.serialize_text(&mut writer, String::new());
+        drop(writer);
+        guids.push(asset_db().write().unwrap().import(&path).unwrap());
+    }
+    let a = ExtRes::<Texture>::load(guids[0], false);
+    let b = ExtRes::<Texture>::load(guids[1], false);
+    let stats = res_mgr().stats();
+    assert_eq!(stats.per_type["Texture"].count, 2);
+    assert!(stats.total_bytes >= 8192);
+    assert!(stats.resident.iter().all(|r| r.reason == ResidentReason::Referenced && r.users == 1));
+
+    // unreferenced assets stay cached while within budget
+    drop(a);
+    drop(b);
+    let stats = res_mgr().stats();
+    assert_eq!(stats.cached_bytes, stats.total_bytes);
+    assert!(stats.resident.iter().all(|r| r.reason == ResidentReason::Cached && r.users == 0));
+
+    // least recently used goes first
+    let b = ExtRes::<Texture>::load(guids[1], false);
+    drop(b);
+    res_mgr().set_budget(6000);
+    let stats = res_mgr().stats();
+    assert_eq!(stats.resident.len(), 1);
+    assert_eq!(stats.resident[0].guid, guids[1]);
+    assert!(res_mgr().handles::<Texture>().read().unwrap().get(&guids[0]).is_none());
+
+    // referenced assets are never evicted, with no budget they go with their last user
+    res_mgr().set_budget(0);
+    assert_eq!(res_mgr().stats().resident.len(), 0);
+    let a = ExtRes::<Texture>::load(guids[0], false);
+    assert_eq!(a.get().pixels.len(), 4096);
+    assert_eq!(res_mgr().stats().resident.len(), 1);
+    drop(a);
+    assert_eq!(res_mgr().stats().resident.len(), 0);
+    // only ExtRes count as users, a handle kept elsewhere doesn't pin the asset
+    let a = ExtRes::<Texture>::load(guids[0], false);
+    let handle = a.handle().clone();
+    drop(a);
+    assert_eq!(handle.users(), 0);
+    assert_eq!(res_mgr().stats().resident.len(), 0);
+    drop(handle);
+    res_mgr().set_budget(usize::MAX);
+}
+// resource type defined outside the engine, as a plugin would
+#[derive(Default)]
+struct AudioClip {
+    sample_rate: u32,
+}
+impl Serializable for AudioClip {
+    fn is_multi_line(&self) -> bool { true }
+    fn get_type_uuid(&self) -> Option<Uuid> { None }
+    fn serialize_binary(&self, io: &mut dyn std::io::Write) { self.sample_rate.serialize_binary(io); }
+    fn deserialize_binary(&mut self, io: &mut dyn std::io::Read) { self.sample_rate.deserialize_binary(io); }
+    fn serialize_text(&self, io: &mut SerializeTextWriter, indent: String) { self.sample_rate.serialize_text(io, indent); }
+    fn deserialize_text(&mut self, yaml: &Yaml) { self.sample_rate = yaml["sample_rate"].as_i64().unwrap() as u32; }
+}
+impl ExtSerializable<AudioClip> for AudioClip {}
+fn test_resource_registry() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    assert!(!res_mgr().registry.is_registered::<AudioClip>());
+    let unregistered = std::panic::catch_unwind(|| ExtRes::<AudioClip>::load(Uuid::new_v4(), false));
+    assert!(unregistered.is_err());
+
+    std::fs::create_dir_all(root.join("audio")).unwrap();
+    std::fs::write(root.join("audio/beep.clip"), "sample_rate : 44100\n").unwrap();
+    res_mgr().registry.register_with::<AudioClip>(&["clip", "wav"], |path, _| eldra::data::loader::read_yaml_source(path), |clip, src| {
+        match src {
+            eldra::data::loader::SourceData::Yaml(doc) => { clip.deserialize_text(&doc); Ok(()) },
+            _ => Err("clips are imported from yaml".to_string()),
+        }
+    });
+    assert!(res_mgr().registry.type_names().contains(&"AudioClip"));
+    assert_eq!(res_mgr().registry.types_for_extension(".WAV"), vec![std::any::TypeId::of::<AudioClip>()]);
+
+    // reference by path without extension picks the registered one
+    let mut clip = ExtRes::<AudioClip>::default();
+    clip.deserialize_text(&Yaml::String("audio/beep".to_string()));
+    assert_eq!(clip.get().sample_rate, 44100);
+    assert_eq!(*clip.guid(), asset_db().read().unwrap().guid_of("audio/beep.clip").unwrap());
+    assert_eq!(res_mgr().handles::<AudioClip>().read().unwrap().len(), 1);
+    assert!(res_mgr().stats().per_type.contains_key("AudioClip"));
+}
+// resource referencing others of its type, to build dependency cycles. a Vec, the default
+// value of an ExtRes<Linked> field would build another Linked
+#[derive(Default)]
+struct Linked {
+    next: Vec<ExtRes<Linked>>,
+}
+impl Serializable for Linked {
+    fn is_multi_line(&self) -> bool { true }
+    fn get_type_uuid(&self) -> Option<Uuid> { None }
+    fn serialize_binary(&self, io: &mut dyn std::io::Write) { self.next.serialize_binary(io); }
+    fn deserialize_binary(&mut self, io: &mut dyn std::io::Read) { self.next.deserialize_binary(io); }
+    fn serialize_text(&self, io: &mut SerializeTextWriter, indent: String) { self.next.serialize_text(io, indent); }
+    fn deserialize_text(&mut self, yaml: &Yaml) { self.next.deserialize_text(&yaml["next"]); }
+}
+impl ExtSerializable<Linked> for Linked {}
+fn test_dependency_cycle() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    std::fs::create_dir_all(root.join("links")).unwrap();
+    for (name, next) in [("a", "links/b.link"), ("b", "links/a.link"), ("self", "links/self.link"), ("c", "links/d.link"), ("d", "links/c.link")] {
+        std::fs::write(root.join(format!("links/{}.link", name)), format!("next :\n  - \"{}\"\n", next)).unwrap();
+    }
+    res_mgr().registry.register_with::<Linked>(&["link"], |path, _| eldra::data::loader::read_yaml_source(path), |link, src| {
+        match src {
+            eldra::data::loader::SourceData::Yaml(doc) => { link.deserialize_text(&doc); Ok(()) },
+            _ => Err("links are yaml".to_string()),
+        }
+    });
+    let guid = |path: &str| asset_db().write().unwrap().import(path).unwrap();
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    // the reference closing the cycle fails, the first resource loads
+    let a = ExtRes::<Linked>::load(guid("links/a.link"), false);
+    assert_eq!(a.state(), LoadState::Loaded);
+    assert_eq!(a.get().next[0].state(), LoadState::Failed);
+    assert_eq!(a.get().next[0].handle().error().unwrap(), "dependency cycle links/a.link -> links/b.link -> links/a.link");
+    let itself = ExtRes::<Linked>::load(guid("links/self.link"), false);
+    assert_eq!(itself.state(), LoadState::Failed);
+    assert!(itself.handle().error().unwrap().contains("links/self.link -> links/self.link"));
+    // in the background, the loads complete instead of waiting on each other
+    let c = ExtRes::<Linked>::load_async(guid("links/c.link"), false);
+    let mut frames = 0;
+    while !res_mgr().is_idle() && frames < 1000 {
+        res_mgr().update();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        frames += 1;
+    }
+    eldra::log::set_log_sink(None);
+    assert!(res_mgr().is_idle());
+    assert_eq!(c.state(), LoadState::Loaded);
+    assert_eq!(c.get().next[0].state(), LoadState::Failed);
+    assert!(c.get().next[0].handle().error().unwrap().starts_with("dependency cycle links/d.link -> links/c.link"));
+    assert!(logged.lock().unwrap().iter().any(|m| m.contains("dependency cycle")));
+}
+fn test_vfs() {
+    use std::io::{Read, Seek, SeekFrom};
+    use eldra::fs::{vfs, normalize, Vfs_mount_dir, Vfs_mount_pack};
+    use eldra::fs::pack::{PackArchive, PackWriter};
+    let dir = current_dir().unwrap().join("../../bin/vfs");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("loose/data")).unwrap();
+    assert_eq!(normalize("./data\\a/../b.txt"), "data/b.txt");
+
+    let pack = dir.join("data.pack");
+    let mut writer = PackWriter::create(&pack).unwrap();
+    writer.add("data/b.txt", b"packed").unwrap();
+    writer.add("data/c.txt", b"0123456789").unwrap();
+    assert!(writer.add("./data/c.txt", b"twice").is_err());
+    writer.finish().unwrap();
+    let archive = PackArchive::open(&pack).unwrap();
+    assert_eq!(archive.len(), 2);
+    assert!(archive.verify().is_empty());
+    // an entry whose range overflows is rejected, not read
+    let mut bytes = std::fs::read(&pack).unwrap();
+    let index = u64::from_le_bytes(bytes[12..20].try_into().unwrap()) as usize;
+    let name_len = u32::from_le_bytes(bytes[index..index + 4].try_into().unwrap()) as usize;
+    bytes[index + 4 + name_len..index + 12 + name_len].copy_from_slice(&u64::MAX.to_le_bytes());
+    let corrupted = dir.join("corrupted.pack");
+    std::fs::write(&corrupted, &bytes).unwrap();
+    assert!(PackArchive::open(&corrupted).is_err());
+    bytes[12..20].copy_from_slice(&(u64::MAX - 2).to_le_bytes());
+    std::fs::write(&corrupted, &bytes).unwrap();
+    assert!(PackArchive::open(&corrupted).is_err());
+    // the exports return 0 and log instead of panicking
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    let point_c = convert_c_str("game");
+    let corrupted_c = convert_c_str(corrupted.to_str().unwrap());
+    assert_eq!(Vfs_mount_pack(point_c, corrupted_c), 0);
+    assert_eq!(Vfs_mount_pack(point_c, c"\xff.pack".as_ptr()), 0);
+    assert_eq!(Vfs_mount_dir(c"\xff".as_ptr(), corrupted_c), 0);
+    eldra::log::set_log_sink(None);
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("mount ")));
+    drop_c_str(point_c);
+    drop_c_str(corrupted_c);
+
+    let pack_id = vfs().write().unwrap().mount_pack("game", &pack).unwrap();
+    assert_eq!(vfs().read().unwrap().read_to_string(std::path::Path::new("game/data/b.txt")).unwrap(), "packed");
+    // packed files are aligned for in place blobs
+    assert_eq!(vfs().read().unwrap().map(std::path::Path::new("game/data/c.txt")).unwrap().offset % BLOB_ALIGN, 0);
+    let mut file = vfs().read().unwrap().open(std::path::Path::new("game/data/c.txt")).unwrap();
+    file.seek(SeekFrom::End(-3)).unwrap();
+    let mut tail = String::new();
+    file.read_to_string(&mut tail).unwrap();
+    assert_eq!(tail, "789");
+
+    // loose files override the pack
+    std::fs::write(dir.join("loose/data/b.txt"), "loose").unwrap();
+    let loose_id = vfs().write().unwrap().mount_dir("game", &dir.join("loose"));
+    assert_eq!(vfs().read().unwrap().read_to_string(std::path::Path::new("game/data/b.txt")).unwrap(), "loose");
+    assert_eq!(vfs().read().unwrap().read_to_string(std::path::Path::new("game/data/c.txt")).unwrap(), "0123456789");
+    assert!(vfs().read().unwrap().files().contains(&"game/data/c.txt".to_string()));
+    vfs().write().unwrap().unmount(loose_id);
+    assert_eq!(vfs().read().unwrap().read_to_string(std::path::Path::new("game/data/b.txt")).unwrap(), "packed");
+    vfs().write().unwrap().unmount(pack_id);
+    assert!(!vfs().read().unwrap().exists(std::path::Path::new("game/data/b.txt")));
+}
+fn png_bytes(w: u32, h: u32, rgba: [u8; 4]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::RgbaImage::from_pixel(w, h, image::Rgba(rgba))
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png).unwrap();
+    bytes
+}
+// glTF json embedding data as its only buffer, body holds the other members
+fn embedded_gltf(data: &[u8], body: &str) -> String {
+    use base64::prelude::*;
+    format!(r#"{{
+  "asset": {{ "version": "2.0" }},
+  "buffers": [ {{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }} ],{}
+}}"#, data.len(), BASE64_STANDARD.encode(data), body)
+}
+fn test_gltf_materials() {
+    use base64::prelude::*;
+    use eldra::data::material::{AlphaMode, ShadingModel};
+    use eldra::data::texture::{FilterMode, WrapMode};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    std::fs::create_dir_all(root.join("models")).unwrap();
+    std::fs::write(root.join("models/checker.png"), png_bytes(4, 4, [0, 0, 0, 255])).unwrap();
+    let positions: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    let gltf = embedded_gltf(&positions, &format!(r#"
+  "bufferViews": [ {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }} ],
+  "accessors": [ {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }} ],
+  "images": [ {{ "uri": "data:image/png;base64,{}" }}, {{ "uri": "checker.png" }} ],
+  "samplers": [ {{ "magFilter": 9728, "minFilter": 9728, "wrapS": 33071, "wrapT": 33648 }} ],
+  "textures": [ {{ "source": 0, "sampler": 0 }}, {{ "source": 1 }} ],
+  "materials": [
+    {{ "name": "red", "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }}, "metallicFactor": 0.0, "roughnessFactor": 0.5 }},
+       "normalTexture": {{ "index": 1, "scale": 2.0 }}, "alphaMode": "MASK", "alphaCutoff": 0.25, "doubleSided": true }},
+    {{ "name": "gloss", "extensions": {{ "KHR_materials_pbrSpecularGlossiness": {{
+       "diffuseFactor": [0.5, 0.5, 0.5, 1.0], "diffuseTexture": {{ "index": 1 }}, "glossinessFactor": 0.25 }} }} }}
+  ],
+  "extensionsUsed": [ "KHR_materials_pbrSpecularGlossiness" ],
+  "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0 }}, "material": 0 }}, {{ "attributes": {{ "POSITION": 0 }}, "material": 1 }} ] }} ]"#, BASE64_STANDARD.encode(png_bytes(2, 2, [255, 0, 0, 255]))));
+    std::fs::write(root.join("models/scene.gltf"), gltf).unwrap();
+
+    // loading only reads, the materials are extracted by an explicit import
+    let guid = asset_db().write().unwrap().import("models/scene.gltf").unwrap();
+    let unextracted = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(unextracted.state(), LoadState::Failed);
+    assert!(unextracted.handle().error().unwrap().contains("import the file first"));
+    assert!(!root.join("models/scene.gltf.import").exists());
+    drop(unextracted);
+    res_mgr().collect();
+    let guid = res_mgr().import_source("models/scene.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let prims = &model.get().parts[0].primitives;
+    let red = &prims[0].material;
+    let (mat, tex) = (red.get(), red.get().tex.get());
+    assert_eq!(mat.name, "red");
+    assert_eq!((mat.metallic, mat.roughness, mat.normal_scale, mat.alpha_cutoff), (0.0, 0.5, 2.0, 0.25));
+    assert_eq!(mat.alpha_mode, AlphaMode::MASK);
+    assert!(mat.double_sided);
+    // embedded image with its sampler
+    assert_eq!((tex.width, tex.height), (2, 2));
+    assert_eq!(&tex.pixels[..4], &[255, 0, 0, 255]);
+    assert_eq!((tex.sampler.wrap_u, tex.sampler.wrap_v), (WrapMode::CLAMP_TO_EDGE, WrapMode::MIRRORED_REPEAT));
+    assert_eq!(tex.sampler.mag_filter, FilterMode::NEAREST);
+    assert!(!tex.sampler.mipmaps);
+    // external image, shared by both materials
+    assert_eq!(mat.normal_tex.get().width, 4);
+    let gloss = prims[1].material.get();
+    assert_eq!(gloss.shading, ShadingModel::SPECULAR_GLOSSINESS);
+    assert_eq!((gloss.base_color.x, gloss.glossiness), (0.5, 0.25));
+    assert_eq!(gloss.tex.guid(), mat.normal_tex.guid());
+
+    // extracted as regular assets, the guid is kept on reimport
+    let mat_path = "models/scene.gltf.import/material_0.yaml";
+    assert_eq!(asset_db().read().unwrap().guid_of(mat_path), Some(*red.guid()));
+    let mut reloaded = eldra::data::material::Material::default();
+    let docs = yaml_rust2::YamlLoader::load_from_str(&std::fs::read_to_string(root.join(mat_path)).unwrap()).unwrap();
+    reloaded.deserialize_text(&docs[0]);
+    assert_eq!((reloaded.metallic, reloaded.double_sided, reloaded.alpha_mode), (0.0, true, AlphaMode::MASK));
+    // survives the binary form
+    let bin_path = root.join("models/red.bin");
+    serialize_binary_file(&*red.get(), bin_path.to_str().unwrap()).unwrap();
+    let mut cooked = eldra::data::material::Material::default();
+    deserialize_binary_file(&mut cooked, bin_path.to_str().unwrap()).unwrap();
+    assert_eq!((cooked.metallic, cooked.double_sided, cooked.alpha_cutoff), (0.0, true, 0.25));
+
+    // loading a texture doesn't import its image
+    std::fs::write(root.join("models/plain.png"), png_bytes(1, 1, [0, 0, 255, 255])).unwrap();
+    std::fs::write(root.join("models/plain.yaml"), "image :\n  field_type : \"String\"\n  value : \"plain.png\"\n").unwrap();
+    let guid = asset_db().write().unwrap().import_typed("models/plain.yaml", "Texture").unwrap();
+    let plain = ExtRes::<Texture>::load(guid, false);
+    assert_eq!(plain.state(), LoadState::Loaded, "{:?}", plain.handle().error());
+    assert_eq!(plain.get().width, 1);
+    assert!(!root.join("models/plain.png.meta").exists());
+
+    // an extracted asset that can't be written fails the import
+    std::fs::copy(root.join("models/scene.gltf"), root.join("models/blocked.gltf")).unwrap();
+    std::fs::create_dir_all(root.join("models/blocked.gltf.import/material_0.yaml")).unwrap();
+    assert!(res_mgr().import_source("models/blocked.gltf").unwrap_err().contains("material_0.yaml"));
+}
+fn skinned_gltf(joint_indices: [u8; 12]) -> String {
+    let mut data: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    data.extend_from_slice(&joint_indices);
+    data.extend([1f32, 0., 0., 0., 0.5, 0.5, 0., 0., 1., 0., 0., 0.].iter().flat_map(|f| f.to_le_bytes()));
+    // column major, the second one moves by (0, -1, 0)
+    data.extend([1f32, 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.].iter().flat_map(|f| f.to_le_bytes()));
+    data.extend([1f32, 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., -1., 0., 1.].iter().flat_map(|f| f.to_le_bytes()));
+    data.extend([0u16, 1, 2, 0].iter().flat_map(|i| i.to_le_bytes()));
+    embedded_gltf(&data, r#"
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 36, "byteLength": 12 },
+    { "buffer": 0, "byteOffset": 48, "byteLength": 48 },
+    { "buffer": 0, "byteOffset": 96, "byteLength": 128 },
+    { "buffer": 0, "byteOffset": 224, "byteLength": 6, "target": 34963 }
+  ],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+    { "bufferView": 1, "componentType": 5121, "count": 3, "type": "VEC4" },
+    { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" },
+    { "bufferView": 3, "componentType": 5126, "count": 2, "type": "MAT4" },
+    { "bufferView": 4, "componentType": 5123, "count": 3, "type": "SCALAR" }
+  ],
+  "meshes": [ { "name": "body", "primitives": [ { "attributes": { "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2 }, "indices": 4 } ] } ],
+  "nodes": [
+    { "name": "character", "mesh": 0, "skin": 0 },
+    { "name": "root", "translation": [0, 1, 0], "children": [2] },
+    { "name": "helper", "translation": [1, 0, 0], "children": [3] },
+    { "name": "tip", "translation": [0, 2, 0], "rotation": [0, 0, 0.70710677, 0.70710677] }
+  ],
+  "skins": [ { "name": "rig", "joints": [3, 1], "inverseBindMatrices": 3 } ],
+  "scenes": [ { "nodes": [0, 1] } ]"#)
+}
+fn test_gltf_skin() {
+    use eldra::comp::render_component::RenderComponent;
+    use eldra::data::skeleton::Skeleton;
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    std::fs::create_dir_all(root.join("models")).unwrap();
+    std::fs::write(root.join("models/skinned.gltf"), skinned_gltf([0, 0, 0, 0, 0, 1, 0, 0, 1, 0, 0, 0])).unwrap();
+    let guid = res_mgr().import_source("models/skinned.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let part = &model.get().parts[0];
+    assert_eq!(part.name, "body");
+    let skeleton = &part.skeleton;
+    assert_eq!(skeleton.get().name, "rig");
+    // skin order, the parent comes after its child
+    assert_eq!(skeleton.get().joint_count(), 2);
+    assert_eq!(skeleton.get().find("root"), Some(1));
+    let tip = &skeleton.get().joints[0];
+    assert_eq!((tip.name.as_str(), tip.parent), ("tip", 1));
+    // the helper node is folded into the rest pose
+    assert!((tip.translation - Vec3f::new(1., 2., 0.)).norm() < 1e-5);
+    assert!((tip.rotation.z - 0.70710677).abs() < 1e-5);
+    assert_eq!(skeleton.get().joints[1].parent, -1);
+    assert_eq!(skeleton.get().joints[1].inverse_bind[(1, 3)], -1.);
+    assert!(skeleton.get().validate().is_ok());
+    // bind pose : world * inverse bind is identity
+    let world = skeleton.get().rest_world_matrices();
+    assert!((world[1] * skeleton.get().joints[1].inverse_bind - Mat4f::identity()).norm() < 1e-5);
+    assert!((world[0].column(3).xyz() - Vec3f::new(1., 3., 0.)).norm() < 1e-5);
+
+    // populated alongside the mesh
+    let mut render = RenderComponent::default();
+    render.set_object(model.clone(), 0);
+    assert_eq!(render.skeleton().map(|s| *s.guid()), Some(*skeleton.guid()));
+    // extracted asset, text and binary
+    let path = "models/skinned.gltf.import/skeleton_0.yaml";
+    assert_eq!(asset_db().read().unwrap().guid_of(path), Some(*skeleton.guid()));
+    let bin_path = root.join("models/skeleton.bin");
+    serialize_binary_file(&*skeleton.get(), bin_path.to_str().unwrap()).unwrap();
+    let mut cooked = Skeleton::default();
+    deserialize_binary_file(&mut cooked, bin_path.to_str().unwrap()).unwrap();
+    assert_eq!((cooked.joints[0].parent, cooked.joints[1].name.as_str()), (1, "root"));
+    assert_eq!(cooked.joints[1].inverse_bind, skeleton.get().joints[1].inverse_bind);
+
+    // joint index 2 of a 2 joints skin
+    std::fs::write(root.join("models/bad_skin.gltf"), skinned_gltf([0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0])).unwrap();
+    let guid = res_mgr().import_source("models/bad_skin.gltf").unwrap();
+    let bad = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(bad.state(), LoadState::Failed);
+    assert!(bad.handle().error().unwrap().contains("references joint 2"));
+
+    // a parent cycle fails the load, the matrices of a broken skeleton are still computed
+    let mut cyclic = Skeleton { joints: cooked.joints.clone(), ..Default::default() };
+    cyclic.joints[1].parent = 0;
+    assert!(cyclic.validate().unwrap_err().contains("cycle"));
+    assert_eq!(cyclic.rest_world_matrices().len(), 2);
+    {
+        let mut writer = SerializeTextWriter::new(root.join("models/cyclic_skeleton.yaml").to_str().unwrap());
+        cyclic.serialize_text(&mut writer, String::new());
+    }
+    let guid = asset_db().write().unwrap().import_typed("models/cyclic_skeleton.yaml", "Skeleton").unwrap();
+    let cyclic = ExtRes::<Skeleton>::load(guid, false);
+    assert_eq!(cyclic.state(), LoadState::Failed);
+    assert!(cyclic.handle().error().unwrap().contains("cycle"));
+}
+fn test_gltf_scene() {
+    use eldra::comp::render_component::RenderComponent;
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let gltf_c = convert_c_str(root.join("models/skinned.gltf").to_str().unwrap());
+    let prefab_c = convert_c_str(root.join("prefabs/skinned.yaml").to_str().unwrap());
+    let scene = Entity_import_gltf(gltf_c, prefab_c);
+    assert_ne!(scene, 0);
+    Entity_tick(scene, 0.);
+    let check = |scene: &Rc<RefCell<Entity>>| {
+        let e = scene.borrow();
+        let names: Vec<String> = e.children.iter().map(|c| c.borrow().name.clone()).collect();
+        assert_eq!(names, ["character", "root"]);
+        let character = e.children[0].borrow();
+        let render = character.get_component::<RenderComponent>().unwrap();
+        assert_eq!(render.part, 0);
+        let skeleton = render.skeleton().unwrap();
+        assert_eq!(skeleton.get().name, "rig");
+        assert_eq!(render.object().get().parts[0].skeleton.guid(), skeleton.guid());
+        // root -> helper -> tip, helper has no mesh
+        let helper = e.children[1].borrow().children[0].clone();
+        assert!(helper.borrow().get_component::<RenderComponent>().is_none());
+        let tip = helper.borrow().children[0].clone();
+        assert_eq!(tip.borrow().name, "tip");
+        let tr = tip.borrow().get_component::<TransformComponent>().unwrap().local_matrix;
+        assert!((tr.column(3).xyz() - Vector3::new(0., 2., 0.)).norm() < 1e-5);
+    };
+    let imported = entity_cast(&scene).unwrap();
+    check(&imported);
+    let tip = imported.borrow().children[1].borrow().children[0].borrow().children[0].clone();
+    let world = tip.borrow().get_component::<TransformComponent>().unwrap().world_matrix;
+    assert!((world.column(3).xyz() - Vector3::new(1., 3., 0.)).norm() < 1e-5);
+
+    // the prefab loads back as the same tree
+    let prefab = Entity_new();
+    Entity_deserialize_yaml(prefab, prefab_c);
+    check(&entity_cast(&prefab).unwrap());
+    Entity_destroy(prefab);
+    Entity_destroy(scene);
+
+    // failures return 0 and go to the log
+    let logged = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    eldra::log::set_log_sink(Some(std::sync::Arc::new(move |_, message: &str| sink.lock().unwrap().push(message.to_string()))));
+    let missing_c = convert_c_str(root.join("models/missing.gltf").to_str().unwrap());
+    assert_eq!(Entity_import_gltf(missing_c, std::ptr::null()), 0);
+    assert_eq!(Entity_import_gltf(c"\xff.gltf".as_ptr(), std::ptr::null()), 0);
+    // the prefab directory is a file
+    let unwritable_c = convert_c_str(root.join("models/skinned.gltf/skinned.yaml").to_str().unwrap());
+    assert_eq!(Entity_import_gltf(gltf_c, unwritable_c), 0);
+    eldra::log::set_log_sink(None);
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("gltf scene import failed")));
+    assert!(logged.lock().unwrap().iter().any(|m| m.starts_with("gltf prefab save failed")));
+    drop_c_str(unwritable_c);
+    drop_c_str(missing_c);
+    drop_c_str(gltf_c);
+    drop_c_str(prefab_c);
+}
+fn test_gltf_export() {
+    use eldra::data::gltf_export::Entity_export_gltf;
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let skinned = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/skinned.gltf").unwrap(), false);
+    for ext in ["gltf", "glb"] {
+        let mut out = Vec::new();
+        if ext == "gltf" { skinned.get().write_gltf(&mut out).unwrap() } else { skinned.get().write_glb(&mut out).unwrap() }
+        // the gltf crate checks the json schema and the indices between objects on load
+        let gltf = gltf::Gltf::from_slice(&out).unwrap_or_else(|e| panic!("{}: {}", ext, e));
+        let doc = &gltf.document;
+        let prim = doc.meshes().next().unwrap().primitives().next().unwrap();
+        let position = prim.get(&gltf::Semantic::Positions).unwrap();
+        assert_eq!(position.min().unwrap(), gltf_json::Value::from(vec![0f32, 0., 0.]));
+        assert_eq!(position.max().unwrap(), gltf_json::Value::from(vec![1f32, 1., 0.]));
+        // index views are tightly packed
+        let indices = prim.indices().unwrap();
+        assert_eq!(indices.count(), 3);
+        assert!(indices.view().unwrap().stride().is_none());
+        assert_eq!(indices.view().unwrap().target(), Some(gltf::buffer::Target::ElementArrayBuffer));
+        // 4 u8 joints per vertex, no padding needed
+        assert!(prim.get(&gltf::Semantic::Joints(0)).unwrap().view().unwrap().stride().is_none());
+        let skin = doc.nodes().find_map(|n| n.skin()).unwrap();
+        assert_eq!(skin.joints().map(|j| j.name().unwrap().to_string()).collect::<Vec<_>>(), ["tip", "root"]);
+        assert_eq!(doc.default_scene().unwrap().nodes().count(), 2);
+    }
+
+    // reimported from a glb file
+    let mut out = Vec::new();
+    skinned.get().write_glb(&mut out).unwrap();
+    std::fs::write(root.join("models/exported.glb"), out).unwrap();
+    let guid = res_mgr().import_source("models/exported.glb").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let (a, b) = (&skinned.get().parts[0].primitives[0], &model.get().parts[0].primitives[0]);
+    assert_eq!(model.get().read_joints(&b.skin[0].joints).unwrap(), skinned.get().read_joints(&a.skin[0].joints).unwrap());
+    assert_eq!(model.get().parts[0].skeleton.get().joints[0].parent, 1);
+
+    // materials and textures
+    let scene = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/scene.gltf").unwrap(), false);
+    let mut out = Vec::new();
+    scene.get().write_gltf(&mut out).unwrap();
+    let gltf = gltf::Gltf::from_slice(&out).unwrap();
+    let doc = &gltf.document;
+    assert_eq!(doc.materials().count(), 2);
+    assert_eq!(doc.textures().count(), 2);
+    let red = doc.materials().next().unwrap();
+    assert_eq!((red.name(), red.alpha_mode(), red.alpha_cutoff()), (Some("red"), gltf::material::AlphaMode::Mask, Some(0.25)));
+    assert_eq!(red.normal_texture().unwrap().scale(), 2.0);
+    let sampler = red.pbr_metallic_roughness().base_color_texture().unwrap().texture().sampler();
+    assert_eq!(sampler.mag_filter(), Some(gltf::texture::MagFilter::Nearest));
+    assert_eq!(sampler.wrap_t(), gltf::texture::WrappingMode::MirroredRepeat);
+    let gloss = doc.materials().nth(1).unwrap().pbr_specular_glossiness().unwrap();
+    assert_eq!(gloss.glossiness_factor(), 0.25);
+    assert!(gltf.extensions_used().any(|e| e == "KHR_materials_pbrSpecularGlossiness"));
+    std::fs::write(root.join("models/exported.gltf"), out).unwrap();
+    let guid = res_mgr().import_source("models/exported.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let red = &model.get().parts[0].primitives[0].material;
+    assert_eq!(&red.get().tex.get().pixels[..4], &[255, 0, 0, 255]);
+
+    // node hierarchy of an entity tree
+    let gltf_c = convert_c_str(root.join("models/skinned.gltf").to_str().unwrap());
+    let out_c = convert_c_str(root.join("models/entities.glb").to_str().unwrap());
+    let entity = Entity_import_gltf(gltf_c, std::ptr::null());
+    assert!(Entity_export_gltf(entity, out_c));
+    let gltf = gltf::Gltf::from_slice(&std::fs::read(root.join("models/entities.glb")).unwrap()).unwrap();
+    let top = gltf.document.default_scene().unwrap().nodes().next().unwrap();
+    assert_eq!(top.name(), Some("skinned"));
+    // the entity, not the joint of the same name
+    let tip = gltf.document.nodes().find(|n| n.name() == Some("tip") && matches!(n.transform(), gltf::scene::Transform::Matrix { .. })).unwrap();
+    assert_eq!(tip.transform().decomposed().0, [0., 2., 0.]);
+    let character = gltf.document.nodes().find(|n| n.name() == Some("character")).unwrap();
+    assert!(character.mesh().is_some() && character.skin().is_some());
+    Entity_destroy(entity);
+    drop_c_str(gltf_c);
+    drop_c_str(out_c);
+
+    // a view past the end of the buffer fails the export instead of panicking
+    let mut broken = RenderObject::default();
+    let mut part = eldra::data::render_object::RenderPart::default();
+    let mut prim = eldra::data::render_object::Primitive::default();
+    prim.position.data_type = eldra::data::render_object::DataType::VEC3;
+    prim.position.component_type = eldra::data::render_object::ComponentType::F32;
+    prim.position.count = 3;
+    prim.position.byte_size = 36;
+    prim.position.vertex_stride = 12;
+    part.primitives.push(prim);
+    broken.parts.push(part);
+    broken.buffer = Blob::from(vec![0; 12]);
+    assert!(broken.write_gltf(&mut Vec::new()).is_err());
+    assert!(broken.write_glb(&mut Vec::new()).is_err());
+}
+fn test_animation_clip() {
+    use eldra::data::animation_clip::{AnimationClip, AnimationTrack, Interpolation, TrackPath};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let floats: Vec<f32> = [
+        vec![0., 0., 0., 1., 0., 0., 0., 1., 0.],
+        vec![0., 1., 2.],
+        vec![0., 0., 0., 2., 0., 0., 2., 4., 0.],
+        vec![0., 0., 0., 1., 0., 0., 0.70710677, 0.70710677, 0., 0., 1., 0.],
+        // in-tangent, value, out-tangent
+        vec![0., 0., 0., 1., 1., 1., 0., 0., 0., 0., 0., 0., 2., 2., 2., 0., 0., 0., 0., 0., 0., 3., 3., 3., 0., 0., 0.],
+        vec![0., 0., 1., 0., 1., 1.],
+    ].concat();
+    let data: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+    let gltf = embedded_gltf(&data, r#"
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 36, "byteLength": 12 },
+    { "buffer": 0, "byteOffset": 48, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 84, "byteLength": 48 },
+    { "buffer": 0, "byteOffset": 132, "byteLength": 108 },
+    { "buffer": 0, "byteOffset": 240, "byteLength": 24 }
+  ],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+    { "bufferView": 1, "componentType": 5126, "count": 3, "type": "SCALAR", "min": [0], "max": [2] },
+    { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3" },
+    { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC4" },
+    { "bufferView": 4, "componentType": 5126, "count": 9, "type": "VEC3" },
+    { "bufferView": 5, "componentType": 5126, "count": 6, "type": "SCALAR" }
+  ],
+  "meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 }, "targets": [ { "POSITION": 0 }, { "POSITION": 0 } ] } ], "weights": [0, 0] } ],
+  "nodes": [ { "name": "mover" }, { "mesh": 0 } ],
+  "animations": [ {
+    "name": "walk",
+    "samplers": [
+      { "input": 1, "output": 2 },
+      { "input": 1, "output": 3, "interpolation": "STEP" },
+      { "input": 1, "output": 4, "interpolation": "CUBICSPLINE" },
+      { "input": 1, "output": 5 }
+    ],
+    "channels": [
+      { "sampler": 0, "target": { "node": 0, "path": "translation" } },
+      { "sampler": 1, "target": { "node": 0, "path": "rotation" } },
+      { "sampler": 2, "target": { "node": 0, "path": "scale" } },
+      { "sampler": 3, "target": { "node": 1, "path": "weights" } }
+    ]
+  } ],
+  "scenes": [ { "nodes": [0, 1] } ]"#);
+    std::fs::write(root.join("models/animated.gltf"), gltf).unwrap();
+    let guid = res_mgr().import_source("models/animated.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let clip = model.get().animations[0].clone();
+    let anim = clip.get();
+    assert_eq!((anim.name.as_str(), anim.duration, anim.tracks.len()), ("walk", 2., 4));
+    let mut out = [0f32; 4];
+    let translation = anim.find("mover", TrackPath::TRANSLATION).unwrap();
+    translation.sample(0.5, &mut out);
+    assert_eq!(&out[..3], &[1., 0., 0.]);
+    translation.sample(1.5, &mut out);
+    assert_eq!(&out[..3], &[2., 2., 0.]);
+    // held after the last key
+    translation.sample(3., &mut out);
+    assert_eq!(&out[..3], &[2., 4., 0.]);
+    let rotation = anim.find("mover", TrackPath::ROTATION).unwrap();
+    assert_eq!(rotation.interpolation, Interpolation::STEP);
+    rotation.sample(1.9, &mut out);
+    assert_eq!(out, [0., 0., 0.70710677, 0.70710677]);
+    // flat tangents ease between the values
+    let scale = anim.find("mover", TrackPath::SCALE).unwrap();
+    scale.sample(0.5, &mut out);
+    assert!((out[0] - 1.5).abs() < 1e-5);
+    scale.sample(0.25, &mut out);
+    assert!((out[0] - 1.15625).abs() < 1e-5);
+    let weights = anim.find("node_1", TrackPath::WEIGHTS).unwrap();
+    assert_eq!((weights.components, weights.node), (2, 1));
+    weights.sample(1.5, &mut out);
+    assert_eq!(&out[..2], &[1., 0.5]);
+
+    // linear rotations are slerped
+    let mut track = AnimationTrack { path: TrackPath::ROTATION, components: 4, times: vec![0., 1.], values: vec![0., 0., 0., 1., 0., 0., 1., 0.], ..Default::default() };
+    track.sample(0.5, &mut out);
+    assert!((out[2] - 0.70710677).abs() < 1e-5 && (out[3] - 0.70710677).abs() < 1e-5);
+    track.values.pop();
+    assert!(track.validate().is_err());
+    // values not covering the keys, or a short out, are not sampled
+    let mut kept = [9f32; 4];
+    track.sample(0.5, &mut kept);
+    assert_eq!(kept, [9.; 4]);
+    track.values.push(1.);
+    track.sample(0.5, &mut kept[..2]);
+    assert_eq!(kept, [9.; 4]);
+    track.components = 3;
+    track.values.truncate(6);
+    assert!(track.validate().unwrap_err().contains("components"));
+
+    // inconsistent tracks fail the load
+    std::fs::create_dir_all(root.join("anims")).unwrap();
+    let broken = AnimationClip { tracks: vec![AnimationTrack { components: 0, times: vec![1., 0.], values: vec![0.; 6], ..Default::default() }], ..Default::default() };
+    {
+        let mut writer = SerializeTextWriter::new(root.join("anims/broken_clip.yaml").to_str().unwrap());
+        broken.serialize_text(&mut writer, String::new());
+    }
+    let guid = asset_db().write().unwrap().import_typed("anims/broken_clip.yaml", "AnimationClip").unwrap();
+    let broken = ExtRes::<AnimationClip>::load(guid, false);
+    assert_eq!(broken.state(), LoadState::Failed);
+    assert!(broken.handle().error().unwrap().contains("not increasing"));
+
+    // extracted asset, text and binary
+    let path = "models/animated.gltf.import/animation_0.yaml";
+    assert_eq!(asset_db().read().unwrap().guid_of(path), Some(*clip.guid()));
+    let mut reloaded = AnimationClip::default();
+    let docs = yaml_rust2::YamlLoader::load_from_str(&std::fs::read_to_string(root.join(path)).unwrap()).unwrap();
+    reloaded.deserialize_text(&docs[0]);
+    assert_eq!(reloaded.tracks[2].interpolation, Interpolation::CUBICSPLINE);
+    assert_eq!(reloaded.tracks[2].values, clip.get().tracks[2].values);
+    let bin_path = root.join("models/animation.bin");
+    serialize_binary_file(&*clip.get(), bin_path.to_str().unwrap()).unwrap();
+    let mut cooked = AnimationClip::default();
+    deserialize_binary_file(&mut cooked, bin_path.to_str().unwrap()).unwrap();
+    assert_eq!((cooked.duration, cooked.tracks[3].path, cooked.tracks[3].target.as_str()), (2., TrackPath::WEIGHTS, "node_1"));
+    // the model references its clips
+    let bin_path = root.join("models/animated_model.bin");
+    serialize_binary_file(&*model.get(), bin_path.to_str().unwrap()).unwrap();
+    let mut cooked = RenderObject::default();
+    deserialize_binary_file(&mut cooked, bin_path.to_str().unwrap()).unwrap();
+    assert_eq!(cooked.animations[0].guid(), clip.guid());
+}
+// one second clip holding a node at a translation
+fn hold_clip(name: &str, target: &str, value: [f32; 3]) -> ExtRes<eldra::data::animation_clip::AnimationClip> {
+    use eldra::data::animation_clip::{AnimationClip, AnimationTrack};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    std::fs::create_dir_all(root.join("anims")).unwrap();
+    let mut clip = AnimationClip::default();
+    let track = AnimationTrack { target: target.to_string(), components: 3, times: vec![0., 1.], values: [value, value].concat(), ..Default::default() };
+    clip.tracks.push(track);
+    clip.update_duration();
+    let path = format!("anims/{}.yaml", name);
+    {
+        let mut writer = SerializeTextWriter::new(root.join(&path).to_str().unwrap());
+        clip.serialize_text(&mut writer, String::new());
+    }
+    let guid = asset_db().write().unwrap().import(&path).unwrap();
+    ExtRes::<AnimationClip>::load(guid, false)
+}
+fn test_animator() {
+    use eldra::comp::animator_component::AnimatorComponent;
+    use eldra::data::skeleton::decompose_matrix;
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let hold = hold_clip;
+    let gltf_c = convert_c_str(root.join("models/animated.gltf").to_str().unwrap());
+    let scene = Entity_import_gltf(gltf_c, std::ptr::null());
+    let entity = entity_cast(&scene).unwrap();
+    let mover = entity.borrow().children[0].clone();
+    let local = |e: &Rc<RefCell<Entity>>| decompose_matrix(&e.borrow().get_component::<TransformComponent>().unwrap().local_matrix);
+    // the imported root plays the first clip
+    Entity_tick(scene, 0.5);
+    let (t, r, s) = local(&mover);
+    assert!((t - Vec3f::new(1., 0., 0.)).norm() < 1e-5);
+    assert!((r - Vec4f::new(0., 0., 0., 1.)).norm() < 1e-5);
+    assert!((s - Vec3f::new(1.5, 1.5, 1.5)).norm() < 1e-5);
+    let world = mover.borrow().get_component::<TransformComponent>().unwrap().world_matrix;
+    assert!((world.column(3).xyz() - Vec3f::new(1., 0., 0.)).norm() < 1e-5);
+    let weights = entity.borrow().get_component::<AnimatorComponent>().unwrap().pose["node_1"].weights.clone();
+    assert_eq!(weights, [0.5, 0.]);
+
+    // half way through a one second cross-fade, walk is at 1s
+    let up = hold("up", "mover", [0., 4., 0.]);
+    entity.borrow_mut().get_component_mut::<AnimatorComponent>().unwrap().play(0, up.clone(), 1.);
+    Entity_tick(scene, 0.5);
+    let (t, _, s) = local(&mover);
+    assert!((t - Vec3f::new(1., 2., 0.)).norm() < 1e-5);
+    // channels missing from the new clip come from the old one
+    assert!((s - Vec3f::new(2., 2., 2.)).norm() < 1e-5);
+    Entity_tick(scene, 0.75);
+    assert!(!entity.borrow().get_component::<AnimatorComponent>().unwrap().layers[0].is_fading());
+    assert!((local(&mover).0 - Vec3f::new(0., 4., 0.)).norm() < 1e-5);
+
+    // a second layer at half weight
+    {
+        let mut e = entity.borrow_mut();
+        let animator = e.get_component_mut::<AnimatorComponent>().unwrap();
+        animator.play(1, hold("down", "mover", [0., 0., 0.]), 0.);
+        animator.set_weight(1, 0.5);
+        animator.evaluate();
+        assert_eq!(animator.pose["mover"].translation, Some(Vec3f::new(0., 2., 0.)));
+        animator.stop(1);
+        animator.evaluate();
+        assert_eq!(animator.pose["mover"].translation, Some(Vec3f::new(0., 4., 0.)));
+        // alone, a layer blends from the rest of the node, captured before it was animated
+        animator.stop(0);
+        animator.play(1, up.clone(), 0.);
+    }
+    Entity_tick(scene, 0.25);
+    assert!((local(&mover).0 - Vec3f::new(0., 2., 0.)).norm() < 1e-5);
+    Entity_destroy(scene);
+    drop_c_str(gltf_c);
+
+    // skinning matrices, joint 1 is the "root" of the rig
+    let model = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/skinned.gltf").unwrap(), false);
+    let mut animator = AnimatorComponent { skeleton: model.get().parts[0].skeleton.clone(), ..Default::default() };
+    animator.evaluate();
+    assert!((animator.skin_matrices[1] - Mat4f::identity()).norm() < 1e-5);
+    let rest = animator.skeleton.get();
+    assert_eq!(animator.joint_matrices, rest.rest_world_matrices());
+    animator.play(0, hold("raise", "root", [0., 2., 0.]), 0.);
+    animator.evaluate();
+    assert!((animator.skin_matrices[1] - Mat4f::new_translation(&Vec3f::new(0., 1., 0.))).norm() < 1e-5);
+    // children follow their parent joint
+    assert!((animator.joint_matrices[0].column(3).xyz() - Vec3f::new(1., 4., 0.)).norm() < 1e-5);
+    // reproducible : same time, same pose
+    let skin = animator.skin_matrices.clone();
+    animator.evaluate();
+    assert_eq!(animator.skin_matrices, skin);
+    // half weight without a base layer, from the rest translation of the joint
+    animator.stop(0);
+    animator.play(1, hold("raise", "root", [0., 2., 0.]), 0.);
+    animator.set_weight(1, 0.5);
+    animator.evaluate();
+    let rest_translation = rest.joints[1].translation;
+    assert!((animator.pose["root"].translation.unwrap() - (rest_translation + Vec3f::new(0., 2., 0.)) * 0.5).norm() < 1e-5);
+    assert_eq!(animator.pose["root"].rotation, None);
+
+    // more than 4 morph weights, a translation track of 5 components is skipped
+    use eldra::comp::animator_component::{sample_clip, Pose};
+    use eldra::data::animation_clip::{AnimationClip, AnimationTrack, TrackPath};
+    let morph = AnimationTrack { target: "morph".to_string(), path: TrackPath::WEIGHTS, components: 6, times: vec![0.], values: vec![0.5; 6], ..Default::default() };
+    let wide = AnimationTrack { target: "wide".to_string(), components: 5, times: vec![0.], values: vec![1.; 5], ..Default::default() };
+    let clip = AnimationClip { tracks: vec![morph, wide], ..Default::default() };
+    let mut pose = Pose::default();
+    sample_clip(&clip, 0., &mut pose);
+    assert_eq!(pose["morph"].weights, [0.5; 6]);
+    assert!(!pose.contains_key("wide"));
+}
+fn test_animation_graph() {
+    use eldra::comp::animator_component::AnimatorComponent;
+    use eldra::data::animation_graph::{*};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let mut graph = AnimationGraph { name: "locomotion".to_string(), ..Default::default() };
+    graph.parameters = vec![
+        AnimationParameter { name: "speed".to_string(), kind: ParameterType::FLOAT, default_value: 0. },
+        AnimationParameter { name: "jump".to_string(), kind: ParameterType::TRIGGER, default_value: 0. },
+    ];
+    let locomotion = AnimationState {
+        name: "move".to_string(),
+        kind: StateKind::BLEND_1D,
+        param_x: "speed".to_string(),
+        motions: vec![
+            BlendMotion { clip: hold_clip("still", "mover", [0., 0., 0.]), position: Vec2f::new(0., 0.) },
+            BlendMotion { clip: hold_clip("run", "mover", [4., 0., 0.]), position: Vec2f::new(2., 0.) },
+        ],
+        ..Default::default()
+    };
+    let jump = AnimationState { name: "jump".to_string(), clip: hold_clip("up", "mover", [0., 4., 0.]), looping: false, ..Default::default() };
+    graph.states = vec![locomotion, jump];
+    let trigger = TransitionCondition { param: "jump".to_string(), op: ConditionOp::IS_TRUE, value: 0. };
+    graph.transitions = vec![
+        AnimationTransition { from: "*".to_string(), to: "jump".to_string(), conditions: vec![trigger], duration: 0.5, ..Default::default() },
+        AnimationTransition { from: "jump".to_string(), to: "move".to_string(), has_exit_time: true, exit_time: 1., ..Default::default() },
+    ];
+    assert!(graph.validate().is_ok());
+    {
+        let mut writer = SerializeTextWriter::new(root.join("anims/locomotion.yaml").to_str().unwrap());
+        graph.serialize_text(&mut writer, String::new());
+    }
+    let guid = asset_db().write().unwrap().import("anims/locomotion.yaml").unwrap();
+    let graph = ExtRes::<AnimationGraph>::load(guid, false);
+    assert_eq!(graph.state(), LoadState::Loaded, "{:?}", graph.handle().error());
+    assert_eq!((graph.get().states[0].kind, graph.get().states[1].looping), (StateKind::BLEND_1D, false));
+    assert_eq!(graph.get().transitions[0].conditions[0].op, ConditionOp::IS_TRUE);
+    assert_eq!(graph.get().states[0].motions[1].position, Vec2f::new(2., 0.));
+
+    let mut animator = AnimatorComponent::default();
+    animator.set_graph(graph.clone());
+    let mover = |animator: &AnimatorComponent| animator.pose["mover"].translation.unwrap();
+    // half way between still and run
+    animator.set_parameter("speed", 1.);
+    animator.tick(0.1, &None);
+    assert_eq!(animator.current_state().as_deref(), Some("move"));
+    assert!((mover(&animator) - Vec3f::new(2., 0., 0.)).norm() < 1e-5);
+    // any state to jump, the trigger is consumed
+    animator.set_trigger("jump");
+    animator.tick(0.1, &None);
+    assert_eq!(animator.current_state().as_deref(), Some("jump"));
+    assert_eq!(animator.parameter("jump"), 0.);
+    assert!((mover(&animator) - Vec3f::new(2., 0., 0.)).norm() < 1e-5);
+    animator.tick(0.25, &None);
+    assert!((mover(&animator) - Vec3f::new(1., 2., 0.)).norm() < 1e-5);
+    animator.tick(0.25, &None);
+    assert!((mover(&animator) - Vec3f::new(0., 4., 0.)).norm() < 1e-5);
+    // back at the end of the jump clip
+    animator.tick(0.4, &None);
+    assert_eq!(animator.current_state().as_deref(), Some("jump"));
+    animator.tick(0.2, &None);
+    assert_eq!(animator.current_state().as_deref(), Some("move"));
+    assert!((mover(&animator) - Vec3f::new(2., 0., 0.)).norm() < 1e-5);
+    // a state gone from the graph keeps the playback
+    animator.playback.as_mut().unwrap().state = 7;
+    animator.tick(0.1, &None);
+    assert_eq!(animator.playback.as_ref().map(|p| p.state), Some(7));
+    animator.playback.as_mut().unwrap().state = 0;
+
+    // 2D blend spaces
+    let motions: Vec<BlendMotion> = [(0., 0.), (1., 0.), (0., 1.)].iter()
+        .map(|(x, y)| BlendMotion { position: Vec2f::new(*x, *y), ..Default::default() }).collect();
+    assert_eq!(blend_weights(StateKind::BLEND_2D, &motions, Vec2f::new(1., 0.)), [0., 1., 0.]);
+    let w = blend_weights(StateKind::BLEND_2D, &motions, Vec2f::new(0.5, 0.5));
+    assert!((w[1] - w[2]).abs() < 1e-6 && (w.iter().sum::<f32>() - 1.).abs() < 1e-6);
+    // 1D clamps to the ends
+    assert_eq!(blend_weights(StateKind::BLEND_1D, &motions[..2], Vec2f::new(3., 0.)), [0., 1.]);
+    // NaN and infinities fall back to the first motion
+    assert_eq!(blend_weights(StateKind::BLEND_1D, &motions[..2], Vec2f::new(f32::NAN, 0.)), [1., 0.]);
+    assert_eq!(blend_weights(StateKind::BLEND_2D, &motions, Vec2f::new(f32::INFINITY, 0.)), [1., 0., 0.]);
+    assert_eq!(blend_weights(StateKind::BLEND_2D, &motions, Vec2f::new(f32::NAN, 0.)), [1., 0., 0.]);
+    assert!(!animator.set_parameter("speed", f32::NAN));
+    assert_eq!(animator.parameter("speed"), 1.);
+
+    let broken = AnimationGraph {
+        states: vec![AnimationState::default()],
+        transitions: vec![AnimationTransition { from: "*".to_string(), conditions: vec![TransitionCondition { param: "missing".to_string(), ..Default::default() }], ..Default::default() }],
+        ..Default::default()
+    };
+    assert!(broken.validate().unwrap_err().contains("missing"));
+    // and isn't loaded
+    {
+        let mut writer = SerializeTextWriter::new(root.join("anims/broken.yaml").to_str().unwrap());
+        broken.serialize_text(&mut writer, String::new());
+    }
+    let guid = asset_db().write().unwrap().import_typed("anims/broken.yaml", "AnimationGraph").unwrap();
+    let broken = ExtRes::<AnimationGraph>::load(guid, false);
+    assert_eq!(broken.state(), LoadState::Failed);
+    assert!(broken.handle().error().unwrap().contains("missing"));
+}
+fn test_morph_targets() {
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    // a triangle with two targets : the top vertex rises and the normals lean along x, the right vertex moves right
+    let floats: Vec<f32> = [
+        vec![0., 0., 0., 1., 0., 0., 0., 1., 0.],
+        vec![0., 0., 1., 0., 0., 1., 0., 0., 1.],
+        vec![0., 0., 0., 0., 0., 0., 0., 1., 0.],
+        vec![1., 0., 0., 1., 0., 0., 1., 0., 0.],
+        vec![0., 0., 0., 1., 0., 0., 0., 0., 0.],
+    ].concat();
+    let data: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+    let gltf = embedded_gltf(&data, r#"
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 72, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 108, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 144, "byteLength": 36 }
+  ],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+    { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+    { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [0, 1, 0] },
+    { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC3" },
+    { "bufferView": 4, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 0, 0] }
+  ],
+  "meshes": [ { "name": "blob", "primitives": [ {
+    "attributes": { "POSITION": 0, "NORMAL": 1 },
+    "targets": [ { "POSITION": 2, "NORMAL": 3 }, { "POSITION": 4 } ]
+  } ], "weights": [0.5, 1.0] } ],
+  "nodes": [ { "mesh": 0 } ],
+  "scenes": [ { "nodes": [0] } ]"#);
+    std::fs::write(root.join("models/morph.gltf"), gltf).unwrap();
+    let guid = res_mgr().import_source("models/morph.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let part = &model.get().parts[0];
+    assert_eq!(part.weights, [0.5, 1.0]);
+    let prim = &part.primitives[0];
+    assert_eq!(prim.targets.len(), 2);
+    assert!(prim.targets[0].normal.is_some() && prim.targets[1].normal.is_none());
+
+    // the default weights
+    let morphed = model.get().morph(prim, &part.weights).unwrap();
+    assert_eq!(morphed.positions, [Vec3f::new(0., 0., 0.), Vec3f::new(2., 0., 0.), Vec3f::new(0., 1.5, 0.)]);
+    let lean = Vec3f::new(0.5, 0., 1.).normalize();
+    assert!(morphed.normals.iter().all(|n| (n - lean).norm() < 1e-5));
+    // no weight, no change
+    let rest = model.get().morph(prim, &[0., 0.]).unwrap();
+    assert_eq!(rest.positions, model.get().read_vec3(&prim.position).unwrap());
+    assert_eq!(rest.normals, model.get().read_vec3(prim.normal.as_ref().unwrap()).unwrap());
+
+    // kept through export and binary cooking
+    let mut out = Vec::new();
+    model.get().write_glb(&mut out).unwrap();
+    let gltf = gltf::Gltf::from_slice(&out).unwrap();
+    let mesh = gltf.document.meshes().next().unwrap();
+    assert_eq!(mesh.weights(), Some(&[0.5f32, 1.0][..]));
+    assert_eq!(mesh.primitives().next().unwrap().morph_targets().count(), 2);
+    let bin_path = root.join("models/morph.bin");
+    serialize_binary_file(&*model.get(), bin_path.to_str().unwrap()).unwrap();
+    let mut cooked = RenderObject::default();
+    deserialize_binary_file(&mut cooked, bin_path.to_str().unwrap()).unwrap();
+    let cooked_prim = &cooked.parts[0].primitives[0];
+    assert_eq!(cooked.morph(cooked_prim, &cooked.parts[0].weights).unwrap().positions, morphed.positions);
+
+    // shaped like the AnimatedMorphCube sample : a cube with a "thin" target halving x and an
+    // "angle" target shearing the top face along x, the weights animated one after the other
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    for (n, u, v) in [(Vec3f::x(), Vec3f::y(), Vec3f::z()), (Vec3f::y(), Vec3f::z(), Vec3f::x()), (Vec3f::z(), Vec3f::x(), Vec3f::y())] {
+        for n in [n, -n] {
+            positions.extend([n - u - v, n + u - v, n + u + v, n - u + v]);
+            normals.extend([n; 4]);
+        }
+    }
+    let indices: Vec<u16> = (0..6).flat_map(|f| [0, 1, 2, 0, 2, 3].map(|i| f * 4 + i)).collect();
+    let thin: Vec<Vec3f> = positions.iter().map(|p| Vec3f::new(-p.x / 2., 0., 0.)).collect();
+    let angle: Vec<Vec3f> = positions.iter().map(|p| Vec3f::new(if p.y > 0. { 0.5 } else { 0. }, 0., 0.)).collect();
+    let mut data: Vec<u8> = [&positions, &normals].iter().flat_map(|v| v.iter().flat_map(|p| p.iter().flat_map(|f| f.to_le_bytes()))).collect();
+    data.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+    data.extend([&thin, &angle].iter().flat_map(|v| v.iter().flat_map(|p| p.iter().flat_map(|f| f.to_le_bytes()))));
+    data.extend([0f32, 1., 2., 3., 0., 0., 1., 0., 1., 1., 0., 0.].iter().flat_map(|f| f.to_le_bytes()));
+    let gltf = embedded_gltf(&data, r#"
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 288 },
+    { "buffer": 0, "byteOffset": 288, "byteLength": 288 },
+    { "buffer": 0, "byteOffset": 576, "byteLength": 72, "target": 34963 },
+    { "buffer": 0, "byteOffset": 648, "byteLength": 288 },
+    { "buffer": 0, "byteOffset": 936, "byteLength": 288 },
+    { "buffer": 0, "byteOffset": 1224, "byteLength": 16 },
+    { "buffer": 0, "byteOffset": 1240, "byteLength": 32 }
+  ],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 24, "type": "VEC3", "min": [-1, -1, -1], "max": [1, 1, 1] },
+    { "bufferView": 1, "componentType": 5126, "count": 24, "type": "VEC3" },
+    { "bufferView": 2, "componentType": 5123, "count": 36, "type": "SCALAR" },
+    { "bufferView": 3, "componentType": 5126, "count": 24, "type": "VEC3", "min": [-0.5, 0, 0], "max": [0.5, 0, 0] },
+    { "bufferView": 4, "componentType": 5126, "count": 24, "type": "VEC3", "min": [0, 0, 0], "max": [0.5, 0, 0] },
+    { "bufferView": 5, "componentType": 5126, "count": 4, "type": "SCALAR", "min": [0], "max": [3] },
+    { "bufferView": 6, "componentType": 5126, "count": 8, "type": "SCALAR" }
+  ],
+  "meshes": [ { "name": "Cube", "primitives": [ {
+    "attributes": { "POSITION": 0, "NORMAL": 1 }, "indices": 2,
+    "targets": [ { "POSITION": 3 }, { "POSITION": 4 } ]
+  } ], "weights": [0, 0], "extras": { "targetNames": ["thin", "angle"] } } ],
+  "nodes": [ { "name": "AnimatedMorphCube", "mesh": 0 } ],
+  "animations": [ { "name": "Square",
+    "samplers": [ { "input": 5, "output": 6 } ],
+    "channels": [ { "sampler": 0, "target": { "node": 0, "path": "weights" } } ]
+  } ],
+  "scenes": [ { "nodes": [0] } ]"#);
+    std::fs::write(root.join("models/morph_cube.gltf"), gltf).unwrap();
+    let guid = res_mgr().import_source("models/morph_cube.gltf").unwrap();
+    let cube = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(cube.state(), LoadState::Loaded, "{:?}", cube.handle().error());
+    let part = &cube.get().parts[0];
+    assert_eq!(part.weights, [0., 0.]);
+    let prim = &part.primitives[0];
+    assert_eq!((prim.position.count, prim.targets.len()), (24, 2));
+    assert!(prim.targets.iter().all(|t| t.normal.is_none()));
+    // thin fully in, angle half way
+    let mut pose = eldra::comp::animator_component::Pose::new();
+    eldra::comp::animator_component::sample_clip(&cube.get().animations[0].get(), 1.5, &mut pose);
+    let weights = &pose["AnimatedMorphCube"].weights;
+    assert_eq!(weights, &[1., 0.5]);
+    let morphed = cube.get().morph(prim, weights).unwrap();
+    let at = |corner: Vec3f| morphed.positions[positions.iter().position(|p| *p == corner).unwrap()];
+    assert_eq!(at(Vec3f::new(1., 1., 1.)), Vec3f::new(0.75, 1., 1.));
+    assert_eq!(at(Vec3f::new(-1., 1., -1.)), Vec3f::new(-0.25, 1., -1.));
+    assert_eq!(at(Vec3f::new(1., -1., 1.)), Vec3f::new(0.5, -1., 1.));
+    assert_eq!(morphed.normals, normals);
+    // back to rest at the end of the loop
+    pose.clear();
+    eldra::comp::animator_component::sample_clip(&cube.get().animations[0].get(), 3., &mut pose);
+    assert_eq!(cube.get().morph(prim, &pose["AnimatedMorphCube"].weights).unwrap().positions, positions);
+}
+fn test_vertex_accessor() {
+    use eldra::data::render_object::{BufferView, ComponentType, DataType, RenderPart, Primitive};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    // typed reads by attribute name
+    let skinned = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/skinned.gltf").unwrap(), false);
+    let prim = &skinned.get().parts[0].primitives[0];
+    let positions = skinned.get().read_attribute::<Vec3f>(prim, "POSITION").unwrap();
+    assert_eq!(positions[2], Vec3f::new(0., 1., 0.));
+    assert_eq!(skinned.get().read_attribute::<Vec4f>(prim, "JOINTS_0").unwrap()[1], Vec4f::new(0., 1., 0., 0.));
+    assert_eq!(skinned.get().read_attribute::<Vec4f>(prim, "WEIGHTS_0").unwrap()[1], Vec4f::new(0.5, 0.5, 0., 0.));
+    assert_eq!(skinned.get().read_indices(prim).unwrap(), Some(vec![0, 1, 2]));
+    assert!(skinned.get().read_attribute::<Vec3f>(prim, "TEXCOORD_0").is_err());
+    // more components than the view has
+    assert!(skinned.get().accessor(&prim.position).unwrap().iter::<Vec4f>().is_err());
+
+    // normalized integers
+    let bytes: Vec<u8> = [255u8, 0, 128, 255].iter().cloned().chain(i16::MIN.to_le_bytes()).chain(16384i16.to_le_bytes()).collect();
+    let mut object = RenderObject { buffer: Blob::from(bytes), ..Default::default() };
+    let color = BufferView { count: 1, byte_size: 4, data_type: DataType::VEC4, component_type: ComponentType::U8, normalized: true, ..Default::default() };
+    let c = object.accessor(&color).unwrap().get::<Vec4f>(0);
+    assert!((c - Vec4f::new(1., 0., 128. / 255., 1.)).norm() < 1e-6);
+    let mut uv = BufferView { offset: 4, count: 1, byte_size: 4, data_type: DataType::VEC2, component_type: ComponentType::S16, normalized: true, ..Default::default() };
+    assert_eq!(object.accessor(&uv).unwrap().get::<Vec2f>(0), Vec2f::new(-1., 16384. / 32767.));
+    // out of the buffer
+    uv.count = 2;
+    assert!(object.accessor(&uv).is_err());
+
+    // writers : in place, otherwise re-packed and the replaced stream dropped from the buffer
+    let mut part = RenderPart::default();
+    let mut prim = Primitive::default();
+    prim.texcoord.push(uv);
+    prim.color.push(color);
+    part.primitives.push(prim);
+    object.parts.push(part);
+    object.write_attribute(0, 0, "COLOR_0", &[Vec4f::new(0., 1., 0.5, 0.25)]).unwrap();
+    assert_eq!(&object.buffer.as_slice()[..4], &[0, 255, 128, 64]);
+    object.write_attribute(0, 0, "TEXCOORD_0", &[Vec2f::new(0.5, -0.5), Vec2f::new(1., -1.)]).unwrap();
+    let uv = &object.parts[0].primitives[0].texcoord[0];
+    assert_eq!((uv.offset, uv.count, uv.vertex_stride), (4, 2, 0));
+    assert_eq!(object.buffer.len(), 12);
+    let read = object.read_attribute::<Vec2f>(&object.parts[0].primitives[0], "TEXCOORD_0").unwrap();
+    assert!((read[0] - Vec2f::new(0.5, -0.5)).norm() < 1e-4 && read[1] == Vec2f::new(1., -1.));
+    assert!(object.write_attribute(0, 0, "COLOR_0", &[Vec3f::zeros()]).is_err());
+    // indices get the smallest type
+    object.write_indices(0, 0, &[0, 1, 70000]).unwrap();
+    let indices = object.parts[0].primitives[0].indice.as_ref().unwrap();
+    assert!(matches!(indices.component_type, ComponentType::U32));
+    assert_eq!(indices.offset % 4, 0);
+    assert_eq!(object.read_indices(&object.parts[0].primitives[0]).unwrap(), Some(vec![0, 1, 70000]));
+
+    // sparse accessors, with and without base data
+    let mut data: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    data.extend([1u8, 0, 0, 0]);
+    data.extend([5f32, 5., 5.].iter().flat_map(|f| f.to_le_bytes()));
+    data.extend([2u8, 0, 0, 0]);
+    data.extend([0f32, 0., 1.].iter().flat_map(|f| f.to_le_bytes()));
+    let gltf = embedded_gltf(&data, r#"
+  "bufferViews": [
+    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 36, "byteLength": 2 },
+    { "buffer": 0, "byteOffset": 40, "byteLength": 12 },
+    { "buffer": 0, "byteOffset": 52, "byteLength": 1 },
+    { "buffer": 0, "byteOffset": 56, "byteLength": 12 }
+  ],
+  "accessors": [
+    { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [5, 5, 5],
+      "sparse": { "count": 1, "indices": { "bufferView": 1, "componentType": 5123 }, "values": { "bufferView": 2 } } },
+    { "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [0, 0, 1],
+      "sparse": { "count": 1, "indices": { "bufferView": 3, "componentType": 5121 }, "values": { "bufferView": 4 } } }
+  ],
+  "meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 }, "targets": [ { "POSITION": 1 } ] } ], "weights": [1] } ],
+  "nodes": [ { "mesh": 0 } ],
+  "scenes": [ { "nodes": [0] } ]"#);
+    std::fs::write(root.join("models/sparse.gltf"), gltf).unwrap();
+    let guid = res_mgr().import_source("models/sparse.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let prim = &model.get().parts[0].primitives[0];
+    let expected = [Vec3f::new(0., 0., 0.), Vec3f::new(5., 5., 5.), Vec3f::new(0., 1., 0.)];
+    assert_eq!(model.get().read_attribute::<Vec3f>(prim, "POSITION").unwrap(), expected);
+    assert!(!prim.targets[0].position.as_ref().unwrap().has_data());
+    assert_eq!(model.get().morph(prim, &model.get().parts[0].weights).unwrap().positions[2], Vec3f::new(0., 1., 1.));
+    // exported dense
+    let mut out = Vec::new();
+    model.get().write_glb(&mut out).unwrap();
+    std::fs::write(root.join("models/dense.glb"), out).unwrap();
+    let guid = res_mgr().import_source("models/dense.glb").unwrap();
+    let dense = ExtRes::<RenderObject>::load(guid, false);
+    let dense_prim = &dense.get().parts[0].primitives[0];
+    assert!(dense_prim.position.sparse.is_none());
+    assert_eq!(dense.get().read_attribute::<Vec3f>(dense_prim, "POSITION").unwrap(), expected);
+    assert_eq!(dense.get().morph(dense_prim, &[1.]).unwrap().positions[2], Vec3f::new(0., 1., 1.));
+}
+
+// one part, one primitive of F32 positions
+fn build_mesh(mode: eldra::data::render_object::PrimitiveMode, positions: &[Vec3f], indices: Option<&[u32]>) -> RenderObject {
+    use eldra::data::render_object::{BufferView, ComponentType, DataType, RenderPart, Primitive};
+    let mut object = RenderObject::default();
+    let mut part = RenderPart::default();
+    let position = BufferView { data_type: DataType::VEC3, component_type: ComponentType::F32, ..Default::default() };
+    let prim = Primitive { mode, position, ..Default::default() };
+    part.primitives.push(prim);
+    object.parts.push(part);
+    object.write_attribute(0, 0, "POSITION", positions).unwrap();
+    if let Some(indices) = indices {
+        object.write_indices(0, 0, indices).unwrap();
+    }
+    object
+}
+fn test_mesh_tools() {
+    use eldra::data::render_object::{BufferView, ComponentType, DataType, PrimitiveMode};
+    use eldra::data::mesh_tools::{acmr, list_indices};
+    let mesh = build_mesh;
+    let sorted = |object: &RenderObject| {
+        let prim = &object.parts[0].primitives[0];
+        let positions = object.read_attribute::<Vec3f>(prim, "POSITION").unwrap();
+        let mut tris: Vec<[[i32; 3]; 3]> = object.triangles(prim).unwrap().iter()
+            .map(|t| t.map(|v| positions[v as usize].map(|c| (c * 1000.0).round() as i32).into())).collect();
+        tris.sort();
+        tris
+    };
+    // strips alternate the winding, fans pivot on the first vertex, loops close
+    assert_eq!(list_indices(&PrimitiveMode::TRIANGLE_STRIP, &[0, 1, 2, 3, 3, 4, 4, 5, 6]).1, vec![0, 1, 2, 1, 3, 2, 4, 5, 6]);
+    assert_eq!(list_indices(&PrimitiveMode::TRIANGLE_FAN, &[0, 1, 2, 3]).1, vec![1, 2, 0, 2, 3, 0]);
+    assert_eq!(list_indices(&PrimitiveMode::LINE_LOOP, &[0, 1, 2]).1, vec![0, 1, 1, 2, 2, 0]);
+    let quad = [Vec3f::new(0., 0., 0.), Vec3f::new(1., 0., 0.), Vec3f::new(0., 1., 0.), Vec3f::new(1., 1., 0.)];
+    let mut strip = mesh(PrimitiveMode::TRIANGLE_STRIP, &quad, None);
+    assert!(strip.generate_normals(0, 0, true).is_err());
+    strip.triangulate(0, 0).unwrap();
+    assert!(matches!(strip.parts[0].primitives[0].mode, PrimitiveMode::TRIANGLES));
+    assert_eq!(strip.read_indices(&strip.parts[0].primitives[0]).unwrap(), Some(vec![0, 1, 2, 1, 3, 2]));
+
+    // smooth normals are shared, flat ones split the vertices
+    strip.generate_normals(0, 0, true).unwrap();
+    let normals = strip.read_attribute::<Vec3f>(&strip.parts[0].primitives[0], "NORMAL").unwrap();
+    assert!(normals.iter().all(|n| (n - Vec3f::z()).norm() < 1e-6));
+    let tent = [Vec3f::new(-1., 0., 0.), Vec3f::new(0., 0., 1.), Vec3f::new(0., 1., 0.), Vec3f::new(1., 0., 0.)];
+    let mut flat = mesh(PrimitiveMode::TRIANGLES, &tent, Some(&[0, 1, 2, 1, 3, 2]));
+    flat.generate_normals(0, 0, false).unwrap();
+    let prim = &flat.parts[0].primitives[0];
+    assert_eq!(prim.position.count, 6);
+    let normals = flat.read_attribute::<Vec3f>(prim, "NORMAL").unwrap();
+    assert!((normals[0] - Vec3f::new(-1., 1., 1.).normalize()).norm() < 1e-6);
+    assert!((normals[3] - Vec3f::new(1., 1., 1.).normalize()).norm() < 1e-6);
+    // the split vertices of the ridge get one smooth normal
+    flat.generate_normals(0, 0, true).unwrap();
+    let normals = flat.read_attribute::<Vec3f>(&flat.parts[0].primitives[0], "NORMAL").unwrap();
+    assert!((normals[1] - normals[3]).norm() < 1e-6 && (normals[1] - Vec3f::new(0., 1., 1.).normalize()).norm() < 1e-6);
+    // edits that re-pack the streams don't grow the buffer
+    let size = flat.buffer.len();
+    assert_eq!(size, 6 * 12 * 2 + 6 * 2);
+    for _ in 0..3 {
+        flat.generate_normals(0, 0, false).unwrap();
+        flat.write_indices(0, 0, &[0, 1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(flat.buffer.len(), size);
+    }
+
+    // tangents follow u, w is the handedness
+    let (buffer, prim) = strip.primitive_mut(0, 0).unwrap();
+    let mut uv = BufferView { data_type: DataType::VEC2, component_type: ComponentType::F32, ..Default::default() };
+    eldra::data::vertex_accessor::write_view(buffer, &mut uv, &[Vec2f::new(0., 0.), Vec2f::new(1., 0.), Vec2f::new(0., 1.), Vec2f::new(1., 1.)]).unwrap();
+    prim.texcoord.push(uv);
+    strip.generate_tangents(0, 0).unwrap();
+    let tangents = strip.read_attribute::<Vec4f>(&strip.parts[0].primitives[0], "TANGENT").unwrap();
+    assert!(tangents.iter().all(|t| (t - Vec4f::new(1., 0., 0., 1.)).norm() < 1e-6));
+    strip.write_attribute(0, 0, "TEXCOORD_0", &[Vec2f::new(0., 0.), Vec2f::new(1., 0.), Vec2f::new(0., -1.), Vec2f::new(1., -1.)]).unwrap();
+    strip.generate_tangents(0, 0).unwrap();
+    let tangents = strip.read_attribute::<Vec4f>(&strip.parts[0].primitives[0], "TANGENT").unwrap();
+    assert!(tangents.iter().all(|t| t.w == -1.0));
+    // per corner reference values of the mikktspace crate regression cube. the planes of
+    // constant u group with their neighbors, their vertices get split
+    let planes: [[([f32; 2], [f32; 3]); 5]; 6] = [
+        [([0., 0.], [1., -1., 1.]), ([0., 1.], [1., -1., -1.]), ([1., 1.], [1., 1., -1.]), ([1., 0.], [1., 1., 1.]), ([0.5, 0.5], [1., 0., 0.])],
+        [([1., 0.], [-1., 1., 1.]), ([1., 1.], [-1., 1., -1.]), ([0., 1.], [-1., -1., -1.]), ([0., 0.], [-1., -1., 1.]), ([0.5, 0.5], [-1., 0., 0.])],
+        [([0., 0.], [1., 1., 1.]), ([0., 1.], [1., 1., -1.]), ([0., 1.], [-1., 1., -1.]), ([0., 0.], [-1., 1., 1.]), ([0., 0.5], [0., 1., 0.])],
+        [([0., 0.], [-1., -1., 1.]), ([0., 1.], [-1., -1., -1.]), ([0., 1.], [1., -1., -1.]), ([0., 0.], [1., -1., 1.]), ([0., 0.5], [0., -1., 0.])],
+        [([0., 0.], [-1., 1., 1.]), ([0., 1.], [-1., -1., 1.]), ([1., 1.], [1., -1., 1.]), ([1., 0.], [1., 1., 1.]), ([0.5, 0.5], [0., 0., 1.])],
+        [([1., 0.], [1., 1., -1.]), ([1., 1.], [1., -1., -1.]), ([0., 1.], [-1., -1., -1.]), ([0., 0.], [-1., 1., -1.]), ([0.5, 0.5], [0., 0., -1.])],
+    ];
+    let points: Vec<(Vec2f, Vec3f)> = planes.iter().flatten().map(|(uv, p)| (Vec2f::from(*uv), Vec3f::from(*p))).collect();
+    let indices: Vec<u32> = (0..6).flat_map(|b| [0, 1, 4, 1, 2, 4, 2, 3, 4, 3, 0, 4].map(|i| b * 5 + i)).collect();
+    let mut cube = mesh(PrimitiveMode::TRIANGLES, &points.iter().map(|(_, p)| p / 2.0).collect::<Vec<_>>(), Some(&indices));
+    let (buffer, prim) = cube.primitive_mut(0, 0).unwrap();
+    let mut normal = BufferView { data_type: DataType::VEC3, component_type: ComponentType::F32, ..Default::default() };
+    let mut uv = BufferView { data_type: DataType::VEC2, component_type: ComponentType::F32, ..Default::default() };
+    eldra::data::vertex_accessor::write_view(buffer, &mut normal, &points.iter().map(|(_, p)| p.normalize()).collect::<Vec<_>>()).unwrap();
+    eldra::data::vertex_accessor::write_view(buffer, &mut uv, &points.iter().map(|(uv, _)| *uv).collect::<Vec<_>>()).unwrap();
+    prim.normal = Some(normal);
+    prim.texcoord.push(uv);
+    cube.generate_tangents(0, 0).unwrap();
+    let (a, b) = (0.40824825, 0.81649655);
+    let expected: [[f32; 4]; 72] = [
+        [a, b, a, -1.], [a, b, -a, -1.], [0., 1., 0., -1.],
+        [a, b, -a, -1.], [-a, b, a, -1.], [0., 1., 0., -1.],
+        [-a, b, a, -1.], [-a, b, -a, -1.], [0., 1., 0., -1.],
+        [-a, b, -a, -1.], [a, b, a, -1.], [0., 1., 0., -1.],
+        [a, b, -a, 1.], [a, b, a, 1.], [0., 1., 0., 1.],
+        [a, b, a, 1.], [-a, b, -a, 1.], [0., 1., 0., 1.],
+        [-a, b, -a, 1.], [-a, b, a, 1.], [0., 1., 0., 1.],
+        [-a, b, a, 1.], [a, b, -a, 1.], [0., 1., 0., 1.],
+        [1., 0., 0., -1.], [1., 0., 0., -1.], [1., 0., 0., -1.],
+        [1., 0., 0., -1.], [1., 0., 0., -1.], [1., 0., 0., -1.],
+        [1., 0., 0., -1.], [1., 0., 0., -1.], [1., 0., 0., -1.],
+        [1., 0., 0., -1.], [1., 0., 0., -1.], [1., 0., 0., -1.],
+        [-a, b, a, 1.], [-a, b, -a, 1.], [1., 0., 0., -1.],
+        [1., 0., 0., -1.], [a, b, -a, -1.], [1., 0., 0., -1.],
+        [a, b, -a, -1.], [a, b, a, -1.], [1., 0., 0., -1.],
+        [a, b, a, -1.], [1., 0., 0., -1.], [1., 0., 0., -1.],
+        [b, a, a, -1.], [b, -a, a, -1.], [1., 0., 0., -1.],
+        [b, -a, a, -1.], [b, a, -a, -1.], [1., 0., 0., -1.],
+        [b, a, -a, -1.], [b, -a, -a, -1.], [1., 0., 0., -1.],
+        [b, -a, -a, -1.], [b, a, a, -1.], [1., 0., 0., -1.],
+        [b, -a, a, 1.], [b, a, a, 1.], [1., 0., 0., 1.],
+        [b, a, a, 1.], [b, -a, -a, 1.], [1., 0., 0., 1.],
+        [b, -a, -a, 1.], [b, a, -a, 1.], [1., 0., 0., 1.],
+        [b, a, -a, 1.], [b, -a, a, 1.], [1., 0., 0., 1.],
+    ];
+    let prim = &cube.parts[0].primitives[0];
+    assert!(prim.position.count > 30);
+    let tangents = cube.read_attribute::<Vec4f>(prim, "TANGENT").unwrap();
+    let positions = cube.read_attribute::<Vec3f>(prim, "POSITION").unwrap();
+    for (c, v) in cube.read_indices(prim).unwrap().unwrap().iter().enumerate() {
+        assert!((tangents[*v as usize] - Vec4f::from(expected[c])).norm() < 1e-6, "corner {}", c);
+        assert_eq!(positions[*v as usize], points[indices[c] as usize].1 / 2.0);
+    }
+
+    // deduplication needs every stream equal, welding only close positions
+    let split = [quad[0], quad[1], quad[2], quad[2], quad[1], quad[3], Vec3f::new(1.0005, 1., 0.)];
+    let mut object = mesh(PrimitiveMode::TRIANGLES, &split, Some(&[0, 1, 2, 3, 4, 5, 4, 5, 6]));
+    let before = sorted(&object);
+    assert_eq!(object.deduplicate_vertices(0, 0).unwrap(), 5);
+    assert_eq!(object.read_indices(&object.parts[0].primitives[0]).unwrap(), Some(vec![0, 1, 2, 2, 1, 3, 1, 3, 4]));
+    assert_eq!(sorted(&object), before);
+    assert_eq!(object.weld_vertices(0, 0, 0.001).unwrap(), 4);
+    let prim = &object.parts[0].primitives[0];
+    assert_eq!(object.read_indices(prim).unwrap(), Some(vec![0, 1, 2, 2, 1, 3]));
+    assert_eq!(object.read_attribute::<Vec3f>(prim, "POSITION").unwrap(), quad.to_vec());
+
+    // cache and overdraw ordering keep the triangles
+    let n = 16;
+    let grid: Vec<Vec3f> = (0..n * n).map(|i| Vec3f::new((i % n) as f32, (i / n) as f32, ((i % n) as f32 * 0.3).sin())).collect();
+    let mut cells: Vec<u32> = (0..(n - 1) * (n - 1)).map(|c| c as u32).collect();
+    // deterministic shuffle
+    for i in (1..cells.len()).rev() {
+        cells.swap(i, (i * 7919 + 13) % (i + 1));
+    }
+    let indices: Vec<u32> = cells.iter().flat_map(|c| {
+        let v = c / (n as u32 - 1) * n as u32 + c % (n as u32 - 1);
+        [v, v + 1, v + n as u32, v + n as u32, v + 1, v + n as u32 + 1]
+    }).collect();
+    let mut object = mesh(PrimitiveMode::TRIANGLES, &grid, Some(&indices));
+    let before = sorted(&object);
+    object.optimize_vertex_cache(0, 0).unwrap();
+    let optimized = object.read_indices(&object.parts[0].primitives[0]).unwrap().unwrap();
+    assert!(acmr(&optimized, 32) < acmr(&indices, 32) * 0.75, "{} {}", acmr(&optimized, 32), acmr(&indices, 32));
+    assert_eq!(sorted(&object), before);
+    object.optimize_overdraw(0, 0).unwrap();
+    assert_eq!(sorted(&object), before);
+
+    // bounds, computed on import
+    let mut object = mesh(PrimitiveMode::TRIANGLES, &tent, None);
+    object.compute_bounds(0, 0).unwrap();
+    let prim = &object.parts[0].primitives[0];
+    assert_eq!((prim.aabb.min, prim.aabb.max), (Vec3f::new(-1., 0., 0.), Vec3f::new(1., 1., 1.)));
+    assert!(tent.iter().all(|p| prim.sphere.contains_point(p)) && prim.sphere.radius <= 1.0 + 1e-6);
+    let skinned = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/skinned.gltf").unwrap(), false);
+    let prim = &skinned.get().parts[0].primitives[0];
+    assert_eq!((prim.aabb.min, prim.aabb.max), (Vec3f::zeros(), Vec3f::new(1., 1., 0.)));
+    assert!(prim.sphere.radius > 0.7 && prim.sphere.radius < 0.72);
+}
+
+fn test_mesh_lods() {
+    use eldra::data::render_object::PrimitiveMode;
+    use eldra::data::mesh_simplify::simplify;
+    use eldra::comp::render_component::RenderComponent;
+    let grid = |n: usize, height: &dyn Fn(f32, f32) -> f32| {
+        let positions: Vec<Vec3f> = (0..n * n).map(|i| {
+            let (x, y) = ((i % n) as f32 / (n - 1) as f32, (i / n) as f32 / (n - 1) as f32);
+            Vec3f::new(x, y, height(x, y))
+        }).collect();
+        let indices: Vec<u32> = (0..(n - 1) * (n - 1)).flat_map(|c| {
+            let v = (c / (n - 1) * n + c % (n - 1)) as u32;
+            [v, v + 1, v + n as u32, v + n as u32, v + 1, v + n as u32 + 1]
+        }).collect();
+        (positions, indices)
+    };
+    let area = |indices: &[u32], positions: &[Vec3f]| indices.chunks_exact(3).map(|t| {
+        let n = (positions[t[1] as usize] - positions[t[0] as usize]).cross(&(positions[t[2] as usize] - positions[t[0] as usize]));
+        assert!(n.z > 0.0, "flipped triangle");
+        n.norm() * 0.5
+    }).sum::<f32>();
+
+    // a plane simplifies without error down to its locked border
+    let (positions, indices) = grid(9, &|_, _| 0.0);
+    let (simple, error) = simplify(&indices, &positions, 0, 1e-3);
+    assert!(simple.len() < indices.len() / 3, "{}", simple.len());
+    assert!(error < 1e-4);
+    assert!((area(&simple, &positions) - 1.0).abs() < 1e-4);
+    let border: Vec<u32> = (0..81).filter(|v| v % 9 == 0 || v % 9 == 8 || v / 9 == 0 || v / 9 == 8).collect();
+    assert!(simple.iter().all(|v| border.contains(v)));
+    // a bumpy one stops at the error
+    let (positions, indices) = grid(17, &|x, y| 0.1 * (x * 9.0).sin() * (y * 7.0).cos());
+    let (coarse, error) = simplify(&indices, &positions, 0, 0.01);
+    assert!(coarse.len() < indices.len() && coarse.len() > 100 && error <= 0.01 && error > 0.0);
+    area(&coarse, &positions);
+
+    // LODs with decreasing thresholds, sharing the vertices
+    let mut object = build_mesh(PrimitiveMode::TRIANGLES, &positions, Some(&indices));
+    object.compute_bounds(0, 0).unwrap();
+    assert_eq!(object.generate_lods(0, 0, &[0.5, 0.25, 0.1], 0.1).unwrap(), 3);
+    let prim = &object.parts[0].primitives[0];
+    let counts: Vec<usize> = (0..4).map(|l| object.accessor(prim.lod_indices(l).unwrap()).unwrap().count()).collect();
+    assert!(counts.windows(2).all(|w| w[1] < w[0]) && counts[1] <= indices.len() / 2, "{:?}", counts);
+    assert!(prim.lods.windows(2).all(|w| w[1].screen_size <= w[0].screen_size && w[1].error >= w[0].error));
+    assert!(prim.lods[2].error <= 0.1 * prim.sphere.radius);
+    assert!(object.accessor(prim.lod_indices(3).unwrap()).unwrap().read::<u32>().unwrap().iter().all(|i| (*i as usize) < positions.len()));
+    assert_eq!(prim.select_lod(f32::MAX), 0);
+    assert_eq!(prim.select_lod(0.0), 3);
+    assert_eq!(prim.select_lod(prim.lods[1].screen_size), 2);
+    // remapping the vertices drops them
+    let mut copy = build_mesh(PrimitiveMode::TRIANGLES, &positions, Some(&indices));
+    copy.generate_lods(0, 0, &[0.5], 0.05).unwrap();
+    // regenerating replaces the previous LODs in the buffer
+    let size = copy.buffer.len();
+    copy.generate_lods(0, 0, &[0.5], 0.05).unwrap();
+    assert_eq!(copy.buffer.len(), size);
+    copy.deduplicate_vertices(0, 0).unwrap();
+    assert!(copy.parts[0].primitives[0].lods.is_empty());
+
+    // selection by distance, scaled by the bias
+    let mut render = RenderComponent::default();
+    assert_eq!(render.lod_bias, 1.0);
+    let fov = std::f32::consts::FRAC_PI_2;
+    let world = Mat4f::new_translation(&Vec3f::new(0., 0., -2.));
+    let at = |distance: f32| Vec3f::new(0.5, 0.5, distance - 2.);
+    assert_eq!(render.select_lod(prim, &world, &at(0.5), fov), 0);
+    let far = render.select_lod(prim, &world, &at(1e5), fov);
+    assert_eq!(far, 3);
+    let threshold = prim.lods[0].screen_size;
+    // the distance at which the projected size meets the first threshold
+    let d = prim.sphere.radius / (threshold * (fov * 0.5).tan());
+    assert_eq!(render.select_lod(prim, &world, &at(d * 0.9), fov), 0);
+    assert_eq!(render.select_lod(prim, &world, &at(d * 1.1), fov), 1);
+    render.lod_bias = 2.0;
+    assert_eq!(render.select_lod(prim, &world, &at(d * 1.1), fov), 0);
+    assert_eq!(render.select_lod(prim, &world, &at(d * 2.2), fov), 1);
+}
+
+fn test_bvh() {
+    use eldra::data::bounds::{Aabb, BoundingSphere, Frustum, Ray};
+    use eldra::scene::bvh::DynamicBvh;
+    // slabs, spheres and frustum planes
+    let unit = Aabb::new(Vec3f::zeros(), Vec3f::new(1., 1., 1.));
+    assert_eq!(unit.ray_distance(&Ray::new(Vec3f::new(-1., 0.5, 0.5), Vec3f::x()), 10.), Some(1.));
+    assert_eq!(unit.ray_distance(&Ray::new(Vec3f::new(0.5, 0.5, 0.5), Vec3f::x()), 10.), Some(0.));
+    assert_eq!(unit.ray_distance(&Ray::new(Vec3f::new(-1., 0.5, 0.5), -Vec3f::x()), 10.), None);
+    assert_eq!(unit.ray_distance(&Ray::new(Vec3f::new(-1., 0.5, 0.5), Vec3f::x()), 0.5), None);
+    assert_eq!(unit.ray_distance(&Ray::new(Vec3f::new(-1., 2., 0.5), Vec3f::x()), 10.), None);
+    let sphere = BoundingSphere::new(Vec3f::zeros(), 1.);
+    assert_eq!(sphere.ray_distance(&Ray::new(Vec3f::new(0., 0., 5.), -Vec3f::z()), 10.), Some(4.));
+    assert_eq!(sphere.ray_distance(&Ray::new(Vec3f::new(0., 0., 5.), Vec3f::z()), 10.), None);
+    let projection = Mat4f::new_orthographic(-1., 1., -1., 1., 0., 10.);
+    // nalgebra maps depth to -1..1, the engine to 0..1
+    let depth = Mat4f::new(1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 0.5, 0.5, 0., 0., 0., 1.);
+    let frustum = Frustum::from_matrix(&(depth * projection));
+    assert!(frustum.contains_point(&Vec3f::new(0., 0., -5.)));
+    assert!(!frustum.contains_point(&Vec3f::new(0., 0., 5.)));
+    assert!(!frustum.contains_point(&Vec3f::new(0., 0., -11.)));
+    assert!(frustum.intersects_aabb(&Aabb::new(Vec3f::new(0.9, 0.9, -2.), Vec3f::new(2., 2., -1.))));
+    assert!(!frustum.intersects_aabb(&Aabb::new(Vec3f::new(1.1, 0., -2.), Vec3f::new(2., 1., -1.))));
+    assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3f::new(1.5, 0., -5.), 0.6)));
+
+    // the tree answers as brute force, while boxes move and leave
+    let mut bvh = DynamicBvh::<usize>::new(0.1);
+    let boxes: Vec<Aabb> = (0..200).map(|i| {
+        let p = Vec3f::new((i * 37 % 101) as f32, (i * 53 % 89) as f32, (i * 19 % 7) as f32);
+        Aabb::new(p, p + Vec3f::new(1. + (i % 3) as f32, 1., 1.))
+    }).collect();
+    let mut proxies: Vec<usize> = boxes.iter().enumerate().map(|(i, b)| bvh.insert(b, i)).collect();
+    bvh.validate().unwrap();
+    assert_eq!(bvh.len(), 200);
+    assert!(bvh.height() <= 16, "{}", bvh.height());
+    let brute = |boxes: &[Aabb], alive: &dyn Fn(usize) -> bool, query: &Aabb| {
+        let mut v: Vec<usize> = (0..boxes.len()).filter(|i| alive(*i) && boxes[*i].intersects(query)).collect();
+        v.sort();
+        v
+    };
+    let exact = |bvh: &DynamicBvh<usize>, boxes: &[Aabb], query: &Aabb| {
+        let mut v: Vec<usize> = bvh.query_aabb(query).into_iter().map(|p| bvh.data(p).unwrap()).filter(|i| boxes[*i].intersects(query)).collect();
+        v.sort();
+        v
+    };
+    let query = Aabb::new(Vec3f::new(20., 20., 0.), Vec3f::new(50., 40., 3.));
+    assert_eq!(exact(&bvh, &boxes, &query), brute(&boxes, &|_| true, &query));
+    assert!(!brute(&boxes, &|_| true, &query).is_empty());
+    // small moves stay in the enlarged box, larger ones reinsert
+    let mut boxes = boxes;
+    let nudge = Vec3f::new(0.05, 0., 0.);
+    boxes[0] = Aabb::new(boxes[0].min + nudge, boxes[0].max + nudge);
+    assert!(!bvh.update(proxies[0], &boxes[0]));
+    for i in 0..100 {
+        let shift = Vec3f::new(-30., 10., 2.);
+        boxes[i] = Aabb::new(boxes[i].min + shift, boxes[i].max + shift);
+        assert!(bvh.update(proxies[i], &boxes[i]));
+    }
+    for i in (100..200).step_by(2) {
+        bvh.remove(proxies[i]);
+    }
+    bvh.validate().unwrap();
+    assert_eq!(bvh.len(), 150);
+    let alive = |i: usize| i < 100 || i % 2 == 1;
+    assert_eq!(exact(&bvh, &boxes, &query), brute(&boxes, &alive, &query));
+    // freed nodes are reused
+    proxies[100] = bvh.insert(&boxes[100], 100);
+    assert!(proxies[100] < 400);
+    bvh.validate().unwrap();
+    // rays come nearest first
+    let c = boxes[151].center();
+    let ray = Ray::new(Vec3f::new(-100., c.y, c.z), Vec3f::x());
+    let hits = bvh.query_ray(&ray, 1000.);
+    assert!(hits.windows(2).all(|w| w[0].0 <= w[1].0));
+    let expected = (0..200).filter(|i| alive(*i) || *i == 100).filter(|i| boxes[*i].ray_distance(&ray, 1000.).is_some()).count();
+    assert!(hits.iter().filter(|(_, p)| boxes[bvh.data(*p).unwrap()].ray_distance(&ray, 1000.).is_some()).count() == expected && expected > 0);
+    for p in proxies.iter().enumerate().filter(|(i, _)| alive(*i) || *i == 100).map(|(_, p)| *p) {
+        bvh.remove(p);
+    }
+    assert!(bvh.is_empty());
+    bvh.validate().unwrap();
+}
+fn test_scene() {
+    use eldra::comp::render_component::RenderComponent;
+    use eldra::data::bounds::{Aabb, Frustum, Ray};
+    use eldra::scene::{Scene, with_scene};
+    let model = ExtRes::<RenderObject>::load(asset_db().read().unwrap().guid_of("models/skinned.gltf").unwrap(), false);
+    assert_eq!(model.get().bounds(-1), Aabb::new(Vec3f::zeros(), Vec3f::new(1., 1., 0.)));
+    assert!((model.get().bounding_sphere(-1).radius - 0.5f32.sqrt()).abs() < 1e-5);
+    let root = Entity::new();
+    root.borrow_mut().add_component(TransformComponent::default());
+    let mut entities = Vec::new();
+    for i in 0..3 {
+        let e = Entity::new();
+        let mut transform = TransformComponent::default();
+        transform.translate(&Vec3f::new(i as f32 * 10., 0., -5.));
+        e.borrow_mut().add_component(transform);
+        let mut render = RenderComponent::default();
+        render.set_object(model.clone(), -1);
+        e.borrow_mut().add_component(render);
+        root.borrow_mut().add_child(e.clone());
+        entities.push(e);
+    }
+    // not drawn
+    root.borrow_mut().add_child(Entity::new());
+    root.borrow_mut().tick(0., &None);
+    let mut scene = Scene::new(root.clone());
+    assert_eq!(scene.update(), 3);
+    assert_eq!(scene.update(), 0);
+    let id = |e: &Rc<RefCell<Entity>>| e.borrow().instance_id;
+    assert_eq!(scene.world_bounds(id(&entities[1])), Some(Aabb::new(Vec3f::new(10., 0., -5.), Vec3f::new(11., 1., -5.))));
+    let ids = |found: Vec<Rc<RefCell<Entity>>>| {
+        let mut v: Vec<i64> = found.iter().map(id).collect();
+        v.sort();
+        v
+    };
+    let near = Aabb::new(Vec3f::new(-1., -1., -6.), Vec3f::new(12., 2., -4.));
+    assert_eq!(ids(scene.query_aabb(&near)), vec![id(&entities[0]), id(&entities[1])]);
+    let depth = Mat4f::new(1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 0.5, 0.5, 0., 0., 0., 1.);
+    let frustum = Frustum::from_matrix(&(depth * Mat4f::new_perspective(1., std::f32::consts::FRAC_PI_2, 0.1, 100.)));
+    assert_eq!(ids(scene.query_frustum(&frustum)), vec![id(&entities[0])]);
+    let hits = scene.query_ray(&Ray::new(Vec3f::new(-5., 0.5, -5.), Vec3f::x()), 100.);
+    assert_eq!(hits.iter().map(|(_, e)| id(e)).collect::<Vec<i64>>(), entities.iter().map(id).collect::<Vec<i64>>());
+    assert_eq!(hits[1].0, 15.);
+
+    // moved by the transform, refit on update
+    entities[2].borrow_mut().get_component_mut::<TransformComponent>().unwrap().translate(&Vec3f::new(-15., 0., 0.));
+    root.borrow_mut().tick(0., &None);
+    assert_eq!(scene.update(), 1);
+    assert_eq!(ids(scene.query_frustum(&frustum)), vec![id(&entities[0]), id(&entities[2])]);
+    scene.bvh.validate().unwrap();
+    // removed from the tree
+    root.borrow_mut().remove_child(&entities[0]);
+    assert_eq!(scene.update(), 1);
+    assert_eq!(ids(scene.query_frustum(&frustum)), vec![id(&entities[2])]);
+    assert_eq!(scene.len(), 2);
+
+    let scene_id = eldra::scene::Scene_new(root.borrow().marker_address());
+    assert_eq!(eldra::scene::Scene_update(scene_id), 2);
+    assert_eq!(with_scene(scene_id, |s| s.len()), Some(2));
+    assert!(eldra::scene::Scene_destroy(scene_id));
+    assert_eq!(with_scene(scene_id, |s| s.len()), None);
+}
+
+// the entity isn't borrowed during the callback, a script may modify it
+extern "C" fn pick_filter(entity: u64) -> bool {
+    entity_cast(&entity).unwrap().borrow_mut().name != "front"
+}
+fn test_picking() {
+    use eldra::comp::render_component::RenderComponent;
+    use eldra::data::bounds::Ray;
+    use eldra::data::render_object::PrimitiveMode;
+    use eldra::scene::Scene;
+    use eldra::scene::picking::{ray_triangle, SceneRayHit, Scene_raycast};
+    let (a, b, c) = (Vec3f::zeros(), Vec3f::x(), Vec3f::y());
+    let (t, u, v) = ray_triangle(&Ray::new(Vec3f::new(0.25, 0.5, 2.), -Vec3f::z()), &a, &b, &c).unwrap();
+    assert_eq!((t, u, v), (2., 0.25, 0.5));
+    assert!(ray_triangle(&Ray::new(Vec3f::new(0.75, 0.5, 2.), -Vec3f::z()), &a, &b, &c).is_none());
+    assert!(ray_triangle(&Ray::new(Vec3f::new(0.25, 0.5, 2.), Vec3f::z()), &a, &b, &c).is_none());
+    assert!(ray_triangle(&Ray::new(Vec3f::new(0.25, 0.5, 2.), Vec3f::x()), &a, &b, &c).is_none());
+
+    // a unit quad, through glb
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let quad = [Vec3f::new(0., 0., 0.), Vec3f::new(1., 0., 0.), Vec3f::new(0., 1., 0.), Vec3f::new(1., 1., 0.)];
+    let mut out = Vec::new();
+    build_mesh(PrimitiveMode::TRIANGLES, &quad, Some(&[0, 1, 2, 2, 1, 3])).write_glb(&mut out).unwrap();
+    std::fs::write(root.join("models/quad.glb"), out).unwrap();
+    let guid = res_mgr().import_source("models/quad.glb").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+    let scene_root = Entity::new();
+    scene_root.borrow_mut().add_component(TransformComponent::default());
+    let quad_entity = |name: &str, z: f32, scale: f32| {
+        let e = Entity::new();
+        e.borrow_mut().name = name.to_string();
+        let mut transform = TransformComponent::default();
+        transform.scale(&Vec3f::repeat(scale));
+        transform.translate(&Vec3f::new(0., 0., z));
+        e.borrow_mut().add_component(transform);
+        let mut render = RenderComponent::default();
+        render.set_object(model.clone(), -1);
+        e.borrow_mut().add_component(render);
+        scene_root.borrow_mut().add_child(e.clone());
+        e
+    };
+    let front = quad_entity("front", -5., 1.);
+    let back = quad_entity("back", -10., 2.);
+    scene_root.borrow_mut().tick(0., &None);
+    let mut scene = Scene::new(scene_root.clone());
+    scene.update();
+    let all = |_: &Rc<RefCell<Entity>>| true;
+
+    let hit = scene.raycast(Vec3f::new(0.25, 0.25, 0.), -Vec3f::z(), all).unwrap();
+    assert!(Rc::ptr_eq(&hit.entity, &front));
+    assert_eq!((hit.part, hit.primitive, hit.triangle), (0, 0, 0));
+    assert!((hit.distance - 5.).abs() < 1e-5);
+    assert!((hit.barycentrics - Vec3f::new(0.5, 0.25, 0.25)).norm() < 1e-5);
+    assert!((hit.point - Vec3f::new(0.25, 0.25, -5.)).norm() < 1e-5);
+    assert!((hit.normal - Vec3f::z()).norm() < 1e-5);
+    let hit = scene.raycast(Vec3f::new(0.75, 0.75, 0.), -Vec3f::z(), all).unwrap();
+    assert_eq!(hit.triangle, 1);
+    assert!((hit.barycentrics - Vec3f::new(0.25, 0.25, 0.5)).norm() < 1e-5);
+    // from behind, the normal faces the ray
+    let hit = scene.raycast(Vec3f::new(0.75, 0.75, -7.), Vec3f::z(), all).unwrap();
+    assert!(Rc::ptr_eq(&hit.entity, &front) && (hit.distance - 2.).abs() < 1e-5);
+    assert!((hit.normal + Vec3f::z()).norm() < 1e-5);
+    // filtered, and outside of the small quad, the scaled one is hit
+    let hit = scene.raycast(Vec3f::new(0.25, 0.25, 0.), -Vec3f::z(), |e: &Rc<RefCell<Entity>>| e.borrow().name != "front").unwrap();
+    assert!(Rc::ptr_eq(&hit.entity, &back) && (hit.distance - 10.).abs() < 1e-5);
+    let hit = scene.raycast(Vec3f::new(1.5, 0.3, 0.), -Vec3f::z(), all).unwrap();
+    assert!(Rc::ptr_eq(&hit.entity, &back));
+    assert!((hit.barycentrics - Vec3f::new(0.1, 0.75, 0.15)).norm() < 1e-5);
+    // inside the bounds of the front quad, but off its triangles
+    assert!(scene.raycast(Vec3f::new(2.5, 0.5, 0.), -Vec3f::z(), all).is_none());
+    assert!(scene.raycast(Vec3f::new(0.25, 0.25, 0.), Vec3f::z(), all).is_none());
+
+    let scene_id = eldra::scene::Scene_new(scene_root.borrow().marker_address());
+    eldra::scene::Scene_update(scene_id);
+    let mut hit = SceneRayHit::default();
+    assert!(Scene_raycast(scene_id, 0.25, 0.25, 0., 0., 0., -1., None, &mut hit));
+    assert_eq!(hit.entity, front.borrow().marker_address());
+    assert!((hit.distance - 5.).abs() < 1e-5 && (hit.normal[2] - 1.).abs() < 1e-5);
+    assert!(Scene_raycast(scene_id, 0.25, 0.25, 0., 0., 0., -1., Some(pick_filter), &mut hit));
+    assert_eq!((hit.entity, hit.triangle), (back.borrow().marker_address(), 0));
+    assert!(!Scene_raycast(scene_id, 5., 5., 0., 0., 0., -1., None, &mut hit));
+    eldra::scene::Scene_destroy(scene_id);
+}
+
+fn test_camera() {
+    use eldra::comp::camera_component::{CameraComponent, ClearMode, Projection};
+    let root = asset_db().read().unwrap().root().to_path_buf();
+    let entity = Entity::new();
+    let mut transform = TransformComponent::default();
+    transform.translate(&Vec3f::new(0., 0., 10.));
+    entity.borrow_mut().add_component(transform);
+    entity.borrow_mut().add_component(CameraComponent::default());
+    entity.borrow_mut().tick(0., &None);
+    let mut e = entity.borrow_mut();
+    let camera = e.get_component_mut::<CameraComponent>().unwrap();
+    assert_eq!(camera.view_matrix().transform_point(&Point3::new(0., 0., 0.)), Point3::new(0., 0., -10.));
+    let close = |a: Vec3f, b: Vec3f| (a - b).norm() < 1e-3;
+
+    // 90 degrees, 200x100 pixels
+    camera.set_perspective(std::f32::consts::FRAC_PI_2, 1., 100.);
+    assert_eq!(camera.aspect_ratio(200, 100), 2.);
+    let depth = |camera: &CameraComponent, p: Vec3f| camera.world_to_screen(&p, 200, 100).unwrap().z;
+    assert!(close(camera.world_to_screen(&Vec3f::zeros(), 200, 100).unwrap(), Vec3f::new(100., 50., 100. * 9. / 990.)));
+    assert!(close(camera.world_to_screen(&Vec3f::new(10., 5., 0.), 200, 100).unwrap().xy().push(0.), Vec3f::new(150., 25., 0.)));
+    assert!((depth(camera, Vec3f::new(0., 0., 9.)) - 0.).abs() < 1e-5 && (depth(camera, Vec3f::new(0., 0., -90.)) - 1.).abs() < 1e-5);
+    assert!(camera.world_to_screen(&Vec3f::new(0., 0., 11.), 200, 100).is_none());
+    let p = camera.world_to_screen(&Vec3f::new(10., 5., 0.), 200, 100).unwrap();
+    assert!(close(camera.screen_to_world(p.x, p.y, p.z, 200, 100), Vec3f::new(10., 5., 0.)));
+    let frustum = camera.frustum(200, 100);
+    assert!(frustum.contains_point(&Vec3f::zeros()) && !frustum.contains_point(&Vec3f::new(0., 0., -100.)));
+    assert!(!frustum.contains_point(&Vec3f::new(0., 11., 0.)) && frustum.contains_point(&Vec3f::new(19., 0., 0.)));
+    // reverse-Z and infinite far
+    camera.reverse_z = true;
+    assert_eq!(camera.clear_depth(), 0.);
+    assert!((depth(camera, Vec3f::zeros()) - 90. / 990.).abs() < 1e-5);
+    assert!((depth(camera, Vec3f::new(0., 0., 9.)) - 1.).abs() < 1e-5);
+    camera.infinite_far = true;
+    assert!((depth(camera, Vec3f::zeros()) - 0.1).abs() < 1e-5);
+    camera.reverse_z = false;
+    assert!((depth(camera, Vec3f::zeros()) - 0.9).abs() < 1e-5);
+    for reverse in [false, true] {
+        camera.reverse_z = reverse;
+        let frustum = camera.frustum(200, 100);
+        assert!(frustum.contains_point(&Vec3f::new(0., 0., -10000.)) && !frustum.contains_point(&Vec3f::new(0., 0., 9.5)));
+        let p = camera.world_to_screen(&Vec3f::new(3., -2., -40.), 200, 100).unwrap();
+        assert!(close(camera.screen_to_world(p.x, p.y, p.z, 200, 100), Vec3f::new(3., -2., -40.)));
+    }
+    // rays start on the near plane
+    let ray = camera.screen_ray(150., 25., 200, 100);
+    assert!(close(ray.origin, Vec3f::new(1., 0.5, 9.)));
+    assert!(close(ray.direction, Vec3f::new(1., 0.5, -1.).normalize()));
+    camera.reverse_z = false;
+    camera.infinite_far = false;
+
+    // orthographic, 5 units up, over the right half of the target
+    camera.set_orthographic(5., 1., 100.);
+    assert!(close(camera.world_to_screen(&Vec3f::new(4., 2.5, 0.), 200, 100).unwrap().xy().push(0.), Vec3f::new(140., 25., 0.)));
+    let ray = camera.screen_ray(140., 25., 200, 100);
+    assert!(close(ray.origin, Vec3f::new(4., 2.5, 9.)) && close(ray.direction, -Vec3f::z()));
+    camera.viewport = Vec4f::new(0.5, 0., 0.5, 1.);
+    assert_eq!(camera.viewport_rect(200, 100), Vec4f::new(100., 0., 100., 100.));
+    assert_eq!(camera.aspect_ratio(200, 100), 1.);
+    assert!(close(camera.world_to_screen(&Vec3f::new(5., 0., 0.), 200, 100).unwrap().xy().push(0.), Vec3f::new(200., 50., 0.)));
+    camera.aspect = 2.;
+    assert!(close(camera.world_to_screen(&Vec3f::new(5., 0., 0.), 200, 100).unwrap().xy().push(0.), Vec3f::new(175., 50., 0.)));
+    // layers
+    camera.layer_mask = 0b10;
+    assert!(camera.sees(0b110) && !camera.sees(0b1));
+
+    // follows the transform, serialized with the entity
+    camera.clear = ClearMode::DEPTH;
+    drop(e);
+    entity.borrow_mut().get_component_mut::<TransformComponent>().unwrap().translate(&Vec3f::new(1., 0., 0.));
+    entity.borrow_mut().tick(0., &None);
+    assert_eq!(entity.borrow().get_component::<CameraComponent>().unwrap().world_matrix.column(3).xyz(), Vec3f::new(1., 0., 10.));
+    let path_c = convert_c_str(root.join("prefabs/camera.yaml").to_str().unwrap());
+    Entity_serialize_yaml(entity.borrow().marker_address(), path_c);
+    let copy = Entity_new();
+    Entity_deserialize_yaml(copy, path_c);
+    let copy = entity_cast(&copy).unwrap();
+    let c = copy.borrow();
+    let camera = c.get_component::<CameraComponent>().unwrap();
+    assert_eq!((camera.projection, camera.clear, camera.layer_mask, camera.aspect), (Projection::ORTHOGRAPHIC, ClearMode::DEPTH, 0b10, 2.));
+    assert_eq!((camera.ortho_size, camera.near, camera.far, camera.viewport), (5., 1., 100., Vec4f::new(0.5, 0., 0.5, 1.)));
+    drop_c_str(path_c);
+}
+fn test_lights() {
+    use eldra::comp::light_component::{AreaLight, DirectionalLight, PointLight, SpotLight};
+    use eldra::scene::lights::{extract_lights, GpuLight, GpuShadow, LightKind, LIGHT_FLAG_TWO_SIDED};
+    use std::mem::{offset_of, size_of};
+    // 16 bytes aligned std430 layout
+    assert_eq!((size_of::<GpuLight>(), size_of::<GpuShadow>()), (80, 16));
+    assert_eq!((offset_of!(GpuLight, range), offset_of!(GpuLight, kind), offset_of!(GpuLight, shadow)), (12, 28, 44));
+    assert_eq!((offset_of!(GpuLight, spot_scale), offset_of!(GpuLight, spot_offset)), (60, 76));
+
+    let child = |parent: &Rc<RefCell<Entity>>, local: Mat4f| {
+        let e = Entity::new();
+        let transform = TransformComponent { local_matrix: local, ..Default::default() };
+        e.borrow_mut().add_component(transform);
+        parent.borrow_mut().add_child(e.clone());
+        e
+    };
+    let root = Entity::new();
+    root.borrow_mut().add_component(TransformComponent::default());
+    let lamp = child(&root, Mat4f::new_translation(&Vec3f::new(1., 2., 3.)));
+    lamp.borrow_mut().add_component(PointLight { color: Vec3f::new(1., 0.5, 0.), range: 10., cast_shadows: true, ..Default::default() });
+    let sun = child(&root, Rotation3::from_axis_angle(&Vector3::x_axis(), -std::f32::consts::FRAC_PI_2).to_homogeneous());
+    sun.borrow_mut().add_component(DirectionalLight { intensity: 50000., cast_shadows: true, shadow_resolution: 4096, ..Default::default() });
+    let spot = child(&lamp, Mat4f::new_translation(&Vec3f::new(0., 0., 1.)));
+    spot.borrow_mut().add_component(SpotLight { inner_cone_angle: 0.2, outer_cone_angle: 0.4, ..Default::default() });
+    let panel = child(&root, Mat4f::new_nonuniform_scaling(&Vec3f::new(2., 1., 1.)));
+    panel.borrow_mut().add_component(AreaLight { width: 1., height: 2., two_sided: true, ..Default::default() });
+    let off = child(&root, Mat4f::identity());
+    off.borrow_mut().add_component(PointLight { enabled: false, ..Default::default() });
+    root.borrow_mut().tick(0., &None);
+
+    let data = extract_lights(&root);
+    assert_eq!((data.len(), data.counts), (4, [1, 1, 1, 1]));
+    let ids: Vec<i64> = [&sun, &lamp, &spot, &panel].iter().map(|e| e.borrow().instance_id).collect();
+    assert_eq!(data.entities, ids);
+    let close = |a: [f32; 3], b: [f32; 3]| (Vec3f::from(a) - Vec3f::from(b)).norm() < 1e-5;
+    // directional lights have no position, their shadow comes first
+    let sun = data.of_kind(LightKind::DIRECTIONAL)[0];
+    assert!(close(sun.direction, [0., -1., 0.]) && sun.position == [0.; 3]);
+    assert_eq!((sun.kind, sun.color, sun.shadow, sun.spot_offset), (0, [50000.; 3], 0, 1.));
+    assert_eq!(data.shadows[0], GpuShadow { light: 0, resolution: 4096, bias: 0.005, normal_bias: 1. });
+    let lamp = data.of_kind(LightKind::POINT)[0];
+    assert_eq!((lamp.position, lamp.range, lamp.color, lamp.kind), ([1., 2., 3.], 10., [100., 50., 0.], 1));
+    assert_eq!((lamp.shadow, data.shadows[1].light, data.shadows[1].resolution), (1, 1, 512));
+    // spot under the lamp, saturate(cos * scale + offset) is 1 inside the inner cone and 0 outside the outer one
+    let spot = data.of_kind(LightKind::SPOT)[0];
+    assert_eq!((spot.position, spot.direction, spot.range, spot.shadow), ([1., 2., 4.], [0., 0., -1.], 0., -1));
+    assert!((0.2f32.cos() * spot.spot_scale + spot.spot_offset - 1.).abs() < 1e-4);
+    assert!((0.4f32.cos() * spot.spot_scale + spot.spot_offset).abs() < 1e-4);
+    // area half extents are scaled by the world matrix
+    let panel = data.of_kind(LightKind::AREA)[0];
+    assert_eq!(panel.kind, LightKind::AREA as u32 | LIGHT_FLAG_TWO_SIDED);
+    assert!(close(panel.right, [1., 0., 0.]) && close(panel.up, [0., 1., 0.]) && close(panel.direction, [0., 0., -1.]));
+
+    // header of counts, lights then shadows
+    let bytes = data.as_bytes();
+    assert_eq!((bytes.len(), data.shadows_offset()), (16 + 4 * 80 + 2 * 16, 16 + 4 * 80));
+    let u32_at = |o: usize| u32::from_le_bytes(bytes[o..o + 4].try_into().unwrap());
+    let f32_at = |o: usize| f32::from_le_bytes(bytes[o..o + 4].try_into().unwrap());
+    assert_eq!([u32_at(0), u32_at(4), u32_at(8), u32_at(12)], [1, 1, 1, 1]);
+    assert_eq!((u32_at(16 + 80 + 28), f32_at(16 + 80 + 12), f32_at(16 + 80 + 4)), (1, 10., 2.));
+    assert_eq!(u32_at(16 + 3 * 80 + 28), 3 | LIGHT_FLAG_TWO_SIDED);
+    assert_eq!((u32_at(data.shadows_offset() + 16), u32_at(data.shadows_offset() + 20)), (1, 512));
+    // disabling removes from the extraction
+    let spot_entity = root.borrow().children[0].borrow().children[0].clone();
+    spot_entity.borrow_mut().get_component_mut::<SpotLight>().unwrap().enabled = false;
+    assert_eq!(extract_lights(&root).counts, [1, 1, 0, 1]);
+
+    // KHR_lights_punctual
+    let dir = asset_db().read().unwrap().root().join("models");
+    let positions: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    let gltf = embedded_gltf(&positions, r#"
+  "bufferViews": [ { "buffer": 0, "byteOffset": 0, "byteLength": 36 } ],
+  "accessors": [ { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] } ],
+  "meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 } } ] } ],
+  "extensionsUsed": [ "KHR_lights_punctual" ],
+  "extensions": { "KHR_lights_punctual": { "lights": [
+    { "type": "directional", "color": [1.0, 1.0, 0.5], "intensity": 3.0 },
+    { "type": "point", "intensity": 50.0, "range": 8.0 },
+    { "type": "spot", "intensity": 20.0, "spot": { "innerConeAngle": 0.1, "outerConeAngle": 0.5 } }
+  ] } },
+  "nodes": [
+    { "name": "sun", "rotation": [-0.70710677, 0.0, 0.0, 0.70710677], "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+    { "name": "bulb", "translation": [0.0, 2.0, 0.0], "extensions": { "KHR_lights_punctual": { "light": 1 } } },
+    { "name": "spot", "mesh": 0, "extensions": { "KHR_lights_punctual": { "light": 2 } } }
+  ],
+  "scenes": [ { "nodes": [0, 1, 2] } ]"#);
+    std::fs::write(dir.join("lights.gltf"), gltf).unwrap();
+    let gltf_c = convert_c_str(dir.join("lights.gltf").to_str().unwrap());
+    let prefab_c = convert_c_str(dir.parent().unwrap().join("prefabs/lights.yaml").to_str().unwrap());
+    let scene = Entity_import_gltf(gltf_c, prefab_c);
+    assert_ne!(scene, 0);
+    Entity_tick(scene, 0.);
+    let data = extract_lights(&entity_cast(&scene).unwrap());
+    assert_eq!(data.counts, [1, 1, 1, 0]);
+    assert!(close(data.lights[0].direction, [0., -1., 0.]) && close(data.lights[0].color, [3., 3., 1.5]));
+    assert_eq!((data.lights[1].position, data.lights[1].range, data.lights[1].color), ([0., 2., 0.], 8., [50.; 3]));
+    // from the prefab
+    let prefab = Entity_new();
+    Entity_deserialize_yaml(prefab, prefab_c);
+    let prefab = entity_cast(&prefab).unwrap();
+    let spot = prefab.borrow().children[2].clone();
+    let s = spot.borrow();
+    let light = s.get_component::<SpotLight>().unwrap();
+    assert_eq!((light.intensity, light.range, light.inner_cone_angle, light.outer_cone_angle, light.enabled), (20., 0., 0.1, 0.5, true));
+    drop(s);
+    Entity_destroy(prefab.borrow().marker_address());
+    Entity_destroy(scene);
+    drop_c_str(gltf_c);
+    drop_c_str(prefab_c);
+}
+fn test_draw_lists() {
+    use eldra::comp::animator_component::AnimatorComponent;
+    use eldra::comp::camera_component::CameraComponent;
+    use eldra::comp::render_component::RenderComponent;
+    use eldra::scene::Scene;
+    use eldra::scene::draw_list::{opaque_key, transparent_key, DrawLists, RenderView};
+    // sort keys
+    assert!(opaque_key(0, 5., false) < opaque_key(0, 6., false) && opaque_key(0, 100., false) < opaque_key(1, 0., false));
+    assert!(opaque_key(7, 100., false) < opaque_key(0, 0., true) && opaque_key(0, -1., false) == opaque_key(0, 0., false));
+    assert!(transparent_key(5, 10.) < transparent_key(0, 2.) && transparent_key(0, 2.) < transparent_key(1, 2.));
+
+    // a triangle for each of an opaque, an alpha tested and a blended material
+    let dir = asset_db().read().unwrap().root().join("models");
+    let positions: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter().flat_map(|f| f.to_le_bytes()).collect();
+    let gltf = embedded_gltf(&positions, r#"
+  "bufferViews": [ { "buffer": 0, "byteOffset": 0, "byteLength": 36 } ],
+  "accessors": [ { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] } ],
+  "materials": [ { "name": "stone" }, { "name": "leaf", "alphaMode": "MASK" }, { "name": "glass", "alphaMode": "BLEND" } ],
+  "meshes": [
+    { "primitives": [ { "attributes": { "POSITION": 0 }, "material": 0 } ] },
+    { "primitives": [ { "attributes": { "POSITION": 0 }, "material": 1 } ] },
+    { "primitives": [ { "attributes": { "POSITION": 0 }, "material": 2 } ] }
+  ]"#);
+    std::fs::write(dir.join("draws.gltf"), gltf).unwrap();
+    let guid = res_mgr().import_source("models/draws.gltf").unwrap();
+    let model = ExtRes::<RenderObject>::load(guid, false);
+    assert_eq!(model.state(), LoadState::Loaded, "{:?}", model.handle().error());
+
+    let root = Entity::new();
+    root.borrow_mut().add_component(TransformComponent::default());
+    let add = |part: i32, position: Vec3f, setup: &dyn Fn(&mut RenderComponent)| {
+        let e = Entity::new();
+        let mut transform = TransformComponent::default();
+        transform.translate(&position);
+        e.borrow_mut().add_component(transform);
+        let mut render = RenderComponent::default();
+        render.set_object(model.clone(), part);
+        setup(&mut render);
+        e.borrow_mut().add_component(render);
+        root.borrow_mut().add_child(e.clone());
+        let id = e.borrow().instance_id;
+        id
+    };
+    let near_stone = add(0, Vec3f::new(0., 0., -5.), &|_| {});
+    let far_stone = add(0, Vec3f::new(0., 0., -10.), &|_| {});
+    let leaf = add(1, Vec3f::new(0., 0., -7.), &|_| {});
+    let near_glass = add(2, Vec3f::new(0., 0., -4.), &|_| {});
+    let far_glass = add(2, Vec3f::new(0., 0., -8.), &|_| {});
+    let outside = add(0, Vec3f::new(100., 0., 0.), &|_| {});
+    add(0, Vec3f::new(0., 0., -6.), &|r| r.layers = 0b10);
+    let no_shadow = add(0, Vec3f::new(0., 0., -3.), &|r| r.cast_shadows = false);
+    root.borrow_mut().tick(0., &None);
+    let mut scene = Scene::new(root.clone());
+    scene.update();
+
+    let camera = Entity::new();
+    camera.borrow_mut().add_component(TransformComponent::default());
+    camera.borrow_mut().add_component(CameraComponent { fov_y: std::f32::consts::FRAC_PI_2, layer_mask: 0b1, ..Default::default() });
+    camera.borrow_mut().tick(0., &None);
+    let c = camera.borrow();
+    let lists = DrawLists::from_camera(&scene, c.get_component::<CameraComponent>().unwrap(), 100, 100);
+    let entities = |list: &[eldra::scene::draw_list::DrawItem]| list.iter().map(|d| d.entity).collect::<Vec<i64>>();
+    // culled by the frustum and the layers, opaque front to back then alpha tested
+    assert_eq!(entities(&lists.opaque), [no_shadow, near_stone, far_stone, leaf]);
+    assert_eq!(entities(&lists.transparent), [far_glass, near_glass]);
+    assert_eq!(entities(&lists.shadow), [near_stone, far_stone, leaf]);
+    assert_eq!((lists.len(), lists.materials.len()), (6, 3));
+    let names: Vec<String> = lists.opaque.iter().chain(lists.transparent.iter()).map(|d| lists.materials[d.material as usize].get().name.clone()).collect();
+    assert_eq!(names, ["stone", "stone", "stone", "leaf", "glass", "glass"]);
+    let stone = &lists.opaque[1];
+    assert_eq!((stone.part, stone.primitive, stone.lod, stone.palette_len), (0, 0, 0, 0));
+    assert_eq!(stone.world.column(3).xyz(), Vec3f::new(0., 0., -5.));
+    assert!((stone.depth - 5.).abs() < 1e-5);
+    assert!(lists.opaque.windows(2).all(|w| w[0].sort_key <= w[1].sort_key));
+
+    // casters seen by a light, out of the camera view
+    let mut light = CameraComponent::default();
+    light.set_orthographic(2., 0.1, 20.);
+    light.world_matrix = Mat4f::new_translation(&Vec3f::new(100., 0., 10.));
+    let light_view = RenderView::from_camera(&light, 100, 100);
+    let lists = DrawLists::extract(&scene, &RenderView::from_camera(c.get_component::<CameraComponent>().unwrap(), 100, 100), Some(&light_view));
+    assert_eq!(entities(&lists.shadow), [outside]);
+    assert!((lists.shadow[0].depth - 10.).abs() < 1e-5);
+    drop(c);
+
+    // skinned draws get the bind pose, then the palette of the animator of the skeleton
+    let gltf_c = convert_c_str(dir.join("skinned.gltf").to_str().unwrap());
+    let skinned = Entity_import_gltf(gltf_c, std::ptr::null());
+    Entity_tick(skinned, 0.);
+    let skinned = entity_cast(&skinned).unwrap();
+    let mut scene = Scene::new(skinned.clone());
+    scene.update();
+    camera.borrow_mut().get_component_mut::<TransformComponent>().unwrap().translate(&Vec3f::new(0., 0., 5.));
+    camera.borrow_mut().tick(0., &None);
+    let character = skinned.borrow().children[0].clone();
+    let skeleton = character.borrow().get_component::<RenderComponent>().unwrap().skeleton().unwrap();
+    let extract = || DrawLists::from_camera(&scene, camera.borrow().get_component::<CameraComponent>().unwrap(), 100, 100);
+    let lists = extract();
+    assert_eq!(entities(&lists.opaque), [character.borrow().instance_id]);
+    let draw = &lists.opaque[0];
+    assert_eq!((draw.palette_offset, draw.palette_len as usize), (0, skeleton.get().joint_count()));
+    let rest = skeleton.get().rest_world_matrices();
+    for (i, m) in lists.palettes.iter().enumerate() {
+        assert!((m - rest[i] * skeleton.get().joints[i].inverse_bind).norm() < 1e-5);
+    }
+    let animator = AnimatorComponent {
+        skeleton: skeleton.clone(),
+        skin_matrices: vec![Mat4f::new_translation(&Vec3f::new(0., 1., 0.)); skeleton.get().joint_count()],
+        ..Default::default()
+    };
+    skinned.borrow_mut().add_component(animator);
+    let lists = extract();
+    assert_eq!(lists.palettes, vec![Mat4f::new_translation(&Vec3f::new(0., 1., 0.)); skeleton.get().joint_count()]);
+    Entity_destroy(skinned.borrow().marker_address());
+    drop_c_str(gltf_c);
+}
 pub fn cstr_to_str(c_buf: *const c_char) -> &'static str {
     unsafe {
         let cstr = CStr::from_ptr(c_buf);
@@ -125,10 +2332,37 @@ fn main() {
 
     println!("test transform");
     let entity = test_transform_component();
+    test_transform_translate();
 
     println!("test serialize");
     test_serialize_yaml(entity);
     test_serialize_binary(entity);
+    test_blob_mapped();
+    test_blob_compressed();
+    test_asset_db();
+    test_async_load();
+    test_hot_reload();
+    test_residency();
+    test_resource_registry();
+    test_dependency_cycle();
+    test_gltf_materials();
+    test_gltf_skin();
+    test_gltf_scene();
+    test_gltf_export();
+    test_animation_clip();
+    test_animator();
+    test_animation_graph();
+    test_morph_targets();
+    test_vertex_accessor();
+    test_mesh_tools();
+    test_mesh_lods();
+    test_bvh();
+    test_scene();
+    test_picking();
+    test_camera();
+    test_lights();
+    test_draw_lists();
+    test_vfs();
 
     println!("test cleanup");
     Entity_destroy(entity);
@@ -261,7 +261,7 @@ fn gen_enum_reflection(variants: &Punctuated<Variant, Comma>, ast: &DeriveInput)
             });
             let yaml = format!("{{ enum: \"{}\" }}", id_str);
             yaml_serializer.extend(quote! {
-                #name::#id => { #yaml.to_string().serialize_text(io, indent.clone()); },
+                #name::#id => { let _ = io.write_all(#yaml.as_bytes()); },
             });
             yaml_deserializer.extend(quote! {
                 #id_str => { *self = #name::#id; },
@@ -296,7 +296,7 @@ fn gen_enum_reflection(variants: &Punctuated<Variant, Comma>, ast: &DeriveInput)
                 { format!("{{{{ enum: \"{}\", val: {{}} }}}}", id_str) };
             //println!("YAML {}/{:?}", field_type_str, yaml);
             yaml_serializer.extend(quote! {
-                #name::#id(v) => { format!(#yaml, v).serialize_text(io, indent.clone()); },
+                #name::#id(v) => { let _ = io.write_all(format!(#yaml, v).as_bytes()); },
             });
             yaml_deserializer.extend(quote! {
                 #id_str => {
@@ -337,7 +337,7 @@ fn gen_enum_reflection(variants: &Punctuated<Variant, Comma>, ast: &DeriveInput)
                     _ => { panic!("invalid enum value, please regenerate binary data"); }
                 }
             }
-            fn serialize_text(&self, io: &mut crate::reflection::SerializeTextWriter, indent: String) {
+            fn serialize_text(&self, io: &mut crate::reflection::SerializeTextWriter, _indent: String) {
                 match self {
                     #yaml_serializer
                 }